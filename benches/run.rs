@@ -0,0 +1,81 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Speed of the run loop, with and without optimizations.
+
+extern crate bfrscc;
+#[macro_use]
+extern crate criterion;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use criterion::Criterion;
+use bfrscc::buffer::BufferMachine;
+use bfrscc::machine::MachineExt;
+use bfrscc::program::Program;
+use bfrscc::run::{compile, RunConfig};
+
+/// Nested loops of about 180k steps, copying and clearing cells in the innermost loops
+const NESTED: &str = "++++++++++++++++[>++++++++++++++++[>++++++++++++++++[\
+                      >++++++++++++++++++++++++++++++++[>+>+<<-]>>[-<<+>>]<<[-]<-]<-]<-]";
+
+/// Prints `Hello World!` and a newline
+const HELLO: &str = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.\
+                     <.+++.------.--------.>>+.>++.";
+
+/// Directory of further programs to benchmark, e.g. mandelbrot.bf, which are not part of the
+/// repository
+const PROGRAMS: &str = "benches/programs";
+
+/// Benchmark `source`, unoptimized and optimized.
+fn bench(c: &mut Criterion, name: &str, source: &str) {
+    let prog = compile(source, &RunConfig::default()).unwrap();
+    let optimized = prog.clone().optimized();
+    for &(level, prog) in &[("O0", &prog), ("O1", &optimized)] {
+        c.bench_function(&format!("{} {}", name, level), |b| b.iter(|| run(prog)));
+    }
+}
+
+/// Run `prog` with its output kept in memory.
+fn run(prog: &Program) -> Vec<u8> {
+    let mut machine = BufferMachine::new();
+    machine.run(prog).unwrap();
+    machine.take_output()
+}
+
+fn nested(c: &mut Criterion) {
+    bench(c, "nested", NESTED);
+}
+
+fn hello(c: &mut Criterion) {
+    bench(c, "hello", HELLO);
+}
+
+/// Benchmark every `.bf` file in `PROGRAMS`, if there are any, named after the file.
+fn programs(c: &mut Criterion) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(PROGRAMS);
+    let mut files: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "bf"))
+            .collect(),
+        Err(_) => return,
+    };
+    files.sort();
+    for file in files {
+        let name = file.file_stem().unwrap().to_string_lossy().into_owned();
+        let source = fs::read_to_string(&file).unwrap();
+        bench(c, &name, &source);
+    }
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(20);
+    targets = nested, hello, programs
+}
+criterion_main!(benches);
@@ -0,0 +1,1635 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! A basic, certainly not optimized, Brainfuck interpreter.
+//!
+//! `bfrscc help` lists the commands and their options, the README describes them.
+
+#![cfg_attr(feature = "clippy", feature(plugin))]
+#![cfg_attr(feature = "clippy", plugin(clippy))]
+#![warn(missing_docs)]
+extern crate bfrscc_core;
+
+mod manifest;
+mod progress;
+mod report;
+mod testing;
+
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use bfrscc_core::alias::{parse_alias, parse_aliases};
+use bfrscc_core::asm::assemble;
+use bfrscc_core::backend::{self, Backend, Cfg, Precompute, C};
+use bfrscc_core::batch::run_parallel;
+use bfrscc_core::brackets::BracketTable;
+use bfrscc_core::buffer::BufferMachine;
+use bfrscc_core::bytecode;
+use bfrscc_core::cell::CellType;
+use bfrscc_core::checkpoint::{parse_duration, Checkpoint, Checkpointing};
+use bfrscc_core::coredump::CoreDump;
+use bfrscc_core::debugger::Debugger;
+use bfrscc_core::decompile::decompile;
+use bfrscc_core::diagnostics::{ColorChoice, Diagnostic, MessageFormat, Severity};
+use bfrscc_core::disasm::disassemble;
+use bfrscc_core::interpreter::Interpreter;
+use bfrscc_core::json::{self, Object};
+use bfrscc_core::lint::lint;
+use bfrscc_core::logging::{self, Level};
+use bfrscc_core::loops::loops;
+use bfrscc_core::lexer::{lexer_with_options, source_from_bytes, CommentPolicy, Dialect,
+                    LexerOptions};
+use bfrscc_core::meta::{meta_input, DBFI};
+use bfrscc_core::mmap::Mapping;
+use bfrscc_core::mutate::{mutate, Verdict};
+use bfrscc_core::parser::validate_all;
+use bfrscc_core::preprocess::{self, Expanded};
+use bfrscc_core::program::Program;
+use bfrscc_core::provenance::explain;
+use bfrscc_core::recording::Recording;
+use bfrscc_core::repl::{self, Repl};
+use bfrscc_core::repro::{hash, Environment};
+use bfrscc_core::run::{compile, interpreter, program_part, run, run_program, run_with_config,
+                  Arithmetic, Progress, RunConfig, RunError, RunReport, Sandbox, TapeDump,
+                  Underflow};
+use bfrscc_core::rundiff::diff_runs;
+use bfrscc_core::sampler::hotspots;
+use bfrscc_core::shadow::shadow_run;
+use bfrscc_core::slice::slice;
+use bfrscc_core::stream::{compile_reader, compile_slice};
+use bfrscc_core::tape::{CircularTape, DumpFormat, GridTape, LinearTape, Tape, TapeView};
+use bfrscc_core::transpile::transpile_dir;
+use manifest::{Manifest, MANIFEST_FILE};
+use progress::ProgressLine;
+use report::html;
+use testing::{diff, run_test, sidecar, Outcome, DEFAULT_MAX_STEPS};
+
+/// Commands and their options, printed by `bfrscc help`
+const USAGE: &str = "\
+Usage:
+bfrscc [-v|-vv] run <program.bf> [-O] [--shadow] [--meta] [--max-steps <n>] [--max-tape <n>]
+                  [--max-output-bytes <n>] [--max-time <duration>] [--max-loop-iters <n>]
+                  [--sandbox strict|default|off]
+                  [--max-length <n>] [--max-depth <n>]
+                  [--core <file> | --no-core] [--comments ignore|error|dialect]
+                  [--alias <alias>=<char>]... [--aliases <file>] [--repeat-counts]
+                  [--tapes <n> | --grid | --random [--seed <n>]] [--newline lf|crlf|raw]
+                  [--eof 0|255] [--arithmetic wrap|saturate|trap] [--cells u8|i8|i16|i32]
+                  [--underflow grow|wrap:<cells>|error]
+                  [--tape-init <file>|<c0,c1,...>] [--pointer <n>]
+                  [--dump-tape[=hex|dec|chars] [--range <a>..<b>] [--dump-file <file>]]
+                  [--exit-from-cell[=<cell>]] [--quiet] [--strict] [--stats]
+                  [--preprocess] [--cfg <file.dot>] [--color auto|always|never]
+                  [--message-format human|json] [--stream] [--mmap] [--report <file.json>]
+                  [--sample-hz <n>] [--checkpoint-every <duration> [--checkpoint-file <file>]]
+                  [--resume <file>] [--tape-reserve <n>] [--check] [--perf] [--progress]
+bfrscc asm <program.bfa> [--run]
+bfrscc batch <program.bf>... [-O] [--max-steps <n>] [--max-tape <n>] [--max-output-bytes <n>]
+             [--random [--seed <n>]]
+bfrscc check <program.bf> [--comments ignore|error|dialect] [--tapes <n> | --grid | --random]
+             [--alias <alias>=<char>]... [--aliases <file>] [--preprocess]
+             [--repeat-counts] [--color auto|always|never] [--message-format human|json]
+bfrscc inspect <core>
+bfrscc explain <program.bf> [-O] [--input <file>] [--max-steps <n>]
+bfrscc slice <program.bf> --cell <n> [--input <file>] [--max-steps <n>]
+bfrscc decompile <program.bf> [--comments ignore|error|dialect]
+                 [--tapes <n> | --grid | --random]
+bfrscc disasm <program.bf> [-O0] [--comments ignore|error|dialect]
+              [--tapes <n> | --grid | --random]
+bfrscc brackets <program.bf> [--json] [--comments ignore|error|dialect]
+                [--alias <alias>=<char>]... [--aliases <file>] [--repeat-counts]
+bfrscc loops <program.bf> [-O] [--comments ignore|error|dialect]
+             [--tapes <n> | --grid | --random]
+bfrscc compile <program.bf> [-o <program.bfc>] [-O0] [--bytecode-version <n>] [--preprocess]
+               [--comments ignore|error|dialect] [--tapes <n> | --grid | --random]
+               [--alias <alias>=<char>]... [--aliases <file>] [--repeat-counts]
+bfrscc debug <program.bf> [-O] [--input <file>] [--comments ignore|error|dialect]
+             [--tapes <n>] [--alias <alias>=<char>]... [--aliases <file>] [--repeat-counts]
+             [--record <file>]
+bfrscc repl [-O] [--input <file>] [--max-steps <n>] [--history <file> | --no-history]
+            [--tapes <n>] [--underflow grow|wrap:<cells>|error]
+bfrscc replay <recording> [--comments ignore|error|dialect] [--tapes <n>]
+              [--alias <alias>=<char>]... [--aliases <file>] [--repeat-counts]
+bfrscc diff-runs <a.rcd> <b.rcd> [--color auto|always|never] [--message-format human|json]
+bfrscc repro <report.json> [--program <program.bf>] [--input <file>]
+bfrscc report <program.bf> [--profile | --sample-hz <n>] [-O] [--input <file>]
+              [--out <file.html>]
+bfrscc test [<program.bf>...] [-O] [--max-steps <n>] [--max-tape <n>] [--max-output-bytes <n>]
+bfrscc mutate [<program.bf>...] [--mutants <n>] [--seed <n>] [--max-steps <n>]
+bfrscc transpile <dir|program.bf> --emit bf|c|cfg --out-dir <dir> [-O] [--precompute]
+                  [--size-report]
+                 [--tape-size <n>|grow] [--max-tape <n>] [--underflow grow|wrap:<cells>|error]
+                 [--cells u8|i8|i16|i32] [--eof 0|255] [--checks] [--freestanding]
+                 [--shared-runtime]
+                 [--comments ignore|error|dialect] [--alias <alias>=<char>]...
+                 [--aliases <file>] [--repeat-counts]
+";
+
+/// Default name of the file written by `run --checkpoint-every`
+const DEFAULT_CHECKPOINT_FILE: &str = "bfrscc.ckpt";
+
+/// Number of hotspots listed by `run --sample-hz`
+const MAX_HOTSPOTS: usize = 10;
+
+/// Number of loops listed by `transpile --size-report`
+const MAX_SIZE_LOOPS: usize = 10;
+
+/// Set by `--quiet`: nothing but the output of the program and errors is written
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Set by `--message-format json`: messages are written as JSON
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Installs a SIGINT handler which sets a flag instead of killing the process, so the run loop
+/// can stop cooperatively and report where it was. A second Ctrl-C terminates immediately.
+#[cfg(unix)]
+mod sigint {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, OnceLock};
+
+    /// `SIGINT` on all unix platforms
+    const SIGINT: i32 = 2;
+
+    extern "C" {
+        fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+        fn _exit(status: i32) -> !;
+    }
+
+    /// Flag shared with the run loop
+    static FLAG: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+    extern "C" fn handler(_: i32) {
+        if let Some(flag) = FLAG.get() {
+            if flag.swap(true, Ordering::SeqCst) {
+                unsafe { _exit(130) }
+            }
+        }
+    }
+
+    /// Install the handler.
+    pub fn install(flag: Arc<AtomicBool>) {
+        if FLAG.set(flag).is_ok() {
+            unsafe {
+                signal(SIGINT, handler);
+            }
+        }
+    }
+}
+
+/// Print an error message and exit with a nonzero exit code. Errors are printed even with
+/// `--quiet`.
+fn fail(msg: &str) -> ! {
+    if JSON.load(Ordering::Relaxed) {
+        eprintln!("{}", Object::new().string("type", "error").string("message", msg).finish());
+    } else {
+        eprintln!("error: {}", msg);
+    }
+    process::exit(1);
+}
+
+/// Parse the value following option `name`.
+fn option_value<T: std::str::FromStr>(args: &mut std::slice::Iter<String>, name: &str) -> T {
+    match args.next().map(|x| x.parse()) {
+        Some(Ok(v)) => v,
+        _ => fail(&format!("option `{}` requires a valid value", name)),
+    }
+}
+
+/// Parse the number of cells following option `name`, which may contain `_` as a digit
+/// separator, e.g. `1_000_000`.
+fn cell_count(args: &mut std::slice::Iter<String>, name: &str) -> usize {
+    match args.next().map(|x| x.replace('_', "").parse()) {
+        Some(Ok(v)) => v,
+        _ => fail(&format!("option `{}` requires a valid value", name)),
+    }
+}
+
+/// Parse the value of `--comments`.
+fn comment_policy(args: &mut std::slice::Iter<String>) -> CommentPolicy {
+    match args.next().map(|x| x.as_str()) {
+        Some("ignore") => CommentPolicy::Ignore,
+        Some("error") => CommentPolicy::Error,
+        Some("dialect") => CommentPolicy::Dialect,
+        _ => fail("option `--comments` requires `ignore`, `error` or `dialect`"),
+    }
+}
+
+/// Parse the value of `--message-format`.
+fn message_format(args: &mut std::slice::Iter<String>) {
+    let format: MessageFormat = option_value(args, "--message-format");
+    JSON.store(format == MessageFormat::Json, Ordering::Relaxed);
+}
+
+/// Parse the value of `--tape-init`: the name of a file containing the cells, or a comma
+/// separated list of cell values.
+fn tape_init(args: &mut std::slice::Iter<String>) -> Vec<u8> {
+    let value: String = option_value(args, "--tape-init");
+    if Path::new(&value).is_file() {
+        return fs::read(&value)
+            .unwrap_or_else(|e| fail(&format!("unable to read {}: {}", value, e)));
+    }
+    value
+        .split(',')
+        .map(|x| x.trim().parse())
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|_| fail(&format!("`{}` is neither a file nor a list of cells", value)))
+}
+
+/// Parse the value of `--range`, `<a>..<b>` with `b` exclusive.
+fn range(args: &mut std::slice::Iter<String>) -> Range<isize> {
+    let value: String = option_value(args, "--range");
+    let bounds: Vec<Option<isize>> = value.split("..").map(|x| x.parse().ok()).collect();
+    match bounds.as_slice() {
+        &[Some(a), Some(b)] if a <= b => a..b,
+        _ => fail("option `--range` requires a range `<a>..<b>`"),
+    }
+}
+
+/// `bfrscc run`
+fn cmd_run(args: &[String]) {
+    let mut config = RunConfig {
+        core_dumped: Some(Arc::new(AtomicBool::new(false))),
+        interrupt: Some(Arc::new(AtomicBool::new(false))),
+        ..RunConfig::default()
+    };
+    #[cfg(unix)]
+    sigint::install(config.interrupt.clone().unwrap());
+    let manifest = project(&mut config);
+    let mut file = None;
+    let (mut dump_format, mut dump_range, mut dump_file) = (None, None, None);
+    let mut exit_cell = None;
+    let mut strict = false;
+    let mut preprocess = false;
+    let mut color = ColorChoice::Auto;
+    let mut stats = false;
+    let mut cfg_file = None;
+    let mut shadow = false;
+    let mut check = false;
+    let mut meta = false;
+    let mut stream = false;
+    let mut mmap = false;
+    let mut report_file = None;
+    let mut sandbox = Sandbox::Off;
+    let (mut checkpoint_every, mut checkpoint_file, mut resume_file) = (None, None, None);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-O" | "--optimize" => config.optimize = true,
+            "--quiet" => QUIET.store(true, Ordering::Relaxed),
+            "--strict" => strict = true,
+            "--preprocess" => preprocess = true,
+            "--color" => color = option_value(&mut args, arg),
+            "--message-format" => message_format(&mut args),
+            "--stats" => stats = true,
+            "--report" => report_file = Some(option_value::<PathBuf>(&mut args, arg)),
+            "--sample-hz" => config.sample_hz = Some(option_value(&mut args, arg)),
+            "--perf" => config.perf = true,
+            "--progress" => config.progress = Some(Arc::new(Progress::new())),
+            "--checkpoint-every" => {
+                let value: String = option_value(&mut args, arg);
+                checkpoint_every = Some(parse_duration(&value).unwrap_or_else(|e| fail(&e)));
+            }
+            "--checkpoint-file" => checkpoint_file = Some(option_value::<PathBuf>(&mut args, arg)),
+            "--resume" => resume_file = Some(option_value::<PathBuf>(&mut args, arg)),
+            "--cfg" => {
+                cfg_file = Some(option_value::<PathBuf>(&mut args, arg));
+                config.profile = true;
+            }
+            "--shadow" => shadow = true,
+            "--check" => check = true,
+            "--meta" => meta = true,
+            "--stream" => stream = true,
+            "--mmap" => mmap = true,
+            "--comments" => config.lexer.comments = comment_policy(&mut args),
+            "--alias" | "--aliases" => config.lexer.aliases.extend(aliases(&mut args, arg)),
+            "--repeat-counts" => config.lexer.repeat_counts = true,
+            "--tapes" => config.lexer.dialect = Dialect::MultiTape(option_value(&mut args, arg)),
+            "--grid" => config.lexer.dialect = Dialect::Grid,
+            "--random" => config.lexer.dialect = Dialect::Random,
+            "--seed" => config.seed = Some(option_value(&mut args, arg)),
+            "--newline" => config.newline = option_value(&mut args, arg),
+            "--eof" => config.eof = option_value(&mut args, arg),
+            "--arithmetic" => config.arithmetic = option_value(&mut args, arg),
+            "--underflow" => config.underflow = option_value(&mut args, arg),
+            "--cells" => config.cells = option_value(&mut args, arg),
+            "--tape-init" => config.tape_init = Some(tape_init(&mut args)),
+            "--pointer" => config.pointer = option_value(&mut args, arg),
+            "--dump-tape" => dump_format = Some(DumpFormat::Dec),
+            _ if arg.starts_with("--dump-tape=") => {
+                dump_format =
+                    Some(arg["--dump-tape=".len()..].parse().unwrap_or_else(|e: String| fail(&e)))
+            }
+            "--range" => dump_range = Some(range(&mut args)),
+            "--dump-file" => dump_file = Some(option_value(&mut args, arg)),
+            "--exit-from-cell" => exit_cell = Some(None),
+            _ if arg.starts_with("--exit-from-cell=") => {
+                exit_cell = Some(Some(arg["--exit-from-cell=".len()..].parse().unwrap_or_else(
+                    |_| fail("option `--exit-from-cell` requires a cell index"),
+                )))
+            }
+            "--max-steps" => config.max_steps = Some(option_value(&mut args, arg)),
+            "--max-tape" => config.max_tape = Some(option_value(&mut args, arg)),
+            "--max-output-bytes" => {
+                config.max_output_bytes = Some(option_value(&mut args, arg));
+            }
+            "--max-time" => {
+                let value: String = option_value(&mut args, arg);
+                config.max_time = Some(parse_duration(&value).unwrap_or_else(|e| fail(&e)));
+            }
+            "--max-loop-iters" => config.max_loop_iters = Some(option_value(&mut args, arg)),
+            "--sandbox" => sandbox = option_value(&mut args, arg),
+            "--tape-reserve" => config.tape_reserve = Some(cell_count(&mut args, arg)),
+            "--max-length" => config.lexer.limits.max_length = Some(option_value(&mut args, arg)),
+            "--max-depth" => config.lexer.limits.max_depth = Some(option_value(&mut args, arg)),
+            "--core" => config.core_file = Some(option_value(&mut args, arg)),
+            "--no-core" => config.core_file = None,
+            _ if file.is_none() => file = Some(arg.clone()),
+            _ => fail(&format!("unexpected argument `{}`", arg)),
+        }
+    }
+    let file = file.unwrap_or_else(|| fail("no program given"));
+    if sandbox != Sandbox::Off && config.lexer.repeat_counts {
+        fail("`--repeat-counts` cannot be combined with `--sandbox`");
+    }
+    // Limits given explicitly, wherever they are given, can only tighten the preset.
+    sandbox.apply(&mut config);
+    let compiled = is_compiled(&file);
+    if let Some(entry) = manifest.as_ref().and_then(|m| m.entry(Path::new(&file))) {
+        if let (None, Some(input)) = (&config.input, &entry.input) {
+            config.input = Some(input.load().unwrap_or_else(|e| {
+                fail(&format!("unable to read the input of {}: {}", file, e))
+            }));
+        }
+    }
+    let bytes_only = config.arithmetic != Arithmetic::Wrap
+        || config.underflow != Underflow::Grow
+        || stream
+        || mmap
+        || check
+        || compiled;
+    if config.cells != CellType::U8 && (bytes_only || dump_format.is_some()) {
+        fail("`--arithmetic`, `--underflow`, `--stream`, `--mmap`, `--check`, `--dump-tape` and \
+              compiled programs require `--cells u8`");
+    }
+    let instrumented = report_file.is_some() || config.sample_hz.is_some();
+    let resumable = checkpoint_every.is_some() || resume_file.is_some();
+    let sourced = stream || mmap || shadow || meta || check || preprocess;
+    if compiled && (sourced || instrumented || resumable) {
+        fail("compiled programs cannot be run with `--stream`, `--mmap`, `--shadow`, `--meta`, \
+              `--check`, `--preprocess`, `--report`, `--sample-hz` or checkpoints");
+    }
+    if check && (stream || mmap || shadow || meta) {
+        fail("`--check` cannot be combined with `--stream`, `--mmap`, `--shadow` or `--meta`");
+    }
+    if report_file.is_some() && (stream || mmap || shadow || meta) {
+        fail("`--report` cannot be combined with `--stream`, `--mmap`, `--shadow` or `--meta`");
+    }
+    if config.sample_hz.is_some() && (stream || mmap || shadow || meta) {
+        fail("`--sample-hz` cannot be combined with `--stream`, `--mmap`, `--shadow` or `--meta`");
+    }
+    if exit_cell.is_some() && (stream || mmap || shadow || meta || check || compiled) {
+        fail("`--exit-from-cell` cannot be combined with `--stream`, `--mmap`, `--shadow`, \
+              `--meta`, `--check` or compiled programs");
+    }
+    if config.perf && (shadow || check) {
+        fail("`--perf` cannot be combined with `--shadow` or `--check`");
+    }
+    if config.progress.is_some() && shadow {
+        fail("`--progress` cannot be combined with `--shadow`");
+    }
+    if checkpoint_file.is_some() && checkpoint_every.is_none() {
+        fail("`--checkpoint-file` requires `--checkpoint-every`");
+    }
+    if (checkpoint_every.is_some() || resume_file.is_some()) && (stream || mmap || shadow || meta) {
+        fail("checkpoints cannot be combined with `--stream`, `--mmap`, `--shadow` or `--meta`");
+    }
+    if let Some(ref path) = resume_file {
+        config.resume = Some(Checkpoint::load(path).unwrap_or_else(|e| {
+            fail(&format!("unable to read checkpoint {}: {}", path.display(), e))
+        }));
+    }
+    // Resumed runs keep writing to the checkpoint they were resumed from.
+    config.checkpoint = checkpoint_every.map(|every| Checkpointing {
+        every,
+        file: checkpoint_file
+            .or(resume_file)
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_CHECKPOINT_FILE)),
+    });
+    config.dump_tape = dump_format.map(|format| TapeDump {
+        format,
+        range: dump_range,
+        file: dump_file,
+    });
+    if stream || mmap || compiled {
+        let unreadable = |e: io::Error| format!("unable to read {}: {}", file, e);
+        let prog = if compiled {
+            let load_error = |e| fail(&format!("unable to load {}: {}", file, e));
+            Ok(bytecode::load(&file).unwrap_or_else(load_error))
+        } else if mmap {
+            let mapping = Mapping::open(Path::new(&file)).unwrap_or_else(|e| fail(&unreadable(e)));
+            compile_slice(&mapping, &config.lexer)
+        } else {
+            let reader = fs::File::open(&file).unwrap_or_else(|e| fail(&unreadable(e)));
+            compile_reader(reader, &config.lexer)
+        };
+        let prog = prog.unwrap_or_else(|e| fail(&format!("{}: {}", file, e)));
+        if prog.opt_level() > 0 && config.arithmetic != Arithmetic::Wrap {
+            // The optimizer removed dead stores assuming cells which wrap around.
+            fail(&format!(
+                "{} is optimized for `--arithmetic wrap`, compile it without `-O`",
+                file
+            ));
+        }
+        let report = with_progress(&config, || match prog.dialect() {
+            Dialect::Grid => run_streamed::<GridTape>(&prog, &config),
+            _ if matches!(config.underflow, Underflow::Wrap(_)) => {
+                run_streamed::<CircularTape>(&prog, &config)
+            }
+            _ => run_streamed::<LinearTape>(&prog, &config),
+        });
+        let report = report.unwrap_or_else(|e| fail(&e.to_string()));
+        if JSON.load(Ordering::Relaxed) {
+            io::stdout().flush().unwrap_or_else(|e| fail(&e.to_string()));
+            eprintln!("{}", report.to_json());
+        } else if stats && !QUIET.load(Ordering::Relaxed) {
+            io::stdout().flush().unwrap_or_else(|e| fail(&e.to_string()));
+            eprintln!("{}", report);
+        }
+        print_perf(&report);
+        return;
+    }
+    let (mut prog, expanded) = load(&file, preprocess);
+    let compiled = compile(&prog, &config)
+        .unwrap_or_else(|e| compile_error(e, &file, &prog, &expanded, color));
+    let warnings = lint(&compiled);
+    for warning in &warnings {
+        let mut diagnostic = Diagnostic::warning(warning, &prog);
+        if strict {
+            diagnostic.severity = Severity::Error;
+        }
+        emit(diagnostic, &file, &prog, &expanded, color);
+    }
+    if strict && !warnings.is_empty() {
+        process::exit(1);
+    }
+    if meta {
+        // Run the program on dbfi, which gets the program and its input as its own input.
+        let inline = compiled.inline_input().map(|x| x.to_vec());
+        let input = match (config.input.take(), inline) {
+            (Some(input), _) | (None, Some(input)) => input,
+            _ => {
+                let mut input = vec![];
+                io::stdin()
+                    .read_to_end(&mut input)
+                    .unwrap_or_else(|e| fail(&e.to_string()));
+                input
+            }
+        };
+        config.input = Some(meta_input(program_part(&prog, &config), &input));
+        config.lexer = LexerOptions::default();
+        prog = DBFI.to_string();
+    }
+    if shadow {
+        match shadow_run(&prog, &config) {
+            Ok(output) => io::stdout().write_all(&output).unwrap_or_else(|e| fail(&e.to_string())),
+            Err(e) => fail(&e.to_string()),
+        }
+        return;
+    }
+    if check {
+        let expected = compiled
+            .expected_output()
+            .map(|x| config.newline.output_all(x))
+            .unwrap_or_else(|| fail(&format!("{} has no `;; expect:` annotation", file)));
+        let (output, res) = with_progress(&config, || match compiled.dialect() {
+            Dialect::Grid => run_captured::<GridTape>(&compiled, &config),
+            _ if matches!(config.underflow, Underflow::Wrap(_)) => {
+                run_captured::<CircularTape>(&compiled, &config)
+            }
+            _ => run_captured::<LinearTape>(&compiled, &config),
+        });
+        // The output is written before errors, e.g. up to the output limit.
+        io::stdout()
+            .write_all(&output)
+            .and_then(|_| io::stdout().flush())
+            .unwrap_or_else(|e| fail(&e.to_string()));
+        res.unwrap_or_else(|e| fail(&e.to_string()));
+        if output != expected {
+            eprint!(
+                "{}",
+                diff(
+                    &String::from_utf8_lossy(&expected),
+                    &String::from_utf8_lossy(&output)
+                )
+            );
+            fail("the output differs from the expected output");
+        }
+        return;
+    }
+    match with_progress(&config, || run_with_config(&prog, &config)) {
+        // The cell under the pointer, or the given cell, becomes the exit code.
+        Ok(mut report) => {
+            if let Some(ref mut env) = report.environment {
+                env.program = Some(file.clone());
+                env.preprocess = preprocess;
+            }
+            if let Some(path) = report_file {
+                fs::write(&path, report.to_json() + "\n").unwrap_or_else(|e| {
+                    fail(&format!("unable to write {}: {}", path.display(), e))
+                });
+            }
+            if let Some(path) = cfg_file {
+                let cfg = Cfg {
+                    counts: report.counts.clone(),
+                };
+                cfg.emit(&compiled)
+                    .map_err(|e| e.to_string())
+                    .and_then(|dot| fs::write(&path, dot).map_err(|e| e.to_string()))
+                    .unwrap_or_else(|e| {
+                        fail(&format!("unable to write {}: {}", path.display(), e))
+                    });
+            }
+            if JSON.load(Ordering::Relaxed) {
+                io::stdout().flush().unwrap_or_else(|e| fail(&e.to_string()));
+                eprintln!("{}", report.to_json());
+            } else if stats && !QUIET.load(Ordering::Relaxed) {
+                io::stdout().flush().unwrap_or_else(|e| fail(&e.to_string()));
+                eprintln!("{}", report);
+            }
+            print_perf(&report);
+            if let Some(ref samples) = report.samples {
+                if !QUIET.load(Ordering::Relaxed) && !JSON.load(Ordering::Relaxed) {
+                    io::stdout().flush().unwrap_or_else(|e| fail(&e.to_string()));
+                    eprintln!("hotspots ({} samples):", samples.iter().sum::<u64>());
+                    for hotspot in hotspots(&compiled, samples, MAX_HOTSPOTS) {
+                        eprintln!("{}", hotspot);
+                    }
+                }
+            }
+            if let (Some(cell), Some(tape)) = (exit_cell, report.tape) {
+                io::stdout().flush().unwrap_or_else(|e| fail(&e.to_string()));
+                process::exit(cell.map_or_else(|| tape.current(), |cell| tape.get(cell)) as i32);
+            }
+        }
+        Err(e) => {
+            let verbose = !QUIET.load(Ordering::Relaxed) && !JSON.load(Ordering::Relaxed);
+            let dumped = config.core_dumped.as_ref().is_some_and(|x| x.load(Ordering::Relaxed));
+            match config.core_file {
+                // Unusable checkpoints and counters are reported on their own.
+                Some(ref core)
+                    if verbose
+                        && dumped
+                        && !matches!(e, RunError::Checkpoint(_) | RunError::Perf(_)) =>
+                {
+                    eprintln!("core dumped to {}", core.display())
+                }
+                _ => {}
+            }
+            match config.checkpoint {
+                Some(ref checkpoint) if verbose && e == RunError::Interrupted => {
+                    eprintln!("checkpoint saved to {}", checkpoint.file.display())
+                }
+                _ => {}
+            }
+            if e == RunError::Interrupted {
+                process::exit(130);
+            }
+            fail(&e.to_string());
+        }
+    }
+}
+
+/// Call `run`, showing the progress of the execution on stderr meanwhile if `config` publishes
+/// it, for `run --progress`.
+fn with_progress<T, F: FnOnce() -> T>(config: &RunConfig, run: F) -> T {
+    let line = config.progress.clone().map(ProgressLine::start);
+    let res = run();
+    if let Some(line) = line {
+        line.finish();
+    }
+    res
+}
+
+/// Print the hardware counters of `report`, for `run --perf`.
+fn print_perf(report: &RunReport) {
+    if let Some(ref perf) = report.perf {
+        if !QUIET.load(Ordering::Relaxed) && !JSON.load(Ordering::Relaxed) {
+            io::stdout().flush().unwrap_or_else(|e| fail(&e.to_string()));
+            eprintln!("{}", perf);
+        }
+    }
+}
+
+/// Run `prog` on a tape of type `T`, for `run --check`. Returns the output, also the part
+/// written before the run failed.
+fn run_captured<T: Tape>(prog: &Program, config: &RunConfig) -> (Vec<u8>, Result<(), RunError>) {
+    let state = match interpreter(prog, config) {
+        Ok(state) => state,
+        Err(e) => return (vec![], Err(e)),
+    };
+    let mut machine: BufferMachine<T> = BufferMachine::with_interpreter(state);
+    let res = run_program(prog, &mut machine, config).map(|_| ());
+    (machine.take_output(), res)
+}
+
+/// Run `prog`, which was compiled by `run --stream`, on a tape of type `T`.
+fn run_streamed<T: Tape>(prog: &Program, config: &RunConfig) -> Result<RunReport, RunError> {
+    let mut machine: Interpreter<T> = interpreter(prog, config)?;
+    run_program(prog, &mut machine, config)
+}
+
+/// `bfrscc repro`
+fn cmd_repro(args: &[String]) {
+    let (mut file, mut program, mut input) = (None, None, None);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--program" => program = Some(option_value::<String>(&mut args, arg)),
+            "--input" => input = Some(option_value::<PathBuf>(&mut args, arg)),
+            _ if file.is_none() => file = Some(arg.clone()),
+            _ => fail(&format!("unexpected argument `{}`", arg)),
+        }
+    }
+    let file = file.unwrap_or_else(|| fail("no report given"));
+    let report = fs::read_to_string(&file)
+        .map_err(|e| e.to_string())
+        .and_then(|text| json::parse(&text))
+        .unwrap_or_else(|e| fail(&format!("unable to read {}: {}", file, e)));
+    let env = match report.get("environment") {
+        Some(&json::Value::Null) | None => fail(&format!("{} has no environment", file)),
+        Some(env) => Environment::from_json(env)
+            .unwrap_or_else(|e| fail(&format!("invalid environment in {}: {}", file, e))),
+    };
+    let program = program
+        .or_else(|| env.program.clone())
+        .unwrap_or_else(|| fail("the report names no program, use `--program`"));
+    let (source, _) = load(&program, env.preprocess);
+    if hash(source.as_bytes()) != env.program_hash {
+        fail(&format!("{} is not the program of the report", program));
+    }
+    let mut config = env.config();
+    config.input = Some(match input {
+        Some(path) => fs::read(&path)
+            .unwrap_or_else(|e| fail(&format!("unable to read {}: {}", path.display(), e))),
+        None => vec![],
+    });
+    let result = run_with_config(&source, &config).unwrap_or_else(|e| fail(&e.to_string()));
+    io::stdout().flush().unwrap_or_else(|e| fail(&e.to_string()));
+    let actual = result.environment.expect("`run_with_config` reports the environment.");
+    if (actual.input_hash, actual.input_len) != (env.input_hash, env.input_len) {
+        fail("the input differs from the input of the report");
+    }
+    match report.get("steps").and_then(json::Value::as_u64) {
+        Some(steps) if steps != result.steps => fail(&format!(
+            "not reproduced: {} steps instead of {}",
+            result.steps, steps
+        )),
+        _ => eprintln!("reproduced: {} steps", result.steps),
+    }
+}
+
+/// `bfrscc batch`
+fn cmd_batch(args: &[String]) {
+    let mut config = RunConfig::default();
+    let mut files = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-O" | "--optimize" => config.optimize = true,
+            "--max-steps" => config.max_steps = Some(option_value(&mut args, arg)),
+            "--max-tape" => config.max_tape = Some(option_value(&mut args, arg)),
+            "--max-output-bytes" => {
+                config.max_output_bytes = Some(option_value(&mut args, arg));
+            }
+            "--random" => config.lexer.dialect = Dialect::Random,
+            "--seed" => config.seed = Some(option_value(&mut args, arg)),
+            _ if arg.starts_with('-') => fail(&format!("unexpected argument `{}`", arg)),
+            _ => files.push(arg.clone()),
+        }
+    }
+    let mut failed = 0;
+    let mut programs = vec![];
+    let mut names = vec![];
+    for file in files {
+        let compiled = read_source(&file)
+            .map_err(|e| e.to_string())
+            .and_then(|source| compile(&source, &config).map_err(|e| e.to_string()));
+        match compiled {
+            Ok(prog) => {
+                programs.push(prog);
+                names.push(file);
+            }
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                failed += 1;
+            }
+        }
+    }
+    for (name, res) in names.iter().zip(run_parallel(&programs, &config)) {
+        match res.result {
+            Ok(_) => println!(
+                "{}: ok, {} bytes of output in {:?}",
+                name,
+                res.output.len(),
+                res.time
+            ),
+            Err(e) => {
+                println!("{}: {}", name, e);
+                failed += 1;
+            }
+        }
+    }
+    eprintln!("{} programs run, {} failed", names.len(), failed);
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+/// Value of `--alias <alias>=<char>`, or the aliases of the file given to `--aliases`
+fn aliases(args: &mut std::slice::Iter<String>, name: &str) -> Vec<(String, char)> {
+    let value: String = option_value(args, name);
+    let aliases = if name == "--alias" {
+        parse_alias(&value).map(|x| vec![x])
+    } else {
+        fs::read_to_string(&value)
+            .map_err(|e| e.to_string())
+            .and_then(|text| parse_aliases(&text))
+            .map_err(|e| format!("{}: {}", value, e))
+    };
+    aliases.unwrap_or_else(|e| fail(&e))
+}
+
+/// Read the program stored in `file`, which does not need to be valid UTF-8.
+fn read_source(file: &str) -> io::Result<String> {
+    fs::read(file).map(source_from_bytes)
+}
+
+/// Apply the defaults of the project manifest in the current directory, if any, to `config`.
+fn project(config: &mut RunConfig) -> Option<Manifest> {
+    let dir = env::current_dir().unwrap_or_else(|e| fail(&e.to_string()));
+    let manifest = Manifest::find(&dir).unwrap_or_else(|e| fail(&e));
+    if let Some(ref manifest) = manifest {
+        manifest.apply(config);
+    }
+    manifest
+}
+
+/// Read the program stored in `file` and expand its directives if `preprocess` is set.
+fn load(file: &str, preprocess: bool) -> (String, Option<Expanded>) {
+    let source = read_source(file)
+        .unwrap_or_else(|e| fail(&format!("unable to read {}: {}", file, e)));
+    if !preprocess {
+        return (source, None);
+    }
+    let expanded = preprocess::preprocess(&source, Path::new(file))
+        .unwrap_or_else(|e| fail(&e.to_string()));
+    (expanded.source.clone(), Some(expanded))
+}
+
+/// Print `diagnostic` about `source` of `file` to stderr. Warnings are not printed with
+/// `--quiet`, errors always are. With preprocessing, where the position came from is noted. JSON
+/// diagnostics are always printed.
+fn emit(
+    mut diagnostic: Diagnostic,
+    file: &str,
+    source: &str,
+    expanded: &Option<Expanded>,
+    color: ColorChoice,
+) {
+    let json = JSON.load(Ordering::Relaxed);
+    if QUIET.load(Ordering::Relaxed) && !json && diagnostic.severity == Severity::Warning {
+        return;
+    }
+    let origin = diagnostic
+        .position
+        .and_then(|pos| expanded.as_ref().and_then(|x| x.origin(pos)));
+    if let Some((from, pos)) = origin {
+        diagnostic
+            .notes
+            .push(format!("expanded from {}, position {}", from, pos));
+    }
+    if json {
+        eprintln!("{}", diagnostic.to_json(file, source));
+    } else {
+        eprint!("{}", diagnostic.render(file, source, color.enabled()));
+    }
+}
+
+/// Print the error of compiling `source` of `file` and exit.
+fn compile_error(
+    e: RunError,
+    file: &str,
+    source: &str,
+    expanded: &Option<Expanded>,
+    color: ColorChoice,
+) -> ! {
+    match e {
+        RunError::Parse(ref e) => emit(Diagnostic::error(e, source), file, source, expanded, color),
+        ref e => fail(&e.to_string()),
+    }
+    process::exit(1);
+}
+
+
+/// `bfrscc asm`
+fn cmd_asm(args: &[String]) {
+    let mut file = None;
+    let mut run = false;
+    for arg in args {
+        match arg.as_str() {
+            "--run" => run = true,
+            _ if file.is_none() => file = Some(arg.clone()),
+            _ => fail(&format!("unexpected argument `{}`", arg)),
+        }
+    }
+    let file = file.unwrap_or_else(|| fail("no program given"));
+    let (source, _) = load(&file, false);
+    let prog = assemble(&source).unwrap_or_else(|e| fail(&format!("{}: {}", file, e)));
+    if run {
+        run_with_config(prog.source(), &RunConfig::default())
+            .unwrap_or_else(|e| fail(&e.to_string()));
+    } else {
+        println!("{}", prog);
+    }
+}
+
+/// `bfrscc decompile`
+fn cmd_decompile(args: &[String]) {
+    let mut config = RunConfig::default();
+    let mut file = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--comments" => config.lexer.comments = comment_policy(&mut args),
+            "--tapes" => config.lexer.dialect = Dialect::MultiTape(option_value(&mut args, arg)),
+            "--grid" => config.lexer.dialect = Dialect::Grid,
+            "--random" => config.lexer.dialect = Dialect::Random,
+            _ if file.is_none() => file = Some(arg.clone()),
+            _ => fail(&format!("unexpected argument `{}`", arg)),
+        }
+    }
+    let file = file.unwrap_or_else(|| fail("no program given"));
+    let (source, _) = load(&file, false);
+    let prog = compile(&source, &config).unwrap_or_else(|e| fail(&format!("{}: {}", file, e)));
+    print!("{}", decompile(&prog));
+}
+
+/// `bfrscc disasm`
+fn cmd_disasm(args: &[String]) {
+    let mut config = RunConfig {
+        optimize: true,
+        ..RunConfig::default()
+    };
+    let mut file = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-O0" => config.optimize = false,
+            "--comments" => config.lexer.comments = comment_policy(&mut args),
+            "--tapes" => config.lexer.dialect = Dialect::MultiTape(option_value(&mut args, arg)),
+            "--grid" => config.lexer.dialect = Dialect::Grid,
+            "--random" => config.lexer.dialect = Dialect::Random,
+            _ if file.is_none() => file = Some(arg.clone()),
+            _ => fail(&format!("unexpected argument `{}`", arg)),
+        }
+    }
+    let file = file.unwrap_or_else(|| fail("no program given"));
+    let prog = if is_compiled(&file) {
+        bytecode::load(&file).unwrap_or_else(|e| fail(&format!("unable to load {}: {}", file, e)))
+    } else {
+        let (source, _) = load(&file, false);
+        compile(&source, &config).unwrap_or_else(|e| fail(&format!("{}: {}", file, e)))
+    };
+    print!("{}", disassemble(&prog));
+}
+
+/// `bfrscc brackets`
+fn cmd_brackets(args: &[String]) {
+    let mut config = RunConfig::default();
+    let mut file = None;
+    let mut json = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--comments" => config.lexer.comments = comment_policy(&mut args),
+            "--alias" | "--aliases" => config.lexer.aliases.extend(aliases(&mut args, arg)),
+            "--repeat-counts" => config.lexer.repeat_counts = true,
+            _ if file.is_none() => file = Some(arg.clone()),
+            _ => fail(&format!("unexpected argument `{}`", arg)),
+        }
+    }
+    let file = file.unwrap_or_else(|| fail("no program given"));
+    let (source, _) = load(&file, false);
+    let tokens = lexer_with_options(program_part(&source, &config), &config.lexer)
+        .unwrap_or_else(|e| fail(&format!("{}: {}", file, e)));
+    let table = BracketTable::new(&tokens);
+    if json {
+        println!("{}", table.to_json());
+    } else {
+        print!("{}", table);
+    }
+}
+
+/// `bfrscc loops`
+fn cmd_loops(args: &[String]) {
+    let mut config = RunConfig::default();
+    let mut file = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-O" => config.optimize = true,
+            "--comments" => config.lexer.comments = comment_policy(&mut args),
+            "--tapes" => config.lexer.dialect = Dialect::MultiTape(option_value(&mut args, arg)),
+            "--grid" => config.lexer.dialect = Dialect::Grid,
+            "--random" => config.lexer.dialect = Dialect::Random,
+            _ if file.is_none() => file = Some(arg.clone()),
+            _ => fail(&format!("unexpected argument `{}`", arg)),
+        }
+    }
+    let file = file.unwrap_or_else(|| fail("no program given"));
+    let (source, _) = load(&file, false);
+    let prog = compile(&source, &config).unwrap_or_else(|e| fail(&format!("{}: {}", file, e)));
+    for info in loops(&prog) {
+        println!("{}", info);
+    }
+}
+
+/// `bfrscc compile`
+fn cmd_compile(args: &[String]) {
+    let mut config = RunConfig {
+        optimize: true,
+        ..RunConfig::default()
+    };
+    let mut file = None;
+    let mut out = None;
+    let mut version = bytecode::VERSION;
+    let mut preprocess = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-O0" => config.optimize = false,
+            "-o" | "--out" => out = Some(option_value::<PathBuf>(&mut args, arg)),
+            "--bytecode-version" => version = option_value(&mut args, arg),
+            "--preprocess" => preprocess = true,
+            "--comments" => config.lexer.comments = comment_policy(&mut args),
+            "--alias" | "--aliases" => config.lexer.aliases.extend(aliases(&mut args, arg)),
+            "--repeat-counts" => config.lexer.repeat_counts = true,
+            "--tapes" => config.lexer.dialect = Dialect::MultiTape(option_value(&mut args, arg)),
+            "--grid" => config.lexer.dialect = Dialect::Grid,
+            "--random" => config.lexer.dialect = Dialect::Random,
+            _ if file.is_none() => file = Some(arg.clone()),
+            _ => fail(&format!("unexpected argument `{}`", arg)),
+        }
+    }
+    let file = file.unwrap_or_else(|| fail("no program given"));
+    let out = out.unwrap_or_else(|| Path::new(&file).with_extension("bfc"));
+    if out == Path::new(&file) {
+        fail(&format!("{} would overwrite the program, use `-o`", out.display()));
+    }
+    let (source, expanded) = load(&file, preprocess);
+    let prog = compile(&source, &config)
+        .unwrap_or_else(|e| compile_error(e, &file, &source, &expanded, ColorChoice::Auto));
+    let bytes = bytecode::encode_version(&prog, version).unwrap_or_else(|e| fail(&e.to_string()));
+    fs::write(&out, bytes)
+        .unwrap_or_else(|e| fail(&format!("unable to write {}: {}", out.display(), e)));
+}
+
+/// Returns `true` if `file` holds bytecode (see `bytecode`) instead of a source.
+fn is_compiled(file: &str) -> bool {
+    let mut header = [0; 4];
+    fs::File::open(file)
+        .and_then(|mut f| f.read_exact(&mut header))
+        .is_ok_and(|_| bytecode::is_bytecode(&header))
+}
+
+/// `bfrscc report`
+fn cmd_report(args: &[String]) {
+    let mut config = RunConfig::default();
+    let mut file = None;
+    let mut out = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-O" | "--optimize" => config.optimize = true,
+            "--profile" => config.profile = true,
+            "--sample-hz" => config.sample_hz = Some(option_value(&mut args, arg)),
+            "--input" => {
+                let path: PathBuf = option_value(&mut args, arg);
+                config.input = Some(fs::read(&path).unwrap_or_else(|e| {
+                    fail(&format!("unable to read {}: {}", path.display(), e))
+                }));
+            }
+            "--out" => out = Some(option_value::<PathBuf>(&mut args, arg)),
+            _ if file.is_none() => file = Some(arg.clone()),
+            _ => fail(&format!("unexpected argument `{}`", arg)),
+        }
+    }
+    let file = file.unwrap_or_else(|| fail("no program given"));
+    let (source, _) = load(&file, false);
+    let prog = compile(&source, &config)
+        .unwrap_or_else(|e| fail(&e.to_string()))
+        .with_name(&file);
+    let state = interpreter(&prog, &config).unwrap_or_else(|e| fail(&e.to_string()));
+    let mut machine: BufferMachine = BufferMachine::with_interpreter(state);
+    let mut report = run_program(&prog, &mut machine, &config)
+        .unwrap_or_else(|e| fail(&e.to_string()));
+    report.tape = Some(TapeView::new(machine.interpreter().tape()).with_names(prog.cell_names()));
+    let page = html(&prog, &report, machine.get_output());
+    match out {
+        Some(path) => fs::write(&path, page)
+            .unwrap_or_else(|e| fail(&format!("unable to write {}: {}", path.display(), e))),
+        None => print!("{}", page),
+    }
+}
+
+/// `bfrscc explain`
+fn cmd_explain(args: &[String]) {
+    let mut config = RunConfig::default();
+    let mut file = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-O" | "--optimize" => config.optimize = true,
+            "--input" => {
+                let path: PathBuf = option_value(&mut args, arg);
+                config.input = Some(fs::read(&path).unwrap_or_else(|e| {
+                    fail(&format!("unable to read {}: {}", path.display(), e))
+                }));
+            }
+            "--max-steps" => config.max_steps = Some(option_value(&mut args, arg)),
+            _ if file.is_none() => file = Some(arg.clone()),
+            _ => fail(&format!("unexpected argument `{}`", arg)),
+        }
+    }
+    let file = file.unwrap_or_else(|| fail("no program given"));
+    let (source, _) = load(&file, false);
+    let prog = compile(&source, &config).unwrap_or_else(|e| fail(&e.to_string()));
+    let explanations = explain(&prog, &config).unwrap_or_else(|e| fail(&e.to_string()));
+    for (i, explanation) in explanations.iter().enumerate() {
+        println!("output {}: {}", i, explanation);
+    }
+}
+
+/// `bfrscc slice`
+fn cmd_slice(args: &[String]) {
+    let mut config = RunConfig::default();
+    let mut file = None;
+    let mut cell = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--cell" => cell = Some(option_value(&mut args, arg)),
+            "--input" => {
+                let path: PathBuf = option_value(&mut args, arg);
+                config.input = Some(fs::read(&path).unwrap_or_else(|e| {
+                    fail(&format!("unable to read {}: {}", path.display(), e))
+                }));
+            }
+            "--max-steps" => config.max_steps = Some(option_value(&mut args, arg)),
+            _ if file.is_none() => file = Some(arg.clone()),
+            _ => fail(&format!("unexpected argument `{}`", arg)),
+        }
+    }
+    let file = file.unwrap_or_else(|| fail("no program given"));
+    let cell = cell.unwrap_or_else(|| fail("no cell given, use `--cell <n>`"));
+    let (source, _) = load(&file, false);
+    let prog = compile(&source, &config).unwrap_or_else(|e| fail(&e.to_string()));
+    let s = slice(&prog, &config, cell).unwrap_or_else(|e| fail(&e.to_string()));
+    println!("{}", s);
+    println!("{}", s.render(prog.source()).trim_end());
+    // Neighboring instructions are listed as one range.
+    let mut ranges: Vec<Range<usize>> = vec![];
+    for span in &s.spans {
+        match ranges.last_mut() {
+            Some(r) if r.end == span.start => r.end = span.end,
+            _ => ranges.push(span.clone()),
+        }
+    }
+    let positions: Vec<String> = ranges
+        .iter()
+        .map(|span| match span.len() {
+            1 => span.start.to_string(),
+            _ => format!("{}..{}", span.start, span.end),
+        })
+        .collect();
+    println!("positions: {}", positions.join(" "));
+}
+
+/// `bfrscc test`
+fn cmd_test(args: &[String]) {
+    let mut config = RunConfig::default();
+    let manifest = project(&mut config);
+    let mut files = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-O" | "--optimize" => config.optimize = true,
+            "--max-steps" => config.max_steps = Some(option_value(&mut args, arg)),
+            "--max-tape" => config.max_tape = Some(option_value(&mut args, arg)),
+            "--max-output-bytes" => {
+                config.max_output_bytes = Some(option_value(&mut args, arg));
+            }
+            _ if arg.starts_with('-') => fail(&format!("unexpected argument `{}`", arg)),
+            _ => files.push(PathBuf::from(arg)),
+        }
+    }
+    let entries: Vec<_> = if files.is_empty() {
+        match manifest {
+            Some(ref manifest) => manifest.programs.clone(),
+            None => fail(&format!("no programs given and no {} found", MANIFEST_FILE)),
+        }
+    } else {
+        files
+            .iter()
+            .map(|file| {
+                let entry = manifest.as_ref().and_then(|m| m.entry(file));
+                entry.cloned().unwrap_or_else(|| sidecar(file))
+            })
+            .collect()
+    };
+    let mut failures = vec![];
+    let (mut passed, mut skipped) = (0, 0);
+    for entry in &entries {
+        let result = run_test(entry, &config);
+        println!("{}", result);
+        match result.outcome {
+            Outcome::Pass => passed += 1,
+            Outcome::Skipped => skipped += 1,
+            Outcome::Fail(ref text) | Outcome::Error(ref text) => {
+                failures.push((result.name.clone(), text.clone()))
+            }
+        }
+    }
+    if !failures.is_empty() {
+        println!("\nfailures:");
+        for (name, text) in &failures {
+            println!("\n---- {} ----\n{}", name, text.trim_end());
+        }
+    }
+    println!(
+        "\ntest result: {}. {} passed; {} failed; {} skipped",
+        if failures.is_empty() { "ok" } else { "FAILED" },
+        passed,
+        failures.len(),
+        skipped
+    );
+    if !failures.is_empty() {
+        process::exit(1);
+    }
+}
+
+/// `bfrscc mutate`
+fn cmd_mutate(args: &[String]) {
+    let mut config = RunConfig::default();
+    let manifest = project(&mut config);
+    let mut files = vec![];
+    let (mut max, mut seed) = (100, 0);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--mutants" => max = option_value(&mut args, arg),
+            "--seed" => seed = option_value(&mut args, arg),
+            "--max-steps" => config.max_steps = Some(option_value(&mut args, arg)),
+            _ if arg.starts_with('-') => fail(&format!("unexpected argument `{}`", arg)),
+            _ => files.push(PathBuf::from(arg)),
+        }
+    }
+    config.max_steps = config.max_steps.or(Some(DEFAULT_MAX_STEPS));
+    let entries: Vec<_> = if files.is_empty() {
+        match manifest {
+            Some(ref manifest) => manifest.programs.clone(),
+            None => fail(&format!("no programs given and no {} found", MANIFEST_FILE)),
+        }
+    } else {
+        files
+            .iter()
+            .map(|file| {
+                let entry = manifest.as_ref().and_then(|m| m.entry(file));
+                entry.cloned().unwrap_or_else(|| sidecar(file))
+            })
+            .collect()
+    };
+    let mut failures = vec![];
+    let (mut killed, mut passed) = (0, 0);
+    for entry in &entries {
+        let name = entry.file.display().to_string();
+        let source = read_source(&name)
+            .unwrap_or_else(|e| fail(&format!("unable to read {}: {}", name, e)));
+        let prog = compile(&source, &config).unwrap_or_else(|e| fail(&e.to_string()));
+        // The programs never read stdin, as in `test`.
+        let input = match entry.input.as_ref().or(sidecar(&entry.file).input.as_ref()) {
+            Some(data) => data
+                .load()
+                .unwrap_or_else(|e| fail(&format!("unable to read the input: {}", e))),
+            None => prog.inline_input().map(|x| x.to_vec()).unwrap_or_default(),
+        };
+        let config = RunConfig {
+            input: Some(input),
+            ..config.clone()
+        };
+        let results = mutate(&source, &config, max, seed)
+            .unwrap_or_else(|e| fail(&format!("{}: {}", name, e)));
+        for result in &results {
+            println!("{}: {}", name, result);
+            match result.verdict {
+                Verdict::Killed => killed += 1,
+                Verdict::Passed => passed += 1,
+                Verdict::Survived => failures.push(format!("{}: {}", name, result.mutation)),
+                Verdict::FalseAlarm(ref d) => {
+                    failures.push(format!("{}: {}: {}", name, result.mutation, d))
+                }
+            }
+        }
+    }
+    if !failures.is_empty() {
+        println!("\nfailures:");
+        for failure in &failures {
+            println!("    {}", failure);
+        }
+    }
+    println!(
+        "\nmutation result: {}. {} killed; {} passed; {} failed",
+        if failures.is_empty() { "ok" } else { "FAILED" },
+        killed,
+        passed,
+        failures.len()
+    );
+    if !failures.is_empty() {
+        process::exit(1);
+    }
+}
+
+/// `bfrscc check`
+fn cmd_check(args: &[String]) {
+    let mut config = RunConfig::default();
+    let mut file = None;
+    let mut preprocess = false;
+    let mut color = ColorChoice::Auto;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--comments" => config.lexer.comments = comment_policy(&mut args),
+            "--alias" | "--aliases" => config.lexer.aliases.extend(aliases(&mut args, arg)),
+            "--repeat-counts" => config.lexer.repeat_counts = true,
+            "--tapes" => config.lexer.dialect = Dialect::MultiTape(option_value(&mut args, arg)),
+            "--grid" => config.lexer.dialect = Dialect::Grid,
+            "--random" => config.lexer.dialect = Dialect::Random,
+            "--preprocess" => preprocess = true,
+            "--color" => color = option_value(&mut args, arg),
+            "--message-format" => message_format(&mut args),
+            _ if file.is_none() => file = Some(arg.clone()),
+            _ => fail(&format!("unexpected argument `{}`", arg)),
+        }
+    }
+    let file = file.unwrap_or_else(|| fail("no program given"));
+    let (source, expanded) = load(&file, preprocess);
+    let tokens = lexer_with_options(program_part(&source, &config), &config.lexer)
+        .unwrap_or_else(|e| compile_error(RunError::Parse(e), &file, &source, &expanded, color));
+    let errors = validate_all(&tokens);
+    for e in &errors {
+        emit(Diagnostic::error(e, &source), &file, &source, &expanded, color);
+    }
+    if !errors.is_empty() {
+        process::exit(1);
+    }
+    let prog = compile(&source, &config)
+        .unwrap_or_else(|e| compile_error(e, &file, &source, &expanded, color));
+    for warning in lint(&prog) {
+        emit(Diagnostic::warning(&warning, &source), &file, &source, &expanded, color);
+    }
+}
+
+/// `bfrscc inspect`
+fn cmd_inspect(args: &[String]) {
+    let file = args.first().unwrap_or_else(|| fail("no core file given"));
+    let core =
+        CoreDump::load(file).unwrap_or_else(|e| fail(&format!("unable to load {}: {}", file, e)));
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    Debugger::new(core.snapshot)
+        .reason(&core.reason)
+        .repl(stdin.lock(), &mut stdout.lock())
+        .unwrap_or_else(|e| fail(&e.to_string()));
+}
+
+/// `bfrscc debug`
+fn cmd_debug(args: &[String]) {
+    let mut config = RunConfig::default();
+    let mut file = None;
+    let mut input = None;
+    let mut record = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-O" | "--optimize" => config.optimize = true,
+            "--input" => input = Some(option_value::<PathBuf>(&mut args, arg)),
+            "--record" => record = Some(option_value::<PathBuf>(&mut args, arg)),
+            "--comments" => config.lexer.comments = comment_policy(&mut args),
+            "--alias" | "--aliases" => config.lexer.aliases.extend(aliases(&mut args, arg)),
+            "--repeat-counts" => config.lexer.repeat_counts = true,
+            "--tapes" => config.lexer.dialect = Dialect::MultiTape(option_value(&mut args, arg)),
+            _ if file.is_none() => file = Some(arg.clone()),
+            _ => fail(&format!("unexpected argument `{}`", arg)),
+        }
+    }
+    let file = file.unwrap_or_else(|| fail("no program given"));
+    let source = read_source(&file)
+        .unwrap_or_else(|e| fail(&format!("unable to read {}: {}", file, e)));
+    let prog = compile(&source, &config).unwrap_or_else(|e| fail(&e.to_string()));
+    // stdin is used for the commands, so the program reads from a file or its inline input.
+    let input = match input {
+        Some(path) => fs::read(&path)
+            .unwrap_or_else(|e| fail(&format!("unable to read {}: {}", path.display(), e))),
+        None => prog.inline_input().map(|x| x.to_vec()).unwrap_or_default(),
+    };
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut debugger = Debugger::live(prog.with_name(&file), input);
+    if record.is_some() {
+        debugger = debugger.record(config.optimize);
+    }
+    debugger
+        .repl(stdin.lock(), &mut stdout.lock())
+        .unwrap_or_else(|e| fail(&e.to_string()));
+    if let (Some(path), Some(recording)) = (record, debugger.recording()) {
+        recording
+            .save(&path)
+            .unwrap_or_else(|e| fail(&format!("unable to write {}: {}", path.display(), e)));
+    }
+}
+
+/// `bfrscc repl`
+fn cmd_repl(args: &[String]) {
+    let mut config = RunConfig {
+        interrupt: Some(Arc::new(AtomicBool::new(false))),
+        ..RunConfig::default()
+    };
+    #[cfg(unix)]
+    sigint::install(config.interrupt.clone().unwrap());
+    let mut history = repl::default_history();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-O" | "--optimize" => config.optimize = true,
+            "--input" => {
+                let path = option_value::<PathBuf>(&mut args, arg);
+                config.input = Some(fs::read(&path).unwrap_or_else(|e| {
+                    fail(&format!("unable to read {}: {}", path.display(), e))
+                }));
+            }
+            "--max-steps" => config.max_steps = Some(option_value(&mut args, arg)),
+            "--history" => history = Some(option_value(&mut args, arg)),
+            "--no-history" => history = None,
+            "--tapes" => config.lexer.dialect = Dialect::MultiTape(option_value(&mut args, arg)),
+            "--underflow" => config.underflow = option_value(&mut args, arg),
+            _ => fail(&format!("unexpected argument `{}`", arg)),
+        }
+    }
+    // stdin is used for the snippets, so `,` reads from a file or nothing.
+    config.input.get_or_insert_with(Vec::new);
+    let mut repl = Repl::new(&config).unwrap_or_else(|e| fail(&e.to_string()));
+    if let Some(path) = history {
+        repl = repl
+            .with_history(&path)
+            .unwrap_or_else(|e| fail(&format!("unable to open {}: {}", path.display(), e)));
+    }
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    repl.repl(stdin.lock(), &mut stdout.lock())
+        .unwrap_or_else(|e| fail(&e.to_string()));
+}
+
+/// `bfrscc replay`
+fn cmd_replay(args: &[String]) {
+    let mut config = RunConfig::default();
+    let mut file = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--comments" => config.lexer.comments = comment_policy(&mut args),
+            "--alias" | "--aliases" => config.lexer.aliases.extend(aliases(&mut args, arg)),
+            "--repeat-counts" => config.lexer.repeat_counts = true,
+            "--tapes" => config.lexer.dialect = Dialect::MultiTape(option_value(&mut args, arg)),
+            _ if file.is_none() => file = Some(arg.clone()),
+            _ => fail(&format!("unexpected argument `{}`", arg)),
+        }
+    }
+    let file = file.unwrap_or_else(|| fail("no recording given"));
+    let recording = Recording::load(&file)
+        .unwrap_or_else(|e| fail(&format!("unable to load {}: {}", file, e)));
+    // Program counters refer to the program compiled like in the session.
+    config.optimize = recording.optimized;
+    let prog = compile(recording.program(), &config).unwrap_or_else(|e| fail(&e.to_string()));
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    Debugger::replay(prog, recording)
+        .repl(stdin.lock(), &mut stdout.lock())
+        .unwrap_or_else(|e| fail(&e.to_string()));
+}
+
+/// `bfrscc diff-runs`
+fn cmd_diff_runs(args: &[String]) {
+    let mut files = vec![];
+    let mut color = ColorChoice::Auto;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--color" => color = option_value(&mut args, arg),
+            "--message-format" => message_format(&mut args),
+            _ if files.len() < 2 => files.push(arg.clone()),
+            _ => fail(&format!("unexpected argument `{}`", arg)),
+        }
+    }
+    if files.len() < 2 {
+        fail("two recordings required");
+    }
+    let runs: Vec<Recording> = files
+        .iter()
+        .map(|file| {
+            Recording::load(file)
+                .unwrap_or_else(|e| fail(&format!("unable to load {}: {}", file, e)))
+        })
+        .collect();
+    match diff_runs(&runs[0], &runs[1]) {
+        Some(divergence) => {
+            for (run, file) in files.iter().enumerate() {
+                let source = runs[run].program();
+                emit(divergence.diagnostic(run), file, source, &None, color);
+            }
+            process::exit(1);
+        }
+        None => {
+            if !QUIET.load(Ordering::Relaxed) && !JSON.load(Ordering::Relaxed) {
+                println!("the runs do not diverge");
+            }
+        }
+    }
+}
+
+/// `bfrscc transpile`
+fn cmd_transpile(args: &[String]) {
+    let mut config = RunConfig::default();
+    let mut src = None;
+    let mut emit = None;
+    let mut out_dir = None;
+    let mut precompute = false;
+    let mut size_report = false;
+    let mut c = C::default();
+    let mut c_options = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-O" | "--optimize" => config.optimize = true,
+            "--precompute" => precompute = true,
+            "--size-report" => size_report = true,
+            "--tape-size" => {
+                c.runtime.tape_size = match args.clone().next().map(|x| x.as_str()) {
+                    Some("grow") => {
+                        args.next();
+                        None
+                    }
+                    _ => Some(cell_count(&mut args, arg)),
+                };
+                c_options = true;
+            }
+            "--max-tape" => {
+                c.runtime.max_tape = Some(option_value(&mut args, arg));
+                c_options = true;
+            }
+            "--underflow" => {
+                c.runtime.underflow = option_value(&mut args, arg);
+                c_options = true;
+            }
+            "--cells" => {
+                c.runtime.cells = option_value(&mut args, arg);
+                c_options = true;
+            }
+            "--eof" => {
+                c.runtime.eof = option_value(&mut args, arg);
+                c_options = true;
+            }
+            "--checks" => {
+                c.runtime.checks = true;
+                c_options = true;
+            }
+            "--freestanding" => {
+                c.runtime.freestanding = true;
+                c_options = true;
+            }
+            "--shared-runtime" => {
+                c.shared_runtime = true;
+                c_options = true;
+            }
+            "--emit" => emit = Some(option_value::<String>(&mut args, arg)),
+            "--out-dir" => out_dir = Some(option_value::<PathBuf>(&mut args, arg)),
+            "--comments" => config.lexer.comments = comment_policy(&mut args),
+            "--alias" | "--aliases" => config.lexer.aliases.extend(aliases(&mut args, arg)),
+            "--repeat-counts" => config.lexer.repeat_counts = true,
+            _ if src.is_none() => src = Some(PathBuf::from(arg)),
+            _ => fail(&format!("unexpected argument `{}`", arg)),
+        }
+    }
+    let src = src.unwrap_or_else(|| fail("no directory given"));
+    let emit = emit.unwrap_or_else(|| fail("option `--emit` is required"));
+    let mut backend = backend::by_name(&emit)
+        .unwrap_or_else(|| fail(&format!("unknown backend `{}`, use `bf`, `c` or `cfg`", emit)));
+    if c_options {
+        if emit != "c" {
+            fail("the options of the tape, the cells, the end of input, `--freestanding` and \
+                  `--shared-runtime` require `--emit c`");
+        }
+        if c.runtime.max_tape.is_some() && c.runtime.tape_size.is_some() {
+            fail("`--max-tape` requires `--tape-size grow`");
+        }
+        backend = Box::new(c);
+    }
+    if precompute {
+        backend = Box::new(Precompute::new(backend));
+    }
+    let out_dir = out_dir.unwrap_or_else(|| fail("option `--out-dir` is required"));
+    let summary = transpile_dir(
+        &src,
+        &out_dir,
+        &*backend,
+        &config.lexer,
+        config.optimize as u8,
+    ).unwrap_or_else(|e| fail(&e.to_string()));
+    for (file, warning) in &summary.warnings {
+        eprintln!("{}: warning: {}", file.display(), warning);
+    }
+    for (file, e) in &summary.failed {
+        eprintln!("{}: {}", file.display(), e);
+    }
+    if size_report {
+        for (file, size) in &summary.sizes {
+            println!("{}: {}", file.display(), size);
+            for lp in size.largest(MAX_SIZE_LOOPS) {
+                println!("    {}", lp);
+            }
+        }
+    }
+    eprintln!(
+        "{} programs transpiled, {} failed",
+        summary.written.len(),
+        summary.failed.len()
+    );
+    if !summary.failed.is_empty() {
+        process::exit(1);
+    }
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    // Verbosity flags are accepted anywhere on the command line.
+    args.retain(|arg| match arg.as_str() {
+        "-v" => {
+            logging::set_level(Level::Info);
+            false
+        }
+        "-vv" => {
+            logging::set_level(Level::Debug);
+            false
+        }
+        _ => true,
+    });
+    match args.first().map(|x| x.as_str()) {
+        Some("run") => cmd_run(&args[1..]),
+        Some("inspect") => cmd_inspect(&args[1..]),
+        Some("asm") => cmd_asm(&args[1..]),
+        Some("batch") => cmd_batch(&args[1..]),
+        Some("check") => cmd_check(&args[1..]),
+        Some("debug") => cmd_debug(&args[1..]),
+        Some("repl") => cmd_repl(&args[1..]),
+        Some("replay") => cmd_replay(&args[1..]),
+        Some("diff-runs") => cmd_diff_runs(&args[1..]),
+        Some("report") => cmd_report(&args[1..]),
+        Some("explain") => cmd_explain(&args[1..]),
+        Some("slice") => cmd_slice(&args[1..]),
+        Some("test") => cmd_test(&args[1..]),
+        Some("repro") => cmd_repro(&args[1..]),
+        Some("mutate") => cmd_mutate(&args[1..]),
+        Some("decompile") => cmd_decompile(&args[1..]),
+        Some("disasm") => cmd_disasm(&args[1..]),
+        Some("loops") => cmd_loops(&args[1..]),
+        Some("brackets") => cmd_brackets(&args[1..]),
+        Some("compile") => cmd_compile(&args[1..]),
+        Some("transpile") => cmd_transpile(&args[1..]),
+        Some("help") | Some("-h") | Some("--help") => print!("{}", USAGE),
+        Some(cmd) => fail(&format!("unknown command `{}`", cmd)),
+        None => {
+            // Hello World
+            let prog = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+            run(prog);
+        }
+    }
+}
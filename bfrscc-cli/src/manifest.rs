@@ -0,0 +1,392 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Project manifest
+//!
+//! A `bfrscc.toml` in a project directory (or one of its parents) holds the defaults of all
+//! runs and describes the programs of the project. Options given on the command line override
+//! the defaults. Only a subset of TOML is supported: tables, arrays of tables, and strings,
+//! integers and booleans as values.
+//!
+//! ```toml
+//! [defaults]
+//! dialect = "standard"     # standard, multitape, grid or random
+//! tapes = 2                # number of tapes of the multitape dialect
+//! comments = "ignore"      # ignore, error or dialect
+//! opt_level = 1            # or `optimize = true`
+//! cell_size = 8            # only 8 bit cells are supported
+//! eof = 0                  # value read at the end of the input: 0 or 255
+//! newline = "lf"
+//! max_steps = 1000000
+//! max_output_bytes = 65536
+//!
+//! [aliases]
+//! "👍" = "+"
+//!
+//! [[program]]
+//! file = "hello.bf"
+//! input = "hello.in"       # file read by `,`, or `input_text = "..."`
+//! expected = "hello.out"   # file with the expected output, or `expected_text = "..."`
+//! ```
+//!
+//! Paths are relative to the directory of the manifest.
+
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use bfrscc_core::alias::parse_alias;
+use bfrscc_core::lexer::{CommentPolicy, Dialect};
+use bfrscc_core::newline::Newline;
+use bfrscc_core::run::{Eof, RunConfig};
+use bfrscc_core::toml::{split_key, strip_comment};
+
+/// File name of the manifest
+pub const MANIFEST_FILE: &str = "bfrscc.toml";
+
+/// Value of a key
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+}
+
+/// Data which is either stored in a file or given inline
+#[derive(Debug, Clone, PartialEq)]
+pub enum Data {
+    /// Path of the file, relative to the manifest already resolved
+    File(PathBuf),
+    /// Inline text
+    Text(String),
+}
+
+impl Data {
+    /// Content of the data
+    pub fn load(&self) -> io::Result<Vec<u8>> {
+        match *self {
+            Data::File(ref path) => fs::read(path),
+            Data::Text(ref text) => Ok(text.as_bytes().to_vec()),
+        }
+    }
+}
+
+/// A program of the project
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// Path of the program
+    pub file: PathBuf,
+    /// Input of the program
+    pub input: Option<Data>,
+    /// Expected output of the program
+    pub expected: Option<Data>,
+}
+
+/// Defaults of a project. `None` keeps the built-in default.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Defaults {
+    /// Dialect of the programs
+    pub dialect: Option<Dialect>,
+    /// Treatment of comments
+    pub comments: Option<CommentPolicy>,
+    /// Optimization level
+    pub opt_level: Option<u8>,
+    /// Value read at the end of the input
+    pub eof: Option<Eof>,
+    /// Translation of line endings
+    pub newline: Option<Newline>,
+    /// Maximum number of executed instructions
+    pub max_steps: Option<u64>,
+    /// Maximum number of cells
+    pub max_tape: Option<usize>,
+    /// Maximum number of bytes written
+    pub max_output_bytes: Option<u64>,
+    /// Instruction aliases
+    pub aliases: Vec<(String, char)>,
+}
+
+impl Defaults {
+    /// Set the default `key` to `value`. The number of tapes is stored in `tapes`.
+    fn set(&mut self, key: &str, value: &Value, tapes: &mut Option<usize>) -> Result<(), String> {
+        match key {
+            "dialect" => self.dialect = Some(dialect(&string(value)?)?),
+            "tapes" => *tapes = Some(int(value)? as usize),
+            "comments" => self.comments = Some(comments(&string(value)?)?),
+            "opt_level" => {
+                let opt_level = int(value)?;
+                self.opt_level = Some(u8::try_from(opt_level).map_err(|_| {
+                    format!("optimization level {} is out of range", opt_level)
+                })?)
+            }
+            "optimize" => self.opt_level = Some(boolean(value)? as u8),
+            "cell_size" => match int(value)? {
+                8 => {}
+                n => return Err(format!("cell size {} is not supported", n)),
+            },
+            "eof" => self.eof = Some(int(value)?.to_string().parse()?),
+            "newline" => self.newline = Some(string(value)?.parse()?),
+            "max_steps" => self.max_steps = Some(int(value)? as u64),
+            "max_tape" => self.max_tape = Some(int(value)? as usize),
+            "max_output_bytes" => self.max_output_bytes = Some(int(value)? as u64),
+            _ => return Err(format!("unknown key `{}`", key)),
+        }
+        Ok(())
+    }
+}
+
+/// Content of a `bfrscc.toml`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Manifest {
+    /// Directory of the manifest
+    pub root: PathBuf,
+    /// Defaults of all runs
+    pub defaults: Defaults,
+    /// Programs of the project
+    pub programs: Vec<Entry>,
+}
+
+impl Manifest {
+    /// Find and load the manifest of the project `dir` belongs to, i.e. the first
+    /// `bfrscc.toml` in `dir` or one of its parents. Returns `Ok(None)` if there is none.
+    pub fn find(dir: &Path) -> Result<Option<Self>, String> {
+        for dir in dir.ancestors() {
+            let path = dir.join(MANIFEST_FILE);
+            if path.is_file() {
+                return Manifest::load(&path).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Load the manifest stored at `path`.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let root = path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+        Manifest::parse(&text, &root).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Parse a manifest, paths are relative to `root`.
+    pub fn parse(text: &str, root: &Path) -> Result<Self, String> {
+        let mut manifest = Manifest {
+            root: root.to_path_buf(),
+            ..Manifest::default()
+        };
+        let mut tapes = None;
+        for table in parse_tables(text)? {
+            match (table.name.as_str(), table.array) {
+                ("defaults", false) => {
+                    for (line, key, value) in table.entries {
+                        manifest
+                            .defaults
+                            .set(&key, &value, &mut tapes)
+                            .map_err(|e| format!("line {}: {}", line, e))?;
+                    }
+                }
+                ("aliases", false) => {
+                    for (line, key, value) in table.entries {
+                        let value = string(&value).map_err(|e| format!("line {}: {}", line, e))?;
+                        let alias = parse_alias(&format!("{}={}", key, value))
+                            .map_err(|e| format!("line {}: {}", line, e))?;
+                        manifest.defaults.aliases.push(alias);
+                    }
+                }
+                ("program", true) => {
+                    let mut entry = Entry {
+                        file: PathBuf::new(),
+                        input: None,
+                        expected: None,
+                    };
+                    for (line, key, value) in table.entries {
+                        let value = string(&value).map_err(|e| format!("line {}: {}", line, e))?;
+                        match key.as_str() {
+                            "file" => entry.file = root.join(value),
+                            "input" => entry.input = Some(Data::File(root.join(value))),
+                            "input_text" => entry.input = Some(Data::Text(value)),
+                            "expected" => entry.expected = Some(Data::File(root.join(value))),
+                            "expected_text" => entry.expected = Some(Data::Text(value)),
+                            _ => return Err(format!("line {}: unknown key `{}`", line, key)),
+                        }
+                    }
+                    if entry.file == PathBuf::new() {
+                        return Err(format!("line {}: program without `file`", table.line));
+                    }
+                    manifest.programs.push(entry);
+                }
+                (name, _) => return Err(format!("line {}: unknown table `{}`", table.line, name)),
+            }
+        }
+        match (manifest.defaults.dialect, tapes) {
+            (Some(Dialect::MultiTape(_)), Some(n)) => {
+                manifest.defaults.dialect = Some(Dialect::MultiTape(n))
+            }
+            (_, Some(_)) => return Err("`tapes` requires `dialect = \"multitape\"`".to_string()),
+            _ => {}
+        }
+        Ok(manifest)
+    }
+
+    /// Apply the defaults to `config`. Command line options are applied afterwards, so they
+    /// override the defaults.
+    pub fn apply(&self, config: &mut RunConfig) {
+        let d = &self.defaults;
+        if let Some(dialect) = d.dialect {
+            config.lexer.dialect = dialect;
+        }
+        if let Some(comments) = d.comments {
+            config.lexer.comments = comments;
+        }
+        if let Some(opt_level) = d.opt_level {
+            config.optimize = opt_level > 0;
+        }
+        if let Some(eof) = d.eof {
+            config.eof = eof;
+        }
+        if let Some(newline) = d.newline {
+            config.newline = newline;
+        }
+        config.max_steps = d.max_steps.or(config.max_steps);
+        config.max_tape = d.max_tape.or(config.max_tape);
+        config.max_output_bytes = d.max_output_bytes.or(config.max_output_bytes);
+        config.lexer.aliases.extend(d.aliases.iter().cloned());
+    }
+
+    /// Entry of the program stored at `file`, if it is part of the project
+    pub fn entry(&self, file: &Path) -> Option<&Entry> {
+        let file = fs::canonicalize(file).ok()?;
+        self.programs
+            .iter()
+            .find(|x| fs::canonicalize(&x.file).ok().as_ref() == Some(&file))
+    }
+}
+
+/// Parse a dialect name. The number of tapes of `multitape` is set separately.
+fn dialect(name: &str) -> Result<Dialect, String> {
+    match name {
+        "standard" => Ok(Dialect::Standard),
+        "multitape" => Ok(Dialect::MultiTape(2)),
+        "grid" => Ok(Dialect::Grid),
+        "random" => Ok(Dialect::Random),
+        _ => Err(format!("unknown dialect `{}`", name)),
+    }
+}
+
+/// Parse a comment policy.
+fn comments(name: &str) -> Result<CommentPolicy, String> {
+    match name {
+        "ignore" => Ok(CommentPolicy::Ignore),
+        "error" => Ok(CommentPolicy::Error),
+        "dialect" => Ok(CommentPolicy::Dialect),
+        _ => Err(format!("unknown comment policy `{}`", name)),
+    }
+}
+
+/// String of `value`
+fn string(value: &Value) -> Result<String, String> {
+    match *value {
+        Value::Str(ref s) => Ok(s.clone()),
+        _ => Err("expected a string".to_string()),
+    }
+}
+
+/// Boolean of `value`
+fn boolean(value: &Value) -> Result<bool, String> {
+    match *value {
+        Value::Bool(b) => Ok(b),
+        _ => Err("expected `true` or `false`".to_string()),
+    }
+}
+
+/// Non-negative integer of `value`
+fn int(value: &Value) -> Result<i64, String> {
+    match *value {
+        Value::Int(n) if n >= 0 => Ok(n),
+        _ => Err("expected a non-negative integer".to_string()),
+    }
+}
+
+/// A table of a TOML document
+struct Table {
+    /// Name of the table, empty for keys before the first table
+    name: String,
+    /// `[[name]]` instead of `[name]`
+    array: bool,
+    /// Line of the header
+    line: usize,
+    /// Line, key and value of every entry
+    entries: Vec<(usize, String, Value)>,
+}
+
+/// Parse the supported subset of TOML.
+fn parse_tables(text: &str) -> Result<Vec<Table>, String> {
+    let mut tables: Vec<Table> = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line_no = i + 1;
+        let err = |msg: &str| format!("line {}: {}", line_no, msg);
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with('[') {
+            let array = line.starts_with("[[");
+            let name = line
+                .trim_start_matches('[')
+                .strip_suffix(if array { "]]" } else { "]" })
+                .ok_or_else(|| err("malformed table header"))?;
+            tables.push(Table {
+                name: name.trim().to_string(),
+                array,
+                line: line_no,
+                entries: vec![],
+            });
+            continue;
+        }
+        let (key, value) = split_key(line).ok_or_else(|| err("expected `<key> = <value>`"))?;
+        let value = parse_value(value.trim()).map_err(|e| err(&e))?;
+        if tables.is_empty() {
+            return Err(err("keys need to be in a table"));
+        }
+        let table = tables.last_mut().unwrap();
+        table.entries.push((line_no, key, value));
+    }
+    Ok(tables)
+}
+
+/// Parse a string, integer or boolean.
+fn parse_value(value: &str) -> Result<Value, String> {
+    match value {
+        "true" => return Ok(Value::Bool(true)),
+        "false" => return Ok(Value::Bool(false)),
+        _ => {}
+    }
+    if let Some(inner) = value.strip_prefix('"') {
+        let inner = inner
+            .strip_suffix('"')
+            .ok_or_else(|| "unterminated string".to_string())?;
+        let mut s = String::new();
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            s.push(match c {
+                '\\' => match chars.next() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('r') => '\r',
+                    Some('0') => '\0',
+                    Some('\\') => '\\',
+                    Some('"') => '"',
+                    _ => return Err("unknown escape sequence".to_string()),
+                },
+                c => c,
+            });
+        }
+        return Ok(Value::Str(s));
+    }
+    value
+        .replace('_', "")
+        .parse()
+        .map(Value::Int)
+        .map_err(|_| format!("unsupported value `{}`", value))
+}
@@ -0,0 +1,104 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Progress display
+//!
+//! Shows the progress the run loop publishes (see `RunConfig::progress`) on stderr while a long
+//! program runs: the steps so far, the steps per second since the last update, the elapsed time
+//! and the index of the next instruction. On a terminal, the line is redrawn in place, otherwise
+//! a line is written every few seconds.
+
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use bfrscc_core::run::Progress;
+
+/// Time between updates on a terminal
+const TERMINAL_PERIOD: Duration = Duration::from_millis(250);
+
+/// Time between lines written to anything else, e.g. a log file
+const LOG_PERIOD: Duration = Duration::from_secs(5);
+
+/// Line on stderr showing the progress of an execution, until it is finished
+pub struct ProgressLine {
+    /// Stops the thread
+    stop: Arc<AtomicBool>,
+    /// The thread updating the line
+    thread: JoinHandle<()>,
+}
+
+impl ProgressLine {
+    /// Start showing `progress`.
+    pub fn start(progress: Arc<Progress>) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let terminal = io::stderr().is_terminal();
+                let period = if terminal { TERMINAL_PERIOD } else { LOG_PERIOD };
+                let start = Instant::now();
+                let (mut last, mut last_steps) = (start, 0);
+                loop {
+                    thread::park_timeout(period);
+                    let stopped = stop.load(Ordering::Relaxed);
+                    let now = Instant::now();
+                    if now - last < period && !stopped {
+                        continue;
+                    }
+                    let steps = progress.steps();
+                    // The final line shows the average rate.
+                    let (since, before) = if stopped { (start, 0) } else { (last, last_steps) };
+                    let rate = (steps - before) as f64 / (now - since).as_secs_f64();
+                    let line = format!(
+                        "{} steps  {} steps/s  {:.1} s  pc {}",
+                        steps,
+                        si(rate),
+                        (now - start).as_secs_f64(),
+                        progress.pc()
+                    );
+                    if terminal {
+                        // `\x1b[K` clears the rest of the previous line.
+                        eprint!("\r\x1b[K{}", line);
+                    } else {
+                        eprint!("{}", line);
+                    }
+                    if stopped || !terminal {
+                        eprintln!();
+                    }
+                    io::stderr().flush().ok();
+                    if stopped {
+                        break;
+                    }
+                    last = now;
+                    last_steps = steps;
+                }
+            })
+        };
+        ProgressLine { stop, thread }
+    }
+
+    /// Show the final progress and stop updating the line.
+    pub fn finish(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.thread.thread().unpark();
+        self.thread.join().ok();
+    }
+}
+
+/// `x` with an SI prefix, e.g. `12.3M`
+fn si(x: f64) -> String {
+    let prefixes = ["", "k", "M", "G", "T"];
+    let mut x = x;
+    let mut idx = 0;
+    while x >= 1000.0 && idx + 1 < prefixes.len() {
+        x /= 1000.0;
+        idx += 1;
+    }
+    format!("{:.1}{}", x, prefixes[idx])
+}
@@ -0,0 +1,180 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # HTML reports
+//!
+//! A self-contained HTML page about an execution: the statistics, the source colored by how
+//! often every instruction ran, the cost of every loop and the final tape. The heatmap and the
+//! loops need the execution counts of `RunConfig::profile`. Without them, the heatmap shows the
+//! samples of `RunConfig::sample_hz`, if any.
+
+use std::fmt::Write;
+use bfrscc_core::lexer::Token;
+use bfrscc_core::program::Program;
+use bfrscc_core::run::RunReport;
+
+/// Maximum number of cells drawn in the tape graph
+const MAX_CELLS: usize = 1024;
+
+/// Write the report of the execution of `prog` which produced `output`.
+pub fn html(prog: &Program, report: &RunReport, output: &[u8]) -> String {
+    let title = escape(prog.name().unwrap_or("program"));
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    writeln!(out, "<title>bfrscc report: {}</title>", title).unwrap();
+    out.push_str(
+        "<style>\n\
+         body { font-family: sans-serif; margin: 2em; }\n\
+         pre { white-space: pre-wrap; word-break: break-all; background: #f8f8f8; padding: 1em; }\n\
+         .comment { color: #999; }\n\
+         table { border-collapse: collapse; }\n\
+         td, th { padding: 0.2em 0.8em; text-align: right; border-bottom: 1px solid #ddd; }\n\
+         </style>\n</head>\n<body>\n",
+    );
+    writeln!(out, "<h1>{}</h1>", title).unwrap();
+    writeln!(out, "<h2>Statistics</h2>\n<pre>{}</pre>", escape(&report.to_string())).unwrap();
+    writeln!(
+        out,
+        "<h2>Output</h2>\n<pre>{}</pre>",
+        escape(&String::from_utf8_lossy(output))
+    ).unwrap();
+    match (&report.counts, &report.samples) {
+        (Some(counts), _) => {
+            source(&mut out, prog, counts, "x");
+            loops(&mut out, prog, report, counts);
+        }
+        (None, Some(samples)) => source(&mut out, prog, samples, " samples"),
+        (None, None) => {
+            writeln!(out, "<h2>Source</h2>\n<pre>{}</pre>", escape(prog.source())).unwrap()
+        }
+    }
+    tape(&mut out, report);
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Write the source, colored by the execution counts or samples. `unit` follows the number in
+/// the tooltips.
+fn source(out: &mut String, prog: &Program, counts: &[u64], unit: &str) {
+    let mut heat: Vec<Option<u64>> = vec![None; prog.source().chars().count()];
+    for (i, &count) in counts.iter().enumerate().take(prog.len()) {
+        for pos in prog.spans(i).into_iter().flatten() {
+            if let Some(h) = heat.get_mut(pos) {
+                *h = Some(count);
+            }
+        }
+    }
+    let max = counts.iter().cloned().max().unwrap_or(0);
+    out.push_str("<h2>Source</h2>\n<pre>");
+    for (c, count) in prog.source().chars().zip(heat) {
+        let c = escape(&c.to_string());
+        match count {
+            None => write!(out, "<span class=\"comment\">{}</span>", c).unwrap(),
+            Some(0) => out.push_str(&c),
+            Some(n) => {
+                // Logarithmic scale, so rarely executed code is still visible.
+                let alpha = 0.15 + 0.85 * ((n as f64).ln_1p() / (max as f64).ln_1p());
+                write!(
+                    out,
+                    "<span style=\"background: rgba(255, 80, 0, {:.2})\" title=\"{}{}\">{}</span>",
+                    alpha, n, unit, c
+                ).unwrap();
+            }
+        }
+    }
+    out.push_str("</pre>\n");
+}
+
+/// Write a table of all loops, most expensive first. The time of a loop is estimated from its
+/// share of the executed instructions.
+fn loops(out: &mut String, prog: &Program, report: &RunReport, counts: &[u64]) {
+    let mut rows: Vec<(usize, u64, u64, u64)> = prog
+        .instructions()
+        .iter()
+        .enumerate()
+        .filter(|&(_, x)| x.token == Token::LoopBegin)
+        .map(|(i, x)| {
+            let end = prog.jump(i);
+            let steps = counts[i..end + 1].iter().sum();
+            (x.position(), counts[i], counts[end], steps)
+        })
+        .collect();
+    rows.sort_by_key(|&(pos, _, _, steps)| (::std::cmp::Reverse(steps), pos));
+    out.push_str("<h2>Loops</h2>\n<table>\n");
+    out.push_str(
+        "<tr><th>position</th><th>depth</th><th>entered</th><th>iterations</th>\
+         <th>steps</th><th>share</th><th>estimated time</th></tr>\n",
+    );
+    for (pos, entered, iterations, steps) in rows {
+        let share = steps as f64 / report.steps.max(1) as f64;
+        writeln!(
+            out,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.1}%</td>\
+             <td>{:?}</td></tr>",
+            pos,
+            prog.loop_depth(pos).unwrap_or(0),
+            entered,
+            iterations,
+            steps,
+            100.0 * share,
+            report.wall_time.mul_f64(share)
+        ).unwrap();
+    }
+    out.push_str("</table>\n");
+}
+
+/// Draw the final tape as a bar chart, the cell under the pointer is highlighted.
+fn tape(out: &mut String, report: &RunReport) {
+    let view = match report.tape {
+        Some(ref view) => view,
+        None => return,
+    };
+    let range = view.range();
+    let cells = (range.end - range.start) as usize;
+    let shown = cells.min(MAX_CELLS);
+    let (width, height) = (6, 128);
+    out.push_str("<h2>Tape</h2>\n");
+    if shown < cells {
+        writeln!(out, "<p>First {} of {} cells</p>", shown, cells).unwrap();
+    }
+    writeln!(
+        out,
+        "<svg width=\"{}\" height=\"{}\" style=\"border: 1px solid #ddd\">",
+        shown * width,
+        height
+    ).unwrap();
+    for (i, idx) in (range.start..range.end).take(shown).enumerate() {
+        let val = view.get(idx) as usize;
+        let bar = (val * height / 255).max(1);
+        let color = if idx == view.pointer() { "#d33" } else { "#36c" };
+        let label = match view.names().get(idx) {
+            Some(name) => format!("{} (cell {})", escape(name), idx),
+            None => format!("cell {}", idx),
+        };
+        writeln!(
+            out,
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\">\
+             <title>{}: {}</title></rect>",
+            i * width,
+            height - bar,
+            width - 1,
+            bar,
+            color,
+            label,
+            val
+        ).unwrap();
+    }
+    out.push_str("</svg>\n");
+}
+
+/// Escape `s` for HTML.
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
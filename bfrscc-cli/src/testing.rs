@@ -0,0 +1,174 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Test runner
+//!
+//! Runs the programs of a project and compares their output with the expected output, which is
+//! given in the manifest or in a sidecar file: for `foo.bf`, `foo.in` is the input and `foo.out`
+//! the expected output. Without either, the `;; expect:` annotations of the program give the
+//! expected output.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use bfrscc_core::buffer::BufferMachine;
+use bfrscc_core::lexer::source_from_bytes;
+use bfrscc_core::run::{compile, interpreter, run_program, RunConfig};
+use manifest::{Data, Entry};
+
+/// Maximum number of steps of a test if the configuration has no limit
+pub const DEFAULT_MAX_STEPS: u64 = 100_000_000;
+
+/// Maximum product of the numbers of lines of a full diff, larger outputs only show the first
+/// difference
+const MAX_DIFF: usize = 1_000_000;
+
+/// Outcome of a test
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    /// The output is as expected
+    Pass,
+    /// The output differs, with a diff of the expected and the actual output
+    Fail(String),
+    /// The program could not be read, parsed or executed
+    Error(String),
+    /// There is no expected output
+    Skipped,
+}
+
+/// Result of a single test
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestResult {
+    /// Name of the program
+    pub name: String,
+    /// Outcome of the test
+    pub outcome: Outcome,
+    /// Time the execution took
+    pub time: Duration,
+}
+
+impl fmt::Display for TestResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let status = match self.outcome {
+            Outcome::Pass => "ok",
+            Outcome::Fail(_) => "FAILED",
+            Outcome::Error(_) => "ERROR",
+            Outcome::Skipped => "skipped",
+        };
+        write!(f, "test {} ... {}", self.name, status)
+    }
+}
+
+/// Entry of `file` with its sidecar files, if they exist
+pub fn sidecar(file: &Path) -> Entry {
+    let data = |ext| {
+        let path = file.with_extension(ext);
+        if path.is_file() {
+            Some(Data::File(path))
+        } else {
+            None
+        }
+    };
+    Entry {
+        file: file.to_path_buf(),
+        input: data("in"),
+        expected: data("out"),
+    }
+}
+
+/// Run the test of `entry`. Input and expected output which are missing in `entry` are taken
+/// from the sidecar files. The program never reads stdin.
+pub fn run_test(entry: &Entry, config: &RunConfig) -> TestResult {
+    let start = Instant::now();
+    let name = entry.file.display().to_string();
+    let outcome = test(entry, config).unwrap_or_else(Outcome::Error);
+    TestResult {
+        name,
+        outcome,
+        time: start.elapsed(),
+    }
+}
+
+/// Outcome of the test of `entry`
+fn test(entry: &Entry, config: &RunConfig) -> Result<Outcome, String> {
+    let side = sidecar(&entry.file);
+    let source = fs::read(&entry.file)
+        .map(source_from_bytes)
+        .map_err(|e| e.to_string())?;
+    let mut config = config.clone();
+    config.max_steps = config.max_steps.or(Some(DEFAULT_MAX_STEPS));
+    let prog = compile(&source, &config).map_err(|e| e.to_string())?;
+    let expected = match entry.expected.as_ref().or(side.expected.as_ref()) {
+        Some(data) => data.load().map_err(|e| format!("unable to read the output: {}", e))?,
+        None => match prog.expected_output() {
+            Some(expected) => expected.to_vec(),
+            None => return Ok(Outcome::Skipped),
+        },
+    };
+    config.input = match entry.input.as_ref().or(side.input.as_ref()) {
+        Some(data) => Some(data.load().map_err(|e| format!("unable to read the input: {}", e))?),
+        None => Some(prog.inline_input().map(|x| x.to_vec()).unwrap_or_default()),
+    };
+    let state = interpreter(&prog, &config).map_err(|e| e.to_string())?;
+    let mut machine: BufferMachine = BufferMachine::with_interpreter(state);
+    run_program(&prog, &mut machine, &config).map_err(|e| e.to_string())?;
+    let actual = machine.take_output();
+    // The expected output is written with `\n`, like the program writes it.
+    let expected = config.newline.output_all(&expected);
+    if actual == expected {
+        Ok(Outcome::Pass)
+    } else {
+        Ok(Outcome::Fail(diff(
+            &String::from_utf8_lossy(&expected),
+            &String::from_utf8_lossy(&actual),
+        )))
+    }
+}
+
+/// Line based diff of `expected` and `actual`: missing lines are prefixed with `-`, unexpected
+/// lines with `+`.
+pub fn diff(expected: &str, actual: &str) -> String {
+    let a: Vec<&str> = expected.split('\n').collect();
+    let b: Vec<&str> = actual.split('\n').collect();
+    if a.len().saturating_mul(b.len()) > MAX_DIFF {
+        let line = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+        return format!(
+            "first difference in line {}:\n-{}\n+{}\n",
+            line + 1,
+            a.get(line).unwrap_or(&""),
+            b.get(line).unwrap_or(&"")
+        );
+    }
+    // Length of the longest common subsequence of `a[i..]` and `b[j..]`
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            out.push_str(&format!(" {}\n", a[i]));
+            i += 1;
+            j += 1;
+        } else if j == b.len() || (i < a.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out.push_str(&format!("-{}\n", a[i]));
+            i += 1;
+        } else {
+            out.push_str(&format!("+{}\n", b[j]));
+            j += 1;
+        }
+    }
+    out
+}
@@ -0,0 +1,184 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Actor
+//!
+//! Runs a program on a worker thread which is controlled through a `Handle` and reports what
+//! happens through a channel of `sink::Event`s. The worker is a `Coroutine` which is resumed in
+//! slices, so it can react to commands while the program is running.
+//!
+//! ```
+//! use bfrscc_core::actor::spawn;
+//! use bfrscc_core::run::RunConfig;
+//! use bfrscc_core::sink::Event;
+//!
+//! let (handle, events) = spawn(",[.,]", &RunConfig::default());
+//! handle.send_input(b"hi");
+//! handle.end_input();
+//! let output: Vec<u8> = events
+//!     .iter()
+//!     .filter_map(|e| match e {
+//!         Event::Byte(b) => Some(b),
+//!         _ => None,
+//!     })
+//!     .collect();
+//! assert_eq!(output, b"hi");
+//! handle.join();
+//! ```
+
+use std::sync::mpsc::{self, Receiver, RecvError, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+use coroutine::{Coroutine, Resume};
+use machine::Machine;
+use run::{RunConfig, RunError, RunReport};
+use sink::Event;
+use tape::TapeView;
+
+/// Number of instructions executed between checks for commands
+const FUEL: u64 = 10_000;
+
+/// Commands sent from the handle to the worker
+#[derive(Debug)]
+enum Command {
+    /// Input for `,`
+    Input(Vec<u8>),
+    /// No more input will be sent
+    EndInput,
+    /// Stop executing until `Resume` is sent
+    Pause,
+    /// Continue executing
+    Resume,
+    /// Stop the execution for good
+    Kill,
+}
+
+/// Controls a program running on a worker thread
+#[derive(Debug)]
+pub struct Handle {
+    /// Sends commands to the worker
+    commands: Sender<Command>,
+    /// The worker thread
+    thread: JoinHandle<()>,
+}
+
+impl Handle {
+    /// Provide input to be read by `,`.
+    pub fn send_input(&self, input: &[u8]) {
+        self.send(Command::Input(input.to_vec()));
+    }
+
+    /// Signal that no more input will be sent. Dropping the handle has the same effect.
+    pub fn end_input(&self) {
+        self.send(Command::EndInput);
+    }
+
+    /// Stop executing the program until `resume` is called.
+    pub fn pause(&self) {
+        self.send(Command::Pause);
+    }
+
+    /// Continue executing a paused program.
+    pub fn resume(&self) {
+        self.send(Command::Resume);
+    }
+
+    /// Stop the program. The last event is `Event::Failed(RunError::Interrupted)`.
+    pub fn kill(&self) {
+        self.send(Command::Kill);
+    }
+
+    /// Wait for the worker thread to finish.
+    pub fn join(self) {
+        drop(self.commands);
+        self.thread.join().ok();
+    }
+
+    /// Send `command`. The worker may have finished already, in which case it is dropped.
+    fn send(&self, command: Command) {
+        self.commands.send(command).ok();
+    }
+}
+
+/// Compile `prog` and run it on a new worker thread with the settings of `config`. The first
+/// event is `Event::Started` and the last one `Event::Finished` or `Event::Failed`, also if
+/// `prog` does not compile. The report of `Event::Finished` contains the number of steps, the
+/// tape and the time, but no I/O statistics.
+pub fn spawn(prog: &str, config: &RunConfig) -> (Handle, Receiver<Event>) {
+    let (commands, command_rx) = mpsc::channel();
+    let (events, event_rx) = mpsc::channel();
+    let (prog, config) = (prog.to_string(), config.clone());
+    let thread = thread::spawn(move || {
+        events.send(Event::Started).ok();
+        let last = match Coroutine::new(&prog, &config) {
+            Ok(co) => match work(co, &command_rx, &events) {
+                Ok(report) => Event::Finished(report),
+                Err(e) => Event::Failed(e),
+            },
+            Err(e) => Event::Failed(e),
+        };
+        events.send(last).ok();
+    });
+    (Handle { commands, thread }, event_rx)
+}
+
+/// Run `co` until it is done, handling the commands of `commands` in between.
+fn work(
+    mut co: Coroutine,
+    commands: &Receiver<Command>,
+    events: &Sender<Event>,
+) -> Result<RunReport, RunError> {
+    let start = Instant::now();
+    let mut paused = false;
+    let mut needs_input = false;
+    loop {
+        // Wait for a command while nothing can be done, otherwise only look for one.
+        let command = if paused || needs_input {
+            commands.recv().map_err(|RecvError| TryRecvError::Disconnected)
+        } else {
+            commands.try_recv()
+        };
+        match command {
+            Ok(Command::Input(input)) => {
+                co.feed(&input);
+                needs_input = false;
+            }
+            Ok(Command::EndInput) | Err(TryRecvError::Disconnected) => {
+                co.end_input();
+                needs_input = false;
+                // Without a handle, a paused program would never continue.
+                paused &= command.is_ok();
+            }
+            Ok(Command::Pause) => paused = true,
+            Ok(Command::Resume) => paused = false,
+            Ok(Command::Kill) => return Err(RunError::Interrupted),
+            Err(TryRecvError::Empty) => {}
+        }
+        if paused || needs_input {
+            continue;
+        }
+        match co.resume(FUEL)? {
+            Resume::Yielded(output) => {
+                for b in output {
+                    events.send(Event::Byte(b)).ok();
+                }
+            }
+            Resume::NeedsInput => needs_input = true,
+            Resume::Done => {
+                let tape = co.machine().interpreter().tape();
+                return Ok(RunReport {
+                    steps: co.steps(),
+                    tape_extent: co.machine().tape_len(),
+                    tape_capacity: co.machine().tape_capacity(),
+                    wall_time: start.elapsed(),
+                    tape: Some(TapeView::new(tape)),
+                    ..RunReport::default()
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,662 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Adapters
+//!
+//! Machines which wrap other machines, to compose them with additional behavior:
+//!
+//! ```
+//! use bfrscc_core::adapter::{Counting, Tee};
+//! use bfrscc_core::buffer::BufferMachine;
+//! use bfrscc_core::machine::MachineExt;
+//!
+//! let prog = "++++++++[>++++++++<-]>+.".parse().unwrap();
+//! let mut machine = Tee::new(Counting::new(BufferMachine::new()), BufferMachine::new());
+//! machine.run(&prog).unwrap();
+//! let (counting, mut buffer) = machine.into_inner();
+//! assert_eq!(counting.counts().output, 1);
+//! assert_eq!(buffer.take_output(), b"A");
+//! ```
+//!
+//! A `Layer` adds behavior to any machine through a `Hook`, which is called after every
+//! operation. Layers stack, so limits, traces, profiles and breakpoints can be combined:
+//!
+//! ```
+//! use bfrscc_core::adapter::{WithLimits, WithProfile};
+//! use bfrscc_core::buffer::BufferMachine;
+//! use bfrscc_core::machine::MachineExt;
+//! use bfrscc_core::run::RunError;
+//!
+//! let prog = "+[>+<]".parse().unwrap();
+//! let mut machine = WithLimits::new(WithProfile::new(BufferMachine::new()), Some(100), None);
+//! assert_eq!(machine.run(&prog).unwrap_err(), RunError::StepLimit(100));
+//! let (profiled, _) = machine.into_inner();
+//! assert_eq!(profiled.hook().cells()[&1], 33);
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+use annotations::CellNames;
+use machine::{clear_stepwise, transfer_stepwise, Machine};
+use program::Op;
+use run::RunError;
+
+/// Runs two machines in lockstep. The control flow follows the first machine, i.e. loops and
+/// `get_val` use its current cell.
+pub struct Tee<A: Machine, B: Machine> {
+    /// Machine which drives the control flow
+    first: A,
+    /// Machine which follows
+    second: B,
+}
+
+impl<A: Machine, B: Machine> Tee<A, B> {
+    /// Constructor
+    pub fn new(first: A, second: B) -> Self {
+        Tee { first, second }
+    }
+
+    /// Machine which drives the control flow
+    pub fn first(&self) -> &A {
+        &self.first
+    }
+
+    /// Machine which follows
+    pub fn second(&self) -> &B {
+        &self.second
+    }
+
+    /// Return both machines.
+    pub fn into_inner(self) -> (A, B) {
+        (self.first, self.second)
+    }
+}
+
+impl<A: Machine, B: Machine> Machine for Tee<A, B> {
+    fn left(&mut self, val: u8) {
+        self.first.left(val);
+        self.second.left(val);
+    }
+
+    fn right(&mut self, val: u8) {
+        self.first.right(val);
+        self.second.right(val);
+    }
+
+    fn up(&mut self, val: u8) {
+        self.first.up(val);
+        self.second.up(val);
+    }
+
+    fn down(&mut self, val: u8) {
+        self.first.down(val);
+        self.second.down(val);
+    }
+
+    fn increase(&mut self, val: u8) {
+        self.first.increase(val);
+        self.second.increase(val);
+    }
+
+    fn decrease(&mut self, val: u8) {
+        self.first.decrease(val);
+        self.second.decrease(val);
+    }
+
+    fn output(&mut self) {
+        self.first.output();
+        self.second.output();
+    }
+
+    fn input(&mut self, val: u8) {
+        self.first.input(val);
+        self.second.input(val);
+    }
+
+    fn get_val(&self) -> u8 {
+        self.first.get_val()
+    }
+
+    fn is_zero(&self) -> bool {
+        self.first.is_zero()
+    }
+
+    /// The larger tape of both machines
+    fn tape_len(&self) -> usize {
+        self.first.tape_len().max(self.second.tape_len())
+    }
+
+    /// The larger capacity of both machines
+    fn tape_capacity(&self) -> usize {
+        self.first.tape_capacity().max(self.second.tape_capacity())
+    }
+
+    fn breakpoint(&mut self) {
+        self.first.breakpoint();
+        self.second.breakpoint();
+    }
+
+    fn switch_tape(&mut self) {
+        self.first.switch_tape();
+        self.second.switch_tape();
+    }
+
+    /// Both machines draw from their own random number generator, they only agree if they are
+    /// seeded identically.
+    fn random(&mut self) {
+        self.first.random();
+        self.second.random();
+    }
+
+    fn status(&self) -> Result<(), RunError> {
+        self.first.status()?;
+        self.second.status()
+    }
+}
+
+/// Number of calls of every operation of a machine
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Counts {
+    /// Calls of `left`
+    pub left: u64,
+    /// Calls of `right`
+    pub right: u64,
+    /// Calls of `up`
+    pub up: u64,
+    /// Calls of `down`
+    pub down: u64,
+    /// Calls of `increase`
+    pub increase: u64,
+    /// Calls of `decrease`
+    pub decrease: u64,
+    /// Calls of `output`
+    pub output: u64,
+    /// Calls of `input`
+    pub input: u64,
+    /// Calls of `breakpoint`
+    pub breakpoint: u64,
+    /// Calls of `switch_tape`
+    pub switch_tape: u64,
+    /// Calls of `random`
+    pub random: u64,
+}
+
+/// Counts the operations executed by the wrapped machine
+pub struct Counting<M: Machine> {
+    /// Wrapped machine
+    inner: M,
+    /// Counters
+    counts: Counts,
+}
+
+impl<M: Machine> Counting<M> {
+    /// Constructor
+    pub fn new(inner: M) -> Self {
+        Counting {
+            inner,
+            counts: Counts::default(),
+        }
+    }
+
+    /// Counters
+    pub fn counts(&self) -> &Counts {
+        &self.counts
+    }
+
+    /// Wrapped machine
+    pub fn inner(&self) -> &M {
+        &self.inner
+    }
+
+    /// Return the wrapped machine.
+    pub fn into_inner(self) -> M {
+        self.inner
+    }
+}
+
+impl<M: Machine> Machine for Counting<M> {
+    fn left(&mut self, val: u8) {
+        self.counts.left += 1;
+        self.inner.left(val);
+    }
+
+    fn right(&mut self, val: u8) {
+        self.counts.right += 1;
+        self.inner.right(val);
+    }
+
+    fn up(&mut self, val: u8) {
+        self.counts.up += 1;
+        self.inner.up(val);
+    }
+
+    fn down(&mut self, val: u8) {
+        self.counts.down += 1;
+        self.inner.down(val);
+    }
+
+    fn increase(&mut self, val: u8) {
+        self.counts.increase += 1;
+        self.inner.increase(val);
+    }
+
+    fn decrease(&mut self, val: u8) {
+        self.counts.decrease += 1;
+        self.inner.decrease(val);
+    }
+
+    fn output(&mut self) {
+        self.counts.output += 1;
+        self.inner.output();
+    }
+
+    fn input(&mut self, val: u8) {
+        self.counts.input += 1;
+        self.inner.input(val);
+    }
+
+    fn get_val(&self) -> u8 {
+        self.inner.get_val()
+    }
+
+    fn is_zero(&self) -> bool {
+        self.inner.is_zero()
+    }
+
+    fn tape_len(&self) -> usize {
+        self.inner.tape_len()
+    }
+
+    fn tape_capacity(&self) -> usize {
+        self.inner.tape_capacity()
+    }
+
+    fn breakpoint(&mut self) {
+        self.counts.breakpoint += 1;
+        self.inner.breakpoint();
+    }
+
+    fn switch_tape(&mut self) {
+        self.counts.switch_tape += 1;
+        self.inner.switch_tape();
+    }
+
+    fn random(&mut self) {
+        self.counts.random += 1;
+        self.inner.random();
+    }
+
+    fn status(&self) -> Result<(), RunError> {
+        self.inner.status()
+    }
+}
+
+/// Operation of a machine, as seen by a `Hook`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operation {
+    /// `left` by the given number of cells
+    Left(u8),
+    /// `right` by the given number of cells
+    Right(u8),
+    /// `up` by the given number of cells
+    Up(u8),
+    /// `down` by the given number of cells
+    Down(u8),
+    /// `increase` by the given value
+    Increase(u8),
+    /// `decrease` by the given value
+    Decrease(u8),
+    /// `output`
+    Output,
+    /// `input` of the given byte
+    Input(u8),
+    /// `breakpoint`
+    Breakpoint,
+    /// `switch_tape`
+    SwitchTape,
+    /// `random`
+    Random,
+}
+
+impl fmt::Display for Operation {
+    /// Writes the brainfuck character of the operation, followed by its argument if it has one.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operation::Left(n) => write!(f, "<{}", n),
+            Operation::Right(n) => write!(f, ">{}", n),
+            Operation::Up(n) => write!(f, "^{}", n),
+            Operation::Down(n) => write!(f, "v{}", n),
+            Operation::Increase(n) => write!(f, "+{}", n),
+            Operation::Decrease(n) => write!(f, "-{}", n),
+            Operation::Output => write!(f, "."),
+            Operation::Input(b) => write!(f, ",{}", b),
+            Operation::Breakpoint => write!(f, "#"),
+            Operation::SwitchTape => write!(f, "~"),
+            Operation::Random => write!(f, "?"),
+        }
+    }
+}
+
+/// Behavior which a `Layer` adds to a machine
+pub trait Hook<M: Machine> {
+    /// Called after `inner` executed `op`.
+    fn after(&mut self, op: Operation, inner: &mut M);
+
+    /// Returns `true` if the hook needs to see every single operation, which is the default.
+    /// Otherwise the layer lets `inner` execute `[-]`, loops with a known effect and runs of
+    /// instructions on the current cell at once (see `Machine::exec_run`) and calls
+    /// `after_batch` afterwards, so that `inner` keeps its fast paths.
+    fn every_operation(&self) -> bool {
+        true
+    }
+
+    /// Called after `inner` executed several operations at once (see `every_operation`). Does
+    /// nothing by default.
+    fn after_batch(&mut self, _inner: &mut M) {}
+
+    /// An error stops the execution (see `Machine::status`). Always `Ok` by default.
+    fn status(&self) -> Result<(), RunError> {
+        Ok(())
+    }
+}
+
+/// Wraps a machine and calls a `Hook` after every operation. Layers can be stacked, e.g.
+/// `WithTrace<WithLimits<Interpreter>, _>`.
+pub struct Layer<M: Machine, H: Hook<M>> {
+    /// Wrapped machine
+    inner: M,
+    /// Added behavior
+    hook: H,
+}
+
+impl<M: Machine, H: Hook<M>> Layer<M, H> {
+    /// Call `hook` after every operation of `inner`.
+    pub fn with_hook(inner: M, hook: H) -> Self {
+        Layer { inner, hook }
+    }
+
+    /// Wrapped machine
+    pub fn inner(&self) -> &M {
+        &self.inner
+    }
+
+    /// Added behavior
+    pub fn hook(&self) -> &H {
+        &self.hook
+    }
+
+    /// Return the wrapped machine and the hook.
+    pub fn into_inner(self) -> (M, H) {
+        (self.inner, self.hook)
+    }
+}
+
+impl<M: Machine, H: Hook<M>> Machine for Layer<M, H> {
+    fn left(&mut self, val: u8) {
+        self.inner.left(val);
+        self.hook.after(Operation::Left(val), &mut self.inner);
+    }
+
+    fn right(&mut self, val: u8) {
+        self.inner.right(val);
+        self.hook.after(Operation::Right(val), &mut self.inner);
+    }
+
+    fn up(&mut self, val: u8) {
+        self.inner.up(val);
+        self.hook.after(Operation::Up(val), &mut self.inner);
+    }
+
+    fn down(&mut self, val: u8) {
+        self.inner.down(val);
+        self.hook.after(Operation::Down(val), &mut self.inner);
+    }
+
+    fn increase(&mut self, val: u8) {
+        self.inner.increase(val);
+        self.hook.after(Operation::Increase(val), &mut self.inner);
+    }
+
+    fn decrease(&mut self, val: u8) {
+        self.inner.decrease(val);
+        self.hook.after(Operation::Decrease(val), &mut self.inner);
+    }
+
+    fn output(&mut self) {
+        self.inner.output();
+        self.hook.after(Operation::Output, &mut self.inner);
+    }
+
+    fn input(&mut self, val: u8) {
+        self.inner.input(val);
+        self.hook.after(Operation::Input(val), &mut self.inner);
+    }
+
+    fn get_val(&self) -> u8 {
+        self.inner.get_val()
+    }
+
+    fn is_zero(&self) -> bool {
+        self.inner.is_zero()
+    }
+
+    fn clear(&mut self) {
+        if self.hook.every_operation() {
+            clear_stepwise(self);
+        } else {
+            self.inner.clear();
+            self.hook.after_batch(&mut self.inner);
+        }
+    }
+
+    fn transfer(&mut self, effects: &[(isize, u8)]) {
+        if self.hook.every_operation() {
+            transfer_stepwise(self, effects);
+        } else {
+            self.inner.transfer(effects);
+            self.hook.after_batch(&mut self.inner);
+        }
+    }
+
+    fn exec_run(&mut self, ops: &[Op], input: &mut dyn Iterator<Item = u8>) -> bool {
+        if self.hook.every_operation() || !self.inner.exec_run(ops, input) {
+            return false;
+        }
+        self.hook.after_batch(&mut self.inner);
+        true
+    }
+
+    fn tape_len(&self) -> usize {
+        self.inner.tape_len()
+    }
+
+    fn tape_capacity(&self) -> usize {
+        self.inner.tape_capacity()
+    }
+
+    fn breakpoint(&mut self) {
+        self.inner.breakpoint();
+        self.hook.after(Operation::Breakpoint, &mut self.inner);
+    }
+
+    fn switch_tape(&mut self) {
+        self.inner.switch_tape();
+        self.hook.after(Operation::SwitchTape, &mut self.inner);
+    }
+
+    fn random(&mut self) {
+        self.inner.random();
+        self.hook.after(Operation::Random, &mut self.inner);
+    }
+
+    fn status(&self) -> Result<(), RunError> {
+        self.inner.status()?;
+        self.hook.status()
+    }
+}
+
+/// Stops the execution after a number of operations or if the tape grows too large
+#[derive(Debug, Clone, Default)]
+pub struct OpLimits {
+    /// Maximum number of operations
+    pub max_ops: Option<u64>,
+    /// Maximum number of cells
+    pub max_tape: Option<usize>,
+    /// Operations executed so far
+    pub ops: u64,
+    /// Limit which was exceeded
+    exceeded: Option<RunError>,
+}
+
+impl<M: Machine> Hook<M> for OpLimits {
+    fn after(&mut self, op: Operation, inner: &mut M) {
+        self.ops += 1;
+        match self.max_ops {
+            Some(max) if self.ops > max => self.exceeded = Some(RunError::StepLimit(max)),
+            _ => {}
+        }
+        let moved = matches!(
+            op,
+            Operation::Left(_) | Operation::Right(_) | Operation::Up(_) | Operation::Down(_)
+                | Operation::SwitchTape
+        );
+        match self.max_tape {
+            Some(max) if moved && inner.tape_len() > max => {
+                self.exceeded = Some(RunError::TapeLimit(max))
+            }
+            _ => {}
+        }
+    }
+
+    fn status(&self) -> Result<(), RunError> {
+        self.exceeded.clone().map_or(Ok(()), Err)
+    }
+}
+
+/// Writes every operation and the value of the current cell afterwards to a writer, one per
+/// line. Write errors are ignored.
+#[derive(Debug)]
+pub struct Trace<W: Write>(pub W);
+
+impl<M: Machine, W: Write> Hook<M> for Trace<W> {
+    fn after(&mut self, op: Operation, inner: &mut M) {
+        writeln!(self.0, "{}\t{}", op, inner.get_val()).ok();
+    }
+}
+
+/// Counts the operations on every cell of a linear tape. Cells are identified by their offset
+/// from the cell the pointer started at.
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    /// Offset of the current cell
+    pointer: isize,
+    /// Operations per cell
+    cells: BTreeMap<isize, u64>,
+}
+
+impl Profile {
+    /// Number of operations on every cell which was changed, read or written
+    pub fn cells(&self) -> &BTreeMap<isize, u64> {
+        &self.cells
+    }
+
+    /// Number of operations on every cell, labeled with the names of `names` where given
+    /// (see `Program::cell_names`)
+    pub fn labeled(&self, names: &CellNames) -> Vec<(String, u64)> {
+        self.cells.iter().map(|(&idx, &n)| (names.label(idx), n)).collect()
+    }
+}
+
+impl<M: Machine> Hook<M> for Profile {
+    fn after(&mut self, op: Operation, _inner: &mut M) {
+        match op {
+            Operation::Left(n) => self.pointer -= n as isize,
+            Operation::Right(n) => self.pointer += n as isize,
+            Operation::Increase(_) | Operation::Decrease(_) | Operation::Output
+            | Operation::Input(_) | Operation::Random => {
+                *self.cells.entry(self.pointer).or_insert(0) += 1
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Calls a function at every `#`. The execution stops with `RunError::Interrupted` once the
+/// function returns `false`.
+pub struct Breakpoints<F> {
+    /// Called with the wrapped machine
+    callback: F,
+    /// Set once the callback returned `false`
+    stopped: bool,
+}
+
+impl<M: Machine, F: FnMut(&mut M) -> bool> Hook<M> for Breakpoints<F> {
+    fn after(&mut self, op: Operation, inner: &mut M) {
+        if op == Operation::Breakpoint && !(self.callback)(inner) {
+            self.stopped = true;
+        }
+    }
+
+    fn status(&self) -> Result<(), RunError> {
+        if self.stopped {
+            Err(RunError::Interrupted)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// A machine which stops after a number of operations or if its tape grows too large
+pub type WithLimits<M> = Layer<M, OpLimits>;
+/// A machine which traces its operations
+pub type WithTrace<M, W> = Layer<M, Trace<W>>;
+/// A machine which counts the operations on every cell
+pub type WithProfile<M> = Layer<M, Profile>;
+/// A machine which calls a function at every `#`
+pub type WithBreakpoints<M, F> = Layer<M, Breakpoints<F>>;
+
+impl<M: Machine> WithLimits<M> {
+    /// Stop `inner` after `max_ops` operations or once its tape exceeds `max_tape` cells.
+    pub fn new(inner: M, max_ops: Option<u64>, max_tape: Option<usize>) -> Self {
+        Layer::with_hook(
+            inner,
+            OpLimits {
+                max_ops,
+                max_tape,
+                ..OpLimits::default()
+            },
+        )
+    }
+}
+
+impl<M: Machine, W: Write> WithTrace<M, W> {
+    /// Write the operations of `inner` to `writer`.
+    pub fn new(inner: M, writer: W) -> Self {
+        Layer::with_hook(inner, Trace(writer))
+    }
+}
+
+impl<M: Machine> WithProfile<M> {
+    /// Count the operations of `inner` per cell.
+    pub fn new(inner: M) -> Self {
+        Layer::with_hook(inner, Profile::default())
+    }
+}
+
+impl<M: Machine, F: FnMut(&mut M) -> bool> WithBreakpoints<M, F> {
+    /// Call `callback` with `inner` at every `#`.
+    pub fn new(inner: M, callback: F) -> Self {
+        Layer::with_hook(
+            inner,
+            Breakpoints {
+                callback,
+                stopped: false,
+            },
+        )
+    }
+}
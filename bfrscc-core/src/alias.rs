@@ -0,0 +1,66 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Aliases
+//!
+//! Instruction aliases let programs use other characters or strings for the brainfuck
+//! instructions, for instance emoji. Alias files use a small subset of TOML:
+//!
+//! ```toml
+//! [aliases]
+//! "👍" = "+"
+//! "👎" = "-"
+//! ook = "."
+//! ```
+//!
+//! The `[aliases]` header is optional.
+
+use toml::{split_key, strip_comment};
+
+/// Characters an alias can stand for
+const TARGETS: &str = "+-<>[],.#!~^v?";
+
+/// Parse an alias given as `<alias>=<char>`, e.g. on the command line.
+pub fn parse_alias(s: &str) -> Result<(String, char), String> {
+    match s.rfind('=') {
+        Some(idx) if idx > 0 => Ok((s[..idx].to_string(), target(&s[idx + 1..])?)),
+        _ => Err(format!("alias `{}` is not of the form <alias>=<char>", s)),
+    }
+}
+
+/// Parse the aliases of an alias file.
+pub fn parse_aliases(text: &str) -> Result<Vec<(String, char)>, String> {
+    let mut aliases = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() || line == "[aliases]" {
+            continue;
+        }
+        let err = |msg: &str| format!("line {}: {}", i + 1, msg);
+        let (key, value) = match split_key(line) {
+            Some(kv) => kv,
+            None => return Err(err("expected `<alias> = \"<char>\"`")),
+        };
+        let value = unquote(value.trim()).ok_or_else(|| err("the value needs to be quoted"))?;
+        aliases.push((key, target(value).map_err(|e| err(&e))?));
+    }
+    Ok(aliases)
+}
+
+/// Strip the quotes of a TOML string.
+fn unquote(s: &str) -> Option<&str> {
+    s.strip_prefix('"')?.strip_suffix('"')
+}
+
+/// The brainfuck character `s` consists of
+fn target(s: &str) -> Result<char, String> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if TARGETS.contains(c) => Ok(c),
+        _ => Err(format!("`{}` is not a brainfuck instruction", s)),
+    }
+}
@@ -0,0 +1,139 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Annotations
+//!
+//! Comments starting with `;;` carry metadata of the program. Like all comments, they must not
+//! contain instructions such as `-` or `.`.
+//!
+//! `;; cell 3 = counter` names a cell. The index is relative to the cell the pointer starts at.
+//! Tape dumps, the debugger and the profile of the cells show the name instead of the index.
+//! Names are single words. A later annotation of the same cell replaces an earlier one.
+//!
+//! ```
+//! use bfrscc_core::program::Program;
+//!
+//! let prog: Program = ";; cell 1 = counter\n++++[>+++<-]".parse().unwrap();
+//! assert_eq!(prog.cell_names().get(1), Some("counter"));
+//! assert_eq!(prog.cell_names().find("counter"), Some(1));
+//! assert_eq!(prog.cell_names().label(0), "0");
+//! ```
+//!
+//! `;; expect: "Hi\n"` gives the expected output, which `bfrscc test` and `bfrscc run --check`
+//! compare with the actual output. The string supports the escapes `\n`, `\r`, `\t`, `\\`,
+//! `\"` and `\xNN`, the latter also for characters which would be instructions. The output of
+//! several annotations is concatenated:
+//!
+//! ```
+//! use bfrscc_core::program::Program;
+//!
+//! let source = ";; expect: \"A\\x2c\"\n;; expect: \"B\\n\"\n";
+//! let prog: Program = source.parse().unwrap();
+//! assert_eq!(prog.expected_output(), Some(&b"A,B\n"[..]));
+//! ```
+
+use std::collections::BTreeMap;
+
+/// Metadata given by the annotations of a program
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Annotations {
+    /// Names of cells
+    pub names: CellNames,
+    /// Expected output, if given
+    pub expect: Option<Vec<u8>>,
+}
+
+impl Annotations {
+    /// Collect the annotations of `source`. Comments starting with `;;` which are no valid
+    /// annotation are ignored.
+    pub fn parse(source: &str) -> Self {
+        let mut annotations = Annotations::default();
+        for line in source.lines() {
+            let text = match line.find(";;") {
+                Some(i) => line[i + 2..].trim(),
+                None => continue,
+            };
+            if let Some((idx, name)) = cell_name(text) {
+                annotations.names.0.insert(idx, name.to_string());
+            } else if let Some(bytes) = text.strip_prefix("expect:").and_then(unquote) {
+                annotations.expect.get_or_insert_with(Vec::new).extend(bytes);
+            }
+        }
+        annotations
+    }
+}
+
+/// Names of cells by their index
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CellNames(BTreeMap<isize, String>);
+
+impl CellNames {
+    /// Returns `true` if no cell has a name.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Name of cell `idx`
+    pub fn get(&self, idx: isize) -> Option<&str> {
+        self.0.get(&idx).map(|x| x.as_str())
+    }
+
+    /// Index of the cell called `name`
+    pub fn find(&self, name: &str) -> Option<isize> {
+        self.0.iter().find(|&(_, n)| n == name).map(|(&idx, _)| idx)
+    }
+
+    /// Name of cell `idx`, or its index if it has none
+    pub fn label(&self, idx: isize) -> String {
+        self.get(idx).map_or_else(|| idx.to_string(), str::to_string)
+    }
+
+    /// Indices and names of the named cells, ordered by index
+    pub fn iter(&self) -> impl Iterator<Item = (isize, &str)> {
+        self.0.iter().map(|(&idx, name)| (idx, name.as_str()))
+    }
+}
+
+/// Parse `cell <index> = <name>`.
+fn cell_name(text: &str) -> Option<(isize, &str)> {
+    let text = text.strip_prefix("cell")?;
+    let mut parts = text.splitn(2, '=');
+    let idx = parts.next()?.trim().parse().ok()?;
+    let name = parts.next()?.trim();
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        None
+    } else {
+        Some((idx, name))
+    }
+}
+
+/// Bytes of the quoted string `text`, with escapes resolved
+fn unquote(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim();
+    let inner = text.strip_prefix('"')?.strip_suffix('"')?;
+    let mut out = vec![];
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next()? {
+            'n' => out.push(b'\n'),
+            'r' => out.push(b'\r'),
+            't' => out.push(b'\t'),
+            'x' => {
+                let hex: String = chars.by_ref().take(2).collect();
+                out.push(u8::from_str_radix(&hex, 16).ok()?);
+            }
+            c @ '\\' | c @ '"' => out.push(c as u8),
+            _ => return None,
+        }
+    }
+    Some(out)
+}
@@ -0,0 +1,399 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Assembler
+//!
+//! A small assembler-like language which is lowered to instructions. Statements are separated
+//! by newlines or `;`, `#` starts a comment:
+//!
+//! | Statement             | Meaning                                            |
+//! |-----------------------|----------------------------------------------------|
+//! | `set x, 10`           | `x = 10` (`'a'` is the code of a character)        |
+//! | `mov x, y`            | `x = y`                                            |
+//! | `add x, y`            | `x += y`, `y` is a register or a number            |
+//! | `sub x, y`            | `x -= y`, `y` is a register or a number            |
+//! | `read x`              | read a byte into `x`                               |
+//! | `print x`             | write `x`                                          |
+//! | `print "Hi\n"`        | write a string                                     |
+//! | `while x` ... `end`   | repeat the statements in between while `x` is not 0 |
+//!
+//! Every register gets its own cell, in the order of their first use. All arithmetic wraps.
+//!
+//! ```
+//! use bfrscc_core::asm::assemble;
+//! use bfrscc_core::machine::MachineExt;
+//! use bfrscc_core::buffer::BufferMachine;
+//!
+//! let prog = assemble("set x, 'A'; set n, 3\nwhile n\n  print x; add x, 1; sub n, 1\nend")
+//!     .unwrap();
+//! let mut machine = BufferMachine::new();
+//! machine.run(&prog).unwrap();
+//! assert_eq!(machine.take_output(), b"ABC");
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use lexer::Token;
+use parser::{Instruction, InstructionStream};
+use program::Program;
+
+/// Error in line `line` (starting at 1) of a program
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsmError {
+    /// Line of the offending statement
+    pub line: usize,
+    /// What is wrong
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+/// Operand of a statement
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    /// Cell of a register
+    Register(usize),
+    /// Constant
+    Value(u8),
+}
+
+/// Parsed statement, registers are already allocated
+#[derive(Debug, Clone, PartialEq)]
+enum Stmt {
+    Set(usize, Operand),
+    Add(usize, Operand),
+    Sub(usize, Operand),
+    Read(usize),
+    Print(usize),
+    PrintStr(Vec<u8>),
+    While(usize),
+    End,
+}
+
+/// Compile `source` to a program. Separators and comments within literals are part of them:
+///
+/// ```
+/// use bfrscc_core::asm::assemble;
+/// use bfrscc_core::machine::MachineExt;
+/// use bfrscc_core::buffer::BufferMachine;
+///
+/// let prog = assemble("set a, ';'; set b, ',' # don't\nset c, '#'; print a; print b; print c")
+///     .unwrap();
+/// let mut machine = BufferMachine::new();
+/// machine.run(&prog).unwrap();
+/// assert_eq!(machine.take_output(), b";,#");
+/// ```
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    let mut registers = vec![];
+    let mut stmts = vec![];
+    let mut open = vec![];
+    for (i, line) in source.lines().enumerate() {
+        let err = |message: String| AsmError {
+            line: i + 1,
+            message,
+        };
+        for stmt in statements(line) {
+            let stmt = parse(stmt, &mut registers).map_err(&err)?;
+            match stmt {
+                Stmt::While(_) => open.push(i + 1),
+                Stmt::End if open.pop().is_none() => {
+                    return Err(err("`end` without `while`".to_string()))
+                }
+                _ => {}
+            }
+            stmts.push(stmt);
+        }
+    }
+    if let Some(line) = open.pop() {
+        return Err(AsmError {
+            line,
+            message: "`while` without `end`".to_string(),
+        });
+    }
+    Ok(Program::from_instructions(lower(&stmts, registers.len())))
+}
+
+/// Statements of `line`, without comments
+fn statements(line: &str) -> Vec<&str> {
+    let end = unquoted(line)
+        .into_iter()
+        .find(|&(_, c)| c == '#')
+        .map_or(line.len(), |(i, _)| i);
+    split(&line[..end], ';')
+        .into_iter()
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+/// Split `s` at every `sep` which is not part of a string or character literal.
+fn split(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut start = 0;
+    for (i, _) in unquoted(s).into_iter().filter(|&(_, c)| c == sep) {
+        parts.push(&s[start..i]);
+        start = i + 1;
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+/// Characters of `s` outside of string and character literals, with their byte indices
+fn unquoted(s: &str) -> Vec<(usize, char)> {
+    let mut chars = vec![];
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quote.is_some() => escaped = true,
+            '"' | '\'' if quote.is_none() => quote = Some(c),
+            _ if quote == Some(c) => quote = None,
+            _ if quote.is_none() => chars.push((i, c)),
+            _ => {}
+        }
+    }
+    chars
+}
+
+/// Parse a single statement and allocate its registers.
+fn parse(stmt: &str, registers: &mut Vec<String>) -> Result<Stmt, String> {
+    let (op, args) = match stmt.find(char::is_whitespace) {
+        Some(idx) => (&stmt[..idx], stmt[idx..].trim()),
+        None => (stmt, ""),
+    };
+    if op == "print" && args.starts_with('"') {
+        return string(args).map(Stmt::PrintStr);
+    }
+    let args: Vec<&str> = if args.is_empty() {
+        vec![]
+    } else {
+        split(args, ',').into_iter().map(str::trim).collect()
+    };
+    let arity = match op {
+        "set" | "mov" | "add" | "sub" => 2,
+        "read" | "print" | "while" => 1,
+        "end" => 0,
+        _ => return Err(format!("unknown statement `{}`", op)),
+    };
+    if args.len() != arity {
+        return Err(format!("`{}` takes {} operands", op, arity));
+    }
+    let mut operands = vec![];
+    for arg in &args {
+        operands.push(operand(arg, registers)?);
+    }
+    let register = |idx: usize| match operands[idx] {
+        Operand::Register(cell) => Ok(cell),
+        Operand::Value(_) => Err(format!("operand `{}` of `{}` is not a register", args[idx], op)),
+    };
+    Ok(match op {
+        "set" => Stmt::Set(register(0)?, operands[1]),
+        "mov" => Stmt::Set(register(0)?, Operand::Register(register(1)?)),
+        "add" => Stmt::Add(register(0)?, operands[1]),
+        "sub" => Stmt::Sub(register(0)?, operands[1]),
+        "read" => Stmt::Read(register(0)?),
+        "print" => Stmt::Print(register(0)?),
+        "while" => Stmt::While(register(0)?),
+        _ => Stmt::End,
+    })
+}
+
+/// Parse a number, a character or a register.
+fn operand(arg: &str, registers: &mut Vec<String>) -> Result<Operand, String> {
+    if arg.starts_with('\'') {
+        let c = string(arg)?;
+        return match c.len() {
+            1 => Ok(Operand::Value(c[0])),
+            _ => Err(format!("`{}` is not a single byte", arg)),
+        };
+    }
+    if arg.starts_with(|c: char| c.is_ascii_digit()) {
+        return arg
+            .parse()
+            .map(Operand::Value)
+            .map_err(|_| format!("`{}` is not a number between 0 and 255", arg));
+    }
+    if arg.is_empty() || !arg.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return Err(format!("`{}` is not a register", arg));
+    }
+    match registers.iter().position(|x| x == arg) {
+        Some(cell) => Ok(Operand::Register(cell)),
+        None => {
+            registers.push(arg.to_string());
+            Ok(Operand::Register(registers.len() - 1))
+        }
+    }
+}
+
+/// Bytes of a string or character literal, which is enclosed in `"` or `'`.
+fn string(arg: &str) -> Result<Vec<u8>, String> {
+    let quote = arg.chars().next().unwrap_or('"');
+    let inner = match arg[1..].strip_suffix(quote) {
+        Some(inner) if arg.len() >= 2 => inner,
+        _ => return Err(format!("unterminated literal `{}`", arg)),
+    };
+    let mut bytes = vec![];
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        let c = match (c, c == '\\') {
+            (_, true) => match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('0') => '\0',
+                Some(c @ '\\') | Some(c @ '"') | Some(c @ '\'') => c,
+                _ => return Err(format!("unknown escape sequence in `{}`", arg)),
+            },
+            (c, false) => c,
+        };
+        let mut buf = [0; 4];
+        bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+    }
+    Ok(bytes)
+}
+
+/// Generates instructions and keeps track of the pointer
+struct Lowering {
+    /// Generated instructions
+    out: InstructionStream,
+    /// Cell under the pointer
+    pointer: usize,
+    /// Cell for temporary values, after all registers
+    temp: usize,
+}
+
+/// Lower `stmts`, which use `registers` cells, to instructions.
+fn lower(stmts: &[Stmt], registers: usize) -> InstructionStream {
+    let mut l = Lowering {
+        out: vec![],
+        pointer: 0,
+        temp: registers,
+    };
+    let mut loops = vec![];
+    for stmt in stmts {
+        match *stmt {
+            Stmt::Set(x, Operand::Value(n)) => {
+                l.clear(x);
+                l.add(n as i32);
+            }
+            Stmt::Set(x, Operand::Register(y)) if x != y => {
+                l.clear(x);
+                l.add_register(x, y, 1);
+            }
+            Stmt::Set(..) => {}
+            Stmt::Add(x, Operand::Value(n)) => {
+                l.goto(x);
+                l.add(n as i32);
+            }
+            Stmt::Sub(x, Operand::Value(n)) => {
+                l.goto(x);
+                l.add(-(n as i32));
+            }
+            Stmt::Add(x, Operand::Register(y)) => l.add_register(x, y, 1),
+            Stmt::Sub(x, Operand::Register(y)) if x == y => l.clear(x),
+            Stmt::Sub(x, Operand::Register(y)) => l.add_register(x, y, -1),
+            Stmt::Read(x) => {
+                l.goto(x);
+                l.push(Token::Input, 1);
+            }
+            Stmt::Print(x) => {
+                l.goto(x);
+                l.push(Token::Output, 1);
+            }
+            Stmt::PrintStr(ref bytes) => {
+                let temp = l.temp;
+                for &b in bytes {
+                    l.clear(temp);
+                    l.add(b as i32);
+                    l.push(Token::Output, 1);
+                }
+                l.clear(temp);
+            }
+            Stmt::While(x) => {
+                l.goto(x);
+                l.push(Token::LoopBegin, 1);
+                loops.push(x);
+            }
+            Stmt::End => {
+                let x = loops.pop().expect("Loops are balanced.");
+                l.goto(x);
+                l.push(Token::LoopEnd, 1);
+            }
+        }
+    }
+    l.out
+}
+
+impl Lowering {
+    /// Append `token`, repeated `n` times.
+    fn push(&mut self, token: Token, mut n: usize) {
+        while n > 0 {
+            let m = n.min(u8::MAX as usize);
+            self.out.push(Instruction::new(0, token, m as u8));
+            n -= m;
+        }
+    }
+
+    /// Move the pointer to `cell`.
+    fn goto(&mut self, cell: usize) {
+        if cell > self.pointer {
+            self.push(Token::MoveRight, cell - self.pointer);
+        } else {
+            self.push(Token::MoveLeft, self.pointer - cell);
+        }
+        self.pointer = cell;
+    }
+
+    /// Add `n` to the current cell, modulo 256.
+    fn add(&mut self, n: i32) {
+        let n = n.rem_euclid(256) as usize;
+        if n <= 128 {
+            self.push(Token::Increase, n);
+        } else {
+            self.push(Token::Decrease, 256 - n);
+        }
+    }
+
+    /// Set `cell` to zero.
+    fn clear(&mut self, cell: usize) {
+        self.goto(cell);
+        self.push(Token::LoopBegin, 1);
+        self.push(Token::Decrease, 1);
+        self.push(Token::LoopEnd, 1);
+    }
+
+    /// Add `factor` times the value of `from` to every cell of `to` and clear `from`.
+    fn transfer(&mut self, from: usize, to: &[(usize, i32)]) {
+        self.goto(from);
+        self.push(Token::LoopBegin, 1);
+        self.push(Token::Decrease, 1);
+        for &(cell, factor) in to {
+            self.goto(cell);
+            self.add(factor);
+        }
+        self.goto(from);
+        self.push(Token::LoopEnd, 1);
+    }
+
+    /// Add `factor` times the value of register `y` to register `x`, `y` is preserved.
+    fn add_register(&mut self, x: usize, y: usize, factor: i32) {
+        let temp = self.temp;
+        if x == y {
+            self.transfer(y, &[(temp, 1)]);
+            self.transfer(temp, &[(x, 1 + factor)]);
+        } else {
+            self.transfer(y, &[(x, factor), (temp, 1)]);
+            self.transfer(temp, &[(y, 1)]);
+        }
+    }
+}
@@ -0,0 +1,631 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Backends
+//!
+//! Backends translate a `Program` into source code of another language, or into a graph.
+//!
+//! `Precompute` wraps a backend and runs programs which read no input at compile time. Only code
+//! writing the recorded output is emitted then:
+//!
+//! ```
+//! use bfrscc_core::backend::{Backend, Brainfuck, Precompute};
+//!
+//! let prog = "++++++++[>++++++++<-]>+.+.".parse().unwrap();
+//! let code = Precompute::new(Brainfuck).emit(&prog).unwrap();
+//! assert_eq!(code, format!("{}.+.\n", "+".repeat(65)));
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+use cell::CellType;
+use lexer::{Dialect, Token};
+use parser::Instruction;
+use program::{LoopKind, Program};
+use run::{run_program, RunConfig};
+use runtime::{self, Runtime};
+
+/// Errors of backends
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackendError {
+    /// The instruction at the given position cannot be translated by the backend
+    Unsupported(usize, Token),
+    /// The settings of the backend cannot be combined, with the reason
+    Settings(String),
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BackendError::Unsupported(pos, ref token) => write!(
+                f,
+                "instruction `{}` at position {} is not supported by this backend",
+                token, pos
+            ),
+            BackendError::Settings(ref msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl Error for BackendError {}
+
+/// Translates programs into another language
+pub trait Backend {
+    /// Name of the backend, as used by `--emit`
+    fn name(&self) -> &str;
+
+    /// File extension of the generated files
+    fn extension(&self) -> &str;
+
+    /// Translate `prog`.
+    fn emit(&self, prog: &Program) -> Result<String, BackendError>;
+
+    /// Translate `prog` like `emit`, and return the bytes of the code generated for every
+    /// instruction of `prog`, by index. Code which belongs to no instruction, e.g. a runtime, is
+    /// in none of the ranges. By default, the code is not attributed (`None`).
+    fn emit_mapped(&self, prog: &Program) -> Result<(String, Option<Spans>), BackendError> {
+        Ok((self.emit(prog)?, None))
+    }
+
+    /// Number of cells left and right of the first cell of the tape of the generated programs,
+    /// `None` if the tape grows. Programs which may leave the tape are reported by
+    /// `lint::lint_bounds`.
+    fn tape_bounds(&self) -> Option<(usize, usize)> {
+        None
+    }
+
+    /// Files shared by all generated programs, by file name. `transpile::transpile_dir` writes
+    /// them into the output directory.
+    fn support_files(&self) -> Vec<(&'static str, String)> {
+        vec![]
+    }
+
+    /// Translate a program which only writes `output`. By default, the program of
+    /// `output_program` is translated.
+    fn emit_constant(&self, output: &[u8]) -> Result<String, BackendError> {
+        self.emit(&output_program(output))
+    }
+}
+
+impl<B: Backend + ?Sized> Backend for Box<B> {
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn extension(&self) -> &str {
+        (**self).extension()
+    }
+
+    fn emit(&self, prog: &Program) -> Result<String, BackendError> {
+        (**self).emit(prog)
+    }
+
+    fn emit_mapped(&self, prog: &Program) -> Result<(String, Option<Spans>), BackendError> {
+        (**self).emit_mapped(prog)
+    }
+
+    fn tape_bounds(&self) -> Option<(usize, usize)> {
+        (**self).tape_bounds()
+    }
+
+    fn support_files(&self) -> Vec<(&'static str, String)> {
+        (**self).support_files()
+    }
+
+    fn emit_constant(&self, output: &[u8]) -> Result<String, BackendError> {
+        (**self).emit_constant(output)
+    }
+}
+
+/// Ranges of generated code, one per instruction (see `Backend::emit_mapped`)
+pub type Spans = Vec<Range<usize>>;
+
+/// Returns the backend called `name`.
+pub fn by_name(name: &str) -> Option<Box<dyn Backend>> {
+    match name {
+        "bf" => Some(Box::new(Brainfuck)),
+        "c" => Some(Box::new(C::default())),
+        "cfg" => Some(Box::new(Cfg::default())),
+        _ => None,
+    }
+}
+
+/// Brainfuck program which writes `output`, using a single cell
+pub fn output_program(output: &[u8]) -> Program {
+    let mut source = String::new();
+    let mut cell = 0u8;
+    for &b in output {
+        let up = b.wrapping_sub(cell);
+        if up <= 128 {
+            source.extend(::std::iter::repeat_n('+', up as usize));
+        } else {
+            source.extend(::std::iter::repeat_n('-', 256 - up as usize));
+        }
+        source.push('.');
+        cell = b;
+    }
+    Program::new(&source)
+}
+
+/// Limits of executions at compile time
+#[derive(Debug, Clone, PartialEq)]
+pub struct Budget {
+    /// Maximum number of executed instructions
+    pub max_steps: u64,
+    /// Maximum number of bytes of output
+    pub max_output: usize,
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Budget {
+            max_steps: 10_000_000,
+            max_output: 1 << 20,
+        }
+    }
+}
+
+/// Output of `prog`, if it reads no input, uses neither random numbers, breakpoints nor the grid,
+/// and terminates within `budget`. The output can serve as an oracle for compiled programs.
+pub fn precompute(prog: &Program, budget: &Budget) -> Option<Vec<u8>> {
+    let dynamic = prog.instructions().iter().any(|x| {
+        matches!(
+            x.token,
+            Token::Input | Token::Random | Token::Breakpoint | Token::MoveUp | Token::MoveDown
+        )
+    });
+    if dynamic || prog.dialect() == Dialect::Grid {
+        return None;
+    }
+    let config = RunConfig {
+        max_steps: Some(budget.max_steps),
+        input: Some(vec![]),
+        ..RunConfig::default()
+    };
+    let mut machine = prog.spawn_machine();
+    run_program(prog, &mut machine, &config).ok()?;
+    Some(machine.take_output()).filter(|x| x.len() <= budget.max_output)
+}
+
+/// Translates programs with `backend`, but programs for which `precompute` succeeds are only
+/// translated into code writing their output (see `Backend::emit_constant`)
+pub struct Precompute<B: Backend> {
+    /// Backend which generates the code
+    pub backend: B,
+    /// Limits of the execution at compile time
+    pub budget: Budget,
+}
+
+impl<B: Backend> Precompute<B> {
+    /// Precompute with the default budget
+    pub fn new(backend: B) -> Self {
+        Precompute {
+            backend,
+            budget: Budget::default(),
+        }
+    }
+}
+
+impl<B: Backend> Backend for Precompute<B> {
+    fn name(&self) -> &str {
+        self.backend.name()
+    }
+
+    fn extension(&self) -> &str {
+        self.backend.extension()
+    }
+
+    fn emit(&self, prog: &Program) -> Result<String, BackendError> {
+        match precompute(prog, &self.budget) {
+            Some(output) => self.backend.emit_constant(&output),
+            None => self.backend.emit(prog),
+        }
+    }
+
+    fn emit_mapped(&self, prog: &Program) -> Result<(String, Option<Spans>), BackendError> {
+        match precompute(prog, &self.budget) {
+            // No instruction is translated.
+            Some(output) => Ok((self.backend.emit_constant(&output)?, None)),
+            None => self.backend.emit_mapped(prog),
+        }
+    }
+
+    fn emit_constant(&self, output: &[u8]) -> Result<String, BackendError> {
+        self.backend.emit_constant(output)
+    }
+
+    fn tape_bounds(&self) -> Option<(usize, usize)> {
+        self.backend.tape_bounds()
+    }
+
+    fn support_files(&self) -> Vec<(&'static str, String)> {
+        self.backend.support_files()
+    }
+}
+
+/// Writes canonical brainfuck, i.e. the program without comments
+pub struct Brainfuck;
+
+impl Backend for Brainfuck {
+    fn name(&self) -> &str {
+        "bf"
+    }
+
+    fn extension(&self) -> &str {
+        "bf"
+    }
+
+    fn emit(&self, prog: &Program) -> Result<String, BackendError> {
+        Ok(format!("{}\n", prog))
+    }
+
+    fn emit_mapped(&self, prog: &Program) -> Result<(String, Option<Spans>), BackendError> {
+        let mut code = String::new();
+        let mut spans = Vec::with_capacity(prog.instructions().len());
+        for instr in prog.instructions() {
+            let start = code.len();
+            code.push_str(&instr.to_string());
+            spans.push(start..code.len());
+        }
+        code.push('\n');
+        Ok((code, Some(spans)))
+    }
+}
+
+/// Writes a C program. `[-]` and loops with a known effect become assignments, loops which run
+/// at most once and if/else constructions become `if` statements (see `program::LoopKind`).
+///
+/// The tape and the I/O are done by the runtime (see `runtime::Runtime`). By default, the tape
+/// has 65536 bytes with the pointer starting in the middle, and the pointer is not checked.
+/// `C::from_config` takes the tape, the cells and the end of input of a `RunConfig`, so the
+/// compiled program behaves like the interpreter:
+///
+/// ```
+/// use bfrscc_core::backend::{Backend, C};
+/// use bfrscc_core::cell::CellType;
+/// use bfrscc_core::run::{RunConfig, Underflow};
+///
+/// let config = RunConfig {
+///     cells: CellType::I16,
+///     underflow: Underflow::Error,
+///     max_tape: Some(100),
+///     ..RunConfig::default()
+/// };
+/// let code = C::from_config(&config).emit(&"+[->+<]<".parse().unwrap()).unwrap();
+/// assert!(code.contains("typedef int16_t bf_cell;"));
+/// assert!(code.contains("bf_error(\"tape limit of 100 cells exceeded\");"));
+/// assert!(code.contains("bf_error(\"pointer moved left of the first cell\");"));
+/// ```
+///
+/// With `shared_runtime`, programs include `bfrscc_rt.h`, and the runtime is written once next
+/// to them (see `Backend::support_files`):
+///
+/// ```
+/// use bfrscc_core::backend::{Backend, C};
+///
+/// let c = C { shared_runtime: true, ..C::default() };
+/// let code = c.emit(&"+.".parse().unwrap()).unwrap();
+/// assert!(code.starts_with("#include \"bfrscc_rt.h\""));
+/// assert!(!code.contains("void bf_right"));
+/// assert_eq!(c.support_files().len(), 2);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct C {
+    /// Settings of the tape and the I/O
+    pub runtime: Runtime,
+    /// Include the runtime from `bfrscc_rt.h` instead of putting it in front of every program
+    pub shared_runtime: bool,
+}
+
+impl C {
+    /// Backend generating programs which behave like the interpreter with `config` (see
+    /// `Runtime::from_config`). Other settings such as `config.arithmetic` are not honored.
+    pub fn from_config(config: &RunConfig) -> Self {
+        C {
+            runtime: Runtime::from_config(config),
+            ..C::default()
+        }
+    }
+
+    /// Backend generating code without the C library, e.g. for kernels, boot loaders or
+    /// firmware. The program becomes the function `void bf_run(void)`, and the environment
+    /// provides the functions doing the I/O:
+    ///
+    /// - `void bf_putchar(unsigned char c)` writes a byte,
+    /// - `int bf_getchar(void)` reads a byte, or returns a negative value at the end of the input,
+    /// - `void bf_fail(const char *msg)` reports an error, only called with checks. The program
+    ///   stops if it returns.
+    ///
+    /// The tape is a static array, which needs to be zeroed like all static data. Freestanding
+    /// code needs a tape of fixed size.
+    ///
+    /// ```
+    /// use bfrscc_core::backend::{Backend, C};
+    ///
+    /// let code = C::freestanding().emit(&",[.,]".parse().unwrap()).unwrap();
+    /// assert!(!code.contains("#include <stdio.h>"));
+    /// assert!(code.contains("void bf_run(void) {"));
+    /// assert!(code.contains("bf_putchar((unsigned char)c);"));
+    /// ```
+    pub fn freestanding() -> Self {
+        let mut c = C::default();
+        c.runtime.freestanding = true;
+        c
+    }
+
+    /// Statement adding `amount`, a C expression, to the cell `cell`
+    fn add(&self, cell: &str, amount: &str) -> String {
+        match self.runtime.cells {
+            CellType::U8 => format!("{} += {};", cell, amount),
+            _ => format!("{} = (bf_cell)((bf_ucell){} + (bf_ucell)({}));", cell, cell, amount),
+        }
+    }
+
+    /// C statement of an instruction other than a bracket, `None` for instructions without
+    /// effect
+    fn statement(&self, instr: &Instruction) -> Result<Option<String>, BackendError> {
+        let n = instr.multiplier;
+        Ok(Some(match instr.token {
+            Token::Increase => self.add(CURRENT, &n.to_string()),
+            Token::Decrease => self.add(CURRENT, &format!("-{}", n)),
+            Token::MoveRight => format!("bf_right({});", n),
+            Token::MoveLeft => format!("bf_left({});", n),
+            Token::Output => format!("bf_put({});", CURRENT),
+            Token::Input => format!("{} = bf_get();", CURRENT),
+            Token::Breakpoint | Token::InputSeparator | Token::Comment => return Ok(None),
+            token => return Err(BackendError::Unsupported(instr.position(), token)),
+        }))
+    }
+}
+
+/// C expression of the current cell
+const CURRENT: &str = "bf_tape[bf_pos]";
+
+impl Backend for C {
+    fn name(&self) -> &str {
+        "c"
+    }
+
+    fn extension(&self) -> &str {
+        "c"
+    }
+
+    fn tape_bounds(&self) -> Option<(usize, usize)> {
+        self.runtime.tape_bounds()
+    }
+
+    fn support_files(&self) -> Vec<(&'static str, String)> {
+        if self.shared_runtime {
+            vec![
+                (runtime::HEADER, self.runtime.header()),
+                (runtime::SOURCE, self.runtime.source()),
+            ]
+        } else {
+            vec![]
+        }
+    }
+
+    fn emit(&self, prog: &Program) -> Result<String, BackendError> {
+        self.emit_mapped(prog).map(|(code, _)| code)
+    }
+
+    fn emit_mapped(&self, prog: &Program) -> Result<(String, Option<Spans>), BackendError> {
+        self.runtime.validate().map_err(BackendError::Settings)?;
+        let mut out = if self.shared_runtime {
+            format!("#include \"{}\"\n", runtime::HEADER)
+        } else {
+            self.runtime.inline()
+        };
+        out.push_str(if self.runtime.freestanding {
+            "\nvoid bf_run(void) {\n"
+        } else {
+            "\nint main(void) {\n"
+        });
+        if prog.ops().iter().any(|op| op.kind == LoopKind::Transfer) {
+            out.push_str("    size_t q;\n");
+        }
+        out.push_str("    bf_init();\n");
+        let instructions = prog.instructions();
+        let ops = prog.ops();
+        let indent = |depth: usize| "    ".repeat(depth);
+        let mut depth = 1;
+        let mut spans = vec![0..0; instructions.len()];
+        let mut i = 0;
+        while i < instructions.len() {
+            let (at, start) = (i, out.len());
+            let instr = &instructions[i];
+            let op = ops[i];
+            match (instr.token, op.kind) {
+                (Token::LoopBegin, LoopKind::Clear) => {
+                    out.push_str(&format!("{}{} = 0;\n", indent(depth), CURRENT));
+                    i = op.jump as usize;
+                }
+                (Token::LoopBegin, LoopKind::Transfer) => {
+                    for &(offset, factor) in prog.effects(i).unwrap_or_default() {
+                        // `bf_at` may move the tape, so it is called before the cells are read.
+                        out.push_str(&format!("{}q = bf_at({});\n", indent(depth), offset));
+                        let cell = "bf_tape[q]";
+                        let amount = match factor {
+                            1 => CURRENT.to_string(),
+                            _ if self.runtime.cells == CellType::U8 => {
+                                format!("{} * {}", CURRENT, factor)
+                            }
+                            _ => format!("(bf_ucell){} * {}", CURRENT, factor),
+                        };
+                        let line = match (factor, self.runtime.cells) {
+                            (u8::MAX, CellType::U8) => format!("{} -= {};", cell, CURRENT),
+                            _ => self.add(cell, &amount),
+                        };
+                        out.push_str(&format!("{}{}\n", indent(depth), line));
+                    }
+                    out.push_str(&format!("{}{} = 0;\n", indent(depth), CURRENT));
+                    i = op.jump as usize;
+                }
+                (Token::LoopBegin, LoopKind::If) | (Token::LoopBegin, LoopKind::IfElse) => {
+                    out.push_str(&format!("{}if ({}) {{\n", indent(depth), CURRENT));
+                    depth += 1;
+                }
+                (Token::LoopBegin, _) => {
+                    out.push_str(&format!("{}while ({}) {{\n", indent(depth), CURRENT));
+                    depth += 1;
+                }
+                // The move to the flag between both loops goes into both branches, the `[` of
+                // the else branch is left out.
+                (Token::LoopEnd, LoopKind::IfElse) => {
+                    let step = self.statement(&instructions[i + 1])?.unwrap_or_default();
+                    out.push_str(&format!("{}{}\n", indent(depth), step));
+                    out.push_str(&format!("{}}} else {{\n", indent(depth - 1)));
+                    out.push_str(&format!("{}{}\n", indent(depth), step));
+                    i += 2;
+                }
+                (Token::LoopEnd, _) => {
+                    depth -= 1;
+                    out.push_str(&format!("{}}}\n", indent(depth)));
+                }
+                _ => {
+                    for l in self.statement(instr)?.iter().flat_map(|x| x.lines()) {
+                        out.push_str(&format!("{}{}\n", indent(depth), l));
+                    }
+                }
+            }
+            spans[at] = start..out.len();
+            i += 1;
+        }
+        out.push_str(if self.runtime.freestanding { "}\n" } else { "    return 0;\n}\n" });
+        Ok((out, Some(spans)))
+    }
+
+    fn emit_constant(&self, output: &[u8]) -> Result<String, BackendError> {
+        let mut out = String::new();
+        if self.runtime.freestanding {
+            out.push_str("#include <stddef.h>\n\n/* Provided by the environment */\n");
+            out.push_str("void bf_putchar(unsigned char c);\n\n");
+        } else {
+            out.push_str("#include <stdio.h>\n\n");
+        }
+        out.push_str("static const char output[] =");
+        if output.is_empty() {
+            out.push_str(" \"\"");
+        }
+        // One string literal per line of the output
+        for line in output.split_inclusive(|&b| b == b'\n') {
+            out.push_str("\n    \"");
+            for &b in line {
+                match b {
+                    b'"' | b'\\' | b'?' => out.push_str(&format!("\\{}", b as char)),
+                    b'\n' => out.push_str("\\n"),
+                    b' '..=b'~' => out.push(b as char),
+                    _ => out.push_str(&format!("\\{:03o}", b)),
+                }
+            }
+            out.push('"');
+        }
+        if self.runtime.freestanding {
+            out.push_str(";\n\nvoid bf_run(void) {\n");
+            out.push_str("    size_t i;\n");
+            out.push_str("    for (i = 0; i < sizeof output - 1; i++) {\n");
+            out.push_str("        bf_putchar((unsigned char)output[i]);\n    }\n}\n");
+        } else {
+            out.push_str(";\n\nint main(void) {\n");
+            out.push_str("    fwrite(output, 1, sizeof output - 1, stdout);\n");
+            out.push_str("    return 0;\n}\n");
+        }
+        Ok(out)
+    }
+}
+
+/// Writes the control-flow graph as a Graphviz DOT graph. Nodes are basic blocks and brackets,
+/// every loop is a cluster.
+#[derive(Debug, Clone, Default)]
+pub struct Cfg {
+    /// Number of executions of every instruction (see `RunConfig::profile`), which are added to
+    /// the nodes
+    pub counts: Option<Vec<u64>>,
+}
+
+/// Maximum number of characters of the code shown in a node
+const CFG_LABEL_LEN: usize = 40;
+
+impl Backend for Cfg {
+    fn name(&self) -> &str {
+        "cfg"
+    }
+
+    fn extension(&self) -> &str {
+        "dot"
+    }
+
+    fn emit(&self, prog: &Program) -> Result<String, BackendError> {
+        let instructions = prog.instructions();
+        let len = instructions.len();
+        let is_bracket =
+            |i: usize| matches!(instructions[i].token, Token::LoopBegin | Token::LoopEnd);
+        // Basic blocks start at every bracket and after every bracket.
+        let starts: Vec<usize> = (0..len)
+            .filter(|&i| i == 0 || is_bracket(i) || is_bracket(i - 1))
+            .collect();
+        let node = |i: usize| if i >= len { "end".to_string() } else { format!("b{}", i) };
+        let mut out = String::new();
+        out.push_str("digraph cfg {\n");
+        out.push_str("    node [shape=box, fontname=monospace];\n");
+        out.push_str("    start [shape=circle];\n    end [shape=doublecircle];\n");
+        out.push_str(&format!("    start -> {};\n", node(0)));
+        let mut edges = vec![];
+        let mut depth = 1;
+        for (k, &start) in starts.iter().enumerate() {
+            let end = starts.get(k + 1).cloned().unwrap_or(len);
+            let x = &instructions[start];
+            let indent = "    ".repeat(depth);
+            let label = match x.token {
+                Token::LoopBegin => {
+                    out.push_str(&format!("{}subgraph cluster_{} {{\n", indent, start));
+                    out.push_str(&format!("{}    style=dashed;\n", indent));
+                    depth += 1;
+                    edges.push((node(start), node(start + 1), "!= 0"));
+                    edges.push((node(start), node(prog.jump(start) + 1), "== 0"));
+                    format!("[ @{}", x.position())
+                }
+                Token::LoopEnd => {
+                    edges.push((node(start), node(prog.jump(start) + 1), "!= 0"));
+                    edges.push((node(start), node(start + 1), "== 0"));
+                    format!("] @{}", x.position())
+                }
+                _ => {
+                    edges.push((node(start), node(end), ""));
+                    let code: String =
+                        instructions[start..end].iter().map(|x| x.to_string()).collect();
+                    let mut label: String = code.chars().take(CFG_LABEL_LEN).collect();
+                    if code.chars().count() > CFG_LABEL_LEN {
+                        label.push_str("...");
+                    }
+                    format!("{} @{}", label, x.position())
+                }
+            };
+            let count = match self.counts {
+                Some(ref counts) => format!("\\n{}x", counts.get(start).cloned().unwrap_or(0)),
+                None => String::new(),
+            };
+            out.push_str(&format!(
+                "{}    {} [label=\"{}{}\"];\n",
+                "    ".repeat(depth - 1),
+                node(start),
+                label,
+                count
+            ));
+            if x.token == Token::LoopEnd {
+                depth -= 1;
+                out.push_str(&format!("{}}}\n", "    ".repeat(depth)));
+            }
+        }
+        for (from, to, label) in edges {
+            out.push_str(&format!("    {} -> {} [label=\"{}\"];\n", from, to, label));
+        }
+        out.push_str("}\n");
+        Ok(out)
+    }
+}
@@ -0,0 +1,101 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Batch execution
+//!
+//! Runs many independent programs on all available cores, for instance a corpus of test programs
+//! or the candidates of a fuzzing campaign.
+//!
+//! ```
+//! use bfrscc_core::batch::run_parallel;
+//! use bfrscc_core::program::Program;
+//! use bfrscc_core::run::{RunConfig, RunError};
+//!
+//! let programs = vec![Program::new("++++++++[>++++++++<-]>+."), Program::new("+[]")];
+//! let config = RunConfig {
+//!     max_steps: Some(1000),
+//!     ..RunConfig::default()
+//! };
+//! let results = run_parallel(&programs, &config);
+//! assert_eq!(results[0].output, b"A");
+//! assert_eq!(results[1].result, Err(RunError::StepLimit(1000)));
+//! ```
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use buffer::BufferMachine;
+use program::Program;
+use run::{interpreter, run_program, RunConfig, RunError, RunReport};
+use tape::LinearTape;
+
+/// Outcome of the execution of a single program
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchResult {
+    /// Output of the program, also if it failed
+    pub output: Vec<u8>,
+    /// Statistics of the execution if it succeeded
+    pub result: Result<RunReport, RunError>,
+    /// Time the execution took
+    pub time: Duration,
+}
+
+/// Run all `programs` in parallel. The limits of `config` apply to every program separately.
+/// Programs read `config.input`, or their inline input, or nothing; stdin is never read. Their
+/// machines are set up like those of `run_with_config`, e.g. with the seed of `config`. The
+/// results are in the same order as the programs.
+pub fn run_parallel(programs: &[Program], config: &RunConfig) -> Vec<BatchResult> {
+    let threads = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(programs.len());
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, BatchResult)> = thread::scope(|s| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut done = vec![];
+                    loop {
+                        let idx = next.fetch_add(1, Ordering::Relaxed);
+                        match programs.get(idx) {
+                            Some(prog) => done.push((idx, run_one(prog, config))),
+                            None => return done,
+                        }
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().expect("Batch worker panicked."))
+            .collect()
+    });
+    results.sort_by_key(|&(idx, _)| idx);
+    results.into_iter().map(|(_, res)| res).collect()
+}
+
+/// Run a single program of a batch.
+fn run_one(prog: &Program, config: &RunConfig) -> BatchResult {
+    let mut config = config.clone();
+    if config.input.is_none() && prog.inline_input().is_none() {
+        config.input = Some(vec![]);
+    }
+    let start = Instant::now();
+    let (output, result) = match interpreter::<LinearTape>(prog, &config) {
+        Ok(interpreter) => {
+            let mut machine = BufferMachine::with_interpreter(interpreter);
+            let result = run_program(prog, &mut machine, &config);
+            (machine.take_output(), result)
+        }
+        Err(e) => (vec![], Err(e)),
+    };
+    BatchResult {
+        output,
+        result,
+        time: start.elapsed(),
+    }
+}
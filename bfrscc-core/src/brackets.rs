@@ -0,0 +1,137 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Bracket pairs
+//!
+//! Matches the brackets of a lexed source, which editors need to jump to the matching bracket
+//! and to fold loops. Unlike parsing, this also works while the program is edited: brackets
+//! without a partner are listed separately, the others are still paired.
+//!
+//! ```
+//! use bfrscc_core::brackets::BracketTable;
+//! use bfrscc_core::lexer::lexer;
+//!
+//! let table = BracketTable::new(&lexer("+[>[-]\n<]]"));
+//! assert_eq!(table.pairs.len(), 2);
+//! assert_eq!((table.pairs[0].open.col, table.pairs[0].close.line), (2, 2));
+//! assert_eq!(table.pairs[1].depth, 2);
+//! assert_eq!(table.unmatched[0].0.position, 9);
+//! ```
+//!
+//! `to_json` writes the table as a single line, with the character position, the byte offset,
+//! the line and the column of every bracket:
+//!
+//! ```text
+//! {"pairs":[{"depth":1,"open":{"position":1,"offset":1,"line":1,"col":2},"close":...}],
+//!  "unmatched":[{"bracket":"]","position":9,"offset":9,"line":2,"col":3}]}
+//! ```
+
+use std::fmt;
+use json::{self, Object};
+use lexer::{Span, Token};
+
+/// A `[` and its matching `]`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BracketPair {
+    /// Nesting depth of the loop, 1 for loops which are not nested
+    pub depth: usize,
+    /// Location of the `[`
+    pub open: Span,
+    /// Location of the `]`
+    pub close: Span,
+}
+
+/// The brackets of a source
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BracketTable {
+    /// Matched brackets, in the order of their `[`
+    pub pairs: Vec<BracketPair>,
+    /// Brackets without a partner, in the order of the source
+    pub unmatched: Vec<(Span, Token)>,
+}
+
+impl BracketTable {
+    /// Match the brackets of `tokens`.
+    pub fn new(tokens: &[(Span, Token)]) -> Self {
+        let mut table = BracketTable::default();
+        let mut open = vec![];
+        for &(span, token) in tokens {
+            match token {
+                Token::LoopBegin => {
+                    open.push(table.pairs.len());
+                    table.pairs.push(BracketPair {
+                        depth: open.len(),
+                        open: span,
+                        close: span,
+                    });
+                }
+                Token::LoopEnd => match open.pop() {
+                    Some(idx) => table.pairs[idx].close = span,
+                    None => table.unmatched.push((span, token)),
+                },
+                _ => {}
+            }
+        }
+        // Loops which are still open are unmatched.
+        for idx in open.into_iter().rev() {
+            let pair = table.pairs.remove(idx);
+            table.unmatched.push((pair.open, Token::LoopBegin));
+        }
+        table.unmatched.sort_by_key(|x| x.0.position);
+        table
+    }
+
+    /// The table as a JSON object
+    pub fn to_json(&self) -> String {
+        let pairs = self.pairs.iter().map(|pair| {
+            Object::new()
+                .integer("depth", pair.depth as u64)
+                .raw("open", &location(Object::new(), &pair.open).finish())
+                .raw("close", &location(Object::new(), &pair.close).finish())
+                .finish()
+        });
+        let unmatched = self.unmatched.iter().map(|&(ref span, token)| {
+            location(Object::new().string("bracket", bracket(token)), span).finish()
+        });
+        Object::new()
+            .raw("pairs", &json::array(pairs))
+            .raw("unmatched", &json::array(unmatched))
+            .finish()
+    }
+}
+
+/// `object` with the members describing `span`
+fn location(object: Object, span: &Span) -> Object {
+    object
+        .integer("position", span.position as u64)
+        .integer("offset", span.offset as u64)
+        .integer("line", span.line as u64)
+        .integer("col", span.col as u64)
+}
+
+impl fmt::Display for BracketTable {
+    /// Writes a line `line:column line:column` for every pair and a line for every bracket
+    /// without a partner.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for pair in &self.pairs {
+            writeln!(f, "{} {}", pair.open, pair.close)?;
+        }
+        for &(ref span, token) in &self.unmatched {
+            writeln!(f, "{} unmatched `{}`", span, bracket(token))?;
+        }
+        Ok(())
+    }
+}
+
+/// The bracket `token` stands for, which may be written as an alias in the source
+fn bracket(token: Token) -> &'static str {
+    if token == Token::LoopBegin {
+        "["
+    } else {
+        "]"
+    }
+}
@@ -0,0 +1,143 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Buffer machine
+
+use interpreter::Interpreter;
+use machine::Machine;
+use program::Op;
+use run::RunError;
+use tape::{LinearTape, Tape};
+
+/// A `Machine` which behaves like the `Interpreter`, but collects the output in a buffer instead
+/// of printing it.
+pub struct BufferMachine<T: Tape = LinearTape> {
+    /// Interpreter doing the actual work
+    interpreter: Interpreter<T>,
+    /// Collected output
+    buffer: Vec<u8>,
+}
+
+impl BufferMachine {
+    /// Constructor
+    pub fn new() -> Self {
+        BufferMachine::with_interpreter(Interpreter::new())
+    }
+}
+
+impl Default for BufferMachine {
+    fn default() -> Self {
+        BufferMachine::new()
+    }
+}
+
+impl<T: Tape> BufferMachine<T> {
+    /// Collect the output of `interpreter`.
+    pub fn with_interpreter(interpreter: Interpreter<T>) -> Self {
+        BufferMachine {
+            interpreter,
+            buffer: vec![],
+        }
+    }
+
+    /// Return the output collected so far.
+    pub fn get_output(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// Return the collected output and leave an empty buffer behind.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        ::std::mem::take(&mut self.buffer)
+    }
+
+    /// Return the underlying interpreter, for instance to look at the tape.
+    pub fn interpreter(&self) -> &Interpreter<T> {
+        &self.interpreter
+    }
+
+    /// Return the underlying interpreter for modification.
+    pub fn interpreter_mut(&mut self) -> &mut Interpreter<T> {
+        &mut self.interpreter
+    }
+}
+
+impl<T: Tape> Machine for BufferMachine<T> {
+    fn left(&mut self, val: u8) {
+        self.interpreter.left(val);
+    }
+
+    fn right(&mut self, val: u8) {
+        self.interpreter.right(val);
+    }
+
+    fn up(&mut self, val: u8) {
+        self.interpreter.up(val);
+    }
+
+    fn down(&mut self, val: u8) {
+        self.interpreter.down(val);
+    }
+
+    fn increase(&mut self, val: u8) {
+        self.interpreter.increase(val);
+    }
+
+    fn decrease(&mut self, val: u8) {
+        self.interpreter.decrease(val);
+    }
+
+    fn clear(&mut self) {
+        self.interpreter.clear();
+    }
+
+    fn transfer(&mut self, effects: &[(isize, u8)]) {
+        self.interpreter.transfer(effects);
+    }
+
+    fn output(&mut self) {
+        let buffer = &mut self.buffer;
+        self.interpreter.output_with(|val| buffer.push(val));
+    }
+
+    fn input(&mut self, val: u8) {
+        self.interpreter.input(val);
+    }
+
+    fn exec_run(&mut self, ops: &[Op], input: &mut dyn Iterator<Item = u8>) -> bool {
+        let buffer = &mut self.buffer;
+        self.interpreter.exec_run_with(ops, input, |val| buffer.push(val));
+        true
+    }
+
+    fn get_val(&self) -> u8 {
+        self.interpreter.get_val()
+    }
+
+    fn tape_len(&self) -> usize {
+        self.interpreter.tape_len()
+    }
+
+    fn tape_capacity(&self) -> usize {
+        self.interpreter.tape_capacity()
+    }
+
+    fn breakpoint(&mut self) {
+        self.interpreter.breakpoint();
+    }
+
+    fn switch_tape(&mut self) {
+        self.interpreter.switch_tape();
+    }
+
+    fn random(&mut self) {
+        self.interpreter.random();
+    }
+
+    fn status(&self) -> Result<(), RunError> {
+        self.interpreter.status()
+    }
+}
@@ -0,0 +1,347 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Bytecode
+//!
+//! Stores the instructions of a compiled, usually optimized, program in a binary file (`.bfc`),
+//! which can be run without optimizing it again and without its source:
+//!
+//! ```
+//! use bfrscc_core::bytecode;
+//! use bfrscc_core::lexer::LexerOptions;
+//! use bfrscc_core::program::Program;
+//!
+//! let prog = Program::compile("++++++++[>++++++++<-]>+.", &LexerOptions::default(), 1).unwrap();
+//! let bytes = bytecode::encode(&prog);
+//! assert!(bytecode::is_bytecode(&bytes));
+//! let loaded = bytecode::decode(&bytes).unwrap();
+//! assert_eq!(loaded.ops(), prog.ops());
+//! assert_eq!(loaded.opt_level(), 1);
+//! ```
+//!
+//! The format is stable: files of a version are read by all later versions of bfrscc, and
+//! `encode_version` writes files for older ones. Files of newer versions are rejected with
+//! `BytecodeError::NewerVersion`. Integers are little endian.
+//!
+//! ```text
+//! magic          4 bytes  "\0bfc"
+//! version        u16      2
+//! opt level      u8
+//! dialect        u8       0 standard, 1 multi-tape, 2 grid, 3 random
+//! tapes          u32      number of tapes of the multi-tape dialect, 0 otherwise
+//! inline input   u8       1 if the program has inline input, 0 otherwise
+//!                u32      length of the inline input, if any
+//!                         bytes of the inline input, if any
+//! instructions   u32      number of instructions
+//!                         token (u8) and multiplier (u8) of every instruction
+//! checksum       u64      FNV-1a hash of all bytes before it (since version 2)
+//! ```
+//!
+//! The tokens are numbered in the order `+-<>[],.#~^v?`. Everything else the run loop needs,
+//! e.g. the jump targets and the shapes of the loops, is derived from the instructions when they
+//! are loaded. The source, and with it the annotations, is not stored; the loaded program has
+//! the canonical brainfuck of its instructions as its source.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use lexer::{Dialect, LexerOptions, Token};
+use parser::{Instruction, InstructionStream};
+use program::Program;
+use repro::hash;
+
+/// Header of a bytecode file
+pub const MAGIC: &[u8; 4] = b"\0bfc";
+
+/// Version of the format written by this version of bfrscc, the newest one it reads
+pub const VERSION: u16 = 2;
+
+/// Version which introduced the checksum
+const CHECKSUM_VERSION: u16 = 2;
+
+/// Reasons for bytecode to be rejected
+#[derive(Debug)]
+pub enum BytecodeError {
+    /// The bytecode could not be read or written
+    Io(io::Error),
+    /// The bytes do not start with `MAGIC`
+    NotBytecode,
+    /// The bytecode was written in a version of the format this version of bfrscc does not
+    /// know, usually by a newer one
+    NewerVersion(u16),
+    /// Another version of the format was requested which cannot be written
+    UnsupportedVersion(u16),
+    /// The bytecode ends in the middle of a field
+    Truncated,
+    /// The checksum stored in the bytecode differs from the one of its content
+    Checksum {
+        /// Checksum in the bytecode
+        stored: u64,
+        /// Checksum of the content
+        actual: u64,
+    },
+    /// A field has an invalid value, with a description
+    Corrupt(String),
+}
+
+impl fmt::Display for BytecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BytecodeError::Io(ref e) => e.fmt(f),
+            BytecodeError::NotBytecode => write!(f, "not a bfrscc bytecode file"),
+            BytecodeError::NewerVersion(version) => write!(
+                f,
+                "bytecode version {} is newer than the versions 1 to {} this version of bfrscc \
+                 reads, compile the program again",
+                version, VERSION
+            ),
+            BytecodeError::UnsupportedVersion(version) => write!(
+                f,
+                "bytecode version {} cannot be written, the versions are 1 to {}",
+                version, VERSION
+            ),
+            BytecodeError::Truncated => write!(f, "the bytecode is truncated"),
+            BytecodeError::Checksum { stored, actual } => write!(
+                f,
+                "the bytecode is corrupt, its checksum is {:016x} instead of {:016x}",
+                actual, stored
+            ),
+            BytecodeError::Corrupt(ref msg) => write!(f, "the bytecode is corrupt: {}", msg),
+        }
+    }
+}
+
+impl Error for BytecodeError {}
+
+impl From<io::Error> for BytecodeError {
+    fn from(e: io::Error) -> Self {
+        BytecodeError::Io(e)
+    }
+}
+
+/// Tokens by their number in the format
+const TOKENS: [Token; 13] = [
+    Token::Increase,
+    Token::Decrease,
+    Token::MoveLeft,
+    Token::MoveRight,
+    Token::LoopBegin,
+    Token::LoopEnd,
+    Token::Input,
+    Token::Output,
+    Token::Breakpoint,
+    Token::SwitchTape,
+    Token::MoveUp,
+    Token::MoveDown,
+    Token::Random,
+];
+
+/// Returns `true` if `bytes` start like a bytecode file, i.e. with `MAGIC`.
+pub fn is_bytecode(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Serialize the packed instructions of `prog`, which may also be compiled from a stream.
+pub fn encode(prog: &Program) -> Vec<u8> {
+    encode_version(prog, VERSION).expect("The current version can be written.")
+}
+
+/// Like `encode`, in version `version` of the format, e.g. for an older version of bfrscc.
+pub fn encode_version(prog: &Program, version: u16) -> Result<Vec<u8>, BytecodeError> {
+    if version == 0 || version > VERSION {
+        return Err(BytecodeError::UnsupportedVersion(version));
+    }
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&version.to_le_bytes());
+    bytes.push(prog.opt_level());
+    let (dialect, tapes) = match prog.dialect() {
+        Dialect::Standard => (0, 0),
+        Dialect::MultiTape(n) => (1, n as u32),
+        Dialect::Grid => (2, 0),
+        Dialect::Random => (3, 0),
+    };
+    bytes.push(dialect);
+    bytes.extend_from_slice(&tapes.to_le_bytes());
+    match prog.inline_input() {
+        Some(input) => {
+            bytes.push(1);
+            bytes.extend_from_slice(&(input.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(input);
+        }
+        None => bytes.push(0),
+    }
+    bytes.extend_from_slice(&(prog.len() as u32).to_le_bytes());
+    for op in prog.ops() {
+        let token = TOKENS
+            .iter()
+            .position(|&x| x == op.token)
+            .expect("Programs only consist of instructions.");
+        bytes.push(token as u8);
+        bytes.push(op.multiplier);
+    }
+    if version >= CHECKSUM_VERSION {
+        let checksum = hash(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+    }
+    Ok(bytes)
+}
+
+/// Returns the version of the format of `bytes`, which is checked by `decode` as well.
+pub fn version(bytes: &[u8]) -> Result<u16, BytecodeError> {
+    if !is_bytecode(bytes) {
+        return Err(BytecodeError::NotBytecode);
+    }
+    let mut r = Reader {
+        bytes: &bytes[MAGIC.len()..],
+    };
+    match r.u16()? {
+        0 => Err(corrupt("version 0")),
+        version if version > VERSION => Err(BytecodeError::NewerVersion(version)),
+        version => Ok(version),
+    }
+}
+
+/// Deserialize a program from `bytes`, which are validated first: the magic number, the
+/// version, the checksum and the instructions.
+pub fn decode(bytes: &[u8]) -> Result<Program, BytecodeError> {
+    let version = version(bytes)?;
+    let mut content = bytes;
+    if version >= CHECKSUM_VERSION {
+        let at = bytes
+            .len()
+            .checked_sub(8)
+            .filter(|&at| at >= MAGIC.len() + 2)
+            .ok_or(BytecodeError::Truncated)?;
+        let mut stored = [0; 8];
+        stored.copy_from_slice(&bytes[at..]);
+        let (stored, actual) = (u64::from_le_bytes(stored), hash(&bytes[..at]));
+        if stored != actual {
+            return Err(BytecodeError::Checksum { stored, actual });
+        }
+        content = &bytes[..at];
+    }
+    let mut r = Reader {
+        bytes: &content[MAGIC.len() + 2..],
+    };
+    let opt_level = r.u8()?;
+    let dialect = match (r.u8()?, r.u32()?) {
+        (0, _) => Dialect::Standard,
+        (1, n) => Dialect::MultiTape(n as usize),
+        (2, _) => Dialect::Grid,
+        (3, _) => Dialect::Random,
+        (x, _) => return Err(corrupt(&format!("invalid dialect {}", x))),
+    };
+    let inline_input = match r.u8()? {
+        0 => None,
+        1 => {
+            let len = r.u32()? as usize;
+            Some(r.take(len)?.to_vec())
+        }
+        x => return Err(corrupt(&format!("invalid inline input flag {}", x))),
+    };
+    let len = r.u32()? as usize;
+    let mut instructions: InstructionStream = Vec::with_capacity(len.min(r.bytes.len() / 2));
+    let mut depth = 0usize;
+    for i in 0..len {
+        let (token, multiplier) = (r.u8()?, r.u8()?);
+        let token = *TOKENS
+            .get(token as usize)
+            .ok_or_else(|| corrupt(&format!("invalid token {} of instruction {}", token, i)))?;
+        if multiplier == 0 {
+            return Err(corrupt(&format!("instruction {} is repeated 0 times", i)));
+        }
+        match token {
+            Token::LoopBegin => depth += 1,
+            Token::LoopEnd if depth == 0 => {
+                return Err(corrupt(&format!("unmatched `]` at instruction {}", i)))
+            }
+            Token::LoopEnd => depth -= 1,
+            _ => {}
+        }
+        instructions.push(Instruction::new(i, token, multiplier));
+    }
+    if depth > 0 {
+        return Err(corrupt("unmatched `[`"));
+    }
+    if !r.bytes.is_empty() {
+        return Err(corrupt("trailing bytes after the instructions"));
+    }
+    let lexer = LexerOptions {
+        dialect,
+        ..LexerOptions::default()
+    };
+    Ok(Program::from_compiled(
+        instructions,
+        lexer,
+        inline_input,
+        opt_level,
+    ))
+}
+
+/// Write the bytecode of `prog` into `w`.
+pub fn write_to<W: Write>(prog: &Program, w: &mut W) -> io::Result<()> {
+    w.write_all(&encode(prog))
+}
+
+/// Read a program from the bytecode in `r`.
+pub fn read_from<R: Read>(mut r: R) -> Result<Program, BytecodeError> {
+    let mut bytes = vec![];
+    r.read_to_end(&mut bytes)?;
+    decode(&bytes)
+}
+
+/// Write the bytecode of `prog` to the file `path`.
+pub fn save<P: AsRef<Path>>(prog: &Program, path: P) -> io::Result<()> {
+    let mut f = File::create(path)?;
+    write_to(prog, &mut f)
+}
+
+/// Load a program from the bytecode file `path`.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Program, BytecodeError> {
+    read_from(File::open(path)?)
+}
+
+/// Reads the fields of a bytecode file
+struct Reader<'a> {
+    /// Bytes which are not read yet
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// Take the next `n` bytes.
+    fn take(&mut self, n: usize) -> Result<&'a [u8], BytecodeError> {
+        if self.bytes.len() < n {
+            return Err(BytecodeError::Truncated);
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, BytecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, BytecodeError> {
+        let mut buf = [0; 2];
+        buf.copy_from_slice(self.take(2)?);
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    fn u32(&mut self) -> Result<u32, BytecodeError> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(buf))
+    }
+}
+
+/// Construct a `BytecodeError::Corrupt`.
+fn corrupt(msg: &str) -> BytecodeError {
+    BytecodeError::Corrupt(msg.to_string())
+}
@@ -0,0 +1,274 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Cells
+//!
+//! Some teaching material and derived dialects assume signed or wider cells instead of bytes. A
+//! `CellMachine` runs programs on cells of any type implementing `Cell`: `u8`, `i8`, `i16` and
+//! `i32`. Arithmetic wraps around at the limits of the type, `.` writes the low byte of a cell
+//! and `,` stores the byte read as a value of the type.
+//!
+//! ```
+//! use bfrscc_core::cell::CellMachine;
+//! use bfrscc_core::machine::MachineExt;
+//! use bfrscc_core::program::Program;
+//!
+//! // 16 * 16 is 256, which is 0 in a byte but not in an `i16`.
+//! let prog: Program = "++++++++++++++++[>++++++++++++++++<-]>[>+<-]>>-".parse().unwrap();
+//! let mut machine: CellMachine<i16> = CellMachine::new();
+//! machine.run(&prog).unwrap();
+//! assert_eq!(machine.cells(), [0, 0, 256, -1]);
+//! ```
+
+use std::collections::{TryReserveError, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use machine::Machine;
+use newline::Newline;
+use stdio;
+
+/// Value of a cell
+pub trait Cell: Copy + Default + PartialEq + fmt::Debug + fmt::Display {
+    /// `self + n`, wrapping around
+    fn add(self, n: u8) -> Self;
+
+    /// `self - n`, wrapping around
+    fn sub(self, n: u8) -> Self;
+
+    /// Lowest byte, which is written by `.`
+    fn low_byte(self) -> u8;
+
+    /// Cell holding byte `b`, which was read by `,`
+    fn from_byte(b: u8) -> Self;
+
+    /// `self + n * factor`, wrapping around
+    fn add_times(self, n: Self, factor: i8) -> Self;
+}
+
+/// Implements `Cell` for an integer type.
+macro_rules! cell {
+    ($t:ty) => {
+        impl Cell for $t {
+            fn add(self, n: u8) -> Self {
+                self.wrapping_add(n as $t)
+            }
+
+            fn sub(self, n: u8) -> Self {
+                self.wrapping_sub(n as $t)
+            }
+
+            fn low_byte(self) -> u8 {
+                self as u8
+            }
+
+            fn from_byte(b: u8) -> Self {
+                b as $t
+            }
+
+            fn add_times(self, n: Self, factor: i8) -> Self {
+                self.wrapping_add(n.wrapping_mul(factor as $t))
+            }
+        }
+    };
+}
+
+cell!(u8);
+cell!(i8);
+cell!(i16);
+cell!(i32);
+
+/// Type of the cells of an execution
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum CellType {
+    /// Bytes, the usual cells
+    #[default]
+    U8,
+    /// Signed bytes
+    I8,
+    /// Signed 16 bit integers
+    I16,
+    /// Signed 32 bit integers
+    I32,
+}
+
+impl FromStr for CellType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "u8" => Ok(CellType::U8),
+            "i8" => Ok(CellType::I8),
+            "i16" => Ok(CellType::I16),
+            "i32" => Ok(CellType::I32),
+            _ => Err(format!("unsupported cell type `{}`, use u8, i8, i16 or i32", s)),
+        }
+    }
+}
+
+impl fmt::Display for CellType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CellType::U8 => write!(f, "u8"),
+            CellType::I8 => write!(f, "i8"),
+            CellType::I16 => write!(f, "i16"),
+            CellType::I32 => write!(f, "i32"),
+        }
+    }
+}
+
+/// A `Machine` with cells of type `C` on a tape which is infinite on both sides. Instructions of
+/// dialects other than `#` do nothing.
+#[derive(Debug, Clone)]
+pub struct CellMachine<C: Cell> {
+    /// Cells
+    cells: VecDeque<C>,
+    /// Position of the pointer within `cells`
+    pos: usize,
+    /// Translation of line endings in the output
+    newline: Newline,
+}
+
+impl<C: Cell> CellMachine<C> {
+    /// Machine with a single cell
+    pub fn new() -> Self {
+        CellMachine {
+            cells: ::std::iter::once(C::default()).collect(),
+            pos: 0,
+            newline: Newline::Raw,
+        }
+    }
+
+    /// Set how line endings are written.
+    pub fn set_newline(&mut self, newline: Newline) {
+        self.newline = newline;
+    }
+
+    /// Allocate memory for at least `n` cells, so the tape does not need to grow until then.
+    /// Fails if the memory cannot be allocated.
+    pub fn reserve(&mut self, n: usize) -> Result<(), TryReserveError> {
+        let len = self.cells.len();
+        self.cells.try_reserve(n.saturating_sub(len))
+    }
+
+    /// Return the cells of the tape.
+    pub fn cells(&self) -> Vec<C> {
+        self.cells.iter().cloned().collect()
+    }
+
+    /// Return the position of the pointer within `cells`.
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    /// Value of the current cell
+    pub fn cell(&self) -> C {
+        self.cells[self.pos]
+    }
+
+    /// Set the value of the current cell.
+    fn set(&mut self, val: C) {
+        self.cells[self.pos] = val;
+    }
+
+    /// The cell `offset` cells right of the pointer. The tape grows if necessary.
+    fn neighbour(&mut self, offset: isize) -> &mut C {
+        while (self.pos as isize) + offset < 0 {
+            self.cells.push_front(C::default());
+            self.pos += 1;
+        }
+        let idx = (self.pos as isize + offset) as usize;
+        while self.cells.len() <= idx {
+            self.cells.push_back(C::default());
+        }
+        &mut self.cells[idx]
+    }
+}
+
+impl<C: Cell> Default for CellMachine<C> {
+    fn default() -> Self {
+        CellMachine::new()
+    }
+}
+
+impl<C: Cell> Machine for CellMachine<C> {
+    fn left(&mut self, val: u8) {
+        let n = val as usize;
+        while self.pos < n {
+            self.cells.push_front(C::default());
+            self.pos += 1;
+        }
+        self.pos -= n;
+    }
+
+    fn right(&mut self, val: u8) {
+        self.pos += val as usize;
+        while self.cells.len() <= self.pos {
+            self.cells.push_back(C::default());
+        }
+    }
+
+    fn increase(&mut self, val: u8) {
+        let cell = self.cell().add(val);
+        self.set(cell);
+    }
+
+    fn decrease(&mut self, val: u8) {
+        let cell = self.cell().sub(val);
+        self.set(cell);
+    }
+
+    fn output(&mut self) {
+        self.newline.output(self.get_val(), stdio::write_byte);
+    }
+
+    fn input(&mut self, val: u8) {
+        self.set(C::from_byte(val));
+    }
+
+    fn get_val(&self) -> u8 {
+        self.cell().low_byte()
+    }
+
+    fn is_zero(&self) -> bool {
+        self.cell() == C::default()
+    }
+
+    fn clear(&mut self) {
+        self.set(C::default());
+    }
+
+    /// The factors are bytes, which wrap around. In cells wider than a byte, factors from 128
+    /// on are negative, e.g. `255` of `[->-<]` subtracts the current cell.
+    fn transfer(&mut self, effects: &[(isize, u8)]) {
+        let n = self.cell();
+        for &(offset, factor) in effects {
+            let cell = self.neighbour(offset);
+            *cell = cell.add_times(n, factor as i8);
+        }
+        self.clear();
+    }
+
+    fn tape_len(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn tape_capacity(&self) -> usize {
+        self.cells.capacity()
+    }
+
+    /// Print the pointer and the cells around it to stderr.
+    fn breakpoint(&mut self) {
+        let cells: Vec<String> = self.cells
+            .iter()
+            .enumerate()
+            .skip(self.pos.saturating_sub(8))
+            .take(17)
+            .map(|(i, x)| if i == self.pos { format!("[{}]", x) } else { x.to_string() })
+            .collect();
+        eprintln!("# pointer {}: {}", self.pos, cells.join(" "));
+    }
+}
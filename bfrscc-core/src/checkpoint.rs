@@ -0,0 +1,164 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Checkpoints
+//!
+//! Long runs can save their state at regular intervals (`RunConfig::checkpoint`) and be resumed
+//! from the last checkpoint after an interruption (`RunConfig::resume`). A checkpoint file is a
+//! `Snapshot` preceded by a header, whether the program was optimized and how many bytes were
+//! read and written so far.
+//!
+//! The input is read again from the start when resuming, and the bytes consumed before the
+//! checkpoint are skipped. Output written after the last checkpoint is written again.
+//!
+//! ```
+//! use bfrscc_core::checkpoint::Checkpoint;
+//! use bfrscc_core::snapshot::Snapshot;
+//!
+//! let snapshot = Snapshot::new("+[>,.<]", 2, 1, 40, vec![1, 7]);
+//! let checkpoint = Checkpoint::new(true, 10, 10, snapshot);
+//! let mut file = Vec::new();
+//! checkpoint.write_to(&mut file).unwrap();
+//! assert_eq!(Checkpoint::read_from(&file[..]).unwrap(), checkpoint);
+//! ```
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use snapshot::Snapshot;
+
+/// Header of a checkpoint file
+const MAGIC: &str = "bfrscc-checkpoint 1";
+
+/// When and where checkpoints are written
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpointing {
+    /// Time between two checkpoints
+    pub every: Duration,
+    /// File the checkpoints are written to. Every checkpoint replaces the previous one.
+    pub file: PathBuf,
+}
+
+/// State of an unfinished execution
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    /// Whether the program was optimized. The program counter refers to the compiled program.
+    pub optimized: bool,
+    /// Number of executed `,`
+    pub inputs_read: u64,
+    /// Number of executed `.`
+    pub outputs_written: u64,
+    /// State of the machine
+    pub snapshot: Snapshot,
+}
+
+impl Checkpoint {
+    /// Constructor
+    pub fn new(
+        optimized: bool,
+        inputs_read: u64,
+        outputs_written: u64,
+        snapshot: Snapshot,
+    ) -> Self {
+        Checkpoint {
+            optimized,
+            inputs_read,
+            outputs_written,
+            snapshot,
+        }
+    }
+
+    /// Serialize the checkpoint into `w`.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "{}", MAGIC)?;
+        writeln!(w, "optimized {}", self.optimized)?;
+        writeln!(w, "inputs_read {}", self.inputs_read)?;
+        writeln!(w, "outputs_written {}", self.outputs_written)?;
+        self.snapshot.write_to(w)
+    }
+
+    /// Deserialize a checkpoint from `r`.
+    pub fn read_from<R: Read>(r: R) -> io::Result<Self> {
+        let mut r = BufReader::new(r);
+        let mut line = String::new();
+        r.read_line(&mut line)?;
+        if line.trim_end() != MAGIC {
+            return Err(invalid("not a bfrscc checkpoint file"));
+        }
+        let optimized = field(&mut r, "optimized")?;
+        let inputs_read = field(&mut r, "inputs_read")?;
+        let outputs_written = field(&mut r, "outputs_written")?;
+        Ok(Checkpoint {
+            optimized,
+            inputs_read,
+            outputs_written,
+            snapshot: Snapshot::read_from(r)?,
+        })
+    }
+
+    /// Write the checkpoint to the file `path`. The checkpoint is written to a temporary file
+    /// next to `path` first, so an interruption while writing leaves the previous checkpoint
+    /// intact.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        {
+            let mut f = File::create(&tmp)?;
+            self.write_to(&mut f)?;
+            f.sync_all()?;
+        }
+        fs::rename(&tmp, path)
+    }
+
+    /// Load a checkpoint from the file `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Checkpoint::read_from(File::open(path)?)
+    }
+}
+
+/// Parse a duration such as `500ms`, `10s`, `5m` or `2h`. A number without unit is in seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let split = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+    let (value, unit) = s.split_at(split);
+    let value: f64 = value
+        .parse()
+        .map_err(|_| format!("invalid duration `{}`", s))?;
+    let factor = match unit {
+        "ms" => 0.001,
+        "" | "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        _ => {
+            return Err(format!(
+                "unknown unit `{}` in duration `{}`, use ms, s, m or h",
+                unit, s
+            ))
+        }
+    };
+    Ok(Duration::from_secs_f64(value * factor))
+}
+
+/// Read the line `<name> <value>` from `r`.
+fn field<R: BufRead, T: ::std::str::FromStr>(r: &mut R, name: &str) -> io::Result<T> {
+    let mut line = String::new();
+    r.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    if parts.next() != Some(name) {
+        return Err(invalid(&format!("missing field `{}`", name)));
+    }
+    parts
+        .next()
+        .and_then(|x| x.parse().ok())
+        .ok_or_else(|| invalid(&format!("invalid value of field `{}`", name)))
+}
+
+/// An `InvalidData` error with the given message
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
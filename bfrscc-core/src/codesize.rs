@@ -0,0 +1,171 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Size of generated code
+//!
+//! Attributes the code generated by a backend to the loops of the program (see
+//! `Backend::emit_mapped`), which shows the constructs dominating the output, e.g. when golfing
+//! a program or tuning the optimizer:
+//!
+//! ```
+//! use bfrscc_core::backend::C;
+//! use bfrscc_core::codesize::SizeReport;
+//!
+//! let prog = "++[->+<]>[[-]>++++[-<.>]<]".parse().unwrap();
+//! let report = SizeReport::new(&C::default(), &prog).unwrap();
+//! assert_eq!(report.loops.len(), 4);
+//! // The outer loop contains the clear and the print loop.
+//! let largest = report.largest(1);
+//! assert_eq!((largest[0].line, largest[0].col), (1, 10));
+//! assert_eq!(largest[0].instructions, 17);
+//! ```
+//!
+//! The sizes of loops include the loops nested in them. Code which belongs to no instruction,
+//! such as the runtime of the C backend, is only part of the total size.
+
+use std::cmp::Reverse;
+use std::fmt;
+use std::ops::Range;
+use backend::{Backend, BackendError};
+use diagnostics::line_col;
+use lexer::Token;
+use program::Program;
+
+/// Code generated for a loop of the program
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoopSize {
+    /// Index of the `[`
+    pub index: usize,
+    /// Line of the `[` in the source, starting at 1
+    pub line: usize,
+    /// Column of the `[` in the source, starting at 1
+    pub col: usize,
+    /// Nesting depth, 1 for loops which are not nested
+    pub depth: usize,
+    /// Number of instructions of the loop, including the brackets
+    pub instructions: usize,
+    /// Bytes of the generated code
+    pub bytes: usize,
+    /// Lines of the generated code
+    pub lines: usize,
+    /// Share of the code generated for all instructions of the program
+    pub share: f64,
+}
+
+impl fmt::Display for LoopSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:5.1}% {:>8} bytes {:>6} lines  {}:{}  loop of {} instructions at depth {}",
+            100.0 * self.share,
+            self.bytes,
+            self.lines,
+            self.line,
+            self.col,
+            self.instructions,
+            self.depth
+        )
+    }
+}
+
+/// Size of the code generated for a program
+#[derive(Debug, Clone, PartialEq)]
+pub struct SizeReport {
+    /// Name of the backend
+    pub backend: String,
+    /// Bytes of the generated code
+    pub bytes: usize,
+    /// Lines of the generated code
+    pub lines: usize,
+    /// Number of instructions of the program
+    pub instructions: usize,
+    /// Bytes of the code generated for the instructions, `None` if the backend does not
+    /// attribute its code to instructions, e.g. for precomputed programs
+    pub attributed: Option<usize>,
+    /// Loops in the order of the source, none if the code is not attributed
+    pub loops: Vec<LoopSize>,
+}
+
+impl SizeReport {
+    /// Translate `prog` with `backend` and measure the code.
+    pub fn new(backend: &dyn Backend, prog: &Program) -> Result<Self, BackendError> {
+        let (code, spans) = backend.emit_mapped(prog)?;
+        Ok(SizeReport::from_code(backend.name(), prog, &code, spans.as_deref()))
+    }
+
+    /// Measure `code`, generated for `prog` by backend `backend`. `spans` are the ranges of the
+    /// code generated for the instructions (see `Backend::emit_mapped`).
+    pub fn from_code(
+        backend: &str,
+        prog: &Program,
+        code: &str,
+        spans: Option<&[Range<usize>]>,
+    ) -> Self {
+        let instructions = prog.instructions();
+        let lines = |span: &Range<usize>| code[span.clone()].matches('\n').count();
+        let mut loops = vec![];
+        let mut attributed = None;
+        if let Some(spans) = spans {
+            let total = spans.iter().map(|x| x.len()).sum::<usize>();
+            let mut depth = 0;
+            for (index, instr) in instructions.iter().enumerate() {
+                match instr.token {
+                    Token::LoopBegin => depth += 1,
+                    Token::LoopEnd => depth -= 1,
+                    _ => {}
+                }
+                if instr.token != Token::LoopBegin {
+                    continue;
+                }
+                let body = &spans[index..=prog.jump(index)];
+                let bytes = body.iter().map(|x| x.len()).sum::<usize>();
+                let (line, col) = line_col(prog.source(), instr.position());
+                loops.push(LoopSize {
+                    index,
+                    line,
+                    col,
+                    depth,
+                    instructions: body.len(),
+                    bytes,
+                    lines: body.iter().map(&lines).sum(),
+                    share: bytes as f64 / total.max(1) as f64,
+                });
+            }
+            attributed = Some(total);
+        }
+        SizeReport {
+            backend: backend.to_string(),
+            bytes: code.len(),
+            lines: lines(&(0..code.len())),
+            instructions: instructions.len(),
+            attributed,
+            loops,
+        }
+    }
+
+    /// The at most `n` loops with the most code, largest first
+    pub fn largest(&self, n: usize) -> Vec<&LoopSize> {
+        let mut loops: Vec<&LoopSize> = self.loops.iter().collect();
+        loops.sort_by_key(|x| (Reverse(x.bytes), x.index));
+        loops.truncate(n);
+        loops
+    }
+}
+
+impl fmt::Display for SizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} bytes in {} lines of {} code for {} instructions",
+            self.bytes, self.lines, self.backend, self.instructions
+        )?;
+        match self.attributed {
+            Some(bytes) => write!(f, ", {} bytes of them for the instructions", bytes),
+            None => write!(f, ", not attributed to the instructions"),
+        }
+    }
+}
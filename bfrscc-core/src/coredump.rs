@@ -0,0 +1,85 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Core dump
+//!
+//! When an execution fails, the state of the machine is written to a core file which can be
+//! inspected afterwards with `bfrscc inspect <core>`. A core file is a `Snapshot` preceded by a
+//! header and the reason of the failure.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use snapshot::Snapshot;
+
+/// Header of a core file
+const MAGIC: &str = "bfrscc-core 1";
+
+/// State of a failed execution
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoreDump {
+    /// Why the execution failed
+    pub reason: String,
+    /// State of the machine at the time of the failure
+    pub snapshot: Snapshot,
+}
+
+impl CoreDump {
+    /// Constructor
+    pub fn new(reason: &str, snapshot: Snapshot) -> Self {
+        CoreDump {
+            reason: reason.to_string(),
+            snapshot,
+        }
+    }
+
+    /// Serialize the core dump into `w`.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "{}", MAGIC)?;
+        writeln!(w, "reason {}", self.reason)?;
+        self.snapshot.write_to(w)
+    }
+
+    /// Deserialize a core dump from `r`.
+    pub fn read_from<R: Read>(r: R) -> io::Result<Self> {
+        let mut r = BufReader::new(r);
+        let mut line = String::new();
+        r.read_line(&mut line)?;
+        if line.trim_end() != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a bfrscc core file",
+            ));
+        }
+        line.clear();
+        r.read_line(&mut line)?;
+        let reason = match line.trim_end() {
+            l if l.starts_with("reason") => l["reason".len()..].trim().to_string(),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "missing field `reason`",
+                ))
+            }
+        };
+        Ok(CoreDump {
+            reason,
+            snapshot: Snapshot::read_from(r)?,
+        })
+    }
+
+    /// Write the core dump to the file `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut f = File::create(path)?;
+        self.write_to(&mut f)
+    }
+
+    /// Load a core dump from the file `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        CoreDump::read_from(File::open(path)?)
+    }
+}
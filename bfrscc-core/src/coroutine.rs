@@ -0,0 +1,159 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Coroutine
+//!
+//! Executes a program in slices of a given number of instructions ("fuel"), which allows hosts
+//! such as game engines to interleave brainfuck execution with their own work without ever
+//! blocking:
+//!
+//! ```
+//! use bfrscc_core::coroutine::{Coroutine, Resume};
+//! use bfrscc_core::run::RunConfig;
+//!
+//! let mut co = Coroutine::new(",[.,]", &RunConfig::default()).unwrap();
+//! co.feed(b"hi");
+//! co.end_input();
+//! let mut output = vec![];
+//! loop {
+//!     match co.resume(100).unwrap() {
+//!         Resume::Yielded(out) => output.extend(out),
+//!         Resume::NeedsInput => unreachable!(),
+//!         Resume::Done => break,
+//!     }
+//! }
+//! assert_eq!(output, b"hi");
+//! ```
+
+use std::collections::VecDeque;
+use std::iter;
+use buffer::BufferMachine;
+use lexer::Token;
+use machine::Machine;
+use program::Program;
+use run::{compile, interpreter, step_with_arithmetic, RunConfig, RunError};
+
+/// Result of `Coroutine::resume`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resume {
+    /// The fuel is used up or output is pending. Contains the output produced since the last
+    /// call to `resume`.
+    Yielded(Vec<u8>),
+    /// The program waits for input, use `Coroutine::feed` or `Coroutine::end_input`.
+    NeedsInput,
+    /// The program has finished and all output has been handed out.
+    Done,
+}
+
+/// A program which can be executed piecewise
+pub struct Coroutine {
+    /// Compiled program
+    prog: Program,
+    /// Machine executing the program
+    machine: BufferMachine,
+    /// Input which has been fed but not read yet
+    input: VecDeque<u8>,
+    /// Set if no more input will be fed
+    input_ended: bool,
+    /// Index of the next instruction
+    idx: usize,
+    /// Number of executed instructions
+    steps: u64,
+    /// Limits of the execution. Input settings are ignored.
+    config: RunConfig,
+}
+
+impl Coroutine {
+    /// Compile `prog`. Of `config`, only the lexer, optimizer, tape and limit settings are used.
+    /// The grid dialect is not supported, `^` and `v` do nothing.
+    pub fn new(prog: &str, config: &RunConfig) -> Result<Self, RunError> {
+        let prog = compile(prog, config)?;
+        let inline = prog.inline_input().map(|x| x.to_vec());
+        let mut co = Coroutine {
+            machine: BufferMachine::with_interpreter(interpreter(&prog, config)?),
+            prog,
+            input: VecDeque::new(),
+            input_ended: false,
+            idx: 0,
+            steps: 0,
+            config: config.clone(),
+        };
+        if let Some(inline) = inline {
+            co.feed(&inline);
+            co.end_input();
+        }
+        Ok(co)
+    }
+
+    /// Provide input to be read by `,`.
+    pub fn feed(&mut self, input: &[u8]) {
+        self.input.extend(input);
+    }
+
+    /// Signal that no more input will be fed. Once the fed input is consumed, `,` reads `0`.
+    pub fn end_input(&mut self) {
+        self.input_ended = true;
+    }
+
+    /// Number of instructions executed so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Machine executing the program, for instance to look at the tape.
+    pub fn machine(&self) -> &BufferMachine {
+        &self.machine
+    }
+
+    /// Execute at most `fuel` instructions. Output is handed out with `Resume::Yielded` before
+    /// waiting for input or reporting that the program is done, so no output is lost.
+    pub fn resume(&mut self, fuel: u64) -> Result<Resume, RunError> {
+        for _ in 0..fuel {
+            let needs_input = match self.prog.instructions().get(self.idx) {
+                None => break,
+                Some(instr) => {
+                    instr.token == Token::Input && self.input.is_empty() && !self.input_ended
+                }
+            };
+            if needs_input {
+                break;
+            }
+            if let Some(max) = self.config.max_steps {
+                if self.steps >= max {
+                    return Err(RunError::StepLimit(max));
+                }
+            }
+            let input = &mut self.input;
+            step_with_arithmetic(
+                &self.prog,
+                &mut self.machine,
+                &mut iter::from_fn(|| input.pop_front()),
+                &mut self.idx,
+                self.config.arithmetic,
+            )?;
+            self.machine.status()?;
+            self.steps += 1;
+            if let Some(max) = self.config.max_tape {
+                if self.machine.tape_len() > max {
+                    return Err(RunError::TapeLimit(max));
+                }
+            }
+        }
+        let output = self.machine.take_output();
+        Ok(if !output.is_empty() {
+            Resume::Yielded(output)
+        } else if self.idx >= self.prog.len() {
+            Resume::Done
+        } else if self.prog.instructions()[self.idx].token == Token::Input && self.input.is_empty()
+            && !self.input_ended
+        {
+            Resume::NeedsInput
+        } else {
+            Resume::Yielded(output)
+        })
+    }
+}
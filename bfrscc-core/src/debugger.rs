@@ -0,0 +1,539 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Debugger
+//!
+//! A small command line interface to look at the state of an execution. It is used to browse
+//! core dumps post-mortem (`bfrscc inspect <core>`), to run programs step by step
+//! (`bfrscc debug <program>`) and to move back and forth through a recorded session
+//! (`bfrscc replay <recording>`, see `recording`).
+
+use std::io::{self, BufRead, Write};
+use std::vec;
+use buffer::BufferMachine;
+use interpreter::Interpreter;
+use lexer::Token;
+use program::{LoopKind, Program};
+use recording::{Frame, Recording, KEYFRAME_INTERVAL};
+use run::step;
+use snapshot::Snapshot;
+use tape::{DumpFormat, Tape, TapeView};
+
+/// Number of cells shown left and right of the pointer by default
+const TAPE_WINDOW: usize = 8;
+
+/// Number of characters of source shown left and right of the current instruction
+const CODE_WINDOW: usize = 30;
+
+/// Number of cells `set`, `fill` and `paste` may add to the end of the tape
+const MAX_GROWTH: usize = 1 << 20;
+
+/// Holds the state which is browsed by the debugger
+pub struct Debugger {
+    /// State of the machine
+    snapshot: Snapshot,
+    /// Parsed program
+    prog: Program,
+    /// Why the execution stopped, if known
+    reason: Option<String>,
+    /// Running execution, `None` when browsing a core dump
+    live: Option<Live>,
+    /// Pause as soon as the output ends with this pattern
+    break_output: Option<Vec<u8>>,
+    /// The session being recorded if running, the session being replayed otherwise
+    recording: Option<Recording>,
+}
+
+/// State of an execution which is controlled by the debugger
+struct Live {
+    /// Machine executing the program
+    machine: BufferMachine,
+    /// Remaining input
+    input: vec::IntoIter<u8>,
+    /// Output produced so far
+    output: Vec<u8>,
+}
+
+impl Debugger {
+    /// Constructor. The program of the snapshot needs to compile (see `Snapshot::compile`).
+    pub fn new(snapshot: Snapshot) -> Self {
+        let prog = snapshot.compile().expect("The program of a snapshot compiles.");
+        Debugger {
+            snapshot,
+            prog,
+            reason: None,
+            live: None,
+            break_output: None,
+            recording: None,
+        }
+    }
+
+    /// Debug a running execution of `prog`, which reads `input`. Execution starts paused at the
+    /// first instruction. The grid dialect is not supported, `^` and `v` do nothing.
+    pub fn live(prog: Program, input: Vec<u8>) -> Self {
+        let machine: BufferMachine = BufferMachine::with_interpreter(Interpreter::with_tapes(
+            prog.dialect().tapes(),
+        ));
+        let snapshot = Snapshot::new(prog.source(), 0, 0, 0, vec![0]);
+        Debugger {
+            snapshot,
+            prog,
+            reason: None,
+            live: Some(Live {
+                machine,
+                input: input.into_iter(),
+                output: vec![],
+            }),
+            break_output: None,
+            recording: None,
+        }
+    }
+
+    /// Browse the recorded session `recording` of `prog`, starting at its first step. `prog`
+    /// has to be compiled with the options of the session.
+    pub fn replay(prog: Program, recording: Recording) -> Self {
+        Debugger {
+            snapshot: recording.state_at(0),
+            prog,
+            reason: None,
+            live: None,
+            break_output: None,
+            recording: Some(recording),
+        }
+    }
+
+    /// Record the session of a running execution. `optimized` tells whether the program was
+    /// optimized.
+    pub fn record(mut self, optimized: bool) -> Self {
+        if self.live.is_some() {
+            let mut recording = Recording::new(optimized, self.snapshot.clone());
+            recording.positions = self.prog.instructions().iter().map(|x| x.position()).collect();
+            self.recording = Some(recording);
+        }
+        self
+    }
+
+    /// The recorded or replayed session
+    pub fn recording(&self) -> Option<&Recording> {
+        self.recording.as_ref()
+    }
+
+    /// Set the reason why the execution stopped.
+    pub fn reason(mut self, reason: &str) -> Self {
+        self.reason = Some(reason.to_string());
+        self
+    }
+
+    /// Position of the current instruction within the source.
+    fn source_pos(&self) -> Option<usize> {
+        self.prog.instructions().get(self.snapshot.pc).map(|x| x.position())
+    }
+
+    /// Read commands from `input` until `quit` or end of input and write responses to `out`.
+    ///
+    /// ```
+    /// use bfrscc_core::debugger::Debugger;
+    /// use bfrscc_core::snapshot::Snapshot;
+    ///
+    /// let mut debugger = Debugger::new(Snapshot::new("+.", 1, 0, 1, vec![1]));
+    /// let mut out = Vec::new();
+    /// debugger.repl(&b"fill 0..99999999999 1\nset 4 = 2\n"[..], &mut out).unwrap();
+    /// let out = String::from_utf8(out).unwrap();
+    /// assert!(out.contains("cells beyond 1048576 cannot be written"));
+    /// assert!(out.contains("1 cells written"));
+    /// ```
+    pub fn repl<R: BufRead, W: Write>(&mut self, input: R, out: &mut W) -> io::Result<()> {
+        self.info(out)?;
+        write!(out, "(bfrscc) ")?;
+        out.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.first().cloned() {
+                Some("q") | Some("quit") => return Ok(()),
+                Some("i") | Some("info") => self.info(out)?,
+                Some("t") | Some("tape") => {
+                    let args: Vec<usize> =
+                        words[1..].iter().filter_map(|x| x.parse().ok()).collect();
+                    match args.len() {
+                        0 => self.tape(out, None)?,
+                        2 => self.tape(out, Some((args[0], args[1])))?,
+                        _ => writeln!(out, "usage: tape [<from> <to>]")?,
+                    }
+                }
+                Some("l") | Some("list") => self.list(out)?,
+                Some("break-output") => {
+                    let arg = line.trim_start()["break-output".len()..].trim();
+                    self.break_output = if arg.is_empty() {
+                        None
+                    } else {
+                        Some(unquote(arg))
+                    };
+                    match self.break_output {
+                        Some(ref p) => writeln!(
+                            out,
+                            "pausing when the output ends with {:?}",
+                            String::from_utf8_lossy(p)
+                        )?,
+                        None => writeln!(out, "output breakpoint removed")?,
+                    }
+                }
+                Some("set") => self.set(out, &words[1..].join(" "))?,
+                Some("fill") => self.fill(out, &words[1..])?,
+                Some("paste") => {
+                    let arg = line.trim_start()["paste".len()..].trim();
+                    let pointer = self.snapshot.pointer;
+                    self.write_cells(out, pointer, &unquote(arg))?
+                }
+                Some("c") | Some("continue") => self.resume(out, None)?,
+                Some("b") | Some("back") => match words.get(1).map(|x| x.parse()) {
+                    None => self.back(out, 1)?,
+                    Some(Ok(n)) => self.back(out, n)?,
+                    Some(Err(_)) => writeln!(out, "usage: back [<n>]")?,
+                },
+                Some("goto") => match words.get(1).map(|x| x.parse()) {
+                    Some(Ok(n)) => self.seek(out, n)?,
+                    _ => writeln!(out, "usage: goto <step>")?,
+                },
+                Some("o") | Some("output") => self.output(out)?,
+                Some("s") | Some("step") => match words.get(1).map(|x| x.parse()) {
+                    None => self.resume(out, Some(1))?,
+                    Some(Ok(n)) => self.resume(out, Some(n))?,
+                    Some(Err(_)) => writeln!(out, "usage: step [<n>]")?,
+                },
+                Some("h") | Some("help") => self.help(out)?,
+                Some(cmd) => writeln!(out, "unknown command `{}`, try `help`", cmd)?,
+                None => {}
+            }
+            write!(out, "(bfrscc) ")?;
+            out.flush()?;
+        }
+        writeln!(out)
+    }
+
+    /// Execute `n` instructions, or until the end of the program if `n` is `None`. Execution
+    /// pauses early if the output matches the output breakpoint.
+    fn resume<W: Write>(&mut self, out: &mut W, n: Option<u64>) -> io::Result<()> {
+        let live = match (self.live.as_mut(), self.recording.as_ref()) {
+            (Some(live), _) => live,
+            (None, Some(recording)) => {
+                let end = recording.steps();
+                let steps = n.map_or(end, |n| self.snapshot.steps.saturating_add(n));
+                return self.seek(out, steps);
+            }
+            (None, None) => return writeln!(out, "not running, this is a core dump"),
+        };
+        let start = live.output.len();
+        let mut executed = 0;
+        self.reason = None;
+        while self.snapshot.pc < self.prog.len() && n.is_none_or(|n| executed < n) {
+            let op = self.prog.ops()[self.snapshot.pc];
+            let origin = live.machine.interpreter().tape().origin();
+            step(
+                &self.prog,
+                &mut live.machine,
+                &mut live.input,
+                &mut self.snapshot.pc,
+            );
+            executed += 1;
+            let new = live.machine.take_output();
+            if let Some(ref mut recording) = self.recording {
+                let interpreter = live.machine.interpreter();
+                let tape = interpreter.tape();
+                // After switching tapes, the origin is the one of another tape.
+                let grown = match op.token {
+                    Token::SwitchTape => 0,
+                    _ => tape.origin().saturating_sub(origin),
+                };
+                let mut frame = Frame::new(self.snapshot.pc, tape.pos(), tape.get())
+                    .grown(grown)
+                    .output(&new);
+                frame.input = op.token == Token::Input;
+                recording.push(frame);
+                // Switching tapes and transfer loops change more than the current cell.
+                let transfer = op.token == Token::LoopBegin && op.kind == LoopKind::Transfer;
+                if op.token == Token::SwitchTape
+                    || transfer
+                    || recording.steps() % KEYFRAME_INTERVAL == 0
+                {
+                    recording.keyframe(Snapshot::new(
+                        self.prog.source(),
+                        self.snapshot.pc,
+                        tape.pos(),
+                        0,
+                        tape.cells(),
+                    ));
+                }
+            }
+            if new.is_empty() {
+                continue;
+            }
+            live.output.extend(new);
+            if let Some(ref pattern) = self.break_output {
+                if live.output.ends_with(pattern) {
+                    self.reason = Some(format!(
+                        "output ends with {:?}",
+                        String::from_utf8_lossy(pattern)
+                    ));
+                    break;
+                }
+            }
+        }
+        let interpreter = live.machine.interpreter();
+        self.snapshot.pointer = interpreter.get_pos();
+        self.snapshot.steps += executed;
+        self.snapshot.tape = interpreter.tape().cells();
+        if self.snapshot.pc >= self.prog.len() {
+            self.reason = Some("program finished".to_string());
+        }
+        let new = &live.output[start..];
+        if !new.is_empty() {
+            out.write_all(new)?;
+            if !new.ends_with(b"\n") {
+                writeln!(out)?;
+            }
+        }
+        self.info(out)
+    }
+
+    /// Move `n` steps back in a replayed session.
+    fn back<W: Write>(&mut self, out: &mut W, n: u64) -> io::Result<()> {
+        if self.live.is_some() || self.recording.is_none() {
+            return writeln!(out, "not replaying a recording");
+        }
+        let steps = self.snapshot.steps.saturating_sub(n);
+        self.seek(out, steps)
+    }
+
+    /// Restore the state after `steps` steps of a replayed session. The output written on the
+    /// way is printed when moving forward.
+    fn seek<W: Write>(&mut self, out: &mut W, steps: u64) -> io::Result<()> {
+        let recording = match (self.live.as_ref(), self.recording.as_ref()) {
+            (None, Some(recording)) => recording,
+            _ => return writeln!(out, "not replaying a recording"),
+        };
+        let state = recording.state_at(steps);
+        let output = recording.output_until(state.steps);
+        let new = &output[recording.output_until(self.snapshot.steps).len().min(output.len())..];
+        if !new.is_empty() {
+            out.write_all(new)?;
+            if !new.ends_with(b"\n") {
+                writeln!(out)?;
+            }
+        }
+        self.reason = if state.steps == recording.steps() {
+            Some("end of recording".to_string())
+        } else {
+            None
+        };
+        self.snapshot = state;
+        self.info(out)
+    }
+
+    /// Print the output written so far.
+    fn output<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let output = match (self.live.as_ref(), self.recording.as_ref()) {
+            (Some(live), _) => live.output.clone(),
+            (None, Some(recording)) => recording.output_until(self.snapshot.steps),
+            (None, None) => return writeln!(out, "not running, this is a core dump"),
+        };
+        out.write_all(&output)?;
+        if !output.is_empty() && !output.ends_with(b"\n") {
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    /// `set <cell> = <value>`, the cell may be given by its name.
+    fn set<W: Write>(&mut self, out: &mut W, args: &str) -> io::Result<()> {
+        let args: Vec<&str> = args.split(|c: char| c == '=' || c.is_whitespace())
+            .filter(|x| !x.is_empty())
+            .collect();
+        let cell = args.first().and_then(|x| {
+            x.parse().ok().or_else(|| {
+                let idx = self.prog.cell_names().find(x)?;
+                if idx < 0 {
+                    None
+                } else {
+                    Some(idx as usize)
+                }
+            })
+        });
+        match (args.len(), cell, args.get(1).map(|x| x.parse())) {
+            (2, Some(idx), Some(Ok(val))) => self.write_cells(out, idx, &[val]),
+            _ => writeln!(out, "usage: set <cell> = <value>"),
+        }
+    }
+
+    /// `fill <from>..<to> <value>`, `to` is exclusive.
+    fn fill<W: Write>(&mut self, out: &mut W, args: &[&str]) -> io::Result<()> {
+        let range: Vec<Option<usize>> = args.first()
+            .map(|x| x.split("..").map(|x| x.parse().ok()).collect())
+            .unwrap_or_default();
+        match (range.as_slice(), args.get(1).map(|x| x.parse())) {
+            (&[Some(from), Some(to)], Some(Ok(val))) if from <= to && args.len() == 2 => {
+                if !self.writable(from, to - from) {
+                    return self.too_far(out);
+                }
+                self.write_cells(out, from, &vec![val; to - from])
+            }
+            _ => writeln!(out, "usage: fill <from>..<to> <value>"),
+        }
+    }
+
+    /// Write `vals` to the cells starting at `from`. The tape grows if necessary.
+    fn write_cells<W: Write>(&mut self, out: &mut W, from: usize, vals: &[u8]) -> io::Result<()> {
+        if !self.writable(from, vals.len()) {
+            return self.too_far(out);
+        }
+        let tape = &mut self.snapshot.tape;
+        if tape.len() < from + vals.len() {
+            tape.resize(from + vals.len(), 0);
+        }
+        tape[from..from + vals.len()].copy_from_slice(vals);
+        if let Some(ref mut live) = self.live {
+            let tape = live.machine.interpreter_mut().tape_mut();
+            for (i, &val) in vals.iter().enumerate() {
+                tape.set_cell(from + i, val);
+            }
+            if let Some(ref mut recording) = self.recording {
+                recording.keyframe(self.snapshot.clone());
+            }
+        }
+        writeln!(out, "{} cells written", vals.len())
+    }
+
+    /// Whether `n` cells starting at `from` may be written, which grows the tape by at most
+    /// `MAX_GROWTH` cells.
+    fn writable(&self, from: usize, n: usize) -> bool {
+        from.checked_add(n)
+            .is_some_and(|end| end <= self.snapshot.tape.len().saturating_add(MAX_GROWTH))
+    }
+
+    /// Report cells which may not be written (see `writable`).
+    fn too_far<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(
+            out,
+            "cells beyond {} cannot be written, the tape grows by at most {} cells",
+            self.snapshot.tape.len() + MAX_GROWTH - 1,
+            MAX_GROWTH
+        )
+    }
+
+    /// Print a summary of the state followed by the cells around the pointer.
+    pub fn print_state<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.info(out)?;
+        self.tape(out, None)
+    }
+
+    /// Print a summary of the state.
+    fn info<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        if let Some(ref reason) = self.reason {
+            writeln!(out, "stopped: {}", reason)?;
+        }
+        let span = self.prog.instructions().get(self.snapshot.pc).map(|x| x.span);
+        match span {
+            Some(span) => writeln!(
+                out,
+                "pc:      {} (source position {}, line {}, column {})",
+                self.snapshot.pc, span.position, span.line, span.col
+            )?,
+            None => writeln!(out, "pc:      {} (end of program)", self.snapshot.pc)?,
+        }
+        writeln!(out, "pointer: {}", self.snapshot.pointer)?;
+        writeln!(out, "steps:   {}", self.snapshot.steps)?;
+        writeln!(out, "cells:   {}", self.snapshot.tape.len())
+    }
+
+    /// Print the cells from `range` one per line, or the cells around the pointer on a single
+    /// line if no range is given.
+    fn tape<W: Write>(&self, out: &mut W, range: Option<(usize, usize)>) -> io::Result<()> {
+        let ptr = self.snapshot.pointer;
+        let (from, to) = match range {
+            Some(range) => range,
+            None => {
+                let view = TapeView::from_cells(&self.snapshot.tape, ptr)
+                    .with_names(self.prog.cell_names());
+                return writeln!(out, "{}", view.render(TAPE_WINDOW, DumpFormat::Dec));
+            }
+        };
+        let to = to.min(self.snapshot.tape.len().saturating_sub(1));
+        for (i, val) in self.snapshot.tape.iter().enumerate().take(to + 1).skip(from) {
+            let marker = if i == ptr { ">" } else { " " };
+            write!(out, "{} {:>6}: {:>3} {:?}", marker, i, val, *val as char)?;
+            match self.prog.cell_names().get(i as isize) {
+                Some(name) => writeln!(out, " {}", name)?,
+                None => writeln!(out)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Print the source around the current instruction.
+    fn list<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let src: Vec<char> = self.snapshot.program.chars().collect();
+        let pos = self.source_pos().unwrap_or(src.len());
+        let from = pos.saturating_sub(CODE_WINDOW);
+        let to = (pos + CODE_WINDOW).min(src.len());
+        let snippet: String = src[from..to]
+            .iter()
+            .map(|&c| if c.is_whitespace() { ' ' } else { c })
+            .collect();
+        writeln!(out, "{}", snippet)?;
+        writeln!(out, "{}^", " ".repeat(pos - from))
+    }
+
+    /// Print the available commands.
+    fn help<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "info            show pc, pointer and step count")?;
+        writeln!(out, "tape [<a> <b>]  show tape cells (around the pointer by default)")?;
+        writeln!(out, "list            show source around the current instruction")?;
+        writeln!(out, "set <c> = <v>   set cell <c> (an index or a name) to <v>")?;
+        writeln!(out, "fill <a>..<b> <v>")?;
+        writeln!(out, "                set cells <a> up to (excluding) <b> to <v>")?;
+        writeln!(out, "paste \"<s>\"     write <s> to the cells starting at the pointer")?;
+        if self.live.is_some() {
+            writeln!(out, "step [<n>]      execute one (or <n>) instructions")?;
+            writeln!(out, "continue        run until the end or an output breakpoint")?;
+            writeln!(out, "break-output [\"<s>\"]")?;
+            writeln!(out, "                pause when the output ends with <s> (remove if empty)")?;
+        } else if self.recording.is_some() {
+            writeln!(out, "step [<n>]      move one (or <n>) steps forward")?;
+            writeln!(out, "back [<n>]      move one (or <n>) steps back")?;
+            writeln!(out, "goto <n>        move to the state after <n> steps")?;
+            writeln!(out, "continue        move to the end of the recording")?;
+        }
+        if self.live.is_some() || self.recording.is_some() {
+            writeln!(out, "output          show the output written so far")?;
+        }
+        writeln!(out, "quit            leave the debugger")
+    }
+}
+
+/// Remove surrounding double quotes from `s` and resolve the escapes `\n`, `\t`, `\"` and `\\`.
+fn unquote(s: &str) -> Vec<u8> {
+    let s = if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+        &s[1..s.len() - 1]
+    } else {
+        s
+    };
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => out.push('\n'),
+            ('\\', Some('t')) => out.push('\t'),
+            ('\\', Some(e)) if e == '"' || e == '\\' => out.push(e),
+            _ => {
+                out.push(c);
+                continue;
+            }
+        }
+        chars.next();
+    }
+    out.into_bytes()
+}
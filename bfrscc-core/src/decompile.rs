@@ -0,0 +1,120 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Decompiler
+//!
+//! Lifts a program into pseudo-C to make it easier to understand. `p` is the pointer, cells are
+//! addressed relative to it (`p[1]` is the cell right of the current one). The idioms recognized
+//! by the optimizer (see `optimizer::idiom`) become assignments, `if`s and `if`-`else`s, all
+//! other loops become `while` loops.
+
+use lexer::Token;
+use optimizer::{idiom, matching, Idiom};
+use parser::Instruction;
+use program::Program;
+
+/// Decompile `prog`.
+pub fn decompile(prog: &Program) -> String {
+    let prog = if prog.opt_level() == 0 {
+        prog.clone().optimized()
+    } else {
+        prog.clone()
+    };
+    let mut out = String::new();
+    block(prog.instructions(), 0, &mut out);
+    out
+}
+
+/// Decompile `instructions`, indented by `depth` levels.
+fn block(instructions: &[Instruction], depth: usize, out: &mut String) {
+    let mut idx = 0;
+    while idx < instructions.len() {
+        let x = &instructions[idx];
+        let m = x.multiplier;
+        let stmt = match x.token {
+            Token::Increase => format!("p[0] += {};", m),
+            Token::Decrease => format!("p[0] -= {};", m),
+            Token::MoveRight => format!("p += {};", m),
+            Token::MoveLeft => format!("p -= {};", m),
+            Token::MoveUp => format!("p = up(p, {});", m),
+            Token::MoveDown => format!("p = down(p, {});", m),
+            Token::Output => "putchar(p[0]);".to_string(),
+            Token::Input => "p[0] = getchar();".to_string(),
+            Token::Random => "p[0] = rand();".to_string(),
+            Token::SwitchTape => "p = next_tape(p);".to_string(),
+            Token::Breakpoint => "breakpoint();".to_string(),
+            Token::LoopBegin => {
+                idx = lift(instructions, idx, depth, out) + 1;
+                continue;
+            }
+            Token::LoopEnd | Token::InputSeparator | Token::Comment => String::new(),
+        };
+        if !stmt.is_empty() {
+            line(out, depth, &stmt);
+        }
+        idx += 1;
+    }
+}
+
+/// Decompile the loop starting at index `idx`. Returns the index of its `]`.
+fn lift(instructions: &[Instruction], idx: usize, depth: usize, out: &mut String) -> usize {
+    match idiom(instructions, idx) {
+        Some((Idiom::Clear, end)) => {
+            line(out, depth, "p[0] = 0;");
+            end
+        }
+        Some((Idiom::Transfer(targets), end)) => {
+            for (offset, factor) in targets {
+                let stmt = match factor {
+                    1 => format!("p[{}] += p[0];", offset),
+                    255 => format!("p[{}] -= p[0];", offset),
+                    f if f > 128 => format!("p[{}] -= p[0] * {};", offset, f.wrapping_neg()),
+                    f => format!("p[{}] += p[0] * {};", offset, f),
+                };
+                line(out, depth, &stmt);
+            }
+            line(out, depth, "p[0] = 0;");
+            end
+        }
+        Some((Idiom::If, end)) => {
+            line(out, depth, "if (p[0]) {");
+            block(&instructions[idx + 1..end - 3], depth + 1, out);
+            line(out, depth + 1, "p[0] = 0;");
+            line(out, depth, "}");
+            end
+        }
+        // Both branches end at the flag, which is zero then.
+        Some((Idiom::IfElse(_), else_end)) => {
+            let end = matching(instructions, idx).expect("Brackets are matched.");
+            line(out, depth, "if (p[0]) {");
+            block(&instructions[idx + 1..end - 3], depth + 1, out);
+            line(out, depth + 1, "p[0] = 0;");
+            block(&instructions[end + 1..end + 2], depth + 1, out);
+            line(out, depth, "} else {");
+            block(&instructions[end + 1..end + 2], depth + 1, out);
+            block(&instructions[end + 3..else_end], depth + 1, out);
+            line(out, depth, "}");
+            else_end
+        }
+        None => {
+            let end = matching(instructions, idx).expect("Brackets are matched.");
+            line(out, depth, "while (p[0]) {");
+            block(&instructions[idx + 1..end], depth + 1, out);
+            line(out, depth, "}");
+            end
+        }
+    }
+}
+
+/// Append `stmt` indented by `depth` levels.
+fn line(out: &mut String, depth: usize, stmt: &str) {
+    for _ in 0..depth {
+        out.push_str("    ");
+    }
+    out.push_str(stmt);
+    out.push('\n');
+}
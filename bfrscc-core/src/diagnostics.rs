@@ -0,0 +1,352 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Diagnostics
+//!
+//! Renders errors and warnings together with the offending line of the source, similar to
+//! rustc:
+//!
+//! ```text
+//! error: unmatched `]`
+//!  --> hello.bf:1:8
+//!   |
+//! 1 | +[>+<-]]
+//!   |        ^ this `]` has no matching `[`
+//!   |
+//!   = note: the `[` at 1:2 is closed at 1:7
+//! ```
+
+use std::env;
+use std::io::{self, IsTerminal};
+use std::str::FromStr;
+use error::ParseError;
+use json::{self, Object};
+use lint::Warning;
+
+/// When to use colors
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ColorChoice {
+    /// If stderr is a terminal and `NO_COLOR` is not set
+    #[default]
+    Auto,
+    /// Always
+    Always,
+    /// Never
+    Never,
+}
+
+impl ColorChoice {
+    /// Returns `true` if diagnostics written to stderr should be colored.
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!("unknown color choice `{}`", s)),
+        }
+    }
+}
+
+/// How messages are written
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MessageFormat {
+    /// Rendered for humans
+    #[default]
+    Human,
+    /// One JSON object per line, for tools
+    Json,
+}
+
+impl FromStr for MessageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(MessageFormat::Human),
+            "json" => Ok(MessageFormat::Json),
+            _ => Err(format!("unknown message format `{}`", s)),
+        }
+    }
+}
+
+/// Severity of a diagnostic
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    /// The program is invalid
+    Error,
+    /// The program is valid but suspicious
+    Warning,
+}
+
+/// A message about a position of the source
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// Severity
+    pub severity: Severity,
+    /// Message, without the position
+    pub message: String,
+    /// Offending position, if any
+    pub position: Option<usize>,
+    /// Explanation shown below the caret
+    pub label: String,
+    /// Additional notes
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    /// Diagnostic of a parse error of `source`
+    pub fn error(e: &ParseError, source: &str) -> Self {
+        let brackets = Brackets::new(source);
+        let (message, label, notes) = match *e {
+            ParseError::UnmatchedLoopBegin(_) => (
+                "unmatched `[`".to_string(),
+                "this `[` is never closed",
+                vec![],
+            ),
+            ParseError::UnmatchedLoopEnd(pos) => (
+                "unmatched `]`".to_string(),
+                "this `]` has no matching `[`",
+                brackets.last_closed_before(pos).map_or(vec![], |(open, close)| {
+                    vec![format!(
+                        "the `[` at {} is closed at {}",
+                        brackets.location(open),
+                        brackets.location(close)
+                    )]
+                }),
+            ),
+            ParseError::UnexpectedChar(_, c) => (
+                format!("unexpected character {:?}", c),
+                "not a brainfuck instruction",
+                vec![],
+            ),
+            ParseError::ProgramTooLong(max) => (
+                format!("program is longer than {} characters", max),
+                "",
+                vec![],
+            ),
+            ParseError::NestingTooDeep(pos, max) => (
+                format!("loops nested deeper than {} levels", max),
+                "this loop exceeds the maximum depth",
+                brackets.matching(pos).map_or(vec![], |close| {
+                    vec![format!("the loop is closed at {}", brackets.location(close))]
+                }),
+            ),
+            ParseError::RepeatCountTooLarge(_, max) => (
+                format!("repeat count larger than {}", max),
+                "this count is too large",
+                vec![],
+            ),
+        };
+        Diagnostic {
+            severity: Severity::Error,
+            message,
+            position: e.position(),
+            label: label.to_string(),
+            notes,
+        }
+    }
+
+    /// Diagnostic of a lint warning of `source`
+    pub fn warning(w: &Warning, source: &str) -> Self {
+        let brackets = Brackets::new(source);
+        let (message, label, notes) = match *w {
+            Warning::EmptyLoop(_) => (
+                "empty loop".to_string(),
+                "never terminates once it is entered",
+                vec![],
+            ),
+            Warning::DeadLoop(pos) => (
+                "loop is never entered".to_string(),
+                "the current cell is always zero here",
+                brackets.last_closed_before(pos).map_or(vec![], |(_, close)| {
+                    vec![format!("the previous loop ends at {}", brackets.location(close))]
+                }),
+            ),
+            Warning::DialectChar(_, c) => (
+                format!("`{}` is ignored", c),
+                "only an instruction in a dialect",
+                vec![],
+            ),
+            Warning::UnboundedLoop(pos) => (
+                "pointer may leave the tape".to_string(),
+                "moves the pointer by an unknown amount",
+                brackets.matching(pos).map_or(vec![], |close| {
+                    vec![format!("the loop ends at {}", brackets.location(close))]
+                }),
+            ),
+            Warning::OutOfBounds(_, cell) => (
+                "pointer leaves the tape".to_string(),
+                "moves the pointer outside of the tape",
+                vec![format!("the pointer reaches cell {}", cell)],
+            ),
+        };
+        Diagnostic {
+            severity: Severity::Warning,
+            message,
+            position: Some(w.position()),
+            label: label.to_string(),
+            notes,
+        }
+    }
+
+    /// The diagnostic for `source` stored in `file` as a single line of JSON. Positions are
+    /// character positions, lines and columns start at 1.
+    pub fn to_json(&self, file: &str, source: &str) -> String {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let (line, col) = match self.position.map(|pos| line_col(source, pos)) {
+            Some((line, col)) => (Some(line as u64), Some(col as u64)),
+            None => (None, None),
+        };
+        Object::new()
+            .string("type", "diagnostic")
+            .string("severity", severity)
+            .string("message", &self.message)
+            .string("file", file)
+            .optional("position", self.position.map(|pos| pos as u64))
+            .optional("line", line)
+            .optional("column", col)
+            .string("label", &self.label)
+            .raw("notes", &json::array(self.notes.iter().map(|x| json::string(x))))
+            .finish()
+    }
+
+    /// Render the diagnostic for `source` stored in `file`, with colors if `color` is set.
+    pub fn render(&self, file: &str, source: &str, color: bool) -> String {
+        let paint = |code: &str, text: &str| {
+            if color {
+                format!("\x1b[{}m{}\x1b[0m", code, text)
+            } else {
+                text.to_string()
+            }
+        };
+        let (name, code) = match self.severity {
+            Severity::Error => ("error", "1;31"),
+            Severity::Warning => ("warning", "1;33"),
+        };
+        let mut out = format!(
+            "{}{}\n",
+            paint(code, name),
+            paint("1", &format!(": {}", self.message))
+        );
+        let pos = match self.position {
+            Some(pos) => pos,
+            None => {
+                out.push_str(&format!("{} {}\n", paint("1;34", " -->"), file));
+                return out;
+            }
+        };
+        let (line, col) = line_col(source, pos);
+        let text: String = source
+            .lines()
+            .nth(line - 1)
+            .unwrap_or("")
+            .chars()
+            .map(|c| if c == '\t' { ' ' } else { c })
+            .collect();
+        let width = line.to_string().len();
+        let gutter = paint("1;34", &format!("{} |", " ".repeat(width)));
+        out.push_str(&format!(
+            "{}{} {}:{}:{}\n",
+            " ".repeat(width),
+            paint("1;34", "-->"),
+            file,
+            line,
+            col
+        ));
+        out.push_str(&format!("{}\n", gutter));
+        out.push_str(&format!("{} {}\n", paint("1;34", &format!("{} |", line)), text));
+        out.push_str(&format!(
+            "{} {}{}\n",
+            gutter,
+            " ".repeat(col - 1),
+            paint(code, &format!("^ {}", self.label))
+        ));
+        for note in &self.notes {
+            out.push_str(&format!("{}\n", gutter));
+            out.push_str(&format!(
+                "{} {} {}\n",
+                " ".repeat(width),
+                paint("1;34", "="),
+                paint("1", &format!("note: {}", note))
+            ));
+        }
+        out
+    }
+}
+
+/// Line and column (both starting at 1) of character position `pos` of `source`
+pub fn line_col(source: &str, pos: usize) -> (usize, usize) {
+    let (mut line, mut col) = (1, 1);
+    for c in source.chars().take(pos) {
+        if c == '\n' {
+            line += 1;
+            col = 1;
+        } else {
+            col += 1;
+        }
+    }
+    (line, col)
+}
+
+/// Matched brackets of a source, also if some brackets are unmatched
+struct Brackets<'a> {
+    /// Source
+    source: &'a str,
+    /// Positions of matched pairs, ordered by the position of the `]`
+    pairs: Vec<(usize, usize)>,
+}
+
+impl<'a> Brackets<'a> {
+    /// Match the brackets of `source`.
+    fn new(source: &'a str) -> Self {
+        let mut stack = vec![];
+        let mut pairs = vec![];
+        for (pos, c) in source.chars().enumerate() {
+            match c {
+                '[' => stack.push(pos),
+                ']' => {
+                    if let Some(open) = stack.pop() {
+                        pairs.push((open, pos));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Brackets { source, pairs }
+    }
+
+    /// The pair closed last before `pos`
+    fn last_closed_before(&self, pos: usize) -> Option<(usize, usize)> {
+        self.pairs.iter().rev().find(|&&(_, close)| close < pos).cloned()
+    }
+
+    /// Position of the `]` matching the `[` at `pos`
+    fn matching(&self, pos: usize) -> Option<usize> {
+        self.pairs.iter().find(|&&(open, _)| open == pos).map(|&(_, close)| close)
+    }
+
+    /// `line:column` of `pos`
+    fn location(&self, pos: usize) -> String {
+        let (line, col) = line_col(self.source, pos);
+        format!("{}:{}", line, col)
+    }
+}
@@ -0,0 +1,95 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Disassembler
+//!
+//! Lists the packed instructions the run loop executes (see `Program::ops`), one per line: the
+//! address, a mnemonic with its operand, the jump target and the annotations of brackets
+//! (including the `LoopKind` of recognized loops), and the source spans the instruction
+//! originates from.
+//!
+//! ```text
+//! 0000  add    8                        0..8        ++++++++
+//! 0001  jz     0006  depth 1 balanced   8..9        [
+//! ```
+
+use std::fmt::Write;
+use lexer::Token;
+use program::{LoopKind, Program};
+
+/// Maximum number of characters of source shown per instruction
+const MAX_SOURCE: usize = 24;
+
+/// Disassemble `prog`.
+pub fn disassemble(prog: &Program) -> String {
+    let width = prog.len().saturating_sub(1).to_string().len().max(4);
+    let chars: Vec<char> = prog.source().chars().collect();
+    let mut out = String::new();
+    for (addr, op) in prog.ops().iter().enumerate() {
+        let (mnemonic, operand) = match op.token {
+            Token::Increase => ("add", op.multiplier.to_string()),
+            Token::Decrease => ("sub", op.multiplier.to_string()),
+            Token::MoveRight => ("right", op.multiplier.to_string()),
+            Token::MoveLeft => ("left", op.multiplier.to_string()),
+            Token::MoveUp => ("up", op.multiplier.to_string()),
+            Token::MoveDown => ("down", op.multiplier.to_string()),
+            Token::Output => ("out", String::new()),
+            Token::Input => ("in", String::new()),
+            Token::Breakpoint => ("brk", String::new()),
+            Token::SwitchTape => ("tape", String::new()),
+            Token::Random => ("rand", String::new()),
+            Token::LoopBegin | Token::LoopEnd => {
+                let mut operand = format!("{:0w$}", op.jump, w = width);
+                if op.token == Token::LoopBegin {
+                    write!(operand, "  depth {}", op.depth).unwrap();
+                }
+                if op.balanced {
+                    operand.push_str(" balanced");
+                }
+                if op.io {
+                    operand.push_str(" io");
+                }
+                operand.push_str(match op.kind {
+                    LoopKind::Loop => "",
+                    LoopKind::Clear => " clear",
+                    LoopKind::If => " if",
+                    LoopKind::IfElse => " if-else",
+                    LoopKind::Else => " else",
+                    LoopKind::Transfer => " transfer",
+                });
+                let mnemonic = if op.token == Token::LoopBegin { "jz" } else { "jnz" };
+                (mnemonic, operand)
+            }
+            Token::InputSeparator | Token::Comment => ("nop", String::new()),
+        };
+        let ranges = prog.spans(addr);
+        let spans = ranges
+            .iter()
+            .map(|s| format!("{}..{}", s.start, s.end))
+            .collect::<Vec<_>>()
+            .join(",");
+        let mut text: String = ranges
+            .iter()
+            .flat_map(|s| chars[s.start.min(chars.len())..s.end.min(chars.len())].iter())
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        if text.chars().count() > MAX_SOURCE {
+            text = text.chars().take(MAX_SOURCE - 3).collect::<String>() + "...";
+        }
+        let line = format!(
+            "{:0w$}  {:<6} {:<24} {:<11} {}",
+            addr,
+            mnemonic,
+            operand,
+            spans,
+            text,
+            w = width
+        );
+        writeln!(out, "{}", line.trim_end()).unwrap();
+    }
+    out
+}
@@ -0,0 +1,71 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Errors
+//!
+//! Errors found in the source of a program, by the lexer or the parser.
+
+use std::error::Error;
+use std::fmt;
+
+/// Errors found while lexing a program or validating its stream of tokens
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// `[` at the given position has no matching `]`
+    UnmatchedLoopBegin(usize),
+    /// `]` at the given position has no matching `[`
+    UnmatchedLoopEnd(usize),
+    /// Character at the given position is not allowed
+    UnexpectedChar(usize, char),
+    /// The program is longer than the given maximum number of characters
+    ProgramTooLong(usize),
+    /// `[` at the given position is nested deeper than the given maximum depth
+    NestingTooDeep(usize, usize),
+    /// The repeat count starting at the given position is larger than the given maximum
+    RepeatCountTooLarge(usize, usize),
+}
+
+impl ParseError {
+    /// Position in the source the error refers to, if any
+    pub fn position(&self) -> Option<usize> {
+        match *self {
+            ParseError::UnmatchedLoopBegin(pos)
+            | ParseError::UnmatchedLoopEnd(pos)
+            | ParseError::UnexpectedChar(pos, _)
+            | ParseError::NestingTooDeep(pos, _)
+            | ParseError::RepeatCountTooLarge(pos, _) => Some(pos),
+            ParseError::ProgramTooLong(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::UnmatchedLoopBegin(pos) => write!(f, "unmatched `[` at position {}", pos),
+            ParseError::UnmatchedLoopEnd(pos) => write!(f, "unmatched `]` at position {}", pos),
+            ParseError::UnexpectedChar(pos, c) => {
+                write!(f, "unexpected character {:?} at position {}", c, pos)
+            }
+            ParseError::ProgramTooLong(max) => {
+                write!(f, "program is longer than {} characters", max)
+            }
+            ParseError::NestingTooDeep(pos, max) => write!(
+                f,
+                "`[` at position {} is nested deeper than {} loops",
+                pos, max
+            ),
+            ParseError::RepeatCountTooLarge(pos, max) => write!(
+                f,
+                "repeat count at position {} is larger than {}",
+                pos, max
+            ),
+        }
+    }
+}
+
+impl Error for ParseError {}
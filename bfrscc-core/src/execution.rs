@@ -0,0 +1,197 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Execution events
+//!
+//! Runs a program as an iterator of events, for hosts which want to follow an execution
+//! without callbacks, channels or threads:
+//!
+//! ```
+//! use bfrscc_core::execution::{Event, Execution};
+//! use bfrscc_core::run::RunConfig;
+//!
+//! let config = RunConfig {
+//!     input: Some(b"hi".to_vec()),
+//!     ..RunConfig::default()
+//! };
+//! let mut exec = Execution::new(",[.,]", &config).unwrap();
+//! let mut output = vec![];
+//! for ev in exec.events() {
+//!     match ev {
+//!         Event::Output(b) => output.push(b),
+//!         Event::Finished(report) => assert_eq!(report.inputs_read, 3),
+//!         _ => {}
+//!     }
+//! }
+//! assert_eq!(output, b"hi");
+//! ```
+//!
+//! Every executed instruction is a `Event::Step`, followed by the bytes it wrote. Before a `,`
+//! reads, `Event::InputRequested` gives the host the chance to e.g. flush its output. The last
+//! event is `Event::Finished` or `Event::Failed`. Stopping the iteration pauses the execution,
+//! the next call of `Execution::events` continues it.
+
+use std::collections::VecDeque;
+use std::time::Instant;
+use buffer::BufferMachine;
+use lexer::Token;
+use machine::Machine;
+use program::Program;
+use run::{
+    compile, input_source, interpreter, step_with_arithmetic, RunConfig, RunError, RunReport,
+};
+use tape::TapeView;
+
+/// What happens during an execution
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// The instruction with the given index was executed. Loops which the run loop executes in
+    /// a single step are a single event.
+    Step {
+        /// Index of the instruction
+        pc: usize,
+    },
+    /// `.` wrote the given byte
+    Output(u8),
+    /// The next instruction is a `,`
+    InputRequested,
+    /// The program has finished
+    Finished(RunReport),
+    /// The execution failed
+    Failed(RunError),
+}
+
+/// A program which is executed as its events are consumed
+pub struct Execution {
+    /// Compiled program
+    prog: Program,
+    /// Machine executing the program
+    machine: BufferMachine,
+    /// Bytes read by `,`
+    input: Box<dyn Iterator<Item = u8>>,
+    /// Limits of the execution
+    config: RunConfig,
+    /// Index of the next instruction
+    idx: usize,
+    /// Statistics so far
+    report: RunReport,
+    /// Events which are due before the next instruction is executed
+    pending: VecDeque<Event>,
+    /// Set once `Event::InputRequested` was emitted for the next instruction
+    requested: bool,
+    /// Start of the execution, set by the first event
+    start: Option<Instant>,
+    /// Set once the last event was emitted
+    done: bool,
+}
+
+impl Execution {
+    /// Compile `prog`. `,` reads `config.input`, the inline input of `prog` or stdin like
+    /// `run::run_program`. The lexer, optimizer, tape, arithmetic and limit settings of `config`
+    /// are used as well. The grid dialect is not supported, `^` and `v` do nothing.
+    pub fn new(prog: &str, config: &RunConfig) -> Result<Self, RunError> {
+        Execution::from_program(compile(prog, config)?, config)
+    }
+
+    /// Execute the compiled program `prog` with the settings of `config` (see `Execution::new`).
+    pub fn from_program(prog: Program, config: &RunConfig) -> Result<Self, RunError> {
+        Ok(Execution {
+            machine: BufferMachine::with_interpreter(interpreter(&prog, config)?),
+            input: input_source(config, &prog),
+            config: config.clone(),
+            idx: 0,
+            report: RunReport::default(),
+            pending: VecDeque::new(),
+            requested: false,
+            start: None,
+            done: false,
+            prog,
+        })
+    }
+
+    /// Events of the rest of the execution
+    pub fn events(&mut self) -> Events<'_> {
+        Events { exec: self }
+    }
+
+    /// Machine executing the program, for instance to look at the tape.
+    pub fn machine(&self) -> &BufferMachine {
+        &self.machine
+    }
+
+    /// Execute the next instruction and return its event.
+    fn advance(&mut self) -> Result<Event, RunError> {
+        let start = *self.start.get_or_insert_with(Instant::now);
+        let token = match self.prog.instructions().get(self.idx) {
+            Some(instr) => instr.token,
+            None => {
+                self.done = true;
+                let mut report = self.report.clone();
+                report.tape_extent = self.machine.tape_len();
+                report.tape_capacity = self.machine.tape_capacity();
+                report.wall_time = start.elapsed();
+                report.tape = Some(TapeView::new(self.machine.interpreter().tape()));
+                return Ok(Event::Finished(report));
+            }
+        };
+        if token == Token::Input && !self.requested {
+            self.requested = true;
+            return Ok(Event::InputRequested);
+        }
+        self.requested = false;
+        if let Some(max) = self.config.max_steps {
+            if self.report.steps >= max {
+                return Err(RunError::StepLimit(max));
+            }
+        }
+        let pc = self.idx;
+        step_with_arithmetic(
+            &self.prog,
+            &mut self.machine,
+            &mut *self.input,
+            &mut self.idx,
+            self.config.arithmetic,
+        )?;
+        self.machine.status()?;
+        if let Some(max) = self.config.max_tape {
+            if self.machine.tape_len() > max {
+                return Err(RunError::TapeLimit(max));
+            }
+        }
+        self.report.steps += 1;
+        if token == Token::Input {
+            self.report.inputs_read += 1;
+        }
+        let output = self.machine.take_output();
+        self.report.outputs_written += output.len() as u64;
+        self.pending.extend(output.into_iter().map(Event::Output));
+        Ok(Event::Step { pc })
+    }
+}
+
+/// Iterator over the events of an `Execution`
+pub struct Events<'a> {
+    /// Execution which is advanced
+    exec: &'a mut Execution,
+}
+
+impl<'a> Iterator for Events<'a> {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        if let Some(event) = self.exec.pending.pop_front() {
+            return Some(event);
+        }
+        if self.exec.done {
+            return None;
+        }
+        Some(self.exec.advance().unwrap_or_else(|e| {
+            self.exec.done = true;
+            Event::Failed(e)
+        }))
+    }
+}
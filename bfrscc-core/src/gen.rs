@@ -0,0 +1,168 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Program generator
+//!
+//! Generates random valid programs for property tests, e.g. to check that a backend or an
+//! optimization behaves like the interpreter. Generated programs always terminate: the body of
+//! every loop returns to the cell of the loop, never touches it otherwise and decrements it at
+//! the end.
+//!
+//! ```
+//! use bfrscc_core::gen::{GenConfig, Program};
+//! use bfrscc_core::machine::MachineExt;
+//! use bfrscc_core::run::RunConfig;
+//!
+//! for seed in 0..20 {
+//!     let prog = Program::arbitrary(&GenConfig { seed, ..GenConfig::default() });
+//!     let config = RunConfig {
+//!         input: Some(b"input".to_vec()),
+//!         ..RunConfig::default()
+//!     };
+//!     let mut plain = prog.spawn_machine();
+//!     plain.run_with(&prog, &config).unwrap();
+//!     let optimized = prog.clone().optimized();
+//!     let mut machine = optimized.spawn_machine();
+//!     machine.run_with(&optimized, &config).unwrap();
+//!     assert_eq!(plain.take_output(), machine.take_output(), "{}", prog.source());
+//! }
+//! ```
+
+pub use program::Program;
+use rng::Rng;
+
+/// Settings of the generator
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenConfig {
+    /// Seed of the random number generator, the same seed yields the same program
+    pub seed: u64,
+    /// Approximate number of instructions
+    pub length: usize,
+    /// Maximum number of nested loops
+    pub max_depth: usize,
+    /// Share of `.` and `,` among the instructions, between `0` and `1`
+    pub io_density: f64,
+}
+
+impl Default for GenConfig {
+    fn default() -> Self {
+        GenConfig {
+            seed: 0,
+            length: 64,
+            max_depth: 3,
+            io_density: 0.1,
+        }
+    }
+}
+
+impl Program {
+    /// Random program according to `config` (see `arbitrary_source`)
+    pub fn arbitrary(config: &GenConfig) -> Self {
+        Program::new(&arbitrary_source(config))
+    }
+}
+
+/// Source of a random program according to `config`. Loops are at most `config.max_depth`
+/// deep, so a program executes at most about `256^max_depth * length` instructions.
+pub fn arbitrary_source(config: &GenConfig) -> String {
+    let mut gen = Generator {
+        rng: Rng::new(config.seed),
+        config,
+        out: String::new(),
+        pos: 0,
+        loops: vec![],
+        budget: config.length,
+    };
+    gen.block();
+    gen.out
+}
+
+/// State of the generator
+struct Generator<'a> {
+    /// Source of randomness
+    rng: Rng,
+    /// Settings
+    config: &'a GenConfig,
+    /// Generated source
+    out: String,
+    /// Offset of the current cell from the first one
+    pos: isize,
+    /// Offsets of the cells of the enclosing loops, which must not be changed
+    loops: Vec<isize>,
+    /// Number of instructions left
+    budget: usize,
+}
+
+impl<'a> Generator<'a> {
+    /// Random number below `n`
+    fn below(&mut self, n: usize) -> usize {
+        (self.rng.next_u64() % n as u64) as usize
+    }
+
+    /// Returns `true` with probability `p`.
+    fn chance(&mut self, p: f64) -> bool {
+        ((self.rng.next_u64() >> 11) as f64) < p * (1u64 << 53) as f64
+    }
+
+    /// Emit `c` `n` times.
+    fn emit(&mut self, c: char, n: usize) {
+        self.out.extend(::std::iter::repeat_n(c, n));
+        self.budget = self.budget.saturating_sub(n);
+    }
+
+    /// Move the pointer to the cell at offset `to`.
+    fn move_to(&mut self, to: isize) {
+        let c = if to < self.pos { '<' } else { '>' };
+        let n = (to - self.pos).unsigned_abs();
+        self.emit(c, n);
+        self.pos = to;
+    }
+
+    /// Generate instructions until the budget is used up or the block ends by chance.
+    fn block(&mut self) {
+        while self.budget > 0 && (self.loops.is_empty() || self.below(8) != 0) {
+            let free = !self.loops.contains(&self.pos);
+            if self.chance(self.config.io_density) {
+                if free && self.chance(0.5) {
+                    self.emit(',', 1);
+                } else {
+                    self.emit('.', 1);
+                }
+                continue;
+            }
+            match self.below(6) {
+                0 if free => {
+                    let n = 1 + self.below(8);
+                    self.emit('+', n);
+                }
+                1 if free => {
+                    let n = 1 + self.below(8);
+                    self.emit('-', n);
+                }
+                2 => {
+                    let to = self.pos - 1 - self.below(3) as isize;
+                    self.move_to(to);
+                }
+                3 => {
+                    let to = self.pos + 1 + self.below(3) as isize;
+                    self.move_to(to);
+                }
+                4 | 5 if free && self.loops.len() < self.config.max_depth => {
+                    let cell = self.pos;
+                    self.emit('[', 1);
+                    self.loops.push(cell);
+                    self.block();
+                    self.loops.pop();
+                    self.move_to(cell);
+                    self.emit('-', 1);
+                    self.emit(']', 1);
+                }
+                _ => {}
+            }
+        }
+    }
+}
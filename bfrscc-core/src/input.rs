@@ -0,0 +1,133 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Input providers
+//!
+//! An `InputProvider` is asked for a byte every time a program executes `,`, so a host can
+//! generate the input lazily, e.g. from the state of a game:
+//!
+//! ```
+//! use bfrscc_core::program::Program;
+//! use bfrscc_core::run::{run_with_input, RunConfig};
+//!
+//! let prog: Program = ",.,.".parse().unwrap();
+//! let mut machine = prog.spawn_machine();
+//! let mut next = b'a';
+//! let mut provider = || {
+//!     next += 1;
+//!     Some(next)
+//! };
+//! run_with_input(&prog, &mut machine, &RunConfig::default(), &mut provider).unwrap();
+//! assert_eq!(machine.take_output(), b"bc");
+//! ```
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read, Stderr, Write};
+
+/// Source of the bytes read by `,`
+pub trait InputProvider {
+    /// The next byte, `None` at the end of the input
+    fn next_byte(&mut self) -> Option<u8>;
+}
+
+/// Callbacks provide a byte per call.
+impl<F: FnMut() -> Option<u8>> InputProvider for F {
+    fn next_byte(&mut self) -> Option<u8> {
+        self()
+    }
+}
+
+/// A slice provides its bytes, the slice shrinks while it is read.
+impl InputProvider for &[u8] {
+    fn next_byte(&mut self) -> Option<u8> {
+        let (&first, rest) = self.split_first()?;
+        *self = rest;
+        Some(first)
+    }
+}
+
+/// Provides the items of an iterator
+#[derive(Debug, Clone)]
+pub struct IterInput<I>(pub I);
+
+impl<I: Iterator<Item = u8>> InputProvider for IterInput<I> {
+    fn next_byte(&mut self) -> Option<u8> {
+        self.0.next()
+    }
+}
+
+/// Provides the bytes of a reader. Read errors are treated like the end of the input.
+#[derive(Debug)]
+pub struct ReaderInput<R: Read> {
+    /// Buffered reader
+    reader: BufReader<R>,
+}
+
+impl<R: Read> ReaderInput<R> {
+    /// Read from `reader`.
+    pub fn new(reader: R) -> Self {
+        ReaderInput {
+            reader: BufReader::new(reader),
+        }
+    }
+}
+
+impl<R: Read> InputProvider for ReaderInput<R> {
+    fn next_byte(&mut self) -> Option<u8> {
+        let mut byte = [0];
+        match self.reader.read(&mut byte) {
+            Ok(1) => Some(byte[0]),
+            _ => None,
+        }
+    }
+}
+
+/// Asks for a line whenever the program reads and all previous lines are used up. The line is
+/// provided including its line break.
+#[derive(Debug)]
+pub struct Prompt<R: BufRead, W: Write> {
+    /// Text written before every line is read
+    prompt: String,
+    /// Where the lines are read from
+    reader: R,
+    /// Where the prompt is written to
+    writer: W,
+    /// Rest of the current line
+    line: VecDeque<u8>,
+}
+
+impl Prompt<io::StdinLock<'static>, Stderr> {
+    /// Prompt on stderr and read from stdin.
+    pub fn stdin(prompt: &str) -> Self {
+        Prompt::new(prompt, io::stdin().lock(), io::stderr())
+    }
+}
+
+impl<R: BufRead, W: Write> Prompt<R, W> {
+    /// Write `prompt` to `writer` and read lines from `reader`.
+    pub fn new(prompt: &str, reader: R, writer: W) -> Self {
+        Prompt {
+            prompt: prompt.to_string(),
+            reader,
+            writer,
+            line: VecDeque::new(),
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> InputProvider for Prompt<R, W> {
+    fn next_byte(&mut self) -> Option<u8> {
+        if self.line.is_empty() {
+            write!(self.writer, "{}", self.prompt).ok()?;
+            self.writer.flush().ok()?;
+            let mut line = vec![];
+            self.reader.read_until(b'\n', &mut line).ok()?;
+            self.line.extend(line);
+        }
+        self.line.pop_front()
+    }
+}
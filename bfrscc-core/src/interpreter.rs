@@ -0,0 +1,291 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Interpreter
+
+use std::collections::VecDeque;
+use std::num::Wrapping;
+use machine::Machine;
+use newline::Newline;
+use rng::Rng;
+use lexer::Token;
+use program::Op;
+use run::{RunError, Underflow};
+use stdio;
+use tape::{LinearTape, Tape};
+
+/// Holds the tapes which are currently not in use (multi-tape dialect). Switching tapes rotates
+/// through them.
+#[derive(Default)]
+struct Tapes<T: Tape> {
+    /// Parked tapes, the next tape to be used is at the front
+    parked: VecDeque<T>,
+}
+
+impl<T: Tape> Tapes<T> {
+    /// Create `n` parked tapes.
+    fn new(n: usize) -> Self {
+        Tapes {
+            parked: (0..n).map(|_| T::default()).collect(),
+        }
+    }
+
+    /// Park `tape` and return the next tape. Returns `tape` if there are no parked tapes.
+    fn switch(&mut self, tape: T) -> T {
+        self.parked.push_back(tape);
+        self.parked.pop_front().unwrap()
+    }
+}
+
+/// Holds the state of the interpreter
+pub struct Interpreter<T: Tape = LinearTape> {
+    /// Tape
+    tape: T,
+    /// Other tapes
+    tapes: Tapes<T>,
+    /// Translation of line endings in the output
+    newline: Newline,
+    /// Source of `?` (random dialect)
+    rng: Rng,
+    /// What moving left of the first cell does
+    underflow: Underflow,
+    /// The pointer was moved left of the first cell with `Underflow::Error`
+    underflowed: bool,
+}
+
+impl Interpreter {
+    /// Constructor
+    pub fn new() -> Self {
+        Interpreter::with_tapes(1)
+    }
+
+    /// Return the current tape.
+    pub fn get_tape(&self) -> VecDeque<Wrapping<u8>> {
+        self.tape.get_cells().clone()
+    }
+}
+
+impl<T: Tape> Interpreter<T> {
+    /// Create an interpreter with `n` tapes (at least one) for the multi-tape dialect.
+    pub fn with_tapes(n: usize) -> Self {
+        Interpreter {
+            tape: T::default(),
+            tapes: Tapes::new(n.saturating_sub(1)),
+            newline: Newline::Raw,
+            rng: Rng::default(),
+            underflow: Underflow::Grow,
+            underflowed: false,
+        }
+    }
+
+    /// Seed the random number generator of `?`. Without a seed, the sequence of random bytes is
+    /// the same as with seed `0`.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    /// Set how line endings are written.
+    pub fn set_newline(&mut self, newline: Newline) {
+        self.newline = newline;
+    }
+
+    /// Pass the value of the current cell to `write`, as `.` does, translating line endings.
+    /// Machines which send the output elsewhere than stdout write it through this method.
+    pub fn output_with<F: FnMut(u8)>(&self, write: F) {
+        self.newline.output(self.tape.get(), write);
+    }
+
+    /// Set what moving left of the first cell does. The position of the pointer is taken as the
+    /// index of the cell, so this only makes sense for linear tapes. With `Underflow::Wrap`, tapes
+    /// of fixed size are resized to the size of the circle.
+    pub fn set_underflow(&mut self, underflow: Underflow) {
+        self.underflow = underflow;
+        if let Underflow::Wrap(n) = underflow {
+            self.tape.set_size(n);
+            self.tapes.parked.iter_mut().for_each(|x| x.set_size(n));
+        }
+    }
+
+    /// Return the current position of the pointer.
+    pub fn get_pos(&self) -> usize {
+        self.tape.pos()
+    }
+
+    /// Return the tape which is currently in use.
+    pub fn tape(&self) -> &T {
+        &self.tape
+    }
+
+    /// Return the tape which is currently in use for modification.
+    pub fn tape_mut(&mut self) -> &mut T {
+        &mut self.tape
+    }
+
+    /// Move the pointer by `offset` cells according to the underflow policy.
+    fn shift(&mut self, offset: isize) {
+        match self.underflow {
+            Underflow::Grow => move_by(&mut self.tape, offset),
+            Underflow::Wrap(size) => {
+                let pos = self.tape.pos() as isize;
+                move_by(&mut self.tape, (pos + offset).rem_euclid(size as isize) - pos);
+            }
+            Underflow::Error => {
+                if self.tape.coordinates().0 + (offset as i64) < 0 {
+                    self.underflowed = true;
+                } else {
+                    move_by(&mut self.tape, offset);
+                }
+            }
+        }
+    }
+
+    /// Execute `ops`, a run of instructions on the current cell (see `Machine::exec_run`), and
+    /// pass the bytes written by `.` to `write` like `output_with`. The cell stays in a local
+    /// until the end of the run, instead of going through the tape for every instruction.
+    pub fn exec_run_with<F: FnMut(u8)>(
+        &mut self,
+        ops: &[Op],
+        input: &mut dyn Iterator<Item = u8>,
+        mut write: F,
+    ) {
+        let mut val = self.tape.get();
+        for op in ops {
+            match op.token {
+                Token::Increase => val = val.wrapping_add(op.multiplier),
+                Token::Decrease => val = val.wrapping_sub(op.multiplier),
+                Token::Output => self.newline.output(val, &mut write),
+                Token::Input => val = input.next().unwrap_or(0),
+                _ => unreachable!("Runs only operate on the current cell."),
+            }
+        }
+        self.tape.set(val);
+    }
+}
+
+impl<T: Tape> Machine for Interpreter<T> {
+    fn left(&mut self, val: u8) {
+        self.shift(-(val as isize));
+    }
+
+    fn right(&mut self, val: u8) {
+        self.shift(val as isize);
+    }
+
+    fn up(&mut self, val: u8) {
+        self.tape.up(val as usize);
+    }
+
+    fn down(&mut self, val: u8) {
+        self.tape.down(val as usize);
+    }
+
+    fn increase(&mut self, val: u8) {
+        let val = self.tape.get().wrapping_add(val);
+        self.tape.set(val);
+    }
+
+    fn decrease(&mut self, val: u8) {
+        let val = self.tape.get().wrapping_sub(val);
+        self.tape.set(val);
+    }
+
+    fn clear(&mut self) {
+        self.tape.set(0);
+    }
+
+    fn transfer(&mut self, effects: &[(isize, u8)]) {
+        let val = self.tape.get();
+        // Fail before touching any cell, so the pointer ends up where the transfer started.
+        let leftmost = effects.iter().map(|&(offset, _)| offset).min().unwrap_or(0);
+        if self.underflow == Underflow::Error && self.tape.coordinates().0 + (leftmost as i64) < 0 {
+            self.underflowed = true;
+            return;
+        }
+        let mut pos = 0;
+        for &(offset, factor) in effects {
+            self.shift(offset - pos);
+            let cell = self.tape.get().wrapping_add(val.wrapping_mul(factor));
+            self.tape.set(cell);
+            pos = offset;
+        }
+        self.shift(-pos);
+        self.tape.set(0);
+    }
+
+    fn get_val(&self) -> u8 {
+        self.tape.get()
+    }
+
+    fn output(&mut self) {
+        self.output_with(stdio::write_byte);
+    }
+
+    fn input(&mut self, val: u8) {
+        self.tape.set(val);
+    }
+
+    fn exec_run(&mut self, ops: &[Op], input: &mut dyn Iterator<Item = u8>) -> bool {
+        self.exec_run_with(ops, input, stdio::write_byte);
+        true
+    }
+
+    fn tape_len(&self) -> usize {
+        self.tape.allocated()
+    }
+
+    fn tape_capacity(&self) -> usize {
+        self.tape.capacity()
+    }
+
+    fn random(&mut self) {
+        let val = self.rng.next_u8();
+        self.tape.set(val);
+    }
+
+    fn switch_tape(&mut self) {
+        let tape = ::std::mem::take(&mut self.tape);
+        self.tape = self.tapes.switch(tape);
+    }
+
+    fn status(&self) -> Result<(), RunError> {
+        if self.underflowed {
+            Err(RunError::Underflow(None))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Print the pointer and the cells around it to stderr.
+    fn breakpoint(&mut self) {
+        let pos = self.tape.pos();
+        let cells: Vec<String> = self.tape
+            .cells()
+            .iter()
+            .enumerate()
+            .skip(pos.saturating_sub(8))
+            .take(17)
+            .map(|(i, x)| if i == pos { format!("[{}]", x) } else { x.to_string() })
+            .collect();
+        eprintln!("# pointer {}: {}", pos, cells.join(" "));
+    }
+}
+
+impl Default for Interpreter {
+    /// Default
+    fn default() -> Self {
+        Interpreter::new()
+    }
+}
+
+/// Move the pointer of `tape` by `offset` cells, to the right if it is positive.
+fn move_by<T: Tape>(tape: &mut T, offset: isize) {
+    if offset < 0 {
+        tape.left(offset.unsigned_abs());
+    } else {
+        tape.right(offset as usize);
+    }
+}
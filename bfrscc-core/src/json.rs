@@ -0,0 +1,325 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # JSON
+//!
+//! Just enough JSON to write the machine-readable messages of `--message-format json`: flat
+//! objects of strings, numbers and nested values, written on a single line. `parse` reads them
+//! back, e.g. the run reports given to `bfrscc repro`.
+
+use std::fmt::Write;
+
+/// Builder of a JSON object
+#[derive(Debug, Clone, Default)]
+pub struct Object {
+    /// Members written so far, without the braces
+    members: String,
+}
+
+impl Object {
+    /// Empty object
+    pub fn new() -> Self {
+        Object::default()
+    }
+
+    /// Add member `key` with string value `value`.
+    pub fn string(self, key: &str, value: &str) -> Self {
+        let value = string(value);
+        self.raw(key, &value)
+    }
+
+    /// Add member `key` with numeric value `value`.
+    pub fn number<T: Into<f64>>(self, key: &str, value: T) -> Self {
+        let value = value.into();
+        if value.is_finite() {
+            self.raw(key, &value.to_string())
+        } else {
+            self.raw(key, "null")
+        }
+    }
+
+    /// Add member `key` with integer value `value`.
+    pub fn integer(self, key: &str, value: u64) -> Self {
+        self.raw(key, &value.to_string())
+    }
+
+    /// Add member `key`, `null` if `value` is `None`.
+    pub fn optional(self, key: &str, value: Option<u64>) -> Self {
+        match value {
+            Some(value) => self.integer(key, value),
+            None => self.raw(key, "null"),
+        }
+    }
+
+    /// Add member `key` with `value`, which must already be valid JSON.
+    pub fn raw(mut self, key: &str, value: &str) -> Self {
+        if !self.members.is_empty() {
+            self.members.push(',');
+        }
+        write!(self.members, "{}:{}", string(key), value).unwrap();
+        self
+    }
+
+    /// The object as JSON
+    pub fn finish(self) -> String {
+        format!("{{{}}}", self.members)
+    }
+}
+
+/// `items`, which must already be valid JSON, as an array
+pub fn array<I: IntoIterator<Item = String>>(items: I) -> String {
+    format!("[{}]", items.into_iter().collect::<Vec<_>>().join(","))
+}
+
+/// `s` as a JSON string, quoted and escaped
+pub fn string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Parsed JSON value. Numbers keep their text, so that large integers stay exact.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// `null`
+    Null,
+    /// `true` or `false`
+    Bool(bool),
+    /// Number as written
+    Number(String),
+    /// String, unescaped
+    Str(String),
+    /// Array
+    Array(Vec<Value>),
+    /// Object with its members in order
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Member `key` of an object
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match *self {
+            Value::Object(ref members) => members.iter().find(|x| x.0 == key).map(|x| &x.1),
+            _ => None,
+        }
+    }
+
+    /// Content of a string
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::Str(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Value of a non-negative integer
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Value::Number(ref n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Value of a boolean
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    /// Elements of an array
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match *self {
+            Value::Array(ref items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+/// Parse a JSON document.
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        chars: text.chars().collect(),
+        pos: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    match parser.peek() {
+        None => Ok(value),
+        Some(c) => Err(parser.error(&format!("unexpected `{}` after the value", c))),
+    }
+}
+
+/// State of `parse`
+struct Parser {
+    /// Characters of the document
+    chars: Vec<char>,
+    /// Index of the next character
+    pos: usize,
+}
+
+impl Parser {
+    /// Error at the current position
+    fn error(&self, msg: &str) -> String {
+        format!("invalid JSON at character {}: {}", self.pos, msg)
+    }
+
+    /// Next character, without consuming it
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    /// Consume whitespace.
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// Consume `c` after optional whitespace.
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", c)))
+        }
+    }
+
+    /// Consume the keyword `word` and return `value`.
+    fn keyword(&mut self, word: &str, value: Value) -> Result<Value, String> {
+        for c in word.chars() {
+            if self.peek() != Some(c) {
+                return Err(self.error(&format!("expected `{}`", word)));
+            }
+            self.pos += 1;
+        }
+        Ok(value)
+    }
+
+    /// Parse any value.
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('n') => self.keyword("null", Value::Null),
+            Some('t') => self.keyword("true", Value::Bool(true)),
+            Some('f') => self.keyword("false", Value::Bool(false)),
+            Some('"') => self.string().map(Value::Str),
+            Some('[') => {
+                self.pos += 1;
+                let mut items = vec![];
+                self.skip_whitespace();
+                if self.peek() == Some(']') {
+                    self.pos += 1;
+                    return Ok(Value::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(',') => self.pos += 1,
+                        _ => break,
+                    }
+                }
+                self.expect(']')?;
+                Ok(Value::Array(items))
+            }
+            Some('{') => {
+                self.pos += 1;
+                let mut members = vec![];
+                self.skip_whitespace();
+                if self.peek() == Some('}') {
+                    self.pos += 1;
+                    return Ok(Value::Object(members));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.expect(':')?;
+                    members.push((key, self.value()?));
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(',') => self.pos += 1,
+                        _ => break,
+                    }
+                }
+                self.expect('}')?;
+                Ok(Value::Object(members))
+            }
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let start = self.pos;
+                while self
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_digit() || "+-.eE".contains(c))
+                {
+                    self.pos += 1;
+                }
+                let number: String = self.chars[start..self.pos].iter().collect();
+                match number.parse::<f64>() {
+                    Ok(_) => Ok(Value::Number(number)),
+                    Err(_) => Err(self.error(&format!("invalid number `{}`", number))),
+                }
+            }
+            Some(c) => Err(self.error(&format!("unexpected `{}`", c))),
+            None => Err(self.error("unexpected end")),
+        }
+    }
+
+    /// Parse a string, starting at its opening quote.
+    fn string(&mut self) -> Result<String, String> {
+        if self.peek() != Some('"') {
+            return Err(self.error("expected a string"));
+        }
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            let c = self.peek().ok_or_else(|| self.error("unterminated string"))?;
+            self.pos += 1;
+            match c {
+                '"' => return Ok(s),
+                '\\' => {
+                    let c = self.peek().ok_or_else(|| self.error("unterminated string"))?;
+                    self.pos += 1;
+                    s.push(match c {
+                        '"' => '"',
+                        '\\' => '\\',
+                        '/' => '/',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => {
+                            let hex: String = self.chars.iter().skip(self.pos).take(4).collect();
+                            self.pos += 4;
+                            u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(::std::char::from_u32)
+                                .ok_or_else(|| self.error("invalid escape sequence"))?
+                        }
+                        _ => return Err(self.error("invalid escape sequence")),
+                    });
+                }
+                c => s.push(c),
+            }
+        }
+    }
+}
@@ -0,0 +1,407 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Lexer
+
+use std::fmt;
+use error::ParseError;
+use logging::{span, Level};
+
+/// This type represents a vector of tuples, where each tuple holds a token and its location
+/// within a brainfuck program.
+pub type TokenStream = Vec<(Span, Token)>;
+
+/// Location of a character within the source of a program
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    /// Position in characters, which is what instructions and errors refer to
+    pub position: usize,
+    /// Offset in bytes
+    pub offset: usize,
+    /// Line, starting at 1
+    pub line: usize,
+    /// Column in characters, starting at 1
+    pub col: usize,
+    /// Length in bytes
+    pub len: usize,
+}
+
+impl Span {
+    /// Span of the character at `position` of a source which consists of a single line of ASCII
+    /// characters, e.g. canonical brainfuck
+    pub fn at(position: usize) -> Self {
+        Span {
+            position,
+            offset: position,
+            line: 1,
+            col: position + 1,
+            len: 1,
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    /// Writes `line:column`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+/// Span of the next character while lexing
+struct Cursor(Span);
+
+impl Cursor {
+    /// Cursor at the start of a source
+    fn new() -> Self {
+        Cursor(Span {
+            line: 1,
+            col: 1,
+            ..Span::default()
+        })
+    }
+
+    /// Span of the next character `c`, which is `len` bytes long. Moves past it.
+    #[inline]
+    fn advance(&mut self, c: char, len: usize) -> Span {
+        let span = Span { len, ..self.0 };
+        self.0.position += 1;
+        self.0.offset += len;
+        if c == '\n' {
+            self.0.line += 1;
+            self.0.col = 1;
+        } else {
+            self.0.col += 1;
+        }
+        span
+    }
+}
+
+/// Defines all possible Tokens allowed in a brainfuck program
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Token {
+    /// '+'
+    Increase,
+    /// '-'
+    Decrease,
+    /// '<'
+    MoveLeft,
+    /// '>'
+    MoveRight,
+    /// '['
+    LoopBegin,
+    /// ']'
+    LoopEnd,
+    /// ','
+    Input,
+    /// '.'
+    Output,
+    /// '#' (only with `CommentPolicy::Dialect`)
+    Breakpoint,
+    /// '!' (only with `CommentPolicy::Dialect`)
+    InputSeparator,
+    /// '~' (only with `Dialect::MultiTape`)
+    SwitchTape,
+    /// '^' (only with `Dialect::Grid`)
+    MoveUp,
+    /// 'v' (only with `Dialect::Grid`)
+    MoveDown,
+    /// '?' (only with `Dialect::Random`)
+    Random,
+    /// anything else
+    Comment,
+}
+
+impl fmt::Display for Token {
+    /// Writes the brainfuck character of the token. Comments are written as nothing.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let c = match *self {
+            Token::Increase => "+",
+            Token::Decrease => "-",
+            Token::MoveLeft => "<",
+            Token::MoveRight => ">",
+            Token::LoopBegin => "[",
+            Token::LoopEnd => "]",
+            Token::Input => ",",
+            Token::Output => ".",
+            Token::Breakpoint => "#",
+            Token::InputSeparator => "!",
+            Token::SwitchTape => "~",
+            Token::MoveUp => "^",
+            Token::MoveDown => "v",
+            Token::Random => "?",
+            Token::Comment => "",
+        };
+        write!(f, "{}", c)
+    }
+}
+
+/// Defines how characters which are not brainfuck instructions are treated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommentPolicy {
+    /// Everything else is a comment
+    Ignore,
+    /// Everything else except whitespace is an error
+    Error,
+    /// `#` is a breakpoint and `!` separates the program from its input, everything else is a
+    /// comment
+    Dialect,
+}
+
+/// Brainfuck variants with additional instructions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dialect {
+    /// Plain brainfuck
+    Standard,
+    /// The given number of tapes, `~` switches to the next tape (and from the last one back to
+    /// the first one)
+    MultiTape(usize),
+    /// Experimental: the tape is a two dimensional grid, `^` and `v` move the pointer up and
+    /// down
+    Grid,
+    /// `?` writes a random byte to the current cell
+    Random,
+}
+
+impl Dialect {
+    /// Number of tapes a machine needs for this dialect
+    pub fn tapes(&self) -> usize {
+        match *self {
+            Dialect::MultiTape(n) => n.max(1),
+            _ => 1,
+        }
+    }
+}
+
+/// Limits which protect against pathological programs, e.g. from untrusted sources
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Limits {
+    /// Maximum number of characters of the program
+    pub max_length: Option<usize>,
+    /// Maximum number of nested loops
+    pub max_depth: Option<usize>,
+}
+
+/// Options of the lexer
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexerOptions {
+    /// How to treat characters which are not brainfuck instructions
+    pub comments: CommentPolicy,
+    /// Which additional instructions are recognized
+    pub dialect: Dialect,
+    /// Limits of the program size and complexity
+    pub limits: Limits,
+    /// Strings which stand for a brainfuck character, e.g. `("👍", '+')`. Aliases are replaced
+    /// before lexing, the longest matching alias wins.
+    pub aliases: Vec<(String, char)>,
+    /// A decimal number of at most `parser::MAX_REPEAT_COUNT` before an instruction repeats it,
+    /// e.g. `64+` (see `parser::parser_with_counts`)
+    pub repeat_counts: bool,
+}
+
+impl Default for LexerOptions {
+    fn default() -> Self {
+        LexerOptions {
+            comments: CommentPolicy::Ignore,
+            dialect: Dialect::Standard,
+            limits: Limits::default(),
+            aliases: vec![],
+            repeat_counts: false,
+        }
+    }
+}
+
+/// The lexer takes a brainfuck program and returns a stream of tokens
+pub fn lexer(prog: &str) -> TokenStream {
+    lexer_with_options(prog, &LexerOptions::default()).expect("Ignoring comments cannot fail.")
+}
+
+/// Like `lexer`, but treats characters which are not brainfuck instructions according to
+/// `options` and checks the length of the program.
+pub fn lexer_with_options(prog: &str, options: &LexerOptions) -> Result<TokenStream, ParseError> {
+    let _span = span("lexer");
+    if let Some(max) = options.limits.max_length {
+        if prog.len() > max && prog.chars().count() > max {
+            return Err(ParseError::ProgramTooLong(max));
+        }
+    }
+    let tokens = if options.aliases.is_empty() {
+        tokens_utf8(prog, options)?
+    } else {
+        tokens_with_aliases(&prog.chars().collect::<Vec<_>>(), char::len_utf8, options)?
+    };
+    log_msg!(
+        Level::Info,
+        "lexer: {} tokens, {} instructions",
+        tokens.len(),
+        tokens.iter().filter(|(_, x)| *x != Token::Comment).count()
+    );
+    Ok(tokens)
+}
+
+/// Like `lexer`, but for programs which are not valid UTF-8. Every byte is one character, bytes
+/// which are not ASCII are comments. Positions are byte offsets.
+pub fn lexer_bytes(prog: &[u8]) -> TokenStream {
+    lexer_bytes_with_options(prog, &LexerOptions::default())
+        .expect("Ignoring comments cannot fail.")
+}
+
+/// Like `lexer_with_options`, but for programs which are not valid UTF-8 (see `lexer_bytes`).
+pub fn lexer_bytes_with_options(
+    prog: &[u8],
+    options: &LexerOptions,
+) -> Result<TokenStream, ParseError> {
+    let _span = span("lexer");
+    if let Some(max) = options.limits.max_length {
+        if prog.len() > max {
+            return Err(ParseError::ProgramTooLong(max));
+        }
+    }
+    if options.aliases.is_empty() {
+        let classes = ByteClasses::new(options);
+        let mut cursor = Cursor::new();
+        return prog
+            .iter()
+            .map(|&b| {
+                let span = cursor.advance(b as char, 1);
+                Ok((span, classes.token(span.position, b)?))
+            })
+            .collect();
+    }
+    let chars: Vec<char> = prog.iter().map(|&b| b as char).collect();
+    tokens_with_aliases(&chars, |_| 1, options)
+}
+
+/// Lex `prog` without aliases. ASCII bytes are looked up in a `ByteClasses` table, only the
+/// first byte of other characters needs to be decoded.
+fn tokens_utf8(prog: &str, options: &LexerOptions) -> Result<TokenStream, ParseError> {
+    let classes = ByteClasses::new(options);
+    let bytes = prog.as_bytes();
+    let mut tokens = Vec::with_capacity(bytes.len());
+    let mut cursor = Cursor::new();
+    for (i, &b) in bytes.iter().enumerate() {
+        if b < 0x80 {
+            let span = cursor.advance(b as char, 1);
+            tokens.push((span, classes.token(span.position, b)?));
+        } else if b >= 0xC0 {
+            let c = prog[i..].chars().next().unwrap_or_default();
+            let span = cursor.advance(c, c.len_utf8());
+            tokens.push((span, token(span.position, c, options)?.1));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Lex `chars`, replacing the aliases of `options`. The characters of an alias after the first
+/// one are comments, so there still is one token per character. `len` is the number of bytes of
+/// a character in the source.
+fn tokens_with_aliases(
+    chars: &[char],
+    len: fn(char) -> usize,
+    options: &LexerOptions,
+) -> Result<TokenStream, ParseError> {
+    let mut aliases: Vec<(Vec<char>, char)> = options
+        .aliases
+        .iter()
+        .filter(|(alias, _)| !alias.is_empty())
+        .map(|(alias, c)| (alias.chars().collect(), *c))
+        .collect();
+    aliases.sort_by_key(|(alias, _)| ::std::cmp::Reverse(alias.len()));
+    let mut tokens = Vec::with_capacity(chars.len());
+    let mut cursor = Cursor::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match aliases.iter().find(|(alias, _)| chars[i..].starts_with(alias)) {
+            Some((alias, c)) => {
+                let span = cursor.advance(chars[i], len(chars[i]));
+                tokens.push((span, token(i, *c, options)?.1));
+                for &x in &chars[i + 1..i + alias.len()] {
+                    tokens.push((cursor.advance(x, len(x)), Token::Comment));
+                }
+                i += alias.len();
+            }
+            None => {
+                let span = cursor.advance(chars[i], len(chars[i]));
+                tokens.push((span, token(i, chars[i], options)?.1));
+                i += 1;
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Source of a program stored as `bytes`. Invalid UTF-8 (e.g. binary data in comments) is
+/// replaced by `U+FFFD`, which is a comment, instead of failing.
+pub fn source_from_bytes(bytes: Vec<u8>) -> String {
+    String::from_utf8(bytes)
+        .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
+}
+
+/// Token of character `c` at position `pos`, according to `options`. Aliases are not replaced.
+pub fn token(pos: usize, c: char, options: &LexerOptions) -> Result<(usize, Token), ParseError> {
+    let token = match (c, options.comments) {
+        ('~', _) if matches!(options.dialect, Dialect::MultiTape(_)) => Token::SwitchTape,
+        ('^', _) if options.dialect == Dialect::Grid => Token::MoveUp,
+        ('v', _) if options.dialect == Dialect::Grid => Token::MoveDown,
+        ('?', _) if options.dialect == Dialect::Random => Token::Random,
+        ('+', _) => Token::Increase,
+        ('-', _) => Token::Decrease,
+        ('<', _) => Token::MoveLeft,
+        ('>', _) => Token::MoveRight,
+        (',', _) => Token::Input,
+        ('.', _) => Token::Output,
+        ('[', _) => Token::LoopBegin,
+        (']', _) => Token::LoopEnd,
+        ('#', CommentPolicy::Dialect) => Token::Breakpoint,
+        ('!', CommentPolicy::Dialect) => Token::InputSeparator,
+        (c, _) if options.repeat_counts && c.is_ascii_digit() => Token::Comment,
+        (c, CommentPolicy::Error) if !c.is_whitespace() => {
+            return Err(ParseError::UnexpectedChar(pos, c))
+        }
+        _ => Token::Comment,
+    };
+    Ok((pos, token))
+}
+
+/// Tokens of all 256 bytes according to some `LexerOptions`, so lexing a byte is a table lookup
+/// instead of a `match` on the character. Bytes are characters as in `lexer_bytes`.
+#[derive(Debug, Clone)]
+pub struct ByteClasses {
+    /// Token of every byte, `None` for unexpected characters
+    tokens: [Option<Token>; 256],
+}
+
+impl ByteClasses {
+    /// Classify all bytes according to `options`. Aliases are not taken into account.
+    pub fn new(options: &LexerOptions) -> Self {
+        let mut tokens = [None; 256];
+        for (b, t) in tokens.iter_mut().enumerate() {
+            *t = token(b, b as u8 as char, options).ok().map(|(_, t)| t);
+        }
+        ByteClasses { tokens }
+    }
+
+    /// Token of byte `b` at position `pos`
+    #[inline]
+    pub fn token(&self, pos: usize, b: u8) -> Result<Token, ParseError> {
+        self.tokens[b as usize].ok_or(ParseError::UnexpectedChar(pos, b as char))
+    }
+}
+
+/// Split `prog` at the first `!` which is not inside of a loop into the program and its inline
+/// input. Returns `None` as input if there is no such `!`.
+pub fn split_input(prog: &str) -> (&str, Option<&str>) {
+    let mut depth = 0usize;
+    for (i, c) in prog.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth = depth.saturating_sub(1),
+            '!' if depth == 0 => return (&prog[..i], Some(&prog[i + 1..])),
+            _ => {}
+        }
+    }
+    (prog, None)
+}
@@ -0,0 +1,99 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/// Todo
+#[macro_use]
+pub mod logging;
+#[cfg(feature = "interpreter")]
+pub mod actor;
+#[cfg(feature = "interpreter")]
+pub mod adapter;
+pub mod alias;
+pub mod annotations;
+#[cfg(feature = "interpreter")]
+pub mod asm;
+#[cfg(feature = "backends-c")]
+pub mod backend;
+#[cfg(feature = "interpreter")]
+pub mod batch;
+pub mod brackets;
+#[cfg(feature = "interpreter")]
+pub mod buffer;
+#[cfg(feature = "interpreter")]
+pub mod bytecode;
+#[cfg(feature = "interpreter")]
+pub mod cell;
+#[cfg(feature = "interpreter")]
+pub mod checkpoint;
+#[cfg(feature = "backends-c")]
+pub mod codesize;
+#[cfg(feature = "interpreter")]
+pub mod coredump;
+#[cfg(feature = "interpreter")]
+pub mod coroutine;
+#[cfg(feature = "debugger")]
+pub mod debugger;
+pub mod decompile;
+pub mod diagnostics;
+pub mod disasm;
+pub mod error;
+#[cfg(feature = "interpreter")]
+pub mod execution;
+#[cfg(feature = "interpreter")]
+pub mod gen;
+#[cfg(feature = "interpreter")]
+pub mod input;
+#[cfg(feature = "interpreter")]
+pub mod interpreter;
+pub mod json;
+pub mod lexer;
+pub mod lint;
+pub mod loops;
+pub mod parser;
+pub mod preprocess;
+pub mod program;
+#[cfg(feature = "interpreter")]
+pub mod provenance;
+#[cfg(feature = "debugger")]
+pub mod recording;
+#[cfg(feature = "interpreter")]
+pub mod repl;
+#[cfg(feature = "interpreter")]
+pub mod repro;
+pub mod rng;
+#[cfg(feature = "interpreter")]
+pub mod slice;
+#[cfg(feature = "interpreter")]
+pub mod machine;
+pub mod meta;
+pub mod mmap;
+#[cfg(feature = "interpreter")]
+pub mod mutate;
+pub mod newline;
+pub mod optimizer;
+pub mod perf;
+#[cfg(feature = "interpreter")]
+pub mod run;
+#[cfg(feature = "backends-c")]
+pub mod runtime;
+#[cfg(feature = "debugger")]
+pub mod rundiff;
+#[cfg(feature = "interpreter")]
+pub mod sampler;
+#[cfg(feature = "interpreter")]
+pub mod scripted;
+#[cfg(feature = "interpreter")]
+pub mod shadow;
+#[cfg(feature = "interpreter")]
+pub mod sink;
+pub mod snapshot;
+pub mod stdio;
+pub mod stream;
+pub mod tape;
+pub mod toml;
+#[cfg(feature = "backends-c")]
+pub mod transpile;
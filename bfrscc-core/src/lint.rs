@@ -0,0 +1,155 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Lint
+//!
+//! Finds constructs which are valid brainfuck but most likely not what the author meant.
+
+use std::collections::HashSet;
+use std::fmt;
+use lexer::{split_input, CommentPolicy, Token};
+use program::Program;
+
+/// Characters which are instructions in one of the dialects
+const DIALECT_CHARS: &[char] = &['#', '!', '~', '?'];
+
+/// Suspicious constructs
+#[derive(Debug, Clone, PartialEq)]
+pub enum Warning {
+    /// `[]` at the given position never terminates once it is entered
+    EmptyLoop(usize),
+    /// The loop at the given position directly follows another loop, so its cell is always zero
+    /// and the loop is never entered
+    DeadLoop(usize),
+    /// The character at the given position is an instruction of a dialect which is not enabled,
+    /// so it is ignored. Only reported with `CommentPolicy::Dialect`, which opts into dialects:
+    /// otherwise these characters are as good a comment as any other.
+    DialectChar(usize, char),
+    /// The loop at the given position moves the pointer by an amount which is not known before
+    /// running it, so the pointer may leave a tape of fixed size (see `lint_bounds`)
+    UnboundedLoop(usize),
+    /// The move at the given position takes the pointer to the given cell, which is outside of a
+    /// tape of fixed size (see `lint_bounds`)
+    OutOfBounds(usize, isize),
+}
+
+impl Warning {
+    /// Position in the source the warning refers to
+    pub fn position(&self) -> usize {
+        match *self {
+            Warning::EmptyLoop(pos)
+            | Warning::DeadLoop(pos)
+            | Warning::DialectChar(pos, _)
+            | Warning::UnboundedLoop(pos)
+            | Warning::OutOfBounds(pos, _) => pos,
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Warning::EmptyLoop(pos) => {
+                write!(f, "empty loop at position {} never terminates", pos)
+            }
+            Warning::DeadLoop(pos) => write!(
+                f,
+                "loop at position {} directly follows a loop and is never entered",
+                pos
+            ),
+            Warning::DialectChar(pos, c) => write!(
+                f,
+                "`{}` at position {} is ignored, it is only an instruction in a dialect",
+                c, pos
+            ),
+            Warning::UnboundedLoop(pos) => write!(
+                f,
+                "loop at position {} moves the pointer by an unknown amount and may leave the tape",
+                pos
+            ),
+            Warning::OutOfBounds(pos, cell) => write!(
+                f,
+                "move at position {} takes the pointer to cell {}, outside of the tape",
+                pos, cell
+            ),
+        }
+    }
+}
+
+/// Find suspicious constructs in `prog`.
+///
+/// ```
+/// use bfrscc_core::lexer::{CommentPolicy, LexerOptions};
+/// use bfrscc_core::lint::{lint, Warning};
+/// use bfrscc_core::program::Program;
+///
+/// let prog = Program::new("+[] # done?");
+/// assert_eq!(lint(&prog), vec![Warning::EmptyLoop(1)]);
+/// let options = LexerOptions {
+///     comments: CommentPolicy::Dialect,
+///     ..LexerOptions::default()
+/// };
+/// let prog = Program::compile("+[] # done?", &options, 0).unwrap();
+/// assert_eq!(lint(&prog), vec![Warning::EmptyLoop(1), Warning::DialectChar(10, '?')]);
+/// ```
+pub fn lint(prog: &Program) -> Vec<Warning> {
+    let mut warnings = vec![];
+    for pair in prog.instructions().windows(2) {
+        match (&pair[0].token, &pair[1].token) {
+            (&Token::LoopBegin, &Token::LoopEnd) => {
+                warnings.push(Warning::EmptyLoop(pair[0].position()))
+            }
+            (&Token::LoopEnd, &Token::LoopBegin) => {
+                warnings.push(Warning::DeadLoop(pair[1].position()))
+            }
+            _ => {}
+        }
+    }
+    if prog.lexer_options().comments == CommentPolicy::Dialect {
+        let instructions: HashSet<usize> =
+            (0..prog.len()).flat_map(|i| prog.spans(i)).flatten().collect();
+        for (pos, c) in split_input(prog.source()).0.chars().enumerate() {
+            if DIALECT_CHARS.contains(&c) && !instructions.contains(&pos) {
+                warnings.push(Warning::DialectChar(pos, c));
+            }
+        }
+    }
+    warnings.sort_by_key(Warning::position);
+    warnings
+}
+
+/// Check that the pointer of `prog` stays on a tape of fixed size, with `left` cells left of the
+/// cell the pointer starts at and `right` cells right of it. The check is conservative: it
+/// stops at the first loop which does not return to its cell (see `optimizer::balanced`), as
+/// the pointer may go anywhere after it. At most one warning is returned.
+///
+/// ```
+/// use bfrscc_core::lint::{lint_bounds, Warning};
+/// use bfrscc_core::program::Program;
+///
+/// let prog: Program = ">>[->+<]<<".parse().unwrap();
+/// assert!(lint_bounds(&prog, 0, 3).is_empty());
+/// assert_eq!(lint_bounds(&prog, 0, 2), vec![Warning::OutOfBounds(4, 3)]);
+/// let prog: Program = "+[>+]".parse().unwrap();
+/// assert_eq!(lint_bounds(&prog, 0, 100), vec![Warning::UnboundedLoop(1)]);
+/// ```
+pub fn lint_bounds(prog: &Program, left: usize, right: usize) -> Vec<Warning> {
+    let mut offset = 0isize;
+    for (x, op) in prog.instructions().iter().zip(prog.ops()) {
+        match x.token {
+            Token::MoveRight => offset += x.multiplier as isize,
+            Token::MoveLeft => offset -= x.multiplier as isize,
+            // The moves of a balanced loop cancel out, so its body is checked like straight code.
+            Token::LoopBegin if !op.balanced => return vec![Warning::UnboundedLoop(x.position())],
+            _ => continue,
+        }
+        if offset < -(left as isize) || offset > right as isize {
+            return vec![Warning::OutOfBounds(x.position(), offset)];
+        }
+    }
+    vec![]
+}
@@ -0,0 +1,77 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Logging
+//!
+//! Minimal logging of what each stage of the pipeline did. Messages are written to stderr if
+//! their `Level` is enabled via `set_level`. Within the crate, the `log_msg!` macro emits
+//! messages and `span` logs the duration of a pass.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+/// Verbosity levels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Nothing is logged
+    Off = 0,
+    /// Summary of each stage (`-v`)
+    Info = 1,
+    /// Details of each stage (`-vv`)
+    Debug = 2,
+}
+
+/// Currently enabled level
+static LEVEL: AtomicUsize = AtomicUsize::new(Level::Off as usize);
+
+/// Set the verbosity level.
+pub fn set_level(level: Level) {
+    LEVEL.store(level as usize, Ordering::Relaxed);
+}
+
+/// Returns `true` if messages of the given level are printed.
+pub fn enabled(level: Level) -> bool {
+    level != Level::Off && level as usize <= LEVEL.load(Ordering::Relaxed)
+}
+
+/// Log a message at the given level, e.g. `log_msg!(Level::Info, "{} tokens", n)`. Only for use
+/// within the crate; it is not named `log!` so that it does not clash with the `log` crate.
+macro_rules! log_msg {
+    ($level:expr, $($arg:tt)+) => {
+        if $crate::logging::enabled($level) {
+            eprintln!("[{:?}] {}", $level, format_args!($($arg)+));
+        }
+    };
+}
+
+/// Logs the time spent in a pass when dropped.
+pub struct Span {
+    /// Name of the pass
+    name: &'static str,
+    /// Time the pass was started
+    start: Instant,
+}
+
+/// Start a span for the pass `name`.
+pub fn span(name: &'static str) -> Span {
+    log_msg!(Level::Debug, "{}: started", name);
+    Span {
+        name,
+        start: Instant::now(),
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        log_msg!(
+            Level::Debug,
+            "{}: finished in {:?}",
+            self.name,
+            self.start.elapsed()
+        );
+    }
+}
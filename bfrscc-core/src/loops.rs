@@ -0,0 +1,183 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Loops of a program
+//!
+//! Lists the loops of a program with what is known about them without running it: where they
+//! are, how deeply they are nested, the size of their bodies, how far an iteration moves the
+//! pointer and how the optimizer classified them (see `LoopKind`). This shows which loops the
+//! run loop executes in a single step and which ones it has to iterate:
+//!
+//! ```
+//! use bfrscc_core::loops::{loops, LoopClass};
+//!
+//! let prog = "+[->+<]>[[-]>[>]<]".parse().unwrap();
+//! let loops = loops(&prog);
+//! assert_eq!(loops.len(), 4);
+//! assert_eq!(loops[0].class, LoopClass::Copy);
+//! // The scan nested in the second loop moves the pointer by an unknown distance.
+//! assert_eq!((loops[1].depth, loops[1].body, loops[1].balance), (1, 8, None));
+//! assert_eq!((loops[3].class, loops[3].balance), (LoopClass::Scan, Some(1)));
+//! assert_eq!(loops[3].to_string(), "1:14-1:16  depth 2  body 1  balance +1  scan");
+//! ```
+
+use std::fmt;
+use diagnostics::line_col;
+use lexer::Token;
+use program::{LoopKind, Op, Program};
+
+/// How the optimizer classified a loop
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoopClass {
+    /// `[-]`, executed in a single step
+    Clear,
+    /// A loop adding multiples of the current cell to other cells and clearing it, e.g.
+    /// `[->+<]`, executed in a single step
+    Copy,
+    /// A loop which runs at most once. The run loop iterates it like any other loop.
+    If,
+    /// The if of an if/else, iterated like `If`
+    IfElse,
+    /// The else of an if/else, iterated like `If`
+    Else,
+    /// A loop which only moves the pointer, e.g. `[>]`, searching for a cell which is zero. The
+    /// run loop iterates it like any other loop.
+    Scan,
+    /// Any other loop
+    Unknown,
+}
+
+impl LoopClass {
+    /// Name of the class
+    pub fn name(self) -> &'static str {
+        match self {
+            LoopClass::Clear => "clear",
+            LoopClass::Copy => "copy",
+            LoopClass::If => "if",
+            LoopClass::IfElse => "if-else",
+            LoopClass::Else => "else",
+            LoopClass::Scan => "scan",
+            LoopClass::Unknown => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for LoopClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A loop of a program
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoopInfo {
+    /// Index of the `[`
+    pub index: usize,
+    /// Index of the `]`
+    pub end: usize,
+    /// Line and column of the `[` in the source, starting at 1
+    pub begin_at: (usize, usize),
+    /// Line and column of the `]` in the source, starting at 1
+    pub end_at: (usize, usize),
+    /// Nesting depth, 1 for loops which are not nested
+    pub depth: usize,
+    /// Number of instructions between the brackets
+    pub body: usize,
+    /// Net movement of the pointer in an iteration, `None` if it depends on the cells, e.g.
+    /// because of a nested loop which does not return to its cell, or on the dialect
+    pub balance: Option<isize>,
+    /// Classification of the optimizer
+    pub class: LoopClass,
+}
+
+impl fmt::Display for LoopInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let balance = match self.balance {
+            Some(n) if n > 0 => format!("+{}", n),
+            Some(n) => n.to_string(),
+            None => "?".to_string(),
+        };
+        write!(
+            f,
+            "{}:{}-{}:{}  depth {}  body {}  balance {}  {}",
+            self.begin_at.0,
+            self.begin_at.1,
+            self.end_at.0,
+            self.end_at.1,
+            self.depth,
+            self.body,
+            balance,
+            self.class
+        )
+    }
+}
+
+/// The loops of `prog` in the order of their `[`
+pub fn loops(prog: &Program) -> Vec<LoopInfo> {
+    let ops = prog.ops();
+    let instructions = prog.instructions();
+    let at = |idx: usize| line_col(prog.source(), instructions[idx].position());
+    let mut loops = vec![];
+    for (index, op) in ops.iter().enumerate() {
+        if op.token != Token::LoopBegin {
+            continue;
+        }
+        let end = op.jump as usize;
+        let balance = balance(prog, index + 1, end);
+        let class = match op.kind {
+            LoopKind::Clear => LoopClass::Clear,
+            LoopKind::Transfer => LoopClass::Copy,
+            LoopKind::If => LoopClass::If,
+            LoopKind::IfElse => LoopClass::IfElse,
+            LoopKind::Else => LoopClass::Else,
+            LoopKind::Loop if balance != Some(0) && moves_only(&ops[index + 1..end]) => {
+                LoopClass::Scan
+            }
+            LoopKind::Loop => LoopClass::Unknown,
+        };
+        loops.push(LoopInfo {
+            index,
+            end,
+            begin_at: at(index),
+            end_at: at(end),
+            depth: op.depth as usize,
+            body: end - index - 1,
+            balance,
+            class,
+        });
+    }
+    loops
+}
+
+/// Net movement of the pointer by the instructions from index `from` up to `to` of `prog`, if
+/// it is known. Nested loops must return to their cell.
+fn balance(prog: &Program, from: usize, to: usize) -> Option<isize> {
+    let ops = prog.ops();
+    let mut offset = 0;
+    let mut idx = from;
+    while idx < to {
+        let op = ops[idx];
+        match op.token {
+            Token::MoveRight => offset += op.multiplier as isize,
+            Token::MoveLeft => offset -= op.multiplier as isize,
+            Token::MoveUp | Token::MoveDown | Token::SwitchTape => return None,
+            Token::LoopBegin if !op.balanced => return None,
+            Token::LoopBegin => idx = op.jump as usize,
+            _ => {}
+        }
+        idx += 1;
+    }
+    Some(offset)
+}
+
+/// Returns `true` if `body` is not empty and only moves the pointer left and right.
+fn moves_only(body: &[Op]) -> bool {
+    !body.is_empty()
+        && body
+            .iter()
+            .all(|x| matches!(x.token, Token::MoveRight | Token::MoveLeft))
+}
@@ -0,0 +1,249 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Machine
+//!
+//! `Machine` is object safe, so hosts can choose a machine at runtime. Boxes and references of
+//! machines are machines themselves and can be run like any other:
+//!
+//! ```
+//! use bfrscc_core::adapter::Counting;
+//! use bfrscc_core::buffer::BufferMachine;
+//! use bfrscc_core::machine::{Machine, MachineExt};
+//! use bfrscc_core::program::Program;
+//!
+//! let count = true;
+//! let mut machine: Box<dyn Machine> = if count {
+//!     Box::new(Counting::new(BufferMachine::new()))
+//! } else {
+//!     Box::new(BufferMachine::new())
+//! };
+//! let prog: Program = "+++[>++<-]>".parse().unwrap();
+//! machine.run(&prog).unwrap();
+//! assert_eq!(machine.get_val(), 6);
+//! ```
+
+use program::{Op, Program};
+use run::{run_program, RunConfig, RunError, RunReport};
+
+/// The `Machine` trait needs to be implemented by every interpreter/(cross)compiler
+pub trait Machine {
+    /// Move left
+    fn left(&mut self, val: u8);
+
+    /// Move right
+    fn right(&mut self, val: u8);
+
+    /// Move up (grid dialect). Does nothing by default.
+    fn up(&mut self, _val: u8) {}
+
+    /// Move down (grid dialect). Does nothing by default.
+    fn down(&mut self, _val: u8) {}
+
+    /// Increase
+    fn increase(&mut self, val: u8);
+
+    /// Increase
+    fn decrease(&mut self, val: u8);
+
+    /// Output
+    fn output(&mut self);
+
+    /// Set value
+    fn input(&mut self, val: u8);
+
+    /// Get value
+    fn get_val(&self) -> u8;
+
+    /// Returns `true` if the current cell is zero, which decides whether loops are entered.
+    /// Machines with cells wider than a byte need to override this.
+    fn is_zero(&self) -> bool {
+        self.get_val() == 0
+    }
+
+    /// Executes `[-]`, which the run loop performs in a single step. Decrements the current cell
+    /// until it is zero by default, machines with direct access to their cells should override
+    /// this.
+    fn clear(&mut self) {
+        clear_stepwise(self);
+    }
+
+    /// Executes a loop with the net effect `effects` (see `Program::effects`), which the run loop
+    /// performs in a single step. Executes the loop by default, machines with direct access to
+    /// their cells should override this.
+    fn transfer(&mut self, effects: &[(isize, u8)]) {
+        transfer_stepwise(self, effects);
+    }
+
+    /// Executes `ops`, a run of instructions on the current cell (see `Op::run`), which the run
+    /// loop performs in a single step, and returns `true`. Machines with direct access to their
+    /// cells can keep the value of the cell in a local meanwhile. Returns `false` without
+    /// executing anything by default, the run loop then executes the instructions one by one.
+    fn exec_run(&mut self, _ops: &[Op], _input: &mut dyn Iterator<Item = u8>) -> bool {
+        false
+    }
+
+    /// Number of cells currently allocated on the tape
+    fn tape_len(&self) -> usize;
+
+    /// Number of cells the tape has memory for, at least `tape_len`. The tape never shrinks, so
+    /// this is the peak allocation. Returns `tape_len` by default.
+    fn tape_capacity(&self) -> usize {
+        self.tape_len()
+    }
+
+    /// Called for `#` if breakpoints are enabled. Does nothing by default.
+    fn breakpoint(&mut self) {}
+
+    /// Switch to the next tape (multi-tape dialect). Does nothing by default.
+    fn switch_tape(&mut self) {}
+
+    /// Write a random byte to the current cell (random dialect). Does nothing by default.
+    fn random(&mut self) {}
+
+    /// Checked after every instruction, an error stops the execution. This allows wrappers to
+    /// enforce limits (see `adapter::WithLimits`). Always `Ok` by default.
+    fn status(&self) -> Result<(), RunError> {
+        Ok(())
+    }
+}
+
+/// The default of `Machine::clear`, for machines which only execute `[-]` at once sometimes
+/// (see `adapter::Layer`)
+pub fn clear_stepwise<M: Machine + ?Sized>(machine: &mut M) {
+    while !machine.is_zero() {
+        machine.decrease(1);
+    }
+}
+
+/// The default of `Machine::transfer`, for machines which only execute such loops at once
+/// sometimes (see `adapter::Layer`)
+pub fn transfer_stepwise<M: Machine + ?Sized>(machine: &mut M, effects: &[(isize, u8)]) {
+    while !machine.is_zero() {
+        for &(offset, factor) in effects {
+            shift(machine, offset);
+            machine.increase(factor);
+            shift(machine, -offset);
+        }
+        machine.decrease(1);
+    }
+}
+
+/// Move the pointer of `machine` by `offset` cells, to the right if it is positive.
+fn shift<M: Machine + ?Sized>(machine: &mut M, offset: isize) {
+    let mut n = offset.unsigned_abs();
+    while n > 0 {
+        let step = n.min(u8::MAX as usize);
+        if offset < 0 {
+            machine.left(step as u8);
+        } else {
+            machine.right(step as u8);
+        }
+        n -= step;
+    }
+}
+
+/// Implements `Machine` for a pointer type by forwarding to the machine it points to.
+macro_rules! forward {
+    ($($t:tt)*) => {
+        $($t)* {
+            fn left(&mut self, val: u8) {
+                (**self).left(val);
+            }
+
+            fn right(&mut self, val: u8) {
+                (**self).right(val);
+            }
+
+            fn up(&mut self, val: u8) {
+                (**self).up(val);
+            }
+
+            fn down(&mut self, val: u8) {
+                (**self).down(val);
+            }
+
+            fn increase(&mut self, val: u8) {
+                (**self).increase(val);
+            }
+
+            fn decrease(&mut self, val: u8) {
+                (**self).decrease(val);
+            }
+
+            fn output(&mut self) {
+                (**self).output();
+            }
+
+            fn input(&mut self, val: u8) {
+                (**self).input(val);
+            }
+
+            fn get_val(&self) -> u8 {
+                (**self).get_val()
+            }
+
+            fn is_zero(&self) -> bool {
+                (**self).is_zero()
+            }
+
+            fn clear(&mut self) {
+                (**self).clear();
+            }
+
+            fn transfer(&mut self, effects: &[(isize, u8)]) {
+                (**self).transfer(effects);
+            }
+
+            fn exec_run(&mut self, ops: &[Op], input: &mut dyn Iterator<Item = u8>) -> bool {
+                (**self).exec_run(ops, input)
+            }
+
+            fn tape_len(&self) -> usize {
+                (**self).tape_len()
+            }
+
+            fn tape_capacity(&self) -> usize {
+                (**self).tape_capacity()
+            }
+
+            fn breakpoint(&mut self) {
+                (**self).breakpoint();
+            }
+
+            fn switch_tape(&mut self) {
+                (**self).switch_tape();
+            }
+
+            fn random(&mut self) {
+                (**self).random();
+            }
+
+            fn status(&self) -> Result<(), RunError> {
+                (**self).status()
+            }
+        }
+    };
+}
+
+forward!(impl<M: Machine + ?Sized> Machine for Box<M>);
+forward!(impl<'a, M: Machine + ?Sized> Machine for &'a mut M);
+
+/// Helpers which are available for every `Machine`
+pub trait MachineExt: Machine + Sized {
+    /// Run `prog` on this machine without limits. `,` reads the inline input of `prog` or stdin.
+    fn run(&mut self, prog: &Program) -> Result<RunReport, RunError> {
+        run_program(prog, self, &RunConfig::default())
+    }
+
+    /// Run `prog` on this machine with the settings of `config`.
+    fn run_with(&mut self, prog: &Program, config: &RunConfig) -> Result<RunReport, RunError> {
+        run_program(prog, self, config)
+    }
+}
+
+impl<M: Machine> MachineExt for M {}
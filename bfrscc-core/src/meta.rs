@@ -0,0 +1,31 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Meta
+//!
+//! Running brainfuck programs on a brainfuck interpreter written in brainfuck. This exercises
+//! loops, input handling and end of input in depth and therefore serves as a thorough
+//! correctness check of the interpreter.
+
+/// dbfi, a brainfuck self-interpreter by Daniel B. Cristofani. It reads a program followed by `!`
+/// and the input of the program from its own input.
+pub const DBFI: &str = "\
+>>>+[[-]>>[-]++>+>+++++++[<++++>>++<-]++>>+>+>+++++[>++>++++++<<-]+>>>,<++[[>[\
+->>]<[>>]<<-]<[<]<+>>[>]>[<+>-[[<+>-]>]<[[[-]<]++<-[<+++++++++>[<->-]>>]>>]]<<\
+]<]<[[<]>[[>]>>[>>]+[<<]<[<]<+>>-]>[>]+[->>]<<<<[[<<]<[<]+<<[+>+<<-[>-->+<<-[>\
++<[>>+<<-]]]>[<+>-]<]++>>-->[>]>>[>>]]<<[>>+<[[<]<]>[[<<]<[<]+[-<+>>-[<<+>++>-\
+[<->[<<+>>-]]]<[>+<-]>]>[>]>]>[>>]>>]<<[>>+>>+>>]<<[->>>>>>>>]<<[>.>>>>>>>]<<[\
+>->>>>>]<<[>,>>>]<<[>+>]<<[+<<]<]";
+
+/// Input for `DBFI` which makes it run `prog` on `input`. Comments are stripped from `prog`, in
+/// particular any `!` it might contain.
+pub fn meta_input(prog: &str, input: &[u8]) -> Vec<u8> {
+    let mut res: Vec<u8> = prog.bytes().filter(|x| b"+-<>[],.".contains(x)).collect();
+    res.push(b'!');
+    res.extend_from_slice(input);
+    res
+}
@@ -0,0 +1,148 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Memory-mapped files
+//!
+//! Maps a program file into memory read-only, so very large programs can be lexed directly from
+//! the page cache (see `stream::compile_slice`) instead of being copied into memory first. On
+//! platforms other than unix, the file is read instead.
+
+use std::fs::File;
+use std::io;
+use std::ops::Deref;
+use std::path::Path;
+
+#[cfg(unix)]
+mod sys {
+    use std::os::raw::{c_int, c_long, c_void};
+
+    /// Pages may be read
+    pub const PROT_READ: c_int = 1;
+    /// Changes are private (none are made)
+    pub const MAP_PRIVATE: c_int = 2;
+
+    extern "C" {
+        pub fn mmap(
+            addr: *mut c_void,
+            len: usize,
+            prot: c_int,
+            flags: c_int,
+            fd: c_int,
+            offset: c_long,
+        ) -> *mut c_void;
+        pub fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    }
+}
+
+/// Content of a file mapped into memory
+#[derive(Debug)]
+pub struct Mapping {
+    /// Start of the mapping, null for empty files
+    #[cfg(unix)]
+    ptr: *mut u8,
+    /// Length of the mapping
+    #[cfg(unix)]
+    len: usize,
+    /// Content of the file
+    #[cfg(not(unix))]
+    data: Vec<u8>,
+}
+
+// The mapping is read-only and owned by this value.
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    /// Map the file at `path`. The file must not be modified while it is mapped. Only regular
+    /// files can be mapped, others such as pipes and devices are rejected.
+    ///
+    /// ```
+    /// use std::path::Path;
+    /// use bfrscc_core::mmap::Mapping;
+    ///
+    /// assert!(Mapping::open(Path::new("/dev/null")).is_err());
+    /// ```
+    #[cfg(unix)]
+    pub fn open(path: &Path) -> io::Result<Self> {
+        use std::os::unix::io::AsRawFd;
+        use std::ptr;
+
+        let file = File::open(path)?;
+        let metadata = file.metadata()?;
+        // The length of anything else is not the length of its content.
+        if !metadata.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "only regular files can be mapped",
+            ));
+        }
+        let len = metadata.len() as usize;
+        if len == 0 {
+            return Ok(Mapping {
+                ptr: ptr::null_mut(),
+                len,
+            });
+        }
+        let ptr = unsafe {
+            sys::mmap(
+                ptr::null_mut(),
+                len,
+                sys::PROT_READ,
+                sys::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        // `MAP_FAILED` is `(void *) -1`.
+        if ptr as isize == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Mapping {
+            ptr: ptr as *mut u8,
+            len,
+        })
+    }
+
+    /// Read the file at `path`.
+    #[cfg(not(unix))]
+    pub fn open(path: &Path) -> io::Result<Self> {
+        use std::io::Read;
+
+        let mut data = vec![];
+        File::open(path)?.read_to_end(&mut data)?;
+        Ok(Mapping { data })
+    }
+}
+
+impl Deref for Mapping {
+    type Target = [u8];
+
+    #[cfg(unix)]
+    fn deref(&self) -> &[u8] {
+        if self.ptr.is_null() {
+            &[]
+        } else {
+            unsafe { ::std::slice::from_raw_parts(self.ptr, self.len) }
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn deref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        if !self.ptr.is_null() {
+            unsafe {
+                sys::munmap(self.ptr as *mut _, self.len);
+            }
+        }
+    }
+}
@@ -0,0 +1,219 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Mutation testing
+//!
+//! Checks that shadow execution (see `shadow`) notices broken optimizations. Small mutations are
+//! applied to the optimized instructions of a program, as a faulty optimizer pass would, and
+//! every mutant is compared with the unoptimized program. A breaking mutation should be killed,
+//! that is reported as a divergence, and a preserving mutation has to pass. A breaking mutation
+//! which survives either only changes dead code or points at a gap in the verification.
+//!
+//! ```
+//! use bfrscc_core::mutate::{mutate, Verdict};
+//! use bfrscc_core::run::RunConfig;
+//!
+//! let config = RunConfig {
+//!     input: Some(vec![]),
+//!     ..RunConfig::default()
+//! };
+//! let results = mutate("++++++++[>++++++++<-]>+.", &config, 100, 0).unwrap();
+//! assert!(results.iter().all(|x| x.verdict == Verdict::Killed || x.verdict == Verdict::Passed));
+//! ```
+
+use std::fmt;
+use lexer::Token;
+use parser::{Instruction, InstructionStream};
+use program::Program;
+use rng::Rng;
+use run::{compile, RunConfig};
+use shadow::{compare, ShadowError};
+
+/// A change of the instruction at an index of an instruction stream
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mutation {
+    /// Split an instruction with a multiplier of at least two into two instructions (preserving)
+    Split(usize),
+    /// Insert `><` before an instruction (preserving)
+    Detour(usize),
+    /// Repeat an instruction once more, or once less if the multiplier is at its maximum
+    /// (breaking)
+    Miscount(usize),
+    /// Replace `+` by `-`, `<` by `>` and vice versa (breaking)
+    Invert(usize),
+    /// Remove an instruction other than a bracket (breaking)
+    Remove(usize),
+}
+
+impl Mutation {
+    /// Returns `true` if the mutation changes the behavior of the program, unless it only affects
+    /// dead code.
+    pub fn is_breaking(&self) -> bool {
+        !matches!(*self, Mutation::Split(_) | Mutation::Detour(_))
+    }
+
+    /// Index of the mutated instruction
+    pub fn index(&self) -> usize {
+        match *self {
+            Mutation::Split(i)
+            | Mutation::Detour(i)
+            | Mutation::Miscount(i)
+            | Mutation::Invert(i)
+            | Mutation::Remove(i) => i,
+        }
+    }
+
+    /// Mutated copy of `prog`. The mutation needs to be applicable (see `mutations`).
+    pub fn apply(&self, prog: &[Instruction]) -> InstructionStream {
+        let mut out = prog.to_vec();
+        let i = self.index();
+        match *self {
+            Mutation::Split(_) => {
+                let mut second = out[i].clone();
+                second.multiplier = out[i].multiplier / 2;
+                out[i].multiplier -= second.multiplier;
+                out.insert(i + 1, second);
+            }
+            Mutation::Detour(_) => {
+                let pos = out[i].position();
+                out.insert(i, Instruction::new(pos, Token::MoveLeft, 1));
+                out.insert(i, Instruction::new(pos, Token::MoveRight, 1));
+            }
+            Mutation::Miscount(_) => match out[i].multiplier {
+                u8::MAX => out[i].multiplier -= 1,
+                _ => out[i].multiplier += 1,
+            },
+            Mutation::Invert(_) => {
+                out[i].token = match out[i].token {
+                    Token::Increase => Token::Decrease,
+                    Token::Decrease => Token::Increase,
+                    Token::MoveLeft => Token::MoveRight,
+                    Token::MoveRight => Token::MoveLeft,
+                    token => token,
+                }
+            }
+            Mutation::Remove(_) => {
+                out.remove(i);
+            }
+        }
+        out
+    }
+}
+
+impl fmt::Display for Mutation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Mutation::Split(i) => write!(f, "split instruction {}", i),
+            Mutation::Detour(i) => write!(f, "insert `><` before instruction {}", i),
+            Mutation::Miscount(i) => write!(f, "miscount instruction {}", i),
+            Mutation::Invert(i) => write!(f, "invert instruction {}", i),
+            Mutation::Remove(i) => write!(f, "remove instruction {}", i),
+        }
+    }
+}
+
+/// All mutations applicable to `prog`
+pub fn mutations(prog: &[Instruction]) -> Vec<Mutation> {
+    let mut out = vec![];
+    for (i, instr) in prog.iter().enumerate() {
+        out.push(Mutation::Detour(i));
+        match instr.token {
+            Token::Increase | Token::Decrease | Token::MoveLeft | Token::MoveRight => {
+                if instr.multiplier > 1 {
+                    out.push(Mutation::Split(i));
+                }
+                out.push(Mutation::Miscount(i));
+                out.push(Mutation::Invert(i));
+                out.push(Mutation::Remove(i));
+            }
+            Token::Input | Token::Output => out.push(Mutation::Remove(i)),
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Outcome of the check of a mutant
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    /// The breaking mutation was detected
+    Killed,
+    /// The breaking mutation was not detected
+    Survived,
+    /// The preserving mutation was not reported
+    Passed,
+    /// The preserving mutation was reported, with the divergence
+    FalseAlarm(String),
+}
+
+/// Result of the check of a single mutant
+#[derive(Debug, Clone, PartialEq)]
+pub struct MutantResult {
+    /// Applied mutation
+    pub mutation: Mutation,
+    /// Outcome of the check
+    pub verdict: Verdict,
+}
+
+impl MutantResult {
+    /// Returns `true` if the verification behaved as it should.
+    pub fn is_ok(&self) -> bool {
+        matches!(self.verdict, Verdict::Killed | Verdict::Passed)
+    }
+}
+
+impl fmt::Display for MutantResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let status = match self.verdict {
+            Verdict::Killed => "killed",
+            Verdict::Survived => "SURVIVED",
+            Verdict::Passed => "ok",
+            Verdict::FalseAlarm(_) => "FALSE ALARM",
+        };
+        write!(f, "mutant {} ... {}", self.mutation, status)
+    }
+}
+
+/// Optimize `source`, apply up to `max` mutations chosen with `seed` to the optimized instructions
+/// and compare every mutant with the unoptimized program (see `shadow::compare`). Fails if the
+/// program cannot be compiled or the unmutated comparison fails already, e.g. at the step limit.
+pub fn mutate(
+    source: &str,
+    config: &RunConfig,
+    max: usize,
+    seed: u64,
+) -> Result<Vec<MutantResult>, ShadowError> {
+    let config = RunConfig {
+        optimize: false,
+        ..config.clone()
+    };
+    let plain = compile(source, &config).map_err(ShadowError::Run)?;
+    let opt = plain.clone().optimized_with(config.arithmetic.dead_stores());
+    compare(&plain, &opt, &config)?;
+    let mut candidates = mutations(opt.instructions());
+    // Partial Fisher-Yates shuffle: the first `max` candidates are a random sample.
+    let mut rng = Rng::new(seed);
+    let n = max.min(candidates.len());
+    for i in 0..n {
+        let j = i + (rng.next_u64() % (candidates.len() - i) as u64) as usize;
+        candidates.swap(i, j);
+    }
+    candidates.truncate(n);
+    let mut results = vec![];
+    for mutation in candidates {
+        let mutant: Program = opt.clone().with_instructions(mutation.apply(opt.instructions()));
+        let verdict = match (compare(&plain, &mutant, &config), mutation.is_breaking()) {
+            (Ok(_), true) => Verdict::Survived,
+            (Ok(_), false) => Verdict::Passed,
+            (Err(ShadowError::Run(e)), _) => return Err(ShadowError::Run(e)),
+            (Err(ShadowError::Diverged(_)), true) => Verdict::Killed,
+            (Err(ShadowError::Diverged(d)), false) => Verdict::FalseAlarm(d.to_string()),
+        };
+        results.push(MutantResult { mutation, verdict });
+    }
+    Ok(results)
+}
@@ -0,0 +1,98 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Newline translation
+//!
+//! Brainfuck programs usually expect lines to end with `\n` (10), while some consoles deliver
+//! and expect `\r\n`.
+
+use std::fmt;
+use std::iter::Peekable;
+use std::str::FromStr;
+
+/// How line endings of the input and output are translated
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Newline {
+    /// No translation
+    #[default]
+    Raw,
+    /// `\r\n` in the input is read as `\n`, the output is not translated
+    Lf,
+    /// `\r\n` in the input is read as `\n` and `\n` in the output is written as `\r\n`
+    Crlf,
+}
+
+impl Newline {
+    /// Pass `val`, a byte of the output, to `write`, translating line endings.
+    #[inline]
+    pub fn output<F: FnMut(u8)>(self, val: u8, mut write: F) {
+        if self == Newline::Crlf && val == b'\n' {
+            write(b'\r');
+        }
+        write(val);
+    }
+
+    /// Translate the line endings of a whole output at once, e.g. of an expected output.
+    pub fn output_all(self, output: &[u8]) -> Vec<u8> {
+        let mut translated = Vec::with_capacity(output.len());
+        for &val in output {
+            self.output(val, |x| translated.push(x));
+        }
+        translated
+    }
+
+    /// Translate the line endings of `input`.
+    pub fn input<I: Iterator<Item = u8>>(self, input: I) -> TranslateInput<I> {
+        TranslateInput {
+            input: input.peekable(),
+            strip: self != Newline::Raw,
+        }
+    }
+}
+
+impl FromStr for Newline {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(Newline::Raw),
+            "lf" => Ok(Newline::Lf),
+            "crlf" => Ok(Newline::Crlf),
+            _ => Err(format!("unknown newline mode `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for Newline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Newline::Raw => write!(f, "raw"),
+            Newline::Lf => write!(f, "lf"),
+            Newline::Crlf => write!(f, "crlf"),
+        }
+    }
+}
+
+/// Iterator returned by `Newline::input`
+pub struct TranslateInput<I: Iterator<Item = u8>> {
+    /// Untranslated input
+    input: Peekable<I>,
+    /// Drop `\r` in front of `\n`
+    strip: bool,
+}
+
+impl<I: Iterator<Item = u8>> Iterator for TranslateInput<I> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let val = self.input.next()?;
+        if self.strip && val == b'\r' && self.input.peek() == Some(&b'\n') {
+            return self.input.next();
+        }
+        Some(val)
+    }
+}
@@ -0,0 +1,387 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Optimizer
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use lexer::Token;
+use logging::{span, Level};
+use parser::{Instruction, InstructionStream};
+
+/// Maximum trip count of a loop unrolled by `unroll`
+const UNROLL_MAX_TRIPS: usize = 8;
+
+/// Maximum number of instructions an unrolled loop is replaced with
+const UNROLL_MAX_LEN: usize = 64;
+
+/// Loops with a known effect
+#[derive(Debug, Clone, PartialEq)]
+pub enum Idiom {
+    /// `[-]` or `[+]`: sets the current cell to zero
+    Clear,
+    /// A loop of `+-<>` which decrements the current cell by one and returns to it, e.g.
+    /// `[->+>++<<]`: adds the current cell times the factor to the cell at every offset and
+    /// clears the current cell. The factors wrap, `255` subtracts the current cell.
+    Transfer(Vec<(isize, u8)>),
+    /// A loop which returns to its cell and ends with clearing it, so it runs at most once. The
+    /// body consists of the instructions between the `[` and the final clear.
+    If,
+    /// A flag-based if/else, e.g. `>[-]+<[A>-<[-]]>[B-]`: the flag at the given offset is cleared
+    /// and set to one, then an `If` whose body decrements the flag once follows, a move to the
+    /// flag and a balanced loop on the flag which decrements it once. The loop on the flag, the
+    /// else branch, runs exactly if the body of the `If` did not. Neither body touches the flag
+    /// otherwise.
+    IfElse(isize),
+}
+
+/// Fuse runs of identical `+`, `-`, `<`, `>`, `^` and `v` instructions into a single instruction
+/// with the corresponding `multiplier`. The fused instruction keeps the position of the first
+/// instruction of the run and the source spans of all of them. Loops, input and output are left
+/// untouched, therefore jump targets stay valid.
+pub fn optimize(prog: InstructionStream) -> InstructionStream {
+    let _span = span("optimizer");
+    let len = prog.len();
+    let mut out: InstructionStream = Vec::with_capacity(len);
+    for instr in prog {
+        let foldable = matches!(
+            instr.token,
+            Token::Increase
+                | Token::Decrease
+                | Token::MoveLeft
+                | Token::MoveRight
+                | Token::MoveUp
+                | Token::MoveDown
+        );
+        match out.last_mut() {
+            Some(last) if foldable && last.token == instr.token
+                && (last.multiplier as usize) + (instr.multiplier as usize) <= u8::MAX as usize =>
+            {
+                last.multiplier += instr.multiplier;
+                last.end = instr.end;
+            }
+            _ => out.push(instr),
+        }
+    }
+    log_msg!(
+        Level::Info,
+        "optimizer: fused {} instructions into {}",
+        len,
+        out.len()
+    );
+    out
+}
+
+/// Unroll loops with a small constant trip count. A loop runs exactly `n` times if its cell was
+/// just set to `n` by a clear and `n` `+`, and its body is balanced and decrements the cell once
+/// without touching it otherwise. Such a loop is replaced by `n` copies of its body, if that is
+/// at most `UNROLL_MAX_LEN` instructions, so that later passes see straight-line code. Loops in
+/// the copies are unrolled as well.
+pub fn unroll(prog: InstructionStream) -> InstructionStream {
+    let mut count = 0;
+    let out = unroll_loops(&prog, &mut count);
+    log_msg!(Level::Info, "optimizer: unrolled {} loops", count);
+    out
+}
+
+/// Unroll the loops of `prog` (see `unroll`) and add their number to `count`.
+fn unroll_loops(prog: &[Instruction], count: &mut usize) -> InstructionStream {
+    let mut out: InstructionStream = Vec::with_capacity(prog.len());
+    let mut i = 0;
+    while i < prog.len() {
+        if let Some((trips, end)) = trip_count(prog, i) {
+            let mut inner = 0;
+            let body = unroll_loops(&prog[i + 1..end], &mut inner);
+            if trips * body.len() <= UNROLL_MAX_LEN {
+                for _ in 0..trips {
+                    out.extend(body.iter().cloned());
+                }
+                *count += trips * inner + 1;
+                i = end + 1;
+                continue;
+            }
+        }
+        out.push(prog[i].clone());
+        i += 1;
+    }
+    out
+}
+
+/// Which stores `remove_dead_stores` may remove, depending on what `+` and `-` do at the ends of
+/// the range of a cell (see `run::Arithmetic`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeadStores {
+    /// Stores overwritten by any clear, for cells which wrap around
+    All,
+    /// Only stores overwritten by `[-]`, for cells which saturate: `[+]` never ends on a cell
+    /// which is not zero
+    Decrement,
+    /// None: the removed instructions could not overflow anymore, which a trap would report
+    Keep,
+}
+
+impl FromStr for DeadStores {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(DeadStores::All),
+            "decrement" => Ok(DeadStores::Decrement),
+            "keep" => Ok(DeadStores::Keep),
+            _ => Err(format!("unknown dead stores `{}`, use all, decrement or keep", s)),
+        }
+    }
+}
+
+impl fmt::Display for DeadStores {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DeadStores::All => write!(f, "all"),
+            DeadStores::Decrement => write!(f, "decrement"),
+            DeadStores::Keep => write!(f, "keep"),
+        }
+    }
+}
+
+/// Remove stores which are overwritten before they are read. Within a straight-line region,
+/// `+`, `-` and clears of a cell are dead if the cell is cleared or read by `,` later, without a
+/// `.` on it in between. Regions end at every loop other than a clear and at the instructions of
+/// dialects. Machine-generated programs are full of such stores, e.g. `+++[-]` or `[-][-]`.
+/// `stores` selects the clears which count (see `DeadStores`).
+pub fn remove_dead_stores(prog: InstructionStream, stores: DeadStores) -> InstructionStream {
+    if stores == DeadStores::Keep {
+        return prog;
+    }
+    let mut dead = vec![false; prog.len()];
+    // Indices of the stores to every cell of the region since it was last read, by offset
+    let mut pending: HashMap<isize, Vec<usize>> = HashMap::new();
+    let mut offset = 0isize;
+    let mut i = 0;
+    while i < prog.len() {
+        let x = &prog[i];
+        match x.token {
+            Token::MoveRight => offset += x.multiplier as isize,
+            Token::MoveLeft => offset -= x.multiplier as isize,
+            Token::Increase | Token::Decrease => pending.entry(offset).or_default().push(i),
+            Token::LoopBegin if is_clear(&prog, i) && kills(&prog[i + 1], stores) => {
+                for idx in pending.remove(&offset).unwrap_or_default() {
+                    dead[idx] = true;
+                }
+                pending.insert(offset, (i..i + 3).collect());
+                i += 3;
+                continue;
+            }
+            Token::Input => {
+                for idx in pending.remove(&offset).unwrap_or_default() {
+                    dead[idx] = true;
+                }
+            }
+            Token::Output => {
+                pending.remove(&offset);
+            }
+            _ => pending.clear(),
+        }
+        i += 1;
+    }
+    let count = dead.iter().filter(|&&x| x).count();
+    log_msg!(Level::Info, "optimizer: removed {} dead instructions", count);
+    prog.into_iter()
+        .zip(dead)
+        .filter(|&(_, dead)| !dead)
+        .map(|(x, _)| x)
+        .collect()
+}
+
+/// Trip count of the loop starting at index `idx` of `prog` and the index of its `]`, if the
+/// loop can be unrolled by `unroll`
+fn trip_count(prog: &[Instruction], idx: usize) -> Option<(usize, usize)> {
+    if prog[idx].token != Token::LoopBegin || idx < 4 || !is_clear(prog, idx - 4) {
+        return None;
+    }
+    let set = &prog[idx - 1];
+    let trips = set.multiplier as usize;
+    if set.token != Token::Increase || trips > UNROLL_MAX_TRIPS {
+        return None;
+    }
+    let end = matching(prog, idx)?;
+    let body = &prog[idx + 1..end];
+    if balanced(body) && decrements_once(body, 0) {
+        Some((trips, end))
+    } else {
+        None
+    }
+}
+
+/// Idiom of the loop starting at index `idx` of `prog`, together with the index of its `]`, or
+/// of the `]` of the else branch for `Idiom::IfElse`. Returns `None` for other instructions and
+/// for loops which are not recognized.
+pub fn idiom(prog: &[Instruction], idx: usize) -> Option<(Idiom, usize)> {
+    if prog.get(idx)?.token != Token::LoopBegin {
+        return None;
+    }
+    let end = matching(prog, idx)?;
+    match loop_idiom(prog, idx, end)? {
+        Idiom::IfElse(flag) => Some((Idiom::IfElse(flag), matching(prog, end + 2)?)),
+        idiom => Some((idiom, end)),
+    }
+}
+
+/// Idiom of the loop from the `[` at index `idx` of `prog` to the `]` at index `end`. Only
+/// looks at the body as far as necessary, so that all loops of a program can be classified in
+/// linear time in the common case.
+pub fn loop_idiom(prog: &[Instruction], idx: usize, end: usize) -> Option<Idiom> {
+    let body = &prog[idx + 1..end];
+    if let [ref x] = *body {
+        if matches!(x.token, Token::Increase | Token::Decrease) && x.multiplier % 2 == 1 {
+            return Some(Idiom::Clear);
+        }
+    }
+    if let Some(deltas) = deltas(body) {
+        if deltas.iter().any(|&(offset, delta)| offset == 0 && delta == u8::MAX) {
+            let targets = deltas.into_iter().filter(|&(offset, _)| offset != 0).collect();
+            return Some(Idiom::Transfer(targets));
+        }
+    }
+    if end >= idx + 4 && is_clear(prog, end - 3) && balanced(&prog[idx + 1..end - 3]) {
+        return Some(if_else(prog, idx, end).map_or(Idiom::If, Idiom::IfElse));
+    }
+    None
+}
+
+/// Returns `true` if a loop recognized as `Idiom::Clear` spans the indices `idx..idx + 3`.
+fn is_clear(prog: &[Instruction], idx: usize) -> bool {
+    prog.len() > idx + 2 && prog[idx].token == Token::LoopBegin
+        && prog[idx + 2].token == Token::LoopEnd
+        && loop_idiom(prog, idx, idx + 2) == Some(Idiom::Clear)
+}
+
+/// Returns `true` if a clear with the body `body` overwrites the cell with `stores`.
+fn kills(body: &Instruction, stores: DeadStores) -> bool {
+    stores == DeadStores::All || body.token == Token::Decrease && body.multiplier == 1
+}
+
+/// Offset of the flag if the `If` from index `idx` to `end` is the if of an `Idiom::IfElse`
+fn if_else(prog: &[Instruction], idx: usize, end: usize) -> Option<isize> {
+    let offset = |x: Option<&Instruction>| match x?.token {
+        Token::MoveRight => Some(x?.multiplier as isize),
+        Token::MoveLeft => Some(-(x?.multiplier as isize)),
+        _ => None,
+    };
+    // `>[-]+<` in front of the `[`
+    let flag = offset(prog.get(idx.checked_sub(6)?))?;
+    let set = &prog[idx - 2];
+    if offset(prog.get(idx - 1)) != Some(-flag) || !is_clear(prog, idx - 5)
+        || set.token != Token::Increase || set.multiplier != 1
+    {
+        return None;
+    }
+    // `>[B-]` after the `]`
+    if offset(prog.get(end + 1)) != Some(flag) || prog.get(end + 2)?.token != Token::LoopBegin {
+        return None;
+    }
+    let else_end = matching(prog, end + 2)?;
+    let other = &prog[end + 3..else_end];
+    if decrements_once(&prog[idx + 1..end - 3], flag) && balanced(other)
+        && decrements_once(other, 0)
+    {
+        Some(flag)
+    } else {
+        None
+    }
+}
+
+/// Returns `true` if the only instruction of the balanced `body` which touches the cell at
+/// `offset` is a single `-` outside of nested loops.
+fn decrements_once(body: &[Instruction], offset: isize) -> bool {
+    let (mut pos, mut depth, mut seen) = (0isize, 0usize, false);
+    for x in body {
+        match x.token {
+            Token::MoveRight => pos += x.multiplier as isize,
+            Token::MoveLeft => pos -= x.multiplier as isize,
+            _ if pos != offset => {}
+            Token::Decrease if depth == 0 && x.multiplier == 1 && !seen => seen = true,
+            _ => return false,
+        }
+        match x.token {
+            Token::LoopBegin => depth += 1,
+            Token::LoopEnd => depth -= 1,
+            _ => {}
+        }
+    }
+    seen
+}
+
+/// Index of the `]` matching the `[` at `idx`. Unlike `JumpTable`, this works on parts of a
+/// program.
+pub fn matching(prog: &[Instruction], idx: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, x) in prog.iter().enumerate().skip(idx) {
+        match x.token {
+            Token::LoopBegin => depth += 1,
+            Token::LoopEnd => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Net change of every touched cell of `body`, if it consists of `+-<>` only and returns to its
+/// start. Changes are modulo 256 and sorted by offset.
+fn deltas(body: &[Instruction]) -> Option<Vec<(isize, u8)>> {
+    let mut offset = 0isize;
+    let mut deltas: Vec<(isize, u8)> = vec![];
+    for x in body {
+        let m = x.multiplier;
+        let delta = match x.token {
+            Token::MoveRight => {
+                offset += m as isize;
+                continue;
+            }
+            Token::MoveLeft => {
+                offset -= m as isize;
+                continue;
+            }
+            Token::Increase => m,
+            Token::Decrease => m.wrapping_neg(),
+            _ => return None,
+        };
+        match deltas.iter_mut().find(|x| x.0 == offset) {
+            Some(d) => d.1 = d.1.wrapping_add(delta),
+            None => deltas.push((offset, delta)),
+        }
+    }
+    if offset != 0 {
+        return None;
+    }
+    deltas.sort_by_key(|x| x.0);
+    Some(deltas)
+}
+
+/// Returns `true` if the pointer is at the same cell before and after `body`, i.e. the moves of
+/// `body` cancel out and all loops in it are balanced as well. `body` has matched brackets.
+pub fn balanced(body: &[Instruction]) -> bool {
+    // Net movement of every loop which is still open, and of the body itself
+    let mut offsets = vec![0isize];
+    for x in body {
+        match x.token {
+            Token::MoveRight => *offsets.last_mut().unwrap() += x.multiplier as isize,
+            Token::MoveLeft => *offsets.last_mut().unwrap() -= x.multiplier as isize,
+            Token::MoveUp | Token::MoveDown | Token::SwitchTape => return false,
+            Token::LoopBegin => offsets.push(0),
+            // The loop is popped in any case.
+            Token::LoopEnd if offsets.pop() != Some(0) => return false,
+            _ => {}
+        }
+    }
+    offsets == [0]
+}
@@ -0,0 +1,234 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Parser
+
+use std::fmt;
+use error::ParseError;
+use lexer::{Limits, Span, Token, TokenStream};
+use logging::{span, Level};
+
+/// Largest repeat count accepted by `parser_with_counts`
+pub const MAX_REPEAT_COUNT: usize = 65535;
+
+/// A vector of `Instruction`s. This may be replace with a more complex data structure in the
+/// future.
+pub type InstructionStream = Vec<Instruction>;
+
+/// Describes a single Instruction
+#[derive(Debug, Clone)]
+pub struct Instruction {
+    /// Location of the first character of the instruction, e.g. for diagnostics. Its position
+    /// is the position of the instruction within the program.
+    pub span: Span,
+    /// Source position after the last character of the instruction. Fused instructions may
+    /// contain comments in between (see `Program::spans`).
+    pub end: usize,
+    /// Kind of instruction
+    pub token: Token,
+    /// Indicates how often this instruction is repeated
+    pub multiplier: u8,
+}
+
+impl Instruction {
+    /// Constructor. The span assumes a program of a single line of ASCII characters (see
+    /// `Span::at`).
+    pub fn new(position: usize, token: Token, multiplier: u8) -> Self {
+        Instruction::with_span(Span::at(position), token, multiplier)
+    }
+
+    /// Instruction of a single character at `span`
+    pub fn with_span(span: Span, token: Token, multiplier: u8) -> Self {
+        Instruction {
+            span,
+            end: span.position + 1,
+            token,
+            multiplier,
+        }
+    }
+
+    /// Position of the instruction within the program
+    pub fn position(&self) -> usize {
+        self.span.position
+    }
+}
+
+impl fmt::Display for Instruction {
+    /// Writes the instruction as brainfuck code, repeated `multiplier` times.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for _ in 0..self.multiplier {
+            write!(f, "{}", self.token)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks whether the stream of tokens can be parsed, i.e. whether all brackets are matched.
+pub fn validate(prog: &TokenStream) -> Result<(), ParseError> {
+    validate_with_limits(prog, &Limits::default())
+}
+
+/// Like `validate`, but also checks that loops are not nested deeper than `limits.max_depth`.
+pub fn validate_with_limits(prog: &TokenStream, limits: &Limits) -> Result<(), ParseError> {
+    let max_depth = limits.max_depth.unwrap_or(usize::MAX);
+    let mut stack = vec![];
+    for &(span, ref token) in prog {
+        let pos = span.position;
+        match *token {
+            Token::LoopBegin if stack.len() >= max_depth => {
+                return Err(ParseError::NestingTooDeep(pos, max_depth))
+            }
+            Token::LoopBegin => stack.push(pos),
+            Token::LoopEnd => {
+                stack.pop().ok_or(ParseError::UnmatchedLoopEnd(pos))?;
+            }
+            _ => {}
+        }
+    }
+    match stack.first() {
+        Some(&pos) => Err(ParseError::UnmatchedLoopBegin(pos)),
+        None => Ok(()),
+    }
+}
+
+/// Like `validate`, but does not stop at the first error: every unmatched `[` and `]` is
+/// reported, ordered by position. A `]` without a matching `[` is skipped, so the brackets after
+/// it are still matched.
+pub fn validate_all(prog: &TokenStream) -> Vec<ParseError> {
+    let mut errors = vec![];
+    let mut stack = vec![];
+    for &(span, ref token) in prog {
+        let pos = span.position;
+        match *token {
+            Token::LoopBegin => stack.push(pos),
+            Token::LoopEnd if stack.pop().is_none() => {
+                errors.push(ParseError::UnmatchedLoopEnd(pos))
+            }
+            _ => {}
+        }
+    }
+    errors.extend(stack.into_iter().map(ParseError::UnmatchedLoopBegin));
+    errors.sort_by_key(ParseError::position);
+    errors
+}
+
+/// Like `parser`, but a decimal number in `code` directly before an instruction repeats the
+/// instruction, e.g. `64+`. `prog` needs to be lexed from `code`. Runs of `+-<>^v` become
+/// instructions with the corresponding `multiplier` (split at 255), other instructions are
+/// repeated. Such instructions start at their number. A number before a bracket or a comment is
+/// a comment. Counts larger than `MAX_REPEAT_COUNT` are rejected, so that a few digits cannot
+/// exhaust the memory.
+pub fn parser_with_counts(prog: TokenStream, code: &str) -> Result<InstructionStream, ParseError> {
+    let _span = span("parser");
+    let chars: Vec<char> = code.chars().collect();
+    let mut instr: InstructionStream = vec![];
+    // Span of the first digit, end position and value of the number before the current token
+    let mut count: Option<(Span, usize, usize)> = None;
+    for (span, token) in prog {
+        let pos = span.position;
+        match (token, count) {
+            (Token::Comment, _) => {
+                count = match (chars.get(pos).and_then(|c| c.to_digit(10)), count) {
+                    (Some(d), Some((start, end, n))) if end == pos => {
+                        let n = n * 10 + d as usize;
+                        if n > MAX_REPEAT_COUNT {
+                            return Err(ParseError::RepeatCountTooLarge(
+                                start.position,
+                                MAX_REPEAT_COUNT,
+                            ));
+                        }
+                        Some((start, pos + 1, n))
+                    }
+                    (Some(d), _) => Some((span, pos + 1, d as usize)),
+                    (None, _) => None,
+                };
+                continue;
+            }
+            (token @ Token::LoopBegin, _) | (token @ Token::LoopEnd, _) | (token, None) => {
+                instr.push(Instruction::with_span(span, token, 1))
+            }
+            (token, Some((start, _, n))) => {
+                let foldable = matches!(
+                    token,
+                    Token::Increase
+                        | Token::Decrease
+                        | Token::MoveLeft
+                        | Token::MoveRight
+                        | Token::MoveUp
+                        | Token::MoveDown
+                );
+                let (chunk, times) = if foldable {
+                    (u8::MAX as usize, n.div_ceil(u8::MAX as usize))
+                } else {
+                    (1, n)
+                };
+                let mut left = n;
+                for _ in 0..times {
+                    let mut x = Instruction::with_span(start, token, left.min(chunk) as u8);
+                    x.end = pos + 1;
+                    instr.push(x);
+                    left -= left.min(chunk);
+                }
+            }
+        }
+        count = None;
+    }
+    log_msg!(Level::Info, "parser: {} instructions", instr.len());
+    Ok(instr)
+}
+
+/// Parses the stream of tokens. The stream is assumed to be valid (see `validate`).
+pub fn parser(prog: TokenStream) -> InstructionStream {
+    let _span = span("parser");
+    // get rid of everything that is not an instruction and map to instructions
+    let instr: InstructionStream = prog.into_iter()
+        .filter(|(_, x)| *x != Token::Comment)
+        .map(|(span, x)| Instruction::with_span(span, x, 1))
+        .collect();
+    log_msg!(Level::Info, "parser: {} instructions", instr.len());
+    instr
+}
+
+/// Maps every bracket of an `InstructionStream` to the index of its matching bracket
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JumpTable {
+    /// Index of the matching bracket for every bracket, the index of the instruction itself
+    /// otherwise
+    targets: Vec<usize>,
+}
+
+impl JumpTable {
+    /// Match the brackets of `instr`. All brackets need to be matched (see `validate`).
+    pub fn new(instr: &InstructionStream) -> Self {
+        let mut targets: Vec<usize> = (0..instr.len()).collect();
+        let mut stack = vec![];
+        for (idx, x) in instr.iter().enumerate() {
+            match x.token {
+                Token::LoopBegin => stack.push(idx),
+                Token::LoopEnd => {
+                    let begin = stack.pop().expect("Unmatched `]`, validate the program first.");
+                    log_msg!(
+                        Level::Debug,
+                        "parser: matched [ at {} with ] at {}",
+                        instr[begin].position(),
+                        x.position()
+                    );
+                    targets[begin] = idx;
+                    targets[idx] = begin;
+                }
+                _ => {}
+            }
+        }
+        JumpTable { targets }
+    }
+
+    /// Index of the bracket matching the bracket at index `idx`. For other instructions, `idx` is
+    /// returned.
+    pub fn get(&self, idx: usize) -> usize {
+        self.targets[idx]
+    }
+}
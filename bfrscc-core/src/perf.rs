@@ -0,0 +1,327 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Hardware counters
+//!
+//! Counts what the processor does while the run loop executes a program: the cycles, the
+//! retired instructions and branches, and the mispredicted branches. Unlike the wall time,
+//! these hardly depend on the load of the machine, so they compare dispatch strategies of the
+//! run loop objectively. With `RunConfig::perf`, the counts end up in `RunReport::perf`:
+//!
+//! ```
+//! use bfrscc_core::run::{run_with_config, RunConfig, RunError};
+//!
+//! let config = RunConfig {
+//!     perf: true,
+//!     ..RunConfig::default()
+//! };
+//! match run_with_config("++++++++[>++++++++<-]", &config) {
+//!     Ok(report) => println!("{}", report.perf.unwrap()),
+//!     // Without counters, e.g. in a virtual machine, nothing is executed.
+//!     Err(RunError::Perf(msg)) => println!("{}", msg),
+//!     Err(e) => panic!("{}", e),
+//! }
+//! ```
+//!
+//! The counters are opened with `perf_event_open(2)`, which requires Linux and the `perf`
+//! feature. Only the thread running the program is counted, and only in user space, which
+//! unprivileged processes are usually allowed to do (see `/proc/sys/kernel/perf_event_paranoid`).
+//! Virtual machines often provide no counters at all. Counters which cannot be opened are
+//! missing from the counts, and opening fails if none is available.
+
+use std::fmt;
+use std::io;
+use json::Object;
+
+#[cfg(all(feature = "perf", target_os = "linux"))]
+mod sys {
+    use std::os::raw::{c_int, c_long, c_ulong};
+
+    /// `PERF_TYPE_HARDWARE`
+    pub const TYPE_HARDWARE: u32 = 0;
+    /// `PERF_COUNT_HW_CPU_CYCLES`
+    pub const CPU_CYCLES: u64 = 0;
+    /// `PERF_COUNT_HW_INSTRUCTIONS`
+    pub const INSTRUCTIONS: u64 = 1;
+    /// `PERF_COUNT_HW_BRANCH_INSTRUCTIONS`
+    pub const BRANCH_INSTRUCTIONS: u64 = 4;
+    /// `PERF_COUNT_HW_BRANCH_MISSES`
+    pub const BRANCH_MISSES: u64 = 5;
+    /// `PERF_FORMAT_TOTAL_TIME_ENABLED | PERF_FORMAT_TOTAL_TIME_RUNNING`
+    pub const FORMAT_TIMES: u64 = 1 | 2;
+    /// The `disabled`, `exclude_kernel` and `exclude_hv` bits of the flags
+    pub const FLAGS: u64 = 1 | 1 << 5 | 1 << 6;
+    /// `PERF_FLAG_FD_CLOEXEC`
+    pub const FLAG_FD_CLOEXEC: c_ulong = 8;
+    /// `PERF_EVENT_IOC_ENABLE`
+    pub const IOC_ENABLE: c_ulong = 0x2400;
+    /// `PERF_EVENT_IOC_DISABLE`
+    pub const IOC_DISABLE: c_ulong = 0x2401;
+
+    /// Number of the `perf_event_open` system call
+    #[cfg(target_arch = "x86_64")]
+    pub const PERF_EVENT_OPEN: Option<c_long> = Some(298);
+    #[cfg(target_arch = "x86")]
+    pub const PERF_EVENT_OPEN: Option<c_long> = Some(336);
+    #[cfg(target_arch = "arm")]
+    pub const PERF_EVENT_OPEN: Option<c_long> = Some(364);
+    #[cfg(any(target_arch = "aarch64", target_arch = "riscv64"))]
+    pub const PERF_EVENT_OPEN: Option<c_long> = Some(241);
+    #[cfg(not(any(
+        target_arch = "x86_64",
+        target_arch = "x86",
+        target_arch = "arm",
+        target_arch = "aarch64",
+        target_arch = "riscv64"
+    )))]
+    pub const PERF_EVENT_OPEN: Option<c_long> = None;
+
+    /// First version of `struct perf_event_attr`, which all kernels accept
+    #[repr(C)]
+    #[derive(Default)]
+    pub struct Attr {
+        pub kind: u32,
+        pub size: u32,
+        pub config: u64,
+        pub sample_period: u64,
+        pub sample_type: u64,
+        pub read_format: u64,
+        pub flags: u64,
+        pub wakeup_events: u32,
+        pub bp_type: u32,
+        pub config1: u64,
+    }
+
+    extern "C" {
+        pub fn syscall(number: c_long, ...) -> c_long;
+        pub fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+    }
+}
+
+/// Counts of the run loop. Counters which are not available are `None`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PerfCounts {
+    /// Processor cycles
+    pub cycles: Option<u64>,
+    /// Retired instructions
+    pub instructions: Option<u64>,
+    /// Retired branch instructions
+    pub branches: Option<u64>,
+    /// Mispredicted branches
+    pub branch_misses: Option<u64>,
+}
+
+impl PerfCounts {
+    /// Retired instructions per cycle
+    pub fn instructions_per_cycle(&self) -> Option<f64> {
+        match (self.instructions, self.cycles) {
+            (Some(instructions), Some(cycles)) if cycles > 0 => {
+                Some(instructions as f64 / cycles as f64)
+            }
+            _ => None,
+        }
+    }
+
+    /// Fraction of the branches which were mispredicted
+    pub fn miss_rate(&self) -> Option<f64> {
+        match (self.branch_misses, self.branches) {
+            (Some(misses), Some(branches)) if branches > 0 => Some(misses as f64 / branches as f64),
+            _ => None,
+        }
+    }
+
+    /// Add the counts of another part of the same run, e.g. after resuming it.
+    pub fn add(&mut self, other: &PerfCounts) {
+        let sum = |a: Option<u64>, b: Option<u64>| match (a, b) {
+            (Some(a), Some(b)) => Some(a + b),
+            (a, b) => a.or(b),
+        };
+        self.cycles = sum(self.cycles, other.cycles);
+        self.instructions = sum(self.instructions, other.instructions);
+        self.branches = sum(self.branches, other.branches);
+        self.branch_misses = sum(self.branch_misses, other.branch_misses);
+    }
+
+    /// The counts as a JSON object, `null` for counters which are not available
+    pub fn to_json(&self) -> String {
+        Object::new()
+            .optional("cycles", self.cycles)
+            .optional("instructions", self.instructions)
+            .optional("branches", self.branches)
+            .optional("branch_misses", self.branch_misses)
+            .finish()
+    }
+}
+
+impl fmt::Display for PerfCounts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let count = |x: Option<u64>| x.map_or("not available".to_string(), |x| x.to_string());
+        writeln!(f, "cycles:          {}", count(self.cycles))?;
+        write!(f, "instructions:    {}", count(self.instructions))?;
+        if let Some(ipc) = self.instructions_per_cycle() {
+            write!(f, " ({:.2} per cycle)", ipc)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "branches:        {}", count(self.branches))?;
+        write!(f, "branch misses:   {}", count(self.branch_misses))?;
+        if let Some(rate) = self.miss_rate() {
+            write!(f, " ({:.2}% of branches)", rate * 100.0)?;
+        }
+        Ok(())
+    }
+}
+
+/// Hardware counters of the current thread, which count while they are enabled
+#[derive(Debug)]
+pub struct Counters {
+    /// Cycles, instructions, branches and branch misses, if available
+    #[cfg(all(feature = "perf", target_os = "linux"))]
+    files: [Option<::std::fs::File>; 4],
+}
+
+#[cfg(all(feature = "perf", target_os = "linux"))]
+impl Counters {
+    /// Open the counters, which are disabled at first.
+    pub fn open() -> io::Result<Self> {
+        let events = [
+            sys::CPU_CYCLES,
+            sys::INSTRUCTIONS,
+            sys::BRANCH_INSTRUCTIONS,
+            sys::BRANCH_MISSES,
+        ];
+        let mut error = None;
+        let mut files = [None, None, None, None];
+        for (file, &event) in files.iter_mut().zip(events.iter()) {
+            match Counters::open_event(event) {
+                Ok(f) => *file = Some(f),
+                Err(e) => error = Some(e),
+            }
+        }
+        match error {
+            Some(e) if files.iter().all(Option::is_none) => Err(e),
+            _ => Ok(Counters { files }),
+        }
+    }
+
+    /// Start counting.
+    pub fn enable(&self) -> io::Result<()> {
+        self.control(sys::IOC_ENABLE)
+    }
+
+    /// Stop counting.
+    pub fn disable(&self) -> io::Result<()> {
+        self.control(sys::IOC_DISABLE)
+    }
+
+    /// Counts so far. If other counters needed the hardware as well, the kernel counted only
+    /// part of the time, and the counts are extrapolated to the whole time.
+    pub fn read(&self) -> io::Result<PerfCounts> {
+        use std::io::Read;
+
+        let mut counts = [None; 4];
+        for (count, file) in counts.iter_mut().zip(self.files.iter()) {
+            if let Some(ref file) = *file {
+                // The value, the time enabled and the time running
+                let mut buf = [0; 24];
+                (&*file).read_exact(&mut buf)?;
+                let word = |i: usize| {
+                    let mut bytes = [0; 8];
+                    bytes.copy_from_slice(&buf[8 * i..8 * (i + 1)]);
+                    u64::from_ne_bytes(bytes) as u128
+                };
+                let (value, enabled, running) = (word(0), word(1), word(2));
+                *count = Some(match running {
+                    0 => 0,
+                    running => (value * enabled / running) as u64,
+                });
+            }
+        }
+        Ok(PerfCounts {
+            cycles: counts[0],
+            instructions: counts[1],
+            branches: counts[2],
+            branch_misses: counts[3],
+        })
+    }
+
+    /// Open the counter of hardware event `config` for the current thread.
+    fn open_event(config: u64) -> io::Result<::std::fs::File> {
+        use std::mem;
+        use std::os::raw::c_int;
+        use std::os::unix::io::FromRawFd;
+
+        let number = sys::PERF_EVENT_OPEN.ok_or_else(|| {
+            io::Error::other("not supported on this architecture")
+        })?;
+        let attr = sys::Attr {
+            kind: sys::TYPE_HARDWARE,
+            size: mem::size_of::<sys::Attr>() as u32,
+            config,
+            read_format: sys::FORMAT_TIMES,
+            flags: sys::FLAGS,
+            ..sys::Attr::default()
+        };
+        // The current thread (0) on any processor (-1), without a group (-1)
+        let fd = unsafe {
+            sys::syscall(
+                number,
+                &attr as *const sys::Attr,
+                0 as c_int,
+                -1 as c_int,
+                -1 as c_int,
+                sys::FLAG_FD_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            let e = io::Error::last_os_error();
+            let hint = match e.kind() {
+                io::ErrorKind::NotFound => "not provided by this processor or virtual machine",
+                io::ErrorKind::PermissionDenied => "not permitted, see perf_event_paranoid",
+                _ => return Err(e),
+            };
+            return Err(io::Error::new(e.kind(), hint));
+        }
+        Ok(unsafe { ::std::fs::File::from_raw_fd(fd as c_int) })
+    }
+
+    /// Apply `request` to all counters.
+    fn control(&self, request: ::std::os::raw::c_ulong) -> io::Result<()> {
+        use std::os::unix::io::AsRawFd;
+
+        for file in self.files.iter().flatten() {
+            if unsafe { sys::ioctl(file.as_raw_fd(), request, 0) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(all(feature = "perf", target_os = "linux")))]
+impl Counters {
+    /// Fails, hardware counters require Linux and the `perf` feature.
+    pub fn open() -> io::Result<Self> {
+        Err(io::Error::other(
+            "not supported by this build, which requires Linux and the `perf` feature",
+        ))
+    }
+
+    /// Start counting.
+    pub fn enable(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Stop counting.
+    pub fn disable(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Counts so far
+    pub fn read(&self) -> io::Result<PerfCounts> {
+        Ok(PerfCounts::default())
+    }
+}
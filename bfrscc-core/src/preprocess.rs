@@ -0,0 +1,305 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Preprocessor
+//!
+//! An optional stage before the lexer which composes programs from reusable pieces:
+//!
+//! ```text
+//! @include "print.bf"
+//! @define clear { [-] }
+//! ++++ @use clear
+//! ```
+//!
+//! `@define name { ... }` defines a snippet, `@use name` inserts it and `@include "file"` inserts
+//! a file, relative to the including file. Snippets may use other snippets and are visible in
+//! all files after their definition. Any other `@` is a comment, as in brainfuck. Every
+//! character of the expansion remembers where it came from, see `Expanded::origin`.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use lexer::source_from_bytes;
+
+/// Where a character of the expansion came from
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Origin {
+    /// Index into `Expanded::files`
+    file: usize,
+    /// Position of the character within the file
+    pos: usize,
+}
+
+/// Errors of the preprocessor. The first two fields are the file and the position of the
+/// offending directive.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PreprocessError {
+    /// Malformed directive, with a description
+    Syntax(String, usize, &'static str),
+    /// `@use` of a snippet which is not defined
+    Undefined(String, usize, String),
+    /// `@use` of a snippet within its own expansion
+    RecursiveUse(String, usize, String),
+    /// `@include` of a file within its own expansion
+    RecursiveInclude(String, usize, PathBuf),
+    /// `@include` of a file which cannot be read, with the reason
+    Include(String, usize, PathBuf, String),
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PreprocessError::Syntax(ref file, pos, msg) => {
+                write!(f, "{}: {} at position {}", file, msg, pos)
+            }
+            PreprocessError::Undefined(ref file, pos, ref name) => {
+                write!(f, "{}: snippet `{}` at position {} is not defined", file, name, pos)
+            }
+            PreprocessError::RecursiveUse(ref file, pos, ref name) => {
+                write!(f, "{}: snippet `{}` at position {} uses itself", file, name, pos)
+            }
+            PreprocessError::RecursiveInclude(ref file, pos, ref path) => write!(
+                f,
+                "{}: `{}` at position {} includes itself",
+                file,
+                path.display(),
+                pos
+            ),
+            PreprocessError::Include(ref file, pos, ref path, ref e) => write!(
+                f,
+                "{}: unable to include `{}` at position {}: {}",
+                file,
+                path.display(),
+                pos,
+                e
+            ),
+        }
+    }
+}
+
+impl Error for PreprocessError {}
+
+/// Result of the preprocessor
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expanded {
+    /// Expanded source, which is passed to the lexer
+    pub source: String,
+    /// Origin of every character of `source`
+    origins: Vec<Origin>,
+    /// Names of the main file and all included files
+    files: Vec<String>,
+}
+
+impl Expanded {
+    /// File and position which the character at position `pos` of the expanded source came
+    /// from. For characters of snippets, this is the position within the `@define`.
+    pub fn origin(&self, pos: usize) -> Option<(&str, usize)> {
+        self.origins
+            .get(pos)
+            .map(|o| (self.files[o.file].as_str(), o.pos))
+    }
+}
+
+/// State of the expansion
+struct Preprocessor {
+    /// Names of all files
+    files: Vec<String>,
+    /// Bodies of the defined snippets
+    snippets: HashMap<String, Vec<(char, Origin)>>,
+    /// Snippets which are currently being expanded
+    uses: Vec<String>,
+    /// Files which are currently being expanded
+    includes: Vec<PathBuf>,
+}
+
+/// Expand the directives of `source`, which is stored at `path`. `path` names the source in
+/// errors and includes are resolved relative to it.
+pub fn preprocess(source: &str, path: &Path) -> Result<Expanded, PreprocessError> {
+    let mut pp = Preprocessor {
+        files: vec![path.display().to_string()],
+        snippets: HashMap::new(),
+        uses: vec![],
+        includes: vec![fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())],
+    };
+    let input = chars(source, 0);
+    let mut out = vec![];
+    pp.expand(&input, dir(path), &mut out)?;
+    Ok(Expanded {
+        source: out.iter().map(|&(c, _)| c).collect(),
+        origins: out.into_iter().map(|(_, o)| o).collect(),
+        files: pp.files,
+    })
+}
+
+/// Characters of `source` of file `file` together with their origin
+fn chars(source: &str, file: usize) -> Vec<(char, Origin)> {
+    source
+        .chars()
+        .enumerate()
+        .map(|(pos, c)| (c, Origin { file, pos }))
+        .collect()
+}
+
+/// Directory which includes of the file at `path` are relative to
+fn dir(path: &Path) -> &Path {
+    path.parent().unwrap_or_else(|| Path::new(""))
+}
+
+impl Preprocessor {
+    /// Expand `input` into `out`. Includes are relative to `dir`.
+    fn expand(
+        &mut self,
+        input: &[(char, Origin)],
+        dir: &Path,
+        out: &mut Vec<(char, Origin)>,
+    ) -> Result<(), PreprocessError> {
+        let mut i = 0;
+        while i < input.len() {
+            let (c, origin) = input[i];
+            let directive = if c == '@' { word(input, i + 1) } else { "".into() };
+            match directive.as_str() {
+                "define" => i = self.define(input, i, i + 1 + directive.len())?,
+                "use" => {
+                    let (name, end) = self.name(input, i, i + 1 + directive.len())?;
+                    if self.uses.contains(&name) {
+                        return Err(self.error(origin, |f, p| {
+                            PreprocessError::RecursiveUse(f, p, name.clone())
+                        }));
+                    }
+                    let body = match self.snippets.get(&name) {
+                        Some(body) => body.clone(),
+                        None => {
+                            return Err(self.error(origin, |f, p| {
+                                PreprocessError::Undefined(f, p, name.clone())
+                            }))
+                        }
+                    };
+                    self.uses.push(name);
+                    self.expand(&body, dir, out)?;
+                    self.uses.pop();
+                    i = end;
+                }
+                "include" => i = self.include(input, i, i + 1 + directive.len(), dir, out)?,
+                _ => {
+                    out.push((c, origin));
+                    i += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle the `@define` at `at`, whose name starts after `start`. Returns the position after
+    /// the body.
+    fn define(
+        &mut self,
+        input: &[(char, Origin)],
+        at: usize,
+        start: usize,
+    ) -> Result<usize, PreprocessError> {
+        let (name, end) = self.name(input, at, start)?;
+        let open = skip_whitespace(input, end);
+        if input.get(open).map(|x| x.0) != Some('{') {
+            return Err(self.syntax(input[at].1, "expected `{` after `@define` name"));
+        }
+        let close = match input[open..].iter().position(|x| x.0 == '}') {
+            Some(len) => open + len,
+            None => return Err(self.syntax(input[at].1, "unterminated `@define`")),
+        };
+        self.snippets.insert(name, input[open + 1..close].to_vec());
+        Ok(close + 1)
+    }
+
+    /// Handle the `@include` at `at`, whose path starts after `start`. Returns the position
+    /// after the path.
+    fn include(
+        &mut self,
+        input: &[(char, Origin)],
+        at: usize,
+        start: usize,
+        dir: &Path,
+        out: &mut Vec<(char, Origin)>,
+    ) -> Result<usize, PreprocessError> {
+        let origin = input[at].1;
+        let open = skip_whitespace(input, start);
+        if input.get(open).map(|x| x.0) != Some('"') {
+            return Err(self.syntax(origin, "expected a quoted path after `@include`"));
+        }
+        let close = match input[open + 1..].iter().position(|x| x.0 == '"') {
+            Some(len) => open + 1 + len,
+            None => return Err(self.syntax(origin, "unterminated path of `@include`")),
+        };
+        let path = dir.join(input[open + 1..close].iter().map(|x| x.0).collect::<String>());
+        let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+        if self.includes.contains(&canonical) {
+            return Err(self.error(origin, |f, p| {
+                PreprocessError::RecursiveInclude(f, p, path.clone())
+            }));
+        }
+        let source = fs::read(&path).map(source_from_bytes).map_err(|e| {
+            self.error(origin, |f, p| {
+                PreprocessError::Include(f, p, path.clone(), e.to_string())
+            })
+        })?;
+        self.files.push(path.display().to_string());
+        let chars = chars(&source, self.files.len() - 1);
+        self.includes.push(canonical);
+        self.expand(&chars, self::dir(&path), out)?;
+        self.includes.pop();
+        Ok(close + 1)
+    }
+
+    /// Name following the directive at `at`, starting after `start`, and the position after it
+    fn name(
+        &self,
+        input: &[(char, Origin)],
+        at: usize,
+        start: usize,
+    ) -> Result<(String, usize), PreprocessError> {
+        let start = skip_whitespace(input, start);
+        let name = word(input, start);
+        if name.is_empty() {
+            return Err(self.syntax(input[at].1, "expected a snippet name"));
+        }
+        let end = start + name.chars().count();
+        Ok((name, end))
+    }
+
+    /// Error at `origin`
+    fn error<F>(&self, origin: Origin, f: F) -> PreprocessError
+    where
+        F: FnOnce(String, usize) -> PreprocessError,
+    {
+        f(self.files[origin.file].clone(), origin.pos)
+    }
+
+    /// Syntax error at `origin`
+    fn syntax(&self, origin: Origin, msg: &'static str) -> PreprocessError {
+        self.error(origin, |f, p| PreprocessError::Syntax(f, p, msg))
+    }
+}
+
+/// Word of letters, digits and `_` starting at `start`
+fn word(input: &[(char, Origin)], start: usize) -> String {
+    input
+        .iter()
+        .skip(start)
+        .map(|x| x.0)
+        .take_while(|&c| c.is_alphanumeric() || c == '_')
+        .collect()
+}
+
+/// Position of the first character at or after `start` which is not whitespace
+fn skip_whitespace(input: &[(char, Origin)], start: usize) -> usize {
+    input
+        .iter()
+        .skip(start)
+        .position(|x| !x.0.is_whitespace())
+        .map_or(input.len(), |len| start + len)
+}
@@ -0,0 +1,643 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Program
+//!
+//! A `Program` is what is passed between the stages of the pipeline: it owns the source, the
+//! instructions, the jump table and some metadata.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+use std::sync::Arc;
+use annotations::{Annotations, CellNames};
+#[cfg(feature = "interpreter")]
+use buffer::BufferMachine;
+use error::ParseError;
+#[cfg(feature = "interpreter")]
+use interpreter::Interpreter;
+use lexer::{
+    lexer, lexer_with_options, split_input, CommentPolicy, Dialect, LexerOptions, Span, Token,
+};
+use optimizer::{loop_idiom, optimize, remove_dead_stores, unroll, DeadStores, Idiom};
+use parser::{parser, parser_with_counts, validate_with_limits, InstructionStream, JumpTable};
+
+/// A parsed program together with its source. All positions are positions of characters within
+/// the source. Programs are cheap to clone and can be shared between threads, every execution
+/// gets its own machine (see `spawn_machine`).
+#[derive(Debug, Clone)]
+pub struct Program {
+    /// Shared content of the program
+    inner: Arc<Inner>,
+}
+
+/// Packed form of an instruction, as executed by the run loop. Brackets know the index of their
+/// matching bracket and how their loop behaves, so no lookups or analyses are necessary while
+/// running.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Op {
+    /// Kind of instruction
+    pub token: Token,
+    /// Indicates how often this instruction is repeated
+    pub multiplier: u8,
+    /// Loop depth inside a `[` once it is entered, `0` for other instructions
+    pub depth: u32,
+    /// Index of the matching bracket, the index of the instruction itself otherwise
+    pub jump: u32,
+    /// For brackets: the pointer is at the same cell after every iteration of the loop (see
+    /// `optimizer::balanced`)
+    pub balanced: bool,
+    /// For brackets: the loop reads or writes, directly or in a nested loop
+    pub io: bool,
+    /// For brackets: the shape of the loop
+    pub kind: LoopKind,
+    /// Number of consecutive instructions from this one which only operate on the current cell
+    /// (`+-.,`), `0` for other instructions. The run loop executes such runs at once (see
+    /// `Machine::exec_run`).
+    pub run: u16,
+}
+
+/// Shape of a loop, as recognized by `optimizer::loop_idiom`. Backends can emit conditionals
+/// instead of loops, and the run loop executes `[-]` and `Transfer` loops in a single step.
+/// `If`, `IfElse` and `Else` are only used by backends and analyses: the run loop iterates them
+/// like any other loop, so that a cell written by the debugger within their body is tested
+/// at their `]` as usual.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LoopKind {
+    /// Any other loop
+    #[default]
+    Loop,
+    /// `[-]`, which clears the cell with every `Arithmetic` and type of cells
+    Clear,
+    /// A loop which runs at most once (see `Idiom::If`)
+    If,
+    /// The if of an if/else (see `Idiom::IfElse`)
+    IfElse,
+    /// The else of an if/else, which runs exactly if the if did not
+    Else,
+    /// A loop with a known net effect, executed in a single step (see `Program::effects`)
+    Transfer,
+}
+
+impl LoopKind {
+    /// Returns `true` if the run loop executes the loop in a single step, i.e. never reaches its
+    /// `]`.
+    pub fn is_single_step(&self) -> bool {
+        matches!(*self, LoopKind::Clear | LoopKind::Transfer)
+    }
+}
+
+/// Net effects of loops by the index of their `[` (see `Program::effects`)
+type Effects = HashMap<usize, Vec<(isize, u8)>>;
+
+/// Content of a `Program`
+#[derive(Debug, Clone)]
+struct Inner {
+    /// Source of the program
+    source: String,
+    /// Name of the source, for instance the file name
+    name: Option<String>,
+    /// Options the source was lexed with
+    lexer: LexerOptions,
+    /// Optimization level, `0` means unoptimized
+    opt_level: u8,
+    /// Input following a top-level `!` (with `CommentPolicy::Dialect`)
+    inline_input: Option<Vec<u8>>,
+    /// Parsed instructions
+    instructions: InstructionStream,
+    /// Indices of matching brackets
+    jumps: JumpTable,
+    /// Packed instructions
+    ops: Vec<Op>,
+    /// Net effects of the loops of kind `LoopKind::Transfer`
+    effects: Effects,
+    /// Maps the position of every bracket to the position of its matching bracket
+    brackets: HashMap<usize, usize>,
+    /// Loop depth of every character of the source
+    depths: Vec<usize>,
+    /// Ranges of source positions between instructions, e.g. comments, in order
+    comments: Vec<Range<usize>>,
+    /// Metadata given by annotations in the source
+    annotations: Annotations,
+}
+
+impl Program {
+    /// Lex and parse `source`. All brackets need to be matched, use `str::parse` to handle
+    /// invalid programs gracefully.
+    pub fn new(source: &str) -> Self {
+        Program::from_parts(
+            source,
+            LexerOptions::default(),
+            None,
+            parser(lexer(source)),
+        )
+    }
+
+    /// Lex `source` with `options`, validate and parse it, and optimize it if `opt_level` is
+    /// greater than `0`. With `CommentPolicy::Dialect`, the source is split into code and inline
+    /// input at the first top-level `!`.
+    pub fn compile(
+        source: &str,
+        options: &LexerOptions,
+        opt_level: u8,
+    ) -> Result<Self, ParseError> {
+        let (code, inline) = match options.comments {
+            CommentPolicy::Dialect => split_input(source),
+            _ => (source, None),
+        };
+        let tokens = lexer_with_options(code, options)?;
+        validate_with_limits(&tokens, &options.limits)?;
+        let mut prog = Program::from_parts(
+            source,
+            options.clone(),
+            inline.map(|x| x.as_bytes().to_vec()),
+            if options.repeat_counts {
+                parser_with_counts(tokens, code)?
+            } else {
+                parser(tokens)
+            },
+        );
+        if opt_level > 0 {
+            prog = prog.optimized();
+        }
+        Ok(prog)
+    }
+
+    /// Create a program from `instructions`, e.g. generated by a front-end. The source is the
+    /// canonical brainfuck of the instructions, their positions and spans are set accordingly.
+    /// All brackets need to be matched.
+    pub fn from_instructions(instructions: InstructionStream) -> Self {
+        let mut pos = 0;
+        let instructions: InstructionStream = instructions
+            .into_iter()
+            .map(|mut x| {
+                let len = x.multiplier as usize;
+                x.span = Span {
+                    len,
+                    ..Span::at(pos)
+                };
+                x.end = pos + len;
+                pos += len;
+                x
+            })
+            .collect();
+        let source: String = instructions.iter().map(|x| x.to_string()).collect();
+        Program::from_parts(&source, LexerOptions::default(), None, instructions)
+    }
+
+    /// Like `from_instructions`, for instructions which were lexed with `lexer` and optimized at
+    /// level `opt_level` before, e.g. loaded from bytecode (see `bytecode`).
+    pub fn from_compiled(
+        instructions: InstructionStream,
+        lexer: LexerOptions,
+        inline_input: Option<Vec<u8>>,
+        opt_level: u8,
+    ) -> Self {
+        let mut prog = Program::from_instructions(instructions);
+        let inner = Arc::make_mut(&mut prog.inner);
+        inner.lexer = lexer;
+        inner.inline_input = inline_input;
+        inner.opt_level = opt_level;
+        prog
+    }
+
+    /// Create a program from `instructions` and their packed form `ops`, e.g. compiled from a
+    /// stream (see `stream::compile_reader`). Such a program has no source. The jump targets of
+    /// `ops` need to be set.
+    pub fn from_ops(
+        instructions: InstructionStream,
+        ops: Vec<Op>,
+        lexer: LexerOptions,
+        inline_input: Option<Vec<u8>>,
+        comments: Vec<Range<usize>>,
+    ) -> Self {
+        let jumps = JumpTable::new(&instructions);
+        let brackets = brackets(&instructions, &jumps);
+        Program {
+            inner: Arc::new(Inner {
+                source: String::new(),
+                name: None,
+                lexer,
+                opt_level: 0,
+                inline_input,
+                instructions,
+                jumps,
+                ops,
+                effects: HashMap::new(),
+                brackets,
+                depths: vec![],
+                comments,
+                annotations: Annotations::default(),
+            }),
+        }
+    }
+
+    /// Assemble a program. `instructions` need to be parsed from `source`.
+    fn from_parts(
+        source: &str,
+        lexer: LexerOptions,
+        inline_input: Option<Vec<u8>>,
+        instructions: InstructionStream,
+    ) -> Self {
+        let jumps = JumpTable::new(&instructions);
+        let brackets = brackets(&instructions, &jumps);
+        let mut depth = 0usize;
+        let depths: Vec<usize> = source
+            .chars()
+            .map(|c| match c {
+                '[' => {
+                    depth += 1;
+                    depth - 1
+                }
+                ']' => {
+                    // Inline input does not need to be balanced.
+                    depth = depth.saturating_sub(1);
+                    depth
+                }
+                _ => depth,
+            })
+            .collect();
+        let (ops, effects) = pack(&instructions, &jumps, &depths);
+        let mut comments = vec![];
+        let mut end = 0;
+        for x in &instructions {
+            if x.position() > end {
+                comments.push(end..x.position());
+            }
+            end = end.max(x.end);
+        }
+        Program {
+            inner: Arc::new(Inner {
+                source: source.to_string(),
+                name: None,
+                lexer,
+                opt_level: 0,
+                inline_input,
+                instructions,
+                jumps,
+                ops,
+                effects,
+                brackets,
+                depths,
+                comments,
+                annotations: Annotations::parse(source),
+            }),
+        }
+    }
+
+    /// Return the program with optimized instructions (see `optimizer::optimize`,
+    /// `optimizer::unroll` and `optimizer::remove_dead_stores`), for cells which wrap around.
+    pub fn optimized(self) -> Self {
+        self.optimized_with(DeadStores::All)
+    }
+
+    /// Like `optimized`, but removes only the dead stores selected by `stores`.
+    pub fn optimized_with(mut self, stores: DeadStores) -> Self {
+        let inner = Arc::make_mut(&mut self.inner);
+        let instructions = unroll(optimize(::std::mem::take(&mut inner.instructions)));
+        inner.instructions = remove_dead_stores(instructions, stores);
+        inner.jumps = JumpTable::new(&inner.instructions);
+        let (ops, effects) = pack(&inner.instructions, &inner.jumps, &inner.depths);
+        inner.ops = ops;
+        inner.effects = effects;
+        inner.opt_level = 1;
+        self
+    }
+
+    /// Return the program with `instructions` instead of its own, e.g. the output of a pass which
+    /// is not part of the optimizer. The instructions need to be derived from the source of the
+    /// program and all brackets need to be matched.
+    pub fn with_instructions(mut self, instructions: InstructionStream) -> Self {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.instructions = instructions;
+        inner.jumps = JumpTable::new(&inner.instructions);
+        let (ops, effects) = pack(&inner.instructions, &inner.jumps, &inner.depths);
+        inner.ops = ops;
+        inner.effects = effects;
+        self
+    }
+
+    /// Set the name of the program, for instance the file name.
+    pub fn with_name(mut self, name: &str) -> Self {
+        Arc::make_mut(&mut self.inner).name = Some(name.to_string());
+        self
+    }
+
+    /// Create a machine for an execution of this program. The machine has the default settings,
+    /// e.g. its random bytes are those of seed `0`; `run::interpreter` sets up a machine for a
+    /// `RunConfig`. Every machine has its own tapes, so the program can be parsed once and
+    /// executed many times concurrently:
+    ///
+    /// ```
+    /// use std::thread;
+    /// use bfrscc_core::program::Program;
+    /// use bfrscc_core::run::{run_program, RunConfig};
+    ///
+    /// let prog: Program = "++++++++[>++++++++<-]>+.".parse().unwrap();
+    /// let threads: Vec<_> = (0..4)
+    ///     .map(|_| {
+    ///         let prog = prog.clone();
+    ///         thread::spawn(move || {
+    ///             let mut machine = prog.spawn_machine();
+    ///             run_program(&prog, &mut machine, &RunConfig::default()).unwrap();
+    ///             machine.take_output()
+    ///         })
+    ///     })
+    ///     .collect();
+    /// for t in threads {
+    ///     assert_eq!(t.join().unwrap(), b"A");
+    /// }
+    /// ```
+    #[cfg(feature = "interpreter")]
+    pub fn spawn_machine(&self) -> BufferMachine {
+        BufferMachine::with_interpreter(Interpreter::with_tapes(self.dialect().tapes()))
+    }
+
+    /// Source of the program
+    pub fn source(&self) -> &str {
+        &self.inner.source
+    }
+
+    /// Name of the program
+    pub fn name(&self) -> Option<&str> {
+        self.inner.name.as_deref()
+    }
+
+    /// Options the program was lexed with
+    pub fn lexer_options(&self) -> &LexerOptions {
+        &self.inner.lexer
+    }
+
+    /// Dialect of the program
+    pub fn dialect(&self) -> Dialect {
+        self.inner.lexer.dialect
+    }
+
+    /// Names of cells given by `;; cell <n> = <name>` comments (see `annotations`)
+    pub fn cell_names(&self) -> &CellNames {
+        &self.inner.annotations.names
+    }
+
+    /// Output given by `;; expect: "<output>"` comments (see `annotations`)
+    pub fn expected_output(&self) -> Option<&[u8]> {
+        self.inner.annotations.expect.as_deref()
+    }
+
+    /// Optimization level
+    pub fn opt_level(&self) -> u8 {
+        self.inner.opt_level
+    }
+
+    /// Input following a top-level `!`, if any
+    pub fn inline_input(&self) -> Option<&[u8]> {
+        self.inner.inline_input.as_deref()
+    }
+
+    /// Parsed instructions
+    pub fn instructions(&self) -> &InstructionStream {
+        &self.inner.instructions
+    }
+
+    /// Number of instructions
+    pub fn len(&self) -> usize {
+        self.inner.ops.len()
+    }
+
+    /// Returns `true` if the program has no instructions.
+    pub fn is_empty(&self) -> bool {
+        self.inner.ops.is_empty()
+    }
+
+    /// Index of the instruction matching the bracket at instruction index `idx`. For other
+    /// instructions, `idx` is returned.
+    pub fn jump(&self, idx: usize) -> usize {
+        self.inner.jumps.get(idx)
+    }
+
+    /// Packed instructions, indexed like `instructions`:
+    ///
+    /// ```
+    /// use bfrscc_core::program::Program;
+    ///
+    /// let prog: Program = "+[>+<-]>[.>]".parse().unwrap();
+    /// let ops = prog.ops();
+    /// assert_eq!(ops[1].jump, 6);
+    /// assert!(ops[1].balanced && !ops[1].io);
+    /// assert!(!ops[8].balanced && ops[8].io);
+    /// ```
+    pub fn ops(&self) -> &[Op] {
+        &self.inner.ops
+    }
+
+    /// Net effect of the loop starting at instruction index `idx`, if its body only consists of
+    /// `+-<>`, returns to its cell and decrements it once: an iteration adds the factor to the
+    /// cell at every offset. The whole loop adds the current cell times the factor to the cells
+    /// and clears the current cell. Returns `None` for other loops and instructions.
+    ///
+    /// ```
+    /// use bfrscc_core::program::Program;
+    ///
+    /// let prog: Program = "+++[->++>-<<]".parse().unwrap();
+    /// assert_eq!(prog.effects(3), Some(&[(1, 2), (2, 255)][..]));
+    /// assert_eq!(prog.effects(0), None);
+    /// ```
+    pub fn effects(&self, idx: usize) -> Option<&[(isize, u8)]> {
+        self.inner.effects.get(&idx).map(|x| &x[..])
+    }
+
+    /// Indices of matching brackets
+    pub fn jump_table(&self) -> &JumpTable {
+        &self.inner.jumps
+    }
+
+    /// Index of the instruction which originates from source position `pos`. Returns `None` if
+    /// `pos` is not part of an instruction, e.g. a comment.
+    pub fn instruction_at(&self, pos: usize) -> Option<usize> {
+        let i = self.inner.comments.partition_point(|c| c.end <= pos);
+        if self.inner.comments.get(i).is_some_and(|c| c.start <= pos) {
+            return None;
+        }
+        self.inner.instructions
+            .iter()
+            .position(|x| x.position() <= pos && pos < x.end)
+    }
+
+    /// Ranges of source positions instruction `idx` originates from. Fused instructions may come
+    /// from several ranges which are separated by comments.
+    ///
+    /// ```
+    /// use bfrscc_core::program::Program;
+    ///
+    /// let prog = Program::new("++ +.").optimized();
+    /// assert_eq!(prog.spans(0), [0..2, 3..4]);
+    /// assert_eq!(prog.spans(1), [4..5]);
+    /// ```
+    pub fn spans(&self, idx: usize) -> Vec<Range<usize>> {
+        let x = &self.inner.instructions[idx];
+        let first = self.inner.comments.partition_point(|c| c.end <= x.position());
+        let mut spans = vec![];
+        let mut start = x.position();
+        for c in self.inner.comments[first..].iter().take_while(|c| c.start < x.end) {
+            if start < c.start {
+                spans.push(start..c.start);
+            }
+            start = start.max(c.end);
+        }
+        if start < x.end {
+            spans.push(start..x.end);
+        }
+        spans
+    }
+
+    /// Position of the bracket matching the bracket at `pos`. Returns `None` if there is no
+    /// bracket at `pos`.
+    pub fn matching_bracket(&self, pos: usize) -> Option<usize> {
+        self.inner.brackets.get(&pos).cloned()
+    }
+
+    /// Number of loops enclosing position `pos`. A bracket is not enclosed by its own loop.
+    /// Returns `None` if `pos` is outside of the source.
+    pub fn loop_depth(&self, pos: usize) -> Option<usize> {
+        self.inner.depths.get(pos).cloned()
+    }
+}
+
+/// Map the position of every bracket of `instructions` to the position of its matching bracket.
+fn brackets(instructions: &InstructionStream, jumps: &JumpTable) -> HashMap<usize, usize> {
+    instructions
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| matches!(x.token, Token::LoopBegin | Token::LoopEnd))
+        .map(|(i, x)| (x.position(), instructions[jumps.get(i)].position()))
+        .collect()
+}
+
+/// Pack `instructions` with their jump targets, the loop depths of their positions and the
+/// annotations of their loops. Returns the packed instructions and the net effects of the loops
+/// of kind `LoopKind::Transfer`.
+fn pack(
+    instructions: &InstructionStream,
+    jumps: &JumpTable,
+    depths: &[usize],
+) -> (Vec<Op>, Effects) {
+    let mut ops: Vec<Op> = instructions
+        .iter()
+        .enumerate()
+        .map(|(i, x)| Op {
+            token: x.token,
+            multiplier: x.multiplier,
+            depth: match x.token {
+                Token::LoopBegin => depths.get(x.position()).map_or(1, |&d| d as u32 + 1),
+                _ => 0,
+            },
+            jump: jumps.get(i) as u32,
+            balanced: false,
+            io: false,
+            kind: LoopKind::Loop,
+            run: 0,
+        })
+        .collect();
+    // Runs on the same cell, counted from their ends, so jumps into a run find its rest
+    for i in (0..ops.len()).rev() {
+        if on_cell(ops[i].token) {
+            let rest = ops.get(i + 1).map_or(0, |x| x.run);
+            ops[i].run = rest.saturating_add(1);
+        }
+    }
+    let mut effects = HashMap::new();
+    for i in 0..ops.len() {
+        let end = ops[i].jump as usize;
+        if ops[i].token != Token::LoopBegin || ops[i].kind != LoopKind::Loop {
+            continue;
+        }
+        let (kind, other) = match loop_idiom(instructions, i, end) {
+            Some(Idiom::Clear) => {
+                let x = &instructions[i + 1];
+                match (x.token, x.multiplier) {
+                    (Token::Decrease, 1) => (LoopKind::Clear, None),
+                    _ => continue,
+                }
+            }
+            Some(Idiom::If) => (LoopKind::If, None),
+            Some(Idiom::IfElse(_)) => (LoopKind::IfElse, Some(end + 2)),
+            Some(Idiom::Transfer(targets)) => {
+                effects.insert(i, targets);
+                (LoopKind::Transfer, None)
+            }
+            _ => continue,
+        };
+        ops[i].kind = kind;
+        ops[end].kind = kind;
+        if let Some(begin) = other {
+            let jump = ops[begin].jump as usize;
+            ops[begin].kind = LoopKind::Else;
+            ops[jump].kind = LoopKind::Else;
+        }
+    }
+    // Net movement, balance and I/O of every loop which is still open
+    let mut open: Vec<(isize, bool, bool)> = vec![];
+    for i in 0..ops.len() {
+        let op = ops[i];
+        let m = op.multiplier as isize;
+        match op.token {
+            Token::LoopBegin => open.push((0, true, false)),
+            Token::LoopEnd => {
+                let (offset, inner, io) = open.pop().expect("Brackets are matched.");
+                let balanced = offset == 0 && inner;
+                for &idx in &[i, op.jump as usize] {
+                    ops[idx].balanced = balanced;
+                    ops[idx].io = io;
+                }
+                if let Some(outer) = open.last_mut() {
+                    outer.1 &= balanced;
+                    outer.2 |= io;
+                }
+            }
+            token => {
+                if let Some(l) = open.last_mut() {
+                    match token {
+                        Token::MoveRight => l.0 += m,
+                        Token::MoveLeft => l.0 -= m,
+                        Token::MoveUp | Token::MoveDown | Token::SwitchTape => l.1 = false,
+                        Token::Input | Token::Output => l.2 = true,
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+    (ops, effects)
+}
+
+/// Returns `true` if `token` only operates on the current cell, without moving the pointer or
+/// branching.
+fn on_cell(token: Token) -> bool {
+    matches!(
+        token,
+        Token::Increase | Token::Decrease | Token::Output | Token::Input
+    )
+}
+
+impl FromStr for Program {
+    type Err = ParseError;
+
+    /// Lex, validate and parse `source`.
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        Program::compile(source, &LexerOptions::default(), 0)
+    }
+}
+
+impl fmt::Display for Program {
+    /// Writes the program as canonical brainfuck, i.e. without comments.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for instr in &self.inner.instructions {
+            write!(f, "{}", instr)?;
+        }
+        Ok(())
+    }
+}
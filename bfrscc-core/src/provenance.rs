@@ -0,0 +1,232 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Output provenance
+//!
+//! Explains how every output byte was computed. While the program runs, every cell keeps the
+//! chain of operations which changed it since it was last zero, read or randomized. Repeated
+//! executions of the same instruction are merged, so a chain stays short even if it is built
+//! in a loop. When a byte is written, the chain of its cell is recorded, with neighboring
+//! instructions which ran equally often joined into a single span.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use buffer::BufferMachine;
+use lexer::{Dialect, Token};
+use machine::Machine;
+use program::Program;
+use run::{input_source, interpreter, step, RunConfig, RunError};
+use tape::{GridTape, LinearTape, Tape};
+
+/// Executions of instructions which changed a cell
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    /// Source positions of the instructions
+    pub span: Range<usize>,
+    /// Kind of the instructions
+    pub token: Token,
+    /// Change of the cell by one execution of the span, or the value for `,` and `?`
+    pub value: u8,
+    /// Number of consecutive executions
+    pub count: u64,
+}
+
+impl Link {
+    /// Net change of the cell by all executions, modulo 256
+    pub fn change(&self) -> u8 {
+        let total = (u64::from(self.value) * self.count) as u8;
+        match self.token {
+            Token::Decrease => total.wrapping_neg(),
+            _ => total,
+        }
+    }
+}
+
+impl fmt::Display for Link {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.span.len() > 1 {
+            write!(f, "positions {}..{}: ", self.span.start, self.span.end)?;
+        } else {
+            write!(f, "position {}: ", self.span.start)?;
+        }
+        match self.token {
+            Token::Input => write!(f, "read {}", self.value),
+            Token::Random => write!(f, "random {}", self.value),
+            Token::Decrease => write!(
+                f,
+                "`-` x{}, executed {} {} (-{})",
+                self.value,
+                self.count,
+                times(self.count),
+                self.change().wrapping_neg()
+            ),
+            _ => write!(
+                f,
+                "`+` x{}, executed {} {} (+{})",
+                self.value,
+                self.count,
+                times(self.count),
+                self.change()
+            ),
+        }
+    }
+}
+
+/// `time` or `times`
+fn times(n: u64) -> &'static str {
+    if n == 1 {
+        "time"
+    } else {
+        "times"
+    }
+}
+
+/// How an output byte was computed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explanation {
+    /// The byte
+    pub byte: u8,
+    /// Source position of the `.` which wrote it
+    pub position: usize,
+    /// Index of the tape the cell belongs to
+    pub tape: usize,
+    /// Index of the cell, its column on a grid
+    pub cell: isize,
+    /// Row of the cell, which is `0` unless the program runs on a grid
+    pub row: i64,
+    /// Operations which produced the value, oldest first
+    pub chain: Vec<Link>,
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?} ({}) written at position {} from cell {}",
+            self.byte as char, self.byte, self.position, self.cell
+        )?;
+        if self.row != 0 {
+            write!(f, " of row {}", self.row)?;
+        }
+        if self.tape > 0 {
+            write!(f, " of tape {}", self.tape)?;
+        }
+        for link in &self.chain {
+            write!(f, "\n    {}", link)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run `prog` and explain every byte of its output. Only `config.input`, `config.tape_init`,
+/// `config.pointer`, `config.seed`, `config.eof` and `config.max_steps` are honored.
+///
+/// ```
+/// use bfrscc_core::lexer::{Dialect, LexerOptions};
+/// use bfrscc_core::program::Program;
+/// use bfrscc_core::provenance::explain;
+/// use bfrscc_core::run::RunConfig;
+///
+/// let options = LexerOptions {
+///     dialect: Dialect::Grid,
+///     ..LexerOptions::default()
+/// };
+/// let prog = Program::compile("v+++<<+>>.", &options, 0).unwrap();
+/// let explanations = explain(&prog, &RunConfig::default()).unwrap();
+/// assert_eq!((explanations[0].cell, explanations[0].row), (0, 1));
+/// assert_eq!(explanations[0].chain[0].change(), 3);
+/// ```
+pub fn explain(prog: &Program, config: &RunConfig) -> Result<Vec<Explanation>, RunError> {
+    match prog.dialect() {
+        Dialect::Grid => explain_with::<GridTape>(prog, config),
+        _ => explain_with::<LinearTape>(prog, config),
+    }
+}
+
+/// Implementation of `explain` for a given kind of tape.
+fn explain_with<T: Tape>(
+    prog: &Program,
+    config: &RunConfig,
+) -> Result<Vec<Explanation>, RunError> {
+    let mut machine: BufferMachine<T> = BufferMachine::with_interpreter(interpreter(prog, config)?);
+    let mut input = input_source(config, prog);
+    let tapes = prog.dialect().tapes();
+    let mut chains: HashMap<(usize, (i64, i64)), Vec<Link>> = HashMap::new();
+    let mut explanations = vec![];
+    let (mut idx, mut steps, mut tape) = (0, 0u64, 0);
+    while idx < prog.len() {
+        if let Some(max) = config.max_steps {
+            if steps >= max {
+                return Err(RunError::StepLimit(max));
+            }
+        }
+        let x = &prog.instructions()[idx];
+        let span = x.position()..x.end;
+        let cell = (tape, machine.interpreter().tape().coordinates());
+        step(prog, &mut machine, &mut *input, &mut idx);
+        steps += 1;
+        let val = machine.get_val();
+        match x.token {
+            Token::Increase | Token::Decrease if val == 0 => {
+                chains.remove(&cell);
+            }
+            Token::Increase | Token::Decrease => {
+                let chain = chains.entry(cell).or_default();
+                match chain.iter_mut().find(|l| l.span == span) {
+                    Some(l) => l.count += 1,
+                    None => chain.push(Link {
+                        span,
+                        token: x.token,
+                        value: x.multiplier,
+                        count: 1,
+                    }),
+                }
+            }
+            Token::Input | Token::Random => {
+                let link = Link {
+                    span,
+                    token: x.token,
+                    value: val,
+                    count: 1,
+                };
+                chains.insert(cell, vec![link]);
+            }
+            Token::Output => explanations.push(Explanation {
+                byte: val,
+                position: x.position(),
+                tape: cell.0,
+                cell: (cell.1).0 as isize,
+                row: (cell.1).1,
+                chain: chains.get(&cell).map_or(vec![], |chain| join(chain)),
+            }),
+            Token::SwitchTape => tape = (tape + 1) % tapes,
+            _ => {}
+        }
+    }
+    Ok(explanations)
+}
+
+/// Join neighboring links of the same kind which ran equally often.
+fn join(chain: &[Link]) -> Vec<Link> {
+    let mut joined: Vec<Link> = vec![];
+    for link in chain {
+        match joined.last_mut() {
+            Some(l)
+                if l.span.end == link.span.start
+                    && l.token == link.token
+                    && l.count == link.count
+                    && matches!(l.token, Token::Increase | Token::Decrease) =>
+            {
+                l.span.end = link.span.end;
+                l.value = l.value.wrapping_add(link.value);
+            }
+            _ => joined.push(link.clone()),
+        }
+    }
+    joined
+}
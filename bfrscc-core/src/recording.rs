@@ -0,0 +1,354 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Recording
+//!
+//! A `Recording` holds a complete debugging session (`bfrscc debug --record <file>`): what every
+//! step changed, the bytes read and written, and full snapshots of the state every
+//! `KEYFRAME_INTERVAL` steps. `bfrscc replay <file>` moves back and forth through the session
+//! without executing the program again: the state at any step is restored from the preceding
+//! keyframe by applying the recorded changes.
+//!
+//! A recording is written as text. After the header, the source positions of the instructions
+//! are listed, so recordings can be related to the source without compiling it. Keyframes are
+//! lines `k <steps> <pc> <pointer> <cells>`, every step is a line `s` followed by what changed
+//! compared to the step before: `j<pc>` unless the next instruction follows, `p<pointer>`,
+//! `c<cell>`, `g<n>` if the tape grew by `n` cells to the left, `i` if the cell was read and
+//! `o<byte>` for every byte written. The source of the program comes last.
+//!
+//! ```
+//! use bfrscc_core::recording::{Frame, Recording};
+//! use bfrscc_core::snapshot::Snapshot;
+//!
+//! let mut recording = Recording::new(false, Snapshot::new(",>+.", 0, 0, 0, vec![0]));
+//! recording.push(Frame::new(1, 0, 7).input());
+//! recording.push(Frame::new(2, 1, 0));
+//! recording.push(Frame::new(3, 1, 1));
+//! recording.push(Frame::new(4, 1, 1).output(&[1]));
+//! assert_eq!(recording.state_at(3).tape, vec![7, 1]);
+//! assert_eq!(recording.output_until(4), vec![1]);
+//!
+//! let mut file = Vec::new();
+//! recording.write_to(&mut file).unwrap();
+//! assert_eq!(Recording::read_from(&file[..]).unwrap(), recording);
+//! ```
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::iter;
+use snapshot::Snapshot;
+
+/// Header of a recording
+const MAGIC: &str = "bfrscc-recording 1";
+
+/// Number of steps between two keyframes, which bounds the changes applied to restore a state
+pub const KEYFRAME_INTERVAL: u64 = 1024;
+
+/// State after a single step
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    /// Index of the next instruction
+    pub pc: usize,
+    /// Position of the pointer
+    pub pointer: usize,
+    /// Value of the cell under the pointer
+    pub cell: u8,
+    /// Number of cells the tape grew by to the left, which moves all cells to the right
+    pub grown: usize,
+    /// Whether the cell was read from the input
+    pub input: bool,
+    /// Bytes written
+    pub output: Vec<u8>,
+}
+
+impl Frame {
+    /// Constructor
+    pub fn new(pc: usize, pointer: usize, cell: u8) -> Self {
+        Frame {
+            pc,
+            pointer,
+            cell,
+            grown: 0,
+            input: false,
+            output: vec![],
+        }
+    }
+
+    /// The tape grew by `n` cells to the left.
+    pub fn grown(mut self, n: usize) -> Self {
+        self.grown = n;
+        self
+    }
+
+    /// The cell was read from the input.
+    pub fn input(mut self) -> Self {
+        self.input = true;
+        self
+    }
+
+    /// `bytes` were written.
+    pub fn output(mut self, bytes: &[u8]) -> Self {
+        self.output = bytes.to_vec();
+        self
+    }
+
+    /// The frame leading to `state`, without input and output
+    fn at(state: &Snapshot) -> Self {
+        let cell = state.tape.get(state.pointer).cloned().unwrap_or(0);
+        Frame::new(state.pc, state.pointer, cell)
+    }
+
+    /// Apply the changes of the step to `state`.
+    fn apply(&self, state: &mut Snapshot) {
+        if self.grown > 0 {
+            state.tape.splice(0..0, iter::repeat_n(0, self.grown));
+        }
+        if state.tape.len() <= self.pointer {
+            state.tape.resize(self.pointer + 1, 0);
+        }
+        state.tape[self.pointer] = self.cell;
+        state.pc = self.pc;
+        state.pointer = self.pointer;
+        state.steps += 1;
+    }
+}
+
+/// A recorded session
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    /// Whether the program was optimized. The program counters refer to the compiled program.
+    pub optimized: bool,
+    /// Source position of every instruction of the compiled program, by index. Empty if
+    /// unknown.
+    pub positions: Vec<usize>,
+    /// Full states, ordered by their number of steps. The first one is the initial state. A
+    /// later keyframe with the same number of steps replaces an earlier one, for instance after
+    /// cells were changed in the debugger.
+    keyframes: Vec<Snapshot>,
+    /// The changes of every step
+    frames: Vec<Frame>,
+}
+
+impl Recording {
+    /// Start a recording in the state `initial`.
+    pub fn new(optimized: bool, initial: Snapshot) -> Self {
+        Recording {
+            optimized,
+            positions: vec![],
+            keyframes: vec![initial],
+            frames: vec![],
+        }
+    }
+
+    /// Source of the program
+    pub fn program(&self) -> &str {
+        &self.keyframes[0].program
+    }
+
+    /// The frames of all steps
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// Number of recorded steps
+    pub fn steps(&self) -> u64 {
+        self.frames.len() as u64
+    }
+
+    /// Record the next step.
+    pub fn push(&mut self, frame: Frame) {
+        self.frames.push(frame);
+    }
+
+    /// Record the full state after the last step. Necessary whenever a step or the debugger
+    /// changes more than the cell under the pointer.
+    pub fn keyframe(&mut self, state: Snapshot) {
+        self.keyframes.push(Snapshot {
+            steps: self.steps(),
+            ..state
+        });
+    }
+
+    /// Restore the state after `steps` steps, or after the last step if fewer were recorded.
+    pub fn state_at(&self, steps: u64) -> Snapshot {
+        let steps = steps.min(self.steps());
+        let idx = self.keyframes.iter().rposition(|x| x.steps <= steps).unwrap_or(0);
+        let mut state = self.keyframes[idx].clone();
+        for frame in &self.frames[state.steps as usize..steps as usize] {
+            frame.apply(&mut state);
+        }
+        state
+    }
+
+    /// The bytes written by the first `steps` steps
+    pub fn output_until(&self, steps: u64) -> Vec<u8> {
+        let steps = steps.min(self.steps()) as usize;
+        self.frames[..steps].iter().flat_map(|x| x.output.iter().cloned()).collect()
+    }
+
+    /// Serialize the recording into `w`.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "{}", MAGIC)?;
+        writeln!(w, "optimized {}", self.optimized)?;
+        let positions: Vec<String> = self.positions.iter().map(|x| x.to_string()).collect();
+        writeln!(w, "positions {}", positions.join(","))?;
+        let mut keyframes = self.keyframes.iter().peekable();
+        let mut prev = Frame::new(0, 0, 0);
+        for steps in 0..=self.frames.len() {
+            while let Some(k) = keyframes.next_if(|x| x.steps as usize == steps) {
+                let tape: Vec<String> = k.tape.iter().map(|x| x.to_string()).collect();
+                writeln!(w, "k {} {} {} {}", k.steps, k.pc, k.pointer, tape.join(","))?;
+                prev = Frame::at(k);
+            }
+            let frame = match self.frames.get(steps) {
+                Some(frame) => frame,
+                None => break,
+            };
+            write!(w, "s")?;
+            if frame.pc != prev.pc + 1 {
+                write!(w, " j{}", frame.pc)?;
+            }
+            if frame.pointer != prev.pointer {
+                write!(w, " p{}", frame.pointer)?;
+            }
+            if frame.cell != prev.cell {
+                write!(w, " c{}", frame.cell)?;
+            }
+            if frame.grown > 0 {
+                write!(w, " g{}", frame.grown)?;
+            }
+            if frame.input {
+                write!(w, " i")?;
+            }
+            for byte in &frame.output {
+                write!(w, " o{}", byte)?;
+            }
+            writeln!(w)?;
+            prev = frame.clone();
+        }
+        writeln!(w, "program")?;
+        write!(w, "{}", self.program())
+    }
+
+    /// Deserialize a recording from `r`.
+    pub fn read_from<R: Read>(r: R) -> io::Result<Self> {
+        let mut lines = BufReader::new(r).lines();
+        if lines.next().transpose()?.as_deref() != Some(MAGIC) {
+            return Err(invalid("not a bfrscc recording"));
+        }
+        let optimized = match lines.next().transpose()? {
+            Some(ref line) if line.starts_with("optimized ") => line["optimized ".len()..]
+                .parse()
+                .map_err(|_| invalid("invalid value of field `optimized`"))?,
+            _ => return Err(invalid("missing field `optimized`")),
+        };
+        let positions = match lines.next().transpose()? {
+            Some(ref line) if line.starts_with("positions") => line["positions".len()..]
+                .split(',')
+                .map(|x| x.trim())
+                .filter(|x| !x.is_empty())
+                .map(|x| x.parse().map_err(|_| invalid("invalid value of field `positions`")))
+                .collect::<io::Result<Vec<usize>>>()?,
+            _ => return Err(invalid("missing field `positions`")),
+        };
+        let (mut keyframes, mut frames) = (vec![], vec![]);
+        let mut prev = Frame::new(0, 0, 0);
+        loop {
+            let line = lines.next().transpose()?.ok_or_else(|| invalid("missing program"))?;
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("k") => {
+                    let k = keyframe(words)?;
+                    if k.steps != frames.len() as u64 {
+                        return Err(invalid("keyframe out of order"));
+                    }
+                    prev = Frame::at(&k);
+                    keyframes.push(k);
+                }
+                Some("s") => {
+                    let frame = step(words, &prev)?;
+                    frames.push(frame.clone());
+                    prev = frame;
+                }
+                Some("program") => break,
+                _ => return Err(invalid(&format!("invalid line `{}`", line))),
+            }
+        }
+        if keyframes.first().is_none_or(|k| k.steps != 0) {
+            return Err(invalid("missing initial keyframe"));
+        }
+        let program = lines.collect::<io::Result<Vec<String>>>()?.join("\n");
+        for k in &mut keyframes {
+            k.program = program.clone();
+        }
+        Ok(Recording {
+            optimized,
+            positions,
+            keyframes,
+            frames,
+        })
+    }
+
+    /// Write the recording to the file `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut f = io::BufWriter::new(File::create(path)?);
+        self.write_to(&mut f)?;
+        f.flush()
+    }
+
+    /// Load a recording from the file `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Recording::read_from(File::open(path)?)
+    }
+}
+
+/// Parse the fields of a keyframe line. The program is filled in later.
+fn keyframe<'a, I: Iterator<Item = &'a str>>(mut words: I) -> io::Result<Snapshot> {
+    let mut number = || -> io::Result<usize> {
+        words
+            .next()
+            .and_then(|x| x.parse().ok())
+            .ok_or_else(|| invalid("invalid keyframe"))
+    };
+    let (steps, pc, pointer) = (number()? as u64, number()?, number()?);
+    let tape = words
+        .next()
+        .unwrap_or_default()
+        .split(',')
+        .filter(|x| !x.is_empty())
+        .map(|x| x.parse::<u8>().map_err(|_| invalid("invalid tape cell")))
+        .collect::<io::Result<Vec<u8>>>()?;
+    Ok(Snapshot::new("", pc, pointer, steps, tape))
+}
+
+/// Parse the changes of a step line, relative to the state `prev` after the step before.
+fn step<'a, I: Iterator<Item = &'a str>>(words: I, prev: &Frame) -> io::Result<Frame> {
+    let mut frame = Frame::new(prev.pc + 1, prev.pointer, prev.cell);
+    for word in words {
+        let mut chars = word.chars();
+        let kind = chars.next();
+        let value = chars.as_str();
+        let number = || value.parse::<usize>().map_err(|_| invalid("invalid step"));
+        let byte = || value.parse::<u8>().map_err(|_| invalid("invalid step"));
+        match kind {
+            Some('j') => frame.pc = number()?,
+            Some('p') => frame.pointer = number()?,
+            Some('c') => frame.cell = byte()?,
+            Some('g') => frame.grown = number()?,
+            Some('i') if value.is_empty() => frame.input = true,
+            Some('o') => frame.output.push(byte()?),
+            _ => return Err(invalid(&format!("invalid change `{}`", word))),
+        }
+    }
+    Ok(frame)
+}
+
+/// An `InvalidData` error with the given message
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
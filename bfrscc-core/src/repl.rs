@@ -0,0 +1,214 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # REPL
+//!
+//! Runs snippets of brainfuck one after another on the same machine, so the tape and the pointer
+//! carry over from one snippet to the next. After every snippet, its output and the cells around
+//! the pointer are printed. Lines starting with `:` are commands:
+//!
+//! ```
+//! use bfrscc_core::repl::Repl;
+//! use bfrscc_core::run::RunConfig;
+//!
+//! let mut repl = Repl::new(&RunConfig::default()).unwrap();
+//! let mut out = vec![];
+//! repl.eval("++++++++[>+++++++++<-]", &mut out).unwrap();
+//! repl.eval(">.", &mut out).unwrap();
+//! assert!(String::from_utf8(out).unwrap().contains("H\n0 [72]"));
+//! assert_eq!(repl.session(), "++++++++[>+++++++++<-]\n>.\n");
+//! ```
+//!
+//! The lines entered can be kept in a history file, which is read when the REPL starts and
+//! appended to with every line.
+
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use buffer::BufferMachine;
+use run::{compile, interpreter, run_with_input, RunConfig, RunError};
+use tape::{DumpFormat, TapeView};
+
+/// Number of cells shown left and right of the pointer after every snippet
+const TAPE_WINDOW: usize = 8;
+
+/// Default location of the history file, `.bfrscc_history` in the home directory
+pub fn default_history() -> Option<PathBuf> {
+    ::std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".bfrscc_history"))
+}
+
+/// Runs snippets on a machine which is kept between them
+pub struct Repl {
+    /// Settings of every snippet
+    config: RunConfig,
+    /// Machine the snippets run on
+    machine: BufferMachine,
+    /// Bytes left to be read by `,`
+    input: VecDeque<u8>,
+    /// Snippets which ran successfully since the last reset
+    snippets: Vec<String>,
+    /// The last snippet and the time it took
+    last: Option<(String, Duration)>,
+    /// Lines entered in this and earlier sessions
+    history: Vec<String>,
+    /// File new lines of the history are appended to
+    history_file: Option<File>,
+}
+
+impl Repl {
+    /// REPL running snippets with the settings of `config`. `config.input` is read by the `,` of
+    /// all snippets together; at its end `,` reads `config.eof`. Fails if the memory of
+    /// `config.tape_reserve` cannot be reserved.
+    pub fn new(config: &RunConfig) -> Result<Self, RunError> {
+        Ok(Repl {
+            config: config.clone(),
+            machine: machine(config)?,
+            input: config.input.clone().unwrap_or_default().into(),
+            snippets: vec![],
+            last: None,
+            history: vec![],
+            history_file: None,
+        })
+    }
+
+    /// Load the history from `path` and append every line entered to it. The file is created
+    /// if it does not exist.
+    pub fn with_history(mut self, path: &Path) -> io::Result<Self> {
+        if path.exists() {
+            self.history = fs::read_to_string(path)?.lines().map(str::to_string).collect();
+        }
+        self.history_file = Some(OpenOptions::new().create(true).append(true).open(path)?);
+        Ok(self)
+    }
+
+    /// Lines entered in this and earlier sessions, oldest first
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Snippets which ran successfully since the last reset, one per line. Running them as a
+    /// program reproduces the current tape.
+    pub fn session(&self) -> String {
+        self.snippets.iter().map(|x| format!("{}\n", x)).collect()
+    }
+
+    /// Read lines from `input` until `:quit` or end of input and write responses to `out`.
+    pub fn repl<R: BufRead, W: Write>(&mut self, input: R, out: &mut W) -> io::Result<()> {
+        write!(out, "bfrscc> ")?;
+        out.flush()?;
+        for line in input.lines() {
+            if !self.eval(&line?, out)? {
+                return Ok(());
+            }
+            write!(out, "bfrscc> ")?;
+            out.flush()?;
+        }
+        writeln!(out)
+    }
+
+    /// Run the snippet or command `line`. Returns `false` for `:quit`.
+    pub fn eval<W: Write>(&mut self, line: &str, out: &mut W) -> io::Result<bool> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(true);
+        }
+        self.history.push(line.to_string());
+        if let Some(ref mut file) = self.history_file {
+            writeln!(file, "{}", line)?;
+        }
+        if !line.starts_with(':') {
+            self.snippet(line, out)?;
+            return Ok(true);
+        }
+        let (cmd, arg) = match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], line[i..].trim()),
+            None => (line, ""),
+        };
+        match cmd {
+            ":q" | ":quit" => return Ok(false),
+            ":load" if !arg.is_empty() => match fs::read_to_string(arg) {
+                Ok(source) => self.snippet(&source, out)?,
+                Err(e) => writeln!(out, "unable to read {}: {}", arg, e)?,
+            },
+            ":save" if !arg.is_empty() => match fs::write(arg, self.session()) {
+                Ok(()) => writeln!(out, "{} snippets saved to {}", self.snippets.len(), arg)?,
+                Err(e) => writeln!(out, "unable to write {}: {}", arg, e)?,
+            },
+            ":time" => match self.last {
+                Some((ref snippet, time)) => writeln!(out, "{:?} for `{}`", time, snippet)?,
+                None => writeln!(out, "no snippet has run yet")?,
+            },
+            ":history" => {
+                // The command itself is the last entry.
+                for (i, entry) in self.history.iter().enumerate().rev().skip(1).take(20).rev() {
+                    writeln!(out, "{:>5}  {}", i + 1, entry)?;
+                }
+            }
+            ":reset" => match machine(&self.config) {
+                Ok(machine) => {
+                    self.machine = machine;
+                    self.snippets.clear();
+                    writeln!(out, "tape cleared")?;
+                }
+                Err(e) => writeln!(out, "error: {}", e)?,
+            },
+            ":h" | ":help" => help(out)?,
+            ":load" => writeln!(out, "usage: :load <file.bf>")?,
+            ":save" => writeln!(out, "usage: :save <file.bf>")?,
+            _ => writeln!(out, "unknown command `{}`, try `:help`", cmd)?,
+        }
+        Ok(true)
+    }
+
+    /// Run `snippet` and print its output and the tape afterwards.
+    fn snippet<W: Write>(&mut self, snippet: &str, out: &mut W) -> io::Result<()> {
+        let prog = match compile(snippet, &self.config) {
+            Ok(prog) => prog,
+            Err(e) => return writeln!(out, "error: {}", e),
+        };
+        // An interrupt only stops the snippet which is running.
+        let interrupt = self.config.interrupt.clone();
+        let reset = || interrupt.iter().for_each(|flag| flag.store(false, Ordering::Relaxed));
+        reset();
+        let start = Instant::now();
+        let input = &mut self.input;
+        let mut provider = || input.pop_front();
+        let res = run_with_input(&prog, &mut self.machine, &self.config, &mut provider);
+        self.last = Some((snippet.trim().to_string(), start.elapsed()));
+        reset();
+        let output = self.machine.take_output();
+        if !output.is_empty() {
+            writeln!(out, "{}", String::from_utf8_lossy(&output))?;
+        }
+        match res {
+            Ok(_) => self.snippets.push(snippet.trim().to_string()),
+            Err(e) => writeln!(out, "error: {}", e)?,
+        }
+        let view = TapeView::new(self.machine.interpreter().tape());
+        writeln!(out, "{}", view.render(TAPE_WINDOW, DumpFormat::Dec))
+    }
+}
+
+/// Fresh machine for the settings of `config`
+fn machine(config: &RunConfig) -> Result<BufferMachine, RunError> {
+    let empty = compile("", config).expect("The empty program is valid.");
+    Ok(BufferMachine::with_interpreter(interpreter(&empty, config)?))
+}
+
+/// Print the available commands.
+fn help<W: Write>(out: &mut W) -> io::Result<()> {
+    writeln!(out, "<code>           run code on the current tape")?;
+    writeln!(out, ":load <file.bf>  run the code of a file")?;
+    writeln!(out, ":save <file.bf>  save the snippets since the last reset as a program")?;
+    writeln!(out, ":time            show how long the last snippet took")?;
+    writeln!(out, ":history         show the last entered lines")?;
+    writeln!(out, ":reset           clear the tape and the snippets")?;
+    writeln!(out, ":quit            leave")
+}
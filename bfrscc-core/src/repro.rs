@@ -0,0 +1,313 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Reproducible runs
+//!
+//! `run_with_config` fixes the seed of a run up front and records it in the `Environment` of the
+//! `RunReport`, together with the options and hashes of the program and of the input read. This
+//! is everything needed to repeat the run exactly, e.g. for a bug report: `bfrscc repro` reads
+//! the JSON of the report and executes the run again.
+//!
+//! ```
+//! use bfrscc_core::json;
+//! use bfrscc_core::repro::Environment;
+//! use bfrscc_core::run::{run_with_config, RunConfig};
+//!
+//! let config = RunConfig {
+//!     input: Some(b"ab".to_vec()),
+//!     ..RunConfig::default()
+//! };
+//! let report = run_with_config(",[>,]", &config).unwrap();
+//! let env = report.environment.unwrap();
+//! assert_eq!(env.input_len, 2);
+//! let copy = Environment::from_json(&json::parse(&env.to_json()).unwrap()).unwrap();
+//! assert_eq!(copy, *env);
+//! ```
+
+use std::env;
+use alias::parse_alias;
+use cell::CellType;
+use json::{self, Object, Value};
+use lexer::{CommentPolicy, Dialect, LexerOptions};
+use newline::Newline;
+use run::{Arithmetic, Eof, RunConfig, Underflow};
+
+/// Incremental FNV-1a hash of a sequence of bytes, together with their number
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Digest {
+    /// Hash of the bytes so far
+    pub hash: u64,
+    /// Number of bytes so far
+    pub len: u64,
+}
+
+impl Digest {
+    /// Digest of no bytes
+    pub fn new() -> Self {
+        Digest {
+            hash: 0xcbf2_9ce4_8422_2325,
+            len: 0,
+        }
+    }
+
+    /// Add byte `b`.
+    pub fn update(&mut self, b: u8) {
+        self.hash = (self.hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3);
+        self.len += 1;
+    }
+}
+
+impl Default for Digest {
+    fn default() -> Self {
+        Digest::new()
+    }
+}
+
+/// FNV-1a hash of `bytes`
+pub fn hash(bytes: &[u8]) -> u64 {
+    let mut digest = Digest::new();
+    for &b in bytes {
+        digest.update(b);
+    }
+    digest.hash
+}
+
+/// Everything which determines the outcome of a run
+#[derive(Debug, Clone, PartialEq)]
+pub struct Environment {
+    /// Version of bfrscc
+    pub version: String,
+    /// Architecture and operating system
+    pub platform: String,
+    /// Name of the program, e.g. its file name. Set by the caller.
+    pub program: Option<String>,
+    /// The program was expanded by the preprocessor. Set by the caller.
+    pub preprocess: bool,
+    /// Hash of the source of the program (see `hash`)
+    pub program_hash: u64,
+    /// Hash of the bytes read by `,`, without the values read at the end of the input
+    pub input_hash: u64,
+    /// Number of bytes read by `,`, without the values read at the end of the input
+    pub input_len: u64,
+    /// Seed of the random number generator
+    pub seed: u64,
+    /// Options of the lexer
+    pub lexer: LexerOptions,
+    /// The optimizer was run
+    pub optimize: bool,
+    /// Translation of line endings
+    pub newline: Newline,
+    /// Value read by `,` at the end of the input
+    pub eof: Eof,
+    /// What `+` and `-` do at the limits of a cell
+    pub arithmetic: Arithmetic,
+    /// What moving left of the first cell does
+    pub underflow: Underflow,
+    /// Type of the cells
+    pub cells: CellType,
+    /// Initial content of the tape
+    pub tape_init: Option<Vec<u8>>,
+    /// Initial position of the pointer
+    pub pointer: usize,
+    /// Maximum number of instructions to execute
+    pub max_steps: Option<u64>,
+    /// Maximum number of cells
+    pub max_tape: Option<usize>,
+}
+
+impl Environment {
+    /// Environment of running `source` with `config`, which has a seed, on the input described
+    /// by `input`
+    pub fn new(source: &str, config: &RunConfig, input: &Digest) -> Self {
+        Environment {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            platform: format!("{}-{}", env::consts::ARCH, env::consts::OS),
+            program: None,
+            preprocess: false,
+            program_hash: hash(source.as_bytes()),
+            input_hash: input.hash,
+            input_len: input.len,
+            seed: config.seed.unwrap_or_default(),
+            lexer: config.lexer.clone(),
+            optimize: config.optimize,
+            newline: config.newline,
+            eof: config.eof,
+            arithmetic: config.arithmetic,
+            underflow: config.underflow,
+            cells: config.cells,
+            tape_init: config.tape_init.clone(),
+            pointer: config.pointer,
+            max_steps: config.max_steps,
+            max_tape: config.max_tape,
+        }
+    }
+
+    /// Configuration which repeats the run. The input needs to be set.
+    pub fn config(&self) -> RunConfig {
+        RunConfig {
+            max_steps: self.max_steps,
+            max_tape: self.max_tape,
+            optimize: self.optimize,
+            lexer: self.lexer.clone(),
+            newline: self.newline,
+            tape_init: self.tape_init.clone(),
+            pointer: self.pointer,
+            seed: Some(self.seed),
+            eof: self.eof,
+            arithmetic: self.arithmetic,
+            underflow: self.underflow,
+            cells: self.cells,
+            ..RunConfig::default()
+        }
+    }
+
+    /// The environment as a JSON object. Hashes are written as strings of 16 hex digits.
+    pub fn to_json(&self) -> String {
+        let (dialect, tapes) = match self.lexer.dialect {
+            Dialect::Standard => ("standard", None),
+            Dialect::MultiTape(n) => ("multitape", Some(n as u64)),
+            Dialect::Grid => ("grid", None),
+            Dialect::Random => ("random", None),
+        };
+        let comments = match self.lexer.comments {
+            CommentPolicy::Ignore => "ignore",
+            CommentPolicy::Error => "error",
+            CommentPolicy::Dialect => "dialect",
+        };
+        let aliases = self
+            .lexer
+            .aliases
+            .iter()
+            .map(|&(ref alias, c)| json::string(&format!("{}={}", alias, c)));
+        let tape_init = match self.tape_init {
+            Some(ref cells) => json::array(cells.iter().map(|x| x.to_string())),
+            None => "null".to_string(),
+        };
+        Object::new()
+            .string("version", &self.version)
+            .string("platform", &self.platform)
+            .raw("program", &self.program.as_ref().map_or("null".to_string(), |x| json::string(x)))
+            .raw("preprocess", &self.preprocess.to_string())
+            .string("program_hash", &format!("{:016x}", self.program_hash))
+            .string("input_hash", &format!("{:016x}", self.input_hash))
+            .integer("input_len", self.input_len)
+            .integer("seed", self.seed)
+            .string("dialect", dialect)
+            .optional("tapes", tapes)
+            .string("comments", comments)
+            .raw("aliases", &json::array(aliases))
+            .raw("repeat_counts", &self.lexer.repeat_counts.to_string())
+            .optional("max_length", self.lexer.limits.max_length.map(|x| x as u64))
+            .optional("max_depth", self.lexer.limits.max_depth.map(|x| x as u64))
+            .raw("optimize", &self.optimize.to_string())
+            .string("newline", &self.newline.to_string())
+            .string("eof", &self.eof.to_string())
+            .string("arithmetic", &self.arithmetic.to_string())
+            .string("underflow", &self.underflow.to_string())
+            .string("cells", &self.cells.to_string())
+            .raw("tape_init", &tape_init)
+            .integer("pointer", self.pointer as u64)
+            .optional("max_steps", self.max_steps)
+            .optional("max_tape", self.max_tape.map(|x| x as u64))
+            .finish()
+    }
+
+    /// Read an environment written by `to_json`.
+    pub fn from_json(value: &Value) -> Result<Self, String> {
+        let field = |key: &str| value.get(key).ok_or_else(|| format!("`{}` is missing", key));
+        let string = |key: &str| {
+            field(key)?
+                .as_str()
+                .ok_or_else(|| format!("`{}` is not a string", key))
+        };
+        let int = |key: &str| {
+            field(key)?
+                .as_u64()
+                .ok_or_else(|| format!("`{}` is not a non-negative integer", key))
+        };
+        let optional = |key: &str| match field(key)? {
+            Value::Null => Ok(None),
+            x => x
+                .as_u64()
+                .map(Some)
+                .ok_or_else(|| format!("`{}` is neither an integer nor null", key)),
+        };
+        let boolean = |key: &str| {
+            field(key)?
+                .as_bool()
+                .ok_or_else(|| format!("`{}` is not a boolean", key))
+        };
+        let hex = |key: &str| {
+            u64::from_str_radix(string(key)?, 16).map_err(|_| format!("`{}` is not a hash", key))
+        };
+        let dialect = match string("dialect")? {
+            "standard" => Dialect::Standard,
+            "multitape" => Dialect::MultiTape(int("tapes")? as usize),
+            "grid" => Dialect::Grid,
+            "random" => Dialect::Random,
+            name => return Err(format!("unknown dialect `{}`", name)),
+        };
+        let comments = match string("comments")? {
+            "ignore" => CommentPolicy::Ignore,
+            "error" => CommentPolicy::Error,
+            "dialect" => CommentPolicy::Dialect,
+            name => return Err(format!("unknown comment policy `{}`", name)),
+        };
+        let mut aliases = vec![];
+        for alias in field("aliases")?.as_array().ok_or("`aliases` is not an array")? {
+            aliases.push(parse_alias(alias.as_str().ok_or("an alias is not a string")?)?);
+        }
+        let tape_init = match *field("tape_init")? {
+            Value::Null => None,
+            Value::Array(ref cells) => Some(
+                cells
+                    .iter()
+                    .map(|x| x.as_u64().filter(|&x| x < 256).map(|x| x as u8))
+                    .collect::<Option<Vec<u8>>>()
+                    .ok_or("`tape_init` contains a value which is not a byte")?,
+            ),
+            _ => return Err("`tape_init` is neither an array nor null".to_string()),
+        };
+        let mut lexer = LexerOptions {
+            comments,
+            dialect,
+            aliases,
+            repeat_counts: boolean("repeat_counts")?,
+            ..LexerOptions::default()
+        };
+        lexer.limits.max_length = optional("max_length")?.map(|x| x as usize);
+        lexer.limits.max_depth = optional("max_depth")?.map(|x| x as usize);
+        Ok(Environment {
+            version: string("version")?.to_string(),
+            platform: string("platform")?.to_string(),
+            program: match *field("program")? {
+                Value::Null => None,
+                ref x => Some(x.as_str().ok_or("`program` is not a string")?.to_string()),
+            },
+            preprocess: boolean("preprocess")?,
+            program_hash: hex("program_hash")?,
+            input_hash: hex("input_hash")?,
+            input_len: int("input_len")?,
+            seed: int("seed")?,
+            lexer,
+            optimize: boolean("optimize")?,
+            newline: string("newline")?.parse()?,
+            eof: string("eof")?.parse()?,
+            arithmetic: string("arithmetic")?.parse()?,
+            // Reports written before underflow policies existed always grew the tape.
+            underflow: match value.get("underflow") {
+                Some(_) => string("underflow")?.parse()?,
+                None => Underflow::Grow,
+            },
+            cells: string("cells")?.parse()?,
+            tape_init,
+            pointer: int("pointer")? as usize,
+            max_steps: optional("max_steps")?,
+            max_tape: optional("max_tape")?.map(|x| x as usize),
+        })
+    }
+}
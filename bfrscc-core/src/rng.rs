@@ -0,0 +1,47 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Random number generator
+//!
+//! A small seedable generator (xorshift64*) for the `?` instruction of the random dialect. The
+//! same seed always yields the same sequence of bytes.
+
+/// Seedable pseudo random number generator
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rng {
+    /// State, never zero
+    state: u64,
+}
+
+impl Rng {
+    /// Constructor
+    pub fn new(seed: u64) -> Self {
+        // xorshift gets stuck at zero, so the seed is mixed with a constant.
+        Rng {
+            state: (seed ^ 0x9e37_79b9_7f4a_7c15).max(1),
+        }
+    }
+
+    /// Next random byte
+    pub fn next_u8(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+
+    /// Next random number
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Rng::new(0)
+    }
+}
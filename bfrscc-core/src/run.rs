@@ -0,0 +1,1414 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Run
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::iter;
+use std::fs;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use cell::{Cell, CellMachine, CellType};
+use checkpoint::{Checkpoint, Checkpointing};
+use coredump::CoreDump;
+#[cfg(feature = "debugger")]
+use debugger::Debugger;
+use error::ParseError;
+use input::InputProvider;
+use interpreter::Interpreter;
+use json::Object;
+use lexer::{split_input, CommentPolicy, Dialect, LexerOptions, Token};
+use logging::{span, Level};
+use parser::InstructionStream;
+use perf::{Counters, PerfCounts};
+use repro::{Digest, Environment};
+use machine::Machine;
+use newline::Newline;
+use optimizer::DeadStores;
+use program::{LoopKind, Op, Program};
+use sampler::Sampler;
+use snapshot::Snapshot;
+use stdio;
+use tape::{CircularTape, DumpFormat, GridTape, LinearTape, Tape, TapeView};
+
+/// Number of steps after which `execute_checkpointed` checks whether a checkpoint is due
+const CHECKPOINT_CHUNK: u64 = 1 << 24;
+
+/// Number of cells on each side of the pointer logged when a run fails
+const ERROR_WINDOW: usize = 8;
+
+/// Number of times per second the run loop checks `RunConfig::max_time` and publishes its
+/// progress
+const CLOCK_HZ: u32 = 100;
+
+/// Settings of a single run
+#[derive(Debug, Clone, Default)]
+pub struct RunConfig {
+    /// Maximum number of instructions to execute
+    pub max_steps: Option<u64>,
+    /// Maximum number of cells the tape may grow to
+    pub max_tape: Option<usize>,
+    /// Maximum number of bytes `.` may write
+    pub max_output_bytes: Option<u64>,
+    /// Maximum wall time of the execution, checked every few milliseconds
+    pub max_time: Option<Duration>,
+    /// Maximum number of iterations of a loop each time it is entered, which catches infinite
+    /// loops long before a step limit would. Loops the optimizer executes in a single step
+    /// never exceed it.
+    pub max_loop_iters: Option<u64>,
+    /// Number of cells to allocate memory for before the execution, which avoids growing the
+    /// tape step by step for programs known to use a lot of memory. At most `max_tape` cells
+    /// are reserved. Only the first tape of the multi-tape dialect is pre-allocated.
+    pub tape_reserve: Option<usize>,
+    /// File to write a core dump to if the execution fails
+    pub core_file: Option<PathBuf>,
+    /// Flag which is set to `true` once the core dump has been written to `core_file`
+    pub core_dumped: Option<Arc<AtomicBool>>,
+    /// Save the state at regular intervals, and when the execution is interrupted. Only
+    /// supported for `u8` cells and the standard dialect.
+    pub checkpoint: Option<Checkpointing>,
+    /// Continue the execution from a checkpoint instead of starting from the beginning
+    pub resume: Option<Checkpoint>,
+    /// Flag which is checked before every instruction. Setting it to `true` (for instance from a
+    /// signal handler) stops the execution with `RunError::Interrupted`.
+    pub interrupt: Option<Arc<AtomicBool>>,
+    /// Counters the run loop publishes its progress to a few times per second, for instance to
+    /// display it from another thread
+    pub progress: Option<Arc<Progress>>,
+    /// Run the optimizer before executing the program
+    pub optimize: bool,
+    /// Options passed to the lexer
+    pub lexer: LexerOptions,
+    /// Bytes read by `,`. If `None`, the input following a top-level `!` in the program is used
+    /// (with `CommentPolicy::Dialect`), otherwise stdin.
+    pub input: Option<Vec<u8>>,
+    /// Never read stdin: without `input` and inline input, `,` is at the end of the input right
+    /// away
+    pub no_stdin: bool,
+    /// Translation of line endings of the input and the output
+    pub newline: Newline,
+    /// Initial content of the tape
+    pub tape_init: Option<Vec<u8>>,
+    /// Initial position of the pointer
+    pub pointer: usize,
+    /// Dump the tape after the execution
+    pub dump_tape: Option<TapeDump>,
+    /// Seed of the random number generator (random dialect). If `None`, a seed is taken from the
+    /// system clock.
+    pub seed: Option<u64>,
+    /// Count how often every instruction is executed (see `RunReport::counts`)
+    pub profile: bool,
+    /// Record the instruction being executed this many times per second (see
+    /// `RunReport::samples`)
+    pub sample_hz: Option<u32>,
+    /// Count cycles, instructions and branches of the processor while the program is executed
+    /// (see `RunReport::perf`). Requires Linux and the `perf` feature.
+    pub perf: bool,
+    /// Value read by `,` at the end of the input
+    pub eof: Eof,
+    /// What `+` and `-` do at the limits of a cell. Other cells than `u8` only support
+    /// `Arithmetic::Wrap`.
+    pub arithmetic: Arithmetic,
+    /// What moving left of the first cell does. Only applies to linear tapes of `u8` cells;
+    /// other cells than `u8` only support `Underflow::Grow`.
+    pub underflow: Underflow,
+    /// Type of the cells. Cells other than `u8` are run on a `CellMachine`, which supports
+    /// neither dialects nor tape dumps.
+    pub cells: CellType,
+}
+
+/// Value read by `,` at the end of the input
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Eof {
+    /// `0`
+    #[default]
+    Zero,
+    /// `255`, i.e. `-1`
+    Max,
+}
+
+impl FromStr for Eof {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(Eof::Zero),
+            "255" | "-1" => Ok(Eof::Max),
+            _ => Err(format!("unsupported end of input value `{}`, use 0 or 255", s)),
+        }
+    }
+}
+
+impl fmt::Display for Eof {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Eof::Zero => write!(f, "0"),
+            Eof::Max => write!(f, "255"),
+        }
+    }
+}
+
+/// What moving the pointer left of the first cell of a linear tape does
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Underflow {
+    /// The tape grows to the left
+    #[default]
+    Grow,
+    /// The tape is a circle of the given number of cells: the first cell is right of the last
+    /// one, in both directions
+    Wrap(usize),
+    /// The execution fails with `RunError::Underflow`
+    Error,
+}
+
+impl FromStr for Underflow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "grow" => Ok(Underflow::Grow),
+            "error" => Ok(Underflow::Error),
+            _ => match s.strip_prefix("wrap:").map(str::parse) {
+                Some(Ok(n)) if n > 0 => Ok(Underflow::Wrap(n)),
+                _ => Err(format!(
+                    "unsupported underflow policy `{}`, use grow, wrap:<cells> or error",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Underflow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Underflow::Grow => write!(f, "grow"),
+            Underflow::Wrap(n) => write!(f, "wrap:{}", n),
+            Underflow::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// What `+` and `-` do when the value of a cell leaves the range `0..=255`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Arithmetic {
+    /// `255 + 1` is `0` and `0 - 1` is `255`
+    #[default]
+    Wrap,
+    /// Cells stay at `255` or `0`
+    Saturate,
+    /// The execution fails with `RunError::Overflow`
+    Trap,
+}
+
+impl Arithmetic {
+    /// The dead stores the optimizer may remove with this arithmetic
+    pub fn dead_stores(self) -> DeadStores {
+        match self {
+            Arithmetic::Wrap => DeadStores::All,
+            Arithmetic::Saturate => DeadStores::Decrement,
+            Arithmetic::Trap => DeadStores::Keep,
+        }
+    }
+}
+
+impl FromStr for Arithmetic {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wrap" => Ok(Arithmetic::Wrap),
+            "saturate" => Ok(Arithmetic::Saturate),
+            "trap" => Ok(Arithmetic::Trap),
+            _ => Err(format!(
+                "unsupported arithmetic `{}`, use wrap, saturate or trap",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Arithmetic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Arithmetic::Wrap => write!(f, "wrap"),
+            Arithmetic::Saturate => write!(f, "saturate"),
+            Arithmetic::Trap => write!(f, "trap"),
+        }
+    }
+}
+
+/// Presets of limits for running untrusted programs, e.g. in a service (see `Sandbox::apply`)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Sandbox {
+    /// Tight limits for short programs, e.g. snippets submitted to a web frontend
+    Strict,
+    /// Generous limits, which only stop programs running away
+    Default,
+    /// No limits
+    #[default]
+    Off,
+}
+
+impl Sandbox {
+    /// Restrict `config` to the limits of the preset: the number of steps, the wall time, the
+    /// cells of the tape, the bytes written and the length and nesting depth of the program.
+    /// Limits which are already set in `config` can only be lowered. Repeat counts are turned
+    /// off, since a few digits expand to thousands of instructions. Stdin is never read, and no
+    /// core dumps are written.
+    ///
+    /// ```
+    /// use bfrscc_core::run::{run_with_config, RunConfig, RunError, Sandbox};
+    ///
+    /// let mut config = RunConfig {
+    ///     max_steps: Some(1000),
+    ///     max_output_bytes: Some(1 << 30),
+    ///     ..RunConfig::default()
+    /// };
+    /// Sandbox::Strict.apply(&mut config);
+    /// assert_eq!(config.max_steps, Some(1000));
+    /// assert_eq!(config.max_output_bytes, Some(65536));
+    /// assert_eq!(config.lexer.limits.max_depth, Some(256));
+    /// assert_eq!(run_with_config("+[]", &config).unwrap_err(), RunError::StepLimit(1000));
+    /// ```
+    pub fn apply(&self, config: &mut RunConfig) {
+        let (steps, time, tape, output, length, depth) = match *self {
+            Sandbox::Strict => (
+                10_000_000,
+                Duration::from_secs(1),
+                30_000,
+                64 << 10,
+                64 << 10,
+                256,
+            ),
+            Sandbox::Default => (
+                1_000_000_000,
+                Duration::from_secs(10),
+                1 << 20,
+                1 << 20,
+                16 << 20,
+                4096,
+            ),
+            Sandbox::Off => return,
+        };
+        clamp(&mut config.max_tape, tape);
+        clamp(&mut config.max_steps, steps);
+        clamp(&mut config.max_time, time);
+        clamp(&mut config.max_output_bytes, output);
+        clamp(&mut config.lexer.limits.max_length, length);
+        clamp(&mut config.lexer.limits.max_depth, depth);
+        config.lexer.repeat_counts = false;
+        config.no_stdin = true;
+        config.core_file = None;
+    }
+}
+
+/// Set `limit` to `max` unless it is lower already.
+fn clamp<T: Ord + Copy>(limit: &mut Option<T>, max: T) {
+    *limit = Some(limit.map_or(max, |x| x.min(max)));
+}
+
+impl FromStr for Sandbox {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(Sandbox::Strict),
+            "default" => Ok(Sandbox::Default),
+            "off" => Ok(Sandbox::Off),
+            _ => Err(format!(
+                "unsupported sandbox `{}`, use strict, default or off",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Sandbox {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Sandbox::Strict => write!(f, "strict"),
+            Sandbox::Default => write!(f, "default"),
+            Sandbox::Off => write!(f, "off"),
+        }
+    }
+}
+
+/// Settings of the tape dump after an execution
+#[derive(Debug, Clone)]
+pub struct TapeDump {
+    /// How cells are written
+    pub format: DumpFormat,
+    /// Indices of the cells to dump, all allocated cells if `None`
+    pub range: Option<Range<isize>>,
+    /// File to write the dump to, stderr if `None`
+    pub file: Option<PathBuf>,
+}
+
+/// Statistics of an execution
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunReport {
+    /// Number of executed instructions
+    pub steps: u64,
+    /// Number of executed `,`
+    pub inputs_read: u64,
+    /// Number of executed `.`
+    pub outputs_written: u64,
+    /// Deepest nesting of loops which has been entered
+    pub max_loop_depth: usize,
+    /// Number of allocated cells at the end
+    pub tape_extent: usize,
+    /// Number of cells memory was allocated for at the peak, including `RunConfig::tape_reserve`
+    pub tape_capacity: usize,
+    /// Time spent executing
+    pub wall_time: Duration,
+    /// Final tape, if the machine is known to the runner
+    pub tape: Option<TapeView>,
+    /// Number of executions of every instruction, by index (with `RunConfig::profile`)
+    pub counts: Option<Vec<u64>>,
+    /// Number of samples taken at every instruction, by index (with `RunConfig::sample_hz`)
+    pub samples: Option<Vec<u64>>,
+    /// Iterations of the loops since they were entered, by the index of their `[` (with
+    /// `RunConfig::max_loop_iters`). They are kept when an execution continues in chunks.
+    pub loop_iters: Option<Vec<u64>>,
+    /// Hardware counters of the execution (with `RunConfig::perf`)
+    pub perf: Option<Box<PerfCounts>>,
+    /// Everything needed to repeat the run, if it was started by `run_with_config`
+    pub environment: Option<Box<Environment>>,
+}
+
+impl RunReport {
+    /// The report as a single line of JSON, without the tape and the execution counts
+    pub fn to_json(&self) -> String {
+        let environment = self.environment.as_ref().map(|x| x.to_json());
+        let perf = self.perf.as_ref().map(|x| x.to_json());
+        Object::new()
+            .string("type", "report")
+            .integer("steps", self.steps)
+            .integer("inputs_read", self.inputs_read)
+            .integer("outputs_written", self.outputs_written)
+            .integer("max_loop_depth", self.max_loop_depth as u64)
+            .integer("tape_extent", self.tape_extent as u64)
+            .integer("tape_capacity", self.tape_capacity as u64)
+            .number("wall_time", self.wall_time.as_secs_f64())
+            .raw("perf", perf.as_deref().unwrap_or("null"))
+            .raw("environment", environment.as_deref().unwrap_or("null"))
+            .finish()
+    }
+}
+
+impl fmt::Display for RunReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "steps:           {}", self.steps)?;
+        writeln!(f, "inputs read:     {}", self.inputs_read)?;
+        writeln!(f, "outputs written: {}", self.outputs_written)?;
+        writeln!(f, "max loop depth:  {}", self.max_loop_depth)?;
+        writeln!(f, "tape extent:     {}", self.tape_extent)?;
+        writeln!(f, "tape capacity:   {}", self.tape_capacity)?;
+        write!(f, "wall time:       {:?}", self.wall_time)
+    }
+}
+
+/// Progress of a running execution (see `RunConfig::progress`), published by the run loop
+/// `CLOCK_HZ` times per second and when it stops
+#[derive(Debug, Default)]
+pub struct Progress {
+    /// Steps executed so far
+    steps: AtomicU64,
+    /// Index of the next instruction
+    pc: AtomicUsize,
+}
+
+impl Progress {
+    /// Progress of an execution which has not started yet
+    pub fn new() -> Self {
+        Progress::default()
+    }
+
+    /// Steps executed so far
+    pub fn steps(&self) -> u64 {
+        self.steps.load(Ordering::Relaxed)
+    }
+
+    /// Index of the instruction executed next
+    pub fn pc(&self) -> usize {
+        self.pc.load(Ordering::Relaxed)
+    }
+
+    /// Publish the state of the run loop.
+    fn publish(&self, steps: u64, pc: usize) {
+        self.steps.store(steps, Ordering::Relaxed);
+        self.pc.store(pc, Ordering::Relaxed);
+    }
+}
+
+/// Reasons for an execution to fail
+#[derive(Debug, Clone, PartialEq)]
+pub enum RunError {
+    /// The maximum number of steps was exceeded
+    StepLimit(u64),
+    /// The tape grew beyond the maximum number of cells
+    TapeLimit(usize),
+    /// The program wrote more than the maximum number of bytes. The bytes up to the limit were
+    /// written.
+    OutputLimit(u64),
+    /// The execution took longer than the maximum wall time
+    TimeLimit(Duration),
+    /// The loop between the given source positions of its brackets exceeded the maximum number
+    /// of iterations
+    LoopLimit {
+        /// The maximum number of iterations
+        limit: u64,
+        /// Position of the `[`
+        start: usize,
+        /// Position of the `]`
+        end: usize,
+    },
+    /// The execution was interrupted by the user
+    Interrupted,
+    /// The program could not be parsed
+    Parse(ParseError),
+    /// `+` or `-` at the given source position left the range of a cell (with
+    /// `Arithmetic::Trap`). The position is unknown for programs without instructions.
+    Overflow(Option<usize>),
+    /// A checkpoint could not be written, or does not fit the program
+    Checkpoint(String),
+    /// The instruction at the given source position moved the pointer left of the first cell
+    /// (with `Underflow::Error`). The position is unknown outside of the run loop.
+    Underflow(Option<usize>),
+    /// The hardware counters could not be opened or read (with `RunConfig::perf`)
+    Perf(String),
+    /// Memory for the given number of cells could not be reserved (with
+    /// `RunConfig::tape_reserve`)
+    Reserve(usize),
+    /// The settings cannot be combined, e.g. trapping arithmetic with cells wider than a byte
+    Unsupported(String),
+}
+
+impl RunError {
+    /// Position in the source the error refers to, if any
+    pub fn position(&self) -> Option<usize> {
+        match *self {
+            RunError::Parse(ref e) => e.position(),
+            RunError::Overflow(pos) | RunError::Underflow(pos) => pos,
+            RunError::LoopLimit { start, .. } => Some(start),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RunError::StepLimit(n) => write!(f, "step limit of {} exceeded", n),
+            RunError::TapeLimit(n) => write!(f, "tape limit of {} cells exceeded", n),
+            RunError::OutputLimit(n) => write!(f, "output limit of {} bytes exceeded", n),
+            RunError::TimeLimit(t) => write!(f, "time limit of {:?} exceeded", t),
+            RunError::LoopLimit { limit, start, end } => write!(
+                f,
+                "loop at positions {}..={} exceeded the limit of {} iterations",
+                start, end, limit
+            ),
+            RunError::Interrupted => write!(f, "interrupted"),
+            RunError::Parse(ref e) => e.fmt(f),
+            RunError::Overflow(Some(pos)) => write!(f, "cell overflow at position {}", pos),
+            RunError::Overflow(None) => write!(f, "cell overflow"),
+            RunError::Checkpoint(ref msg) => write!(f, "checkpoint: {}", msg),
+            RunError::Perf(ref msg) => write!(f, "hardware counters: {}", msg),
+            RunError::Reserve(n) => write!(f, "unable to reserve memory for {} cells", n),
+            RunError::Unsupported(ref msg) => write!(f, "unsupported: {}", msg),
+            RunError::Underflow(Some(pos)) => {
+                write!(f, "pointer moved left of the first cell at position {}", pos)
+            }
+            RunError::Underflow(None) => write!(f, "pointer moved left of the first cell"),
+        }
+    }
+}
+
+impl Error for RunError {}
+
+/// Get index of instruction within `InstructionStream`. Maybe solve this with a `HashMap`?
+pub fn get_instruction_idx(stream: &InstructionStream, position: usize) -> Option<usize> {
+    for (idx, elem) in stream.iter().enumerate() {
+        if elem.position() == position {
+            return Some(idx);
+        }
+    }
+    None
+}
+
+/// Run
+pub fn run(prog: &str) {
+    let config = RunConfig::default();
+    let prog = compile(prog, &config).expect("Invalid program.");
+    println!("{:?}", prog.instructions());
+
+    // Interpreter holds the position of the pointer
+    let mut state = Interpreter::new();
+    let mut input = input_source(&config, &prog);
+    execute(&prog, &mut state, &config, &mut *input, &mut 0, &mut RunReport::default())
+        .expect("Execution without limits cannot fail.");
+
+    // Print the final tape
+    println!("Tape: {:?}", state.get_tape());
+}
+
+/// Run a program with the given settings. If the execution fails and `config.core_file` is set,
+/// the state of the interpreter at the time of the failure is written to that file, and
+/// `config.core_dumped` is set if that succeeded. If the
+/// execution was interrupted, the current state is printed to stderr. Without a seed in
+/// `config`, one is taken from the clock. The report contains the environment of the run (see
+/// `repro`). Checkpoints are only supported for `u8` cells and the standard dialect. Cells wider
+/// than a byte only support wrapping arithmetic and a growing tape.
+pub fn run_with_config(prog: &str, config: &RunConfig) -> Result<RunReport, RunError> {
+    let config = &RunConfig {
+        seed: Some(config.seed.unwrap_or_else(clock_seed)),
+        ..config.clone()
+    };
+    if (config.checkpoint.is_some() || config.resume.is_some())
+        && (config.cells != CellType::U8 || config.lexer.dialect != Dialect::Standard)
+    {
+        return Err(RunError::Checkpoint(
+            "only supported for u8 cells and the standard dialect".to_string(),
+        ));
+    }
+    if config.cells != CellType::U8 {
+        if config.arithmetic != Arithmetic::Wrap {
+            return Err(RunError::Unsupported(format!(
+                "{} arithmetic with {} cells",
+                config.arithmetic, config.cells
+            )));
+        }
+        if config.underflow != Underflow::Grow {
+            return Err(RunError::Unsupported(format!(
+                "underflow {} with {} cells",
+                config.underflow, config.cells
+            )));
+        }
+    }
+    if config.perf {
+        Counters::open().map_err(|e| RunError::Perf(e.to_string()))?;
+    }
+    let mut input = Digest::new();
+    let mut report = match (config.cells, config.lexer.dialect) {
+        (CellType::I8, _) => run_cells::<i8>(prog, config, &mut input),
+        (CellType::I16, _) => run_cells::<i16>(prog, config, &mut input),
+        (CellType::I32, _) => run_cells::<i32>(prog, config, &mut input),
+        (CellType::U8, Dialect::Grid) => run_interpreter::<GridTape>(prog, config, &mut input),
+        (CellType::U8, _) if matches!(config.underflow, Underflow::Wrap(_)) => {
+            run_interpreter::<CircularTape>(prog, config, &mut input)
+        }
+        (CellType::U8, _) => run_interpreter::<LinearTape>(prog, config, &mut input),
+    }?;
+    report.environment = Some(Box::new(Environment::new(prog, config, &input)));
+    Ok(report)
+}
+
+/// Implementation of `run_with_config` for cells of type `C`. Core dumps are not written. The
+/// bytes read are added to `digest`.
+fn run_cells<C: Cell>(
+    prog: &str,
+    config: &RunConfig,
+    digest: &mut Digest,
+) -> Result<RunReport, RunError> {
+    let prog = compile(prog, config)?;
+    let mut machine: CellMachine<C> = CellMachine::new();
+    machine.set_newline(config.newline);
+    if let Some(n) = tape_reserve(config) {
+        machine.reserve(n).map_err(|_| RunError::Reserve(n))?;
+    }
+    let bytes = input_bytes(config, &prog).inspect(|&b| digest.update(b));
+    let mut input = with_eof(config.eof, Box::new(bytes));
+    let mut report = RunReport::default();
+    execute(&prog, &mut machine, config, &mut *input, &mut 0, &mut report)?;
+    Ok(report)
+}
+
+/// Implementation of `run_with_config` for a given kind of tape. The bytes read are added to
+/// `digest`.
+fn run_interpreter<T: Tape>(
+    prog: &str,
+    config: &RunConfig,
+    digest: &mut Digest,
+) -> Result<RunReport, RunError> {
+    let instr = compile(prog, config)?;
+    let mut state: Interpreter<T> = interpreter(&instr, config)?;
+    let mut idx = 0;
+    let mut report = RunReport::default();
+    if let Some(ref checkpoint) = config.resume {
+        resume(checkpoint, &instr, program_part(prog, config), config, &mut state)?;
+        idx = checkpoint.snapshot.pc;
+        report.steps = checkpoint.snapshot.steps;
+        report.inputs_read = checkpoint.inputs_read;
+        report.outputs_written = checkpoint.outputs_written;
+    }
+    let res = {
+        let _span = span("run");
+        // The bytes read before the checkpoint are read again, but not passed to the program.
+        let bytes = input_bytes(config, &instr)
+            .inspect(|&b| digest.update(b))
+            .skip(report.inputs_read as usize);
+        let mut input = with_eof(config.eof, Box::new(bytes));
+        let source = program_part(prog, config);
+        execute_checkpointed(
+            &instr,
+            source,
+            &mut state,
+            config,
+            &mut *input,
+            &mut idx,
+            &mut report,
+        )
+    };
+    log_msg!(
+        Level::Info,
+        "run: {} steps, {} cells",
+        report.steps,
+        state.tape_len()
+    );
+    let view = TapeView::new(state.tape()).with_names(instr.cell_names());
+    if let Some(ref dump) = config.dump_tape {
+        let text = view.dump(dump.range.clone(), dump.format);
+        stdio::flush().ok();
+        match dump.file {
+            Some(ref path) => {
+                if let Err(err) = fs::write(path, text) {
+                    eprintln!("Unable to write tape dump {}: {}", path.display(), err);
+                }
+            }
+            None => eprint!("{}", text),
+        }
+    }
+    if let Err(ref e) = res {
+        log_msg!(
+            Level::Info,
+            "run: tape at failure: {}",
+            view.render(ERROR_WINDOW, DumpFormat::Dec)
+        );
+        let snapshot = Snapshot::new(
+            program_part(prog, config),
+            idx,
+            state.get_pos(),
+            report.steps,
+            state.tape().cells(),
+        )
+        .compiled_with(&config.lexer, instr.opt_level(), config.arithmetic.dead_stores());
+        #[cfg(feature = "debugger")]
+        if *e == RunError::Interrupted {
+            let stderr = io::stderr();
+            Debugger::new(snapshot.clone())
+                .reason(&e.to_string())
+                .print_state(&mut stderr.lock())
+                .ok();
+        }
+        if let Some(ref path) = config.core_file {
+            match CoreDump::new(&e.to_string(), snapshot).save(path) {
+                Ok(()) => {
+                    if let Some(ref dumped) = config.core_dumped {
+                        dumped.store(true, Ordering::Relaxed);
+                    }
+                }
+                Err(err) => eprintln!("Unable to write core file {}: {}", path.display(), err),
+            }
+        }
+    }
+    report.tape = Some(view);
+    res.map(|_| report)
+}
+
+/// Restore the state of `checkpoint` in `state`, after checking that it was taken of the
+/// program `source`, which compiles to `prog`.
+fn resume<T: Tape>(
+    checkpoint: &Checkpoint,
+    prog: &Program,
+    source: &str,
+    config: &RunConfig,
+    state: &mut Interpreter<T>,
+) -> Result<(), RunError> {
+    let snapshot = &checkpoint.snapshot;
+    if checkpoint.optimized != config.optimize {
+        return Err(RunError::Checkpoint(format!(
+            "it was taken {} optimizations",
+            if checkpoint.optimized { "with" } else { "without" }
+        )));
+    }
+    // Snapshots do not keep trailing line breaks, so the compiled programs are compared.
+    let same = snapshot.program == source
+        || compile(&snapshot.program, config).is_ok_and(|x| x.ops() == prog.ops());
+    if !same {
+        return Err(RunError::Checkpoint(
+            "it was taken of a different program".to_string(),
+        ));
+    }
+    state.tape_mut().load(&snapshot.tape, snapshot.pointer);
+    Ok(())
+}
+
+/// `execute` in chunks of `CHECKPOINT_CHUNK` steps. Between two chunks, a checkpoint is saved if
+/// `config.checkpoint` is set and its interval has passed since the last one. A checkpoint is
+/// also saved when the execution is interrupted.
+fn execute_checkpointed<T: Tape>(
+    prog: &Program,
+    source: &str,
+    state: &mut Interpreter<T>,
+    config: &RunConfig,
+    input: &mut dyn Iterator<Item = u8>,
+    idx: &mut usize,
+    report: &mut RunReport,
+) -> Result<(), RunError> {
+    let checkpointing = match config.checkpoint {
+        Some(ref x) => x,
+        None => return execute(prog, state, config, input, idx, report),
+    };
+    let max_steps = config.max_steps.unwrap_or(u64::MAX);
+    let mut chunk = config.clone();
+    let mut last = Instant::now();
+    loop {
+        chunk.max_steps = Some(max_steps.min(report.steps.saturating_add(CHECKPOINT_CHUNK)));
+        match execute(prog, state, &chunk, input, idx, report) {
+            Err(RunError::StepLimit(_)) if report.steps < max_steps => {}
+            Err(RunError::Interrupted) => {
+                save_checkpoint(checkpointing, source, state, config, *idx, report)?;
+                return Err(RunError::Interrupted);
+            }
+            res => return res,
+        }
+        if last.elapsed() >= checkpointing.every {
+            save_checkpoint(checkpointing, source, state, config, *idx, report)?;
+            last = Instant::now();
+        }
+    }
+}
+
+/// Save the state of an execution of `source` to the file of `checkpointing`. The output is
+/// flushed first, so nothing written before the checkpoint is lost.
+fn save_checkpoint<T: Tape>(
+    checkpointing: &Checkpointing,
+    source: &str,
+    state: &Interpreter<T>,
+    config: &RunConfig,
+    idx: usize,
+    report: &RunReport,
+) -> Result<(), RunError> {
+    stdio::flush().ok();
+    let snapshot = Snapshot::new(
+        source,
+        idx,
+        state.get_pos(),
+        report.steps,
+        state.tape().cells(),
+    )
+    .compiled_with(&config.lexer, config.optimize as u8, config.arithmetic.dead_stores());
+    Checkpoint::new(
+        config.optimize,
+        report.inputs_read,
+        report.outputs_written,
+        snapshot,
+    )
+    .save(&checkpointing.file)
+    .map_err(|err| {
+        RunError::Checkpoint(format!(
+            "unable to write {}: {}",
+            checkpointing.file.display(),
+            err
+        ))
+    })?;
+    log_msg!(Level::Info, "checkpoint: {} steps", report.steps);
+    Ok(())
+}
+
+/// Create an interpreter for `prog` which is set up according to `config`. Fails if the memory
+/// of `config.tape_reserve` cannot be reserved.
+pub fn interpreter<T: Tape>(prog: &Program, config: &RunConfig) -> Result<Interpreter<T>, RunError> {
+    let mut state: Interpreter<T> = Interpreter::with_tapes(prog.dialect().tapes());
+    state.set_newline(config.newline);
+    state.set_seed(config.seed.unwrap_or_else(clock_seed));
+    if prog.dialect() != Dialect::Grid {
+        state.set_underflow(config.underflow);
+    }
+    if config.tape_init.is_some() || config.pointer > 0 {
+        let cells = config.tape_init.as_deref().unwrap_or_default();
+        state.tape_mut().load(cells, config.pointer);
+    }
+    if let Some(n) = tape_reserve(config) {
+        state.tape_mut().reserve(n).map_err(|_| RunError::Reserve(n))?;
+    }
+    Ok(state)
+}
+
+/// Number of cells to reserve memory for: `config.tape_reserve`, but at most `config.max_tape`
+fn tape_reserve(config: &RunConfig) -> Option<usize> {
+    let max = config.max_tape.unwrap_or(usize::MAX);
+    config.tape_reserve.map(|n| n.min(max))
+}
+
+/// Seed derived from the system clock
+pub fn clock_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() ^ u64::from(d.subsec_nanos()) << 32)
+        .unwrap_or(0)
+}
+
+/// Run a program on an arbitrary `Machine`, for instance a `BufferMachine` which collects the
+/// output. Core dumps are not written.
+pub fn run_on<M: Machine>(
+    prog: &str,
+    machine: &mut M,
+    config: &RunConfig,
+) -> Result<RunReport, RunError> {
+    run_program(&compile(prog, config)?, machine, config)
+}
+
+/// Run a compiled program on `machine`, for instance a machine created with
+/// `Program::spawn_machine`. The input is taken from `config` or the inline input of `prog`,
+/// stdin otherwise. Core dumps are not written.
+pub fn run_program<M: Machine>(
+    prog: &Program,
+    machine: &mut M,
+    config: &RunConfig,
+) -> Result<RunReport, RunError> {
+    let mut input = input_source(config, prog);
+    let mut report = RunReport::default();
+    execute(prog, machine, config, &mut *input, &mut 0, &mut report)?;
+    Ok(report)
+}
+
+/// Run a compiled program on `machine`, asking `provider` for a byte at every `,`. The input
+/// of `config` and the inline input of `prog` are ignored, `config.newline` and `config.eof`
+/// apply. Core dumps are not written.
+pub fn run_with_input<M: Machine>(
+    prog: &Program,
+    machine: &mut M,
+    config: &RunConfig,
+    provider: &mut dyn InputProvider,
+) -> Result<RunReport, RunError> {
+    let provided = config.newline.input(iter::from_fn(|| provider.next_byte()));
+    let mut input = with_eof(config.eof, Box::new(provided));
+    let mut report = RunReport::default();
+    execute(prog, machine, config, &mut *input, &mut 0, &mut report)?;
+    Ok(report)
+}
+
+/// Returns the part of `prog` which is code, i.e. without inline input.
+pub fn program_part<'a>(prog: &'a str, config: &RunConfig) -> &'a str {
+    match config.lexer.comments {
+        CommentPolicy::Dialect => split_input(prog).0,
+        _ => prog,
+    }
+}
+
+/// Lex, validate and parse `prog` with the settings of `config` and optimize it for
+/// `config.arithmetic` if requested.
+pub fn compile(prog: &str, config: &RunConfig) -> Result<Program, RunError> {
+    let plain = Program::compile(prog, &config.lexer, 0).map_err(RunError::Parse)?;
+    Ok(if config.optimize {
+        plain.optimized_with(config.arithmetic.dead_stores())
+    } else {
+        plain
+    })
+}
+
+/// Returns the bytes read by `,`: `config.input` if set, the inline input of `prog` if there is
+/// any and stdin otherwise. Reading beyond the end of the input yields `0`, read errors are
+/// treated like the end of the input. Line endings are translated according to `config.newline`.
+pub fn input_source(config: &RunConfig, prog: &Program) -> Box<dyn Iterator<Item = u8>> {
+    with_eof(config.eof, input_bytes(config, prog))
+}
+
+/// The input of `input_source` up to its end
+fn input_bytes(config: &RunConfig, prog: &Program) -> Box<dyn Iterator<Item = u8>> {
+    let newline = config.newline;
+    match (config.input.clone(), prog.inline_input().map(|x| x.to_vec())) {
+        (Some(input), _) | (None, Some(input)) => Box::new(newline.input(input.into_iter())),
+        (None, None) if config.no_stdin => Box::new(iter::empty()),
+        (None, None) => Box::new(
+            newline.input(stdio::stdin_bytes()),
+        ),
+    }
+}
+
+/// `input` followed by the values read at its end according to `eof`
+fn with_eof<'a>(
+    eof: Eof,
+    input: Box<dyn Iterator<Item = u8> + 'a>,
+) -> Box<dyn Iterator<Item = u8> + 'a> {
+    match eof {
+        // `step` reads `0` at the end of the input.
+        Eof::Zero => input,
+        Eof::Max => Box::new(input.chain(iter::repeat(u8::MAX))),
+    }
+}
+
+/// Execute `prog` on `state`, starting at instruction `idx`. `idx` and `report` are updated while
+/// running, which allows the caller to inspect where the execution stopped.
+fn execute<M: Machine>(
+    prog: &Program,
+    state: &mut M,
+    config: &RunConfig,
+    input: &mut dyn Iterator<Item = u8>,
+    idx: &mut usize,
+    report: &mut RunReport,
+) -> Result<(), RunError> {
+    let perf_error = |e: io::Error| RunError::Perf(e.to_string());
+    let counters = if config.perf {
+        Some(Counters::open().map_err(perf_error)?)
+    } else {
+        None
+    };
+    let start = Instant::now();
+    if let Some(ref counters) = counters {
+        counters.enable().map_err(perf_error)?;
+    }
+    let res = execute_steps(prog, state, config, input, idx, report);
+    let counts = counters.map(|c| c.disable().and_then(|_| c.read()));
+    stdio::flush().ok();
+    report.wall_time += start.elapsed();
+    report.tape_extent = state.tape_len();
+    report.tape_capacity = state.tape_capacity();
+    match counts {
+        Some(Ok(counts)) => report.perf.get_or_insert_with(Box::default).add(&counts),
+        // Errors of the execution take precedence.
+        Some(Err(e)) => return res.and(Err(perf_error(e))),
+        None => {}
+    }
+    res
+}
+
+/// Implementation of `execute`, without the measurements at the end. The limits are hoisted out
+/// of the loop and the packed instructions of `prog` are executed with a local program counter,
+/// which is written back to `idx` when the execution stops.
+fn execute_steps<M: Machine>(
+    prog: &Program,
+    state: &mut M,
+    config: &RunConfig,
+    input: &mut dyn Iterator<Item = u8>,
+    idx: &mut usize,
+    report: &mut RunReport,
+) -> Result<(), RunError> {
+    let ops = prog.ops();
+    if config.profile && report.counts.is_none() {
+        report.counts = Some(vec![0; ops.len()]);
+    }
+    let sampler = config.sample_hz.map(Sampler::start);
+    if sampler.is_some() && report.samples.is_none() {
+        report.samples = Some(vec![0; ops.len()]);
+    }
+    let interrupt = config.interrupt.as_deref();
+    let max_steps = config.max_steps.unwrap_or(u64::MAX);
+    let max_tape = config.max_tape.unwrap_or(usize::MAX);
+    let max_output = config.max_output_bytes.unwrap_or(u64::MAX);
+    let checked = config.arithmetic != Arithmetic::Wrap;
+    let deadline = config.max_time.map(|max| Deadline::start(max, report.wall_time));
+    let progress = config.progress.as_deref().map(Publisher::start);
+    let looped = config.max_loop_iters.is_some();
+    let max_iters = config.max_loop_iters.unwrap_or(u64::MAX);
+    let mut iters = match report.loop_iters.take() {
+        Some(iters) => iters,
+        None if looped => vec![0; ops.len()],
+        None => vec![],
+    };
+    // Sampling, interrupts, the time limit and the progress are handled behind a single branch,
+    // which is skipped without them.
+    let watched =
+        sampler.is_some() || interrupt.is_some() || deadline.is_some() || progress.is_some();
+    // Runs on the same cell are executed at once, unless every instruction needs to be seen.
+    let runs = !checked && report.counts.is_none();
+    let mut pc = *idx;
+    let mut steps = report.steps;
+    let res = loop {
+        if pc >= ops.len() {
+            break Ok(());
+        }
+        if watched {
+            if let Some(ref progress) = progress {
+                progress.tick(steps, pc);
+            }
+            if let Err(e) = watch(sampler.as_ref(), interrupt, deadline.as_ref(), report, pc) {
+                break Err(e);
+            }
+        }
+        if steps >= max_steps {
+            break Err(stop(RunError::StepLimit(max_steps)));
+        }
+        let mut op = ops[pc];
+        let len = op.run as usize;
+        if runs && len > 1 && max_steps - steps >= len as u64 {
+            let run = &ops[pc..pc + len];
+            let (mut inputs, mut outputs) = (0, 0);
+            for x in run {
+                match x.token {
+                    Token::Input => inputs += 1,
+                    Token::Output => outputs += 1,
+                    _ => {}
+                }
+            }
+            // A run exceeding the output limit is executed step by step up to the limit.
+            if max_output - report.outputs_written >= outputs && state.exec_run(run, input) {
+                report.inputs_read += inputs;
+                report.outputs_written += outputs;
+                pc += len;
+                steps += len as u64;
+                continue;
+            }
+        }
+        match op.token {
+            Token::Input => report.inputs_read += 1,
+            Token::Output if report.outputs_written >= max_output => {
+                break Err(stop(RunError::OutputLimit(max_output)));
+            }
+            Token::Output => report.outputs_written += 1,
+            Token::LoopBegin if !state.is_zero() => {
+                report.max_loop_depth = report.max_loop_depth.max(op.depth as usize);
+                if looped {
+                    iters[pc] = 1;
+                }
+            }
+            Token::LoopEnd if looped && !state.is_zero() => {
+                let begin = op.jump as usize;
+                if iters[begin] >= max_iters {
+                    break Err(stop(loop_limit(prog, begin, max_iters)));
+                }
+                iters[begin] += 1;
+            }
+            _ => {}
+        }
+        if let Some(ref mut counts) = report.counts {
+            counts[pc] += 1;
+        }
+        if checked {
+            if overflows(op, state.get_val()) {
+                if let Err(e) = saturate(prog, op, pc, state, config.arithmetic) {
+                    break Err(e);
+                }
+                pc += 1;
+                steps += 1;
+                continue;
+            }
+            op = without_transfer(op);
+        }
+        let last = pc;
+        pc = exec(prog, op, pc, state, input);
+        steps += 1;
+        // Only moves, switching tapes and transfers can grow the tape.
+        if (moves(op.token) || op.kind == LoopKind::Transfer) && state.tape_len() > max_tape {
+            break Err(stop(RunError::TapeLimit(max_tape)));
+        }
+        if let Err(e) = state.status() {
+            break Err(located(prog, last, e));
+        }
+    };
+    *idx = pc;
+    report.steps = steps;
+    if looped {
+        report.loop_iters = Some(iters);
+    }
+    if let Some(ref progress) = config.progress {
+        progress.publish(steps, pc);
+    }
+    res
+}
+
+/// Returns `true` if `token` moves the pointer or switches the tape.
+#[inline]
+fn moves(token: Token) -> bool {
+    matches!(
+        token,
+        Token::MoveLeft | Token::MoveRight | Token::MoveUp | Token::MoveDown | Token::SwitchTape
+    )
+}
+
+/// Returns `true` if `op` takes a cell of value `val` out of the range `0..=255`.
+#[inline]
+fn overflows(op: Op, val: u8) -> bool {
+    match op.token {
+        Token::Increase => val.checked_add(op.multiplier).is_none(),
+        Token::Decrease => val.checked_sub(op.multiplier).is_none(),
+        _ => false,
+    }
+}
+
+/// Stops the run loop with `e`. Calling it marks the branch leading to it as unlikely, so the
+/// checks of the run loop stay out of the way of the instructions.
+#[cold]
+#[inline(never)]
+fn stop(e: RunError) -> RunError {
+    e
+}
+
+/// Take a sample at `pc` if one is due and check whether the execution was interrupted or ran
+/// out of time.
+#[inline]
+fn watch(
+    sampler: Option<&Sampler>,
+    interrupt: Option<&AtomicBool>,
+    deadline: Option<&Deadline>,
+    report: &mut RunReport,
+    pc: usize,
+) -> Result<(), RunError> {
+    if sampler.is_some_and(Sampler::tick) {
+        if let Some(ref mut samples) = report.samples {
+            samples[pc] += 1;
+        }
+    }
+    if interrupt.is_some_and(|flag| flag.load(Ordering::Relaxed)) {
+        return Err(stop(RunError::Interrupted));
+    }
+    if let Some(deadline) = deadline {
+        if deadline.clock.tick() && Instant::now() >= deadline.at {
+            return Err(stop(RunError::TimeLimit(deadline.limit)));
+        }
+    }
+    Ok(())
+}
+
+/// End of the wall time of an execution (see `RunConfig::max_time`). A `Sampler` serves as a
+/// clock, so the time is only read when it ticks.
+struct Deadline {
+    /// Ticks `CLOCK_HZ` times per second
+    clock: Sampler,
+    /// When the time is up
+    at: Instant,
+    /// The maximum wall time
+    limit: Duration,
+}
+
+impl Deadline {
+    /// Start the clock of an execution which may take `limit`, of which `spent` are already
+    /// spent, e.g. before it was resumed.
+    fn start(limit: Duration, spent: Duration) -> Self {
+        Deadline {
+            clock: Sampler::start(CLOCK_HZ),
+            at: Instant::now() + limit.saturating_sub(spent),
+            limit,
+        }
+    }
+}
+
+/// Publishes the progress of the run loop (see `RunConfig::progress`). Like `Deadline`, it uses
+/// a `Sampler` as a clock.
+struct Publisher<'a> {
+    /// Ticks `CLOCK_HZ` times per second
+    clock: Sampler,
+    /// Where the progress is published
+    progress: &'a Progress,
+}
+
+impl<'a> Publisher<'a> {
+    /// Start the clock of publishing to `progress`.
+    fn start(progress: &'a Progress) -> Self {
+        Publisher {
+            clock: Sampler::start(CLOCK_HZ),
+            progress,
+        }
+    }
+
+    /// Publish `steps` and `pc` if an update is due.
+    #[inline]
+    fn tick(&self, steps: u64, pc: usize) {
+        if self.clock.tick() {
+            self.progress.publish(steps, pc);
+        }
+    }
+}
+
+/// Error of the instruction at index `idx` of `prog` overflowing
+#[cold]
+#[inline(never)]
+fn overflow(prog: &Program, idx: usize) -> RunError {
+    RunError::Overflow(prog.instructions().get(idx).map(|x| x.position()))
+}
+
+/// Error of the instruction at index `idx` of `prog` moving the pointer left of the first cell
+#[cold]
+#[inline(never)]
+fn underflow(prog: &Program, idx: usize) -> RunError {
+    RunError::Underflow(prog.instructions().get(idx).map(|x| x.position()))
+}
+
+/// Error of the loop starting at index `idx` of `prog` exceeding `limit` iterations
+#[cold]
+#[inline(never)]
+fn loop_limit(prog: &Program, idx: usize, limit: u64) -> RunError {
+    let instructions = prog.instructions();
+    RunError::LoopLimit {
+        limit,
+        start: instructions[idx].position(),
+        end: instructions[prog.jump(idx)].position(),
+    }
+}
+
+/// `e`, reported by the machine after the instruction at index `idx` of `prog`, with the
+/// position of the instruction if the machine does not know it
+#[cold]
+#[inline(never)]
+fn located(prog: &Program, idx: usize, e: RunError) -> RunError {
+    match e {
+        RunError::Underflow(None) => underflow(prog, idx),
+        e => e,
+    }
+}
+
+/// Execute the overflowing `op` at index `idx` of `prog`: with `Arithmetic::Trap`, the
+/// execution fails, otherwise the cell of `state` is set to its limit.
+#[cold]
+#[inline(never)]
+fn saturate<M: Machine>(
+    prog: &Program,
+    op: Op,
+    idx: usize,
+    state: &mut M,
+    arithmetic: Arithmetic,
+) -> Result<(), RunError> {
+    if arithmetic == Arithmetic::Trap {
+        return Err(overflow(prog, idx));
+    }
+    let val = state.get_val();
+    match op.token {
+        Token::Increase => state.increase(u8::MAX - val),
+        _ => state.decrease(val),
+    };
+    Ok(())
+}
+
+/// Execute the instruction at index `idx` of `prog` on `state` and move `idx` to the next
+/// instruction to be executed. `,` reads from `input`.
+pub fn step<M: Machine>(
+    prog: &Program,
+    state: &mut M,
+    input: &mut dyn Iterator<Item = u8>,
+    idx: &mut usize,
+) {
+    *idx = exec(prog, prog.ops()[*idx], *idx, state, input);
+}
+
+/// Like `step`, but loops are always executed instruction by instruction, also those which the
+/// run loop executes in a single step (see `LoopKind::is_single_step`).
+pub fn step_literally<M: Machine>(
+    prog: &Program,
+    state: &mut M,
+    input: &mut dyn Iterator<Item = u8>,
+    idx: &mut usize,
+) {
+    let op = Op {
+        kind: LoopKind::Loop,
+        ..prog.ops()[*idx]
+    };
+    *idx = exec(prog, op, *idx, state, input);
+}
+
+/// Like `step`, but `+` and `-` at the limits of a cell behave according to `arithmetic`.
+pub fn step_with_arithmetic<M: Machine>(
+    prog: &Program,
+    state: &mut M,
+    input: &mut dyn Iterator<Item = u8>,
+    idx: &mut usize,
+    arithmetic: Arithmetic,
+) -> Result<(), RunError> {
+    let op = prog.ops()[*idx];
+    if arithmetic == Arithmetic::Wrap {
+        step(prog, state, input, idx);
+    } else if !overflows(op, state.get_val()) {
+        *idx = exec(prog, without_transfer(op), *idx, state, input);
+    } else {
+        saturate(prog, op, *idx, state, arithmetic)?;
+        *idx += 1;
+    }
+    Ok(())
+}
+
+/// `op` for execution with arithmetic other than `Arithmetic::Wrap`. Transfers wrap around, so
+/// their loops are executed instruction by instruction.
+fn without_transfer(op: Op) -> Op {
+    match op.kind {
+        LoopKind::Transfer => Op {
+            kind: LoopKind::Loop,
+            ..op
+        },
+        _ => op,
+    }
+}
+
+/// Execute `op`, which is at index `pc` of `prog`, on `state`. Returns the index of the next
+/// instruction.
+#[inline]
+fn exec<M: Machine>(
+    prog: &Program,
+    op: Op,
+    pc: usize,
+    state: &mut M,
+    input: &mut dyn Iterator<Item = u8>,
+) -> usize {
+    let m = op.multiplier;
+    match op.token {
+        // Move right
+        Token::MoveRight => {
+            state.right(m);
+        }
+        // Move left
+        Token::MoveLeft => {
+            state.left(m);
+        }
+        // Move up
+        Token::MoveUp => {
+            state.up(m);
+        }
+        // Move down
+        Token::MoveDown => {
+            state.down(m);
+        }
+        // Increase the value at the current tape position. Allow for buffer overflows!
+        Token::Increase => {
+            state.increase(m);
+        }
+        // Decrease the value at the current tape position. Allow for buffer overflows!
+        Token::Decrease => {
+            state.decrease(m);
+        }
+        // Print the `char` at the current tape position.
+        Token::Output => state.output(),
+        // Read a byte into the current tape position. Reading beyond the end of the input yields
+        // `0`.
+        Token::Input => {
+            state.input(input.next().unwrap_or(0));
+        }
+        // Breakpoints are handled by the machine
+        Token::Breakpoint => state.breakpoint(),
+        // Switch to the next tape
+        Token::SwitchTape => state.switch_tape(),
+        // Write a random byte
+        Token::Random => state.random(),
+        // We found a `[` which indicates the start of a loop. If the value at current tape
+        // is `0`, we jump to the position after the matching `]`. Otherwise do nothing
+        // (means moving on).
+        Token::LoopBegin if state.is_zero() => return op.jump as usize + 1,
+        // `[-]` on a nonzero cell counts as a single step.
+        Token::LoopBegin if op.kind == LoopKind::Clear => {
+            state.clear();
+            return op.jump as usize + 1;
+        }
+        // Loops with a known effect as well.
+        Token::LoopBegin if op.kind == LoopKind::Transfer => {
+            state.transfer(prog.effects(pc).unwrap_or_default());
+            return op.jump as usize + 1;
+        }
+        // We found a `]` which indicates the end of a loop. If the value in the tape at the
+        // current position is nonzero, we move to the matching `[`. Otherwise do nothing
+        // (means moving on).
+        Token::LoopEnd if !state.is_zero() => return op.jump as usize + 1,
+        // Match any other Token...
+        _ => {}
+    }
+    // Move to the next instruction
+    pc + 1
+}
@@ -0,0 +1,221 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Run diff
+//!
+//! Compares two recorded runs (see `recording`), for instance of a program before and after a
+//! change of the optimizer or with two dialects, and finds the first point where they diverge
+//! (`bfrscc diff-runs a.rcd b.rcd`).
+//!
+//! The runs usually take different numbers of steps, so steps are aligned by the instruction
+//! they execute: the `k`-th execution of the instruction at some source position in one run
+//! corresponds to the `k`-th execution of the instruction at the same position in the other
+//! run. Before corresponding steps, the pointers (as offsets from the cell they started at) and
+//! the current cells have to be equal. Instructions executed by only one of the runs, like loops
+//! removed by the optimizer, are skipped. The outputs are compared byte by byte. With several
+//! tapes, the offsets of the pointers include the growth of all tapes to the left.
+//!
+//! ```
+//! use bfrscc_core::recording::{Frame, Recording};
+//! use bfrscc_core::rundiff::{diff_runs, Difference};
+//! use bfrscc_core::snapshot::Snapshot;
+//!
+//! // Runs of `+.` and `-.`
+//! let mut a = Recording::new(false, Snapshot::new("+.", 0, 0, 0, vec![0]));
+//! a.positions = vec![0, 1];
+//! a.push(Frame::new(1, 0, 1));
+//! a.push(Frame::new(2, 0, 1).output(&[1]));
+//! let mut b = Recording::new(false, Snapshot::new("-.", 0, 0, 0, vec![0]));
+//! b.positions = vec![0, 1];
+//! b.push(Frame::new(1, 0, 255));
+//! b.push(Frame::new(2, 0, 255).output(&[255]));
+//!
+//! let divergence = diff_runs(&a, &b).unwrap();
+//! assert_eq!(divergence.difference, Difference::Cell(1, 255));
+//! assert_eq!(divergence.steps, [1, 1]);
+//! assert_eq!(diff_runs(&a, &a), None);
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use diagnostics::{Diagnostic, Severity};
+use recording::Recording;
+
+/// What differs between two runs
+#[derive(Debug, Clone, PartialEq)]
+pub enum Difference {
+    /// Bytes written, `None` if a run wrote fewer bytes
+    Output(Option<u8>, Option<u8>),
+    /// Offsets of the pointers from the cell they started at
+    Pointer(isize, isize),
+    /// Values of the current cells
+    Cell(u8, u8),
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let byte = |x: Option<u8>| match x {
+            Some(x) => format!("{:?}", x as char),
+            None => "nothing".to_string(),
+        };
+        match *self {
+            Difference::Output(a, b) => write!(f, "output {} and {}", byte(a), byte(b)),
+            Difference::Pointer(a, b) => write!(f, "pointer at {} and {}", a, b),
+            Difference::Cell(a, b) => write!(f, "current cell {} and {}", a, b),
+        }
+    }
+}
+
+/// The first point where two runs diverge
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    /// What differs
+    pub difference: Difference,
+    /// Number of steps the runs executed before. For a difference of the output, the next step
+    /// writes the byte.
+    pub steps: [u64; 2],
+    /// Source position of the instruction the runs execute next, `None` at their end
+    pub positions: [Option<usize>; 2],
+}
+
+impl Divergence {
+    /// The divergence as a diagnostic pointing into the source of run `run` (`0` or `1`)
+    pub fn diagnostic(&self, run: usize) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            message: format!("the runs diverge: {}", self.difference),
+            position: self.positions[run],
+            label: format!("after {} steps", self.steps[run]),
+            notes: vec![],
+        }
+    }
+}
+
+/// State of a run before a step
+#[derive(Debug, Clone, Copy)]
+struct Visit {
+    /// Source position of the instruction executed by the step
+    position: Option<usize>,
+    /// Offset of the pointer from the cell it started at
+    pointer: isize,
+    /// Value of the current cell
+    cell: u8,
+}
+
+/// Visits of all steps of a run followed by the state at its end, and the bytes written
+/// together with the step writing them
+fn visits(run: &Recording) -> (Vec<Visit>, Vec<(u64, u8)>) {
+    let initial = run.state_at(0);
+    let mut pc = initial.pc;
+    let mut pointer = initial.pointer as isize;
+    let mut cell = initial.tape.get(initial.pointer).cloned().unwrap_or(0);
+    let mut grown = 0;
+    let mut visits = Vec::with_capacity(run.frames().len());
+    let mut output = vec![];
+    for (step, frame) in run.frames().iter().enumerate() {
+        visits.push(Visit {
+            position: run.positions.get(pc).cloned(),
+            pointer: pointer - grown,
+            cell,
+        });
+        output.extend(frame.output.iter().map(|&x| (step as u64, x)));
+        grown += frame.grown as isize;
+        pc = frame.pc;
+        pointer = frame.pointer as isize;
+        cell = frame.cell;
+    }
+    visits.push(Visit {
+        position: None,
+        pointer: pointer - grown,
+        cell,
+    });
+    (visits, output)
+}
+
+/// Find the first point where the runs `a` and `b` diverge, ordered by the steps of `a`.
+/// Returns `None` if they behave the same.
+pub fn diff_runs(a: &Recording, b: &Recording) -> Option<Divergence> {
+    let (visits_a, output_a) = visits(a);
+    let (visits_b, output_b) = visits(b);
+    let position = |visits: &[Visit], step: u64| visits[step as usize].position;
+
+    // Steps of `b` by the position of their instruction and the number of earlier executions
+    let mut seen = HashMap::new();
+    let mut aligned = HashMap::new();
+    for (step, visit) in visits_b.iter().enumerate() {
+        if let Some(pos) = visit.position {
+            let k = seen.entry(pos).or_insert(0u64);
+            aligned.insert((pos, *k), step);
+            *k += 1;
+        }
+    }
+    let mut state = None;
+    seen.clear();
+    for (step, visit) in visits_a.iter().enumerate() {
+        let pos = match visit.position {
+            Some(pos) => pos,
+            None => continue,
+        };
+        let k = seen.entry(pos).or_insert(0u64);
+        let other = aligned.get(&(pos, *k)).map(|&x| (x, visits_b[x]));
+        *k += 1;
+        let (step_b, visit_b) = match other {
+            Some(other) => other,
+            None => continue,
+        };
+        let difference = if visit.pointer != visit_b.pointer {
+            Difference::Pointer(visit.pointer, visit_b.pointer)
+        } else if visit.cell != visit_b.cell {
+            Difference::Cell(visit.cell, visit_b.cell)
+        } else {
+            continue;
+        };
+        state = Some(Divergence {
+            difference,
+            steps: [step as u64, step_b as u64],
+            positions: [Some(pos), Some(pos)],
+        });
+        break;
+    }
+    let ends = [visits_a.len() as u64 - 1, visits_b.len() as u64 - 1];
+    if state.is_none() {
+        let (end_a, end_b) = (visits_a[ends[0] as usize], visits_b[ends[1] as usize]);
+        let difference = if end_a.pointer != end_b.pointer {
+            Some(Difference::Pointer(end_a.pointer, end_b.pointer))
+        } else if end_a.cell != end_b.cell {
+            Some(Difference::Cell(end_a.cell, end_b.cell))
+        } else {
+            None
+        };
+        state = difference.map(|difference| Divergence {
+            difference,
+            steps: ends,
+            positions: [None, None],
+        });
+    }
+
+    // The steps writing the first differing byte, or the ends of the runs
+    let byte = |output: &[(u64, u8)], i: usize| output.get(i).map(|x| x.1);
+    let mismatch = (0..output_a.len().max(output_b.len()))
+        .find(|&i| byte(&output_a, i) != byte(&output_b, i));
+    let output = mismatch.map(|i| {
+        let steps = [
+            output_a.get(i).map_or(ends[0], |x| x.0),
+            output_b.get(i).map_or(ends[1], |x| x.0),
+        ];
+        Divergence {
+            difference: Difference::Output(byte(&output_a, i), byte(&output_b, i)),
+            steps,
+            positions: [position(&visits_a, steps[0]), position(&visits_b, steps[1])],
+        }
+    });
+    match (state, output) {
+        (Some(state), Some(output)) if output.steps[0] < state.steps[0] => Some(output),
+        (Some(state), _) => Some(state),
+        (None, output) => output,
+    }
+}
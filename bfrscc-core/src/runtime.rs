@@ -0,0 +1,352 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Runtime of compiled programs
+//!
+//! The tape and the I/O of programs compiled by native backends. Backends only emit calls into
+//! the runtime, so growing the tape, the end of the input and the limits behave the same for all
+//! of them. The runtime is C code, which is either put in front of every program or written once
+//! as `bfrscc_rt.h` and `bfrscc_rt.c` and linked with all programs.
+//!
+//! The ABI of the runtime:
+//!
+//! - `bf_cell` is the type of the cells, `bf_ucell` the unsigned type of the same width.
+//! - `bf_cell *bf_tape` points to the first cell, `size_t bf_pos` is the index of the current
+//!   cell. `bf_tape` changes when the tape grows.
+//! - `void bf_init(void)` sets up the tape. It is called before anything else.
+//! - `void bf_right(size_t n)` and `void bf_left(size_t n)` move the pointer, growing or wrapping
+//!   the tape or stopping with an error.
+//! - `size_t bf_at(long off)` is the index of the cell `off` cells from the pointer, which is
+//!   created if needed. The pointer does not move.
+//! - `void bf_put(bf_cell c)` writes the low byte of a cell.
+//! - `bf_cell bf_get(void)` reads a byte, or returns the value for the end of the input.
+//! - `void bf_error(const char *msg)` reports an error and stops the program.
+//!
+//! ```
+//! use bfrscc_core::runtime::Runtime;
+//!
+//! let runtime = Runtime::default();
+//! assert!(runtime.header().contains("void bf_right(size_t n);"));
+//! assert!(runtime.source().starts_with("#include \"bfrscc_rt.h\""));
+//! ```
+
+use cell::CellType;
+use run::{Eof, RunConfig, Underflow};
+
+/// File name of the header of a runtime written separately
+pub const HEADER: &str = "bfrscc_rt.h";
+
+/// File name of the implementation of a runtime written separately
+pub const SOURCE: &str = "bfrscc_rt.c";
+
+/// Number of cells of the default tape. The pointer starts in the middle, which allows moving
+/// left as well.
+const TAPE_SIZE: usize = 65536;
+
+/// Settings of the runtime
+///
+/// By default, the tape has 65536 bytes with the pointer starting in the middle, and the pointer
+/// is not checked. `Runtime::from_config` takes the tape, the cells and the end of input of a
+/// `RunConfig`, so compiled programs behave like the interpreter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Runtime {
+    /// Number of cells of a tape of fixed size, `None` for a tape which grows like the tape of
+    /// the interpreter
+    pub tape_size: Option<usize>,
+    /// Maximum number of cells a growing tape may grow to
+    pub max_tape: Option<usize>,
+    /// What moving left of the first cell does. The pointer of a tape of fixed size starts in
+    /// its middle with `Underflow::Grow`, and at its first cell otherwise. `Underflow::Wrap`
+    /// makes the tape a circle of the given number of cells.
+    pub underflow: Underflow,
+    /// Type of the cells
+    pub cells: CellType,
+    /// Value stored by `,` at the end of the input
+    pub eof: Eof,
+    /// Stop with an error if the pointer leaves a tape of fixed size, which is undefined
+    /// behavior otherwise
+    pub checks: bool,
+    /// Do not use the C library. The environment provides `void bf_putchar(unsigned char c)`,
+    /// `int bf_getchar(void)`, which returns a negative value at the end of the input, and
+    /// `void bf_fail(const char *msg)`, which reports an error. Needs a tape of fixed size.
+    pub freestanding: bool,
+}
+
+impl Default for Runtime {
+    fn default() -> Self {
+        Runtime {
+            tape_size: Some(TAPE_SIZE),
+            max_tape: None,
+            underflow: Underflow::Grow,
+            cells: CellType::U8,
+            eof: Eof::Zero,
+            checks: false,
+            freestanding: false,
+        }
+    }
+}
+
+/// Layout of the tape
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Layout {
+    /// Array of fixed size, with the start index of the pointer and whether moves are checked
+    Fixed(usize, usize, bool),
+    /// Array which grows when the pointer leaves it
+    Growing,
+    /// Circle of the given number of cells
+    Circular(usize),
+}
+
+impl Runtime {
+    /// Runtime behaving like the interpreter with `config`: the tape grows up to
+    /// `config.max_tape` cells and moving left of the first cell does what `config.underflow`
+    /// says. `config.cells` and `config.eof` are honored as well.
+    pub fn from_config(config: &RunConfig) -> Self {
+        Runtime {
+            tape_size: None,
+            max_tape: config.max_tape,
+            underflow: config.underflow,
+            cells: config.cells,
+            eof: config.eof,
+            checks: true,
+            freestanding: false,
+        }
+    }
+
+    /// Number of cells left and right of the first cell of an unchecked tape of fixed size,
+    /// `None` otherwise
+    pub fn tape_bounds(&self) -> Option<(usize, usize)> {
+        match self.layout() {
+            Layout::Fixed(n, start, false) => Some((start, n - start - 1)),
+            _ => None,
+        }
+    }
+
+    /// Error if the settings cannot be combined
+    pub fn validate(&self) -> Result<(), String> {
+        if self.freestanding && self.layout() == Layout::Growing {
+            return Err("freestanding code needs a tape of fixed size".to_string());
+        }
+        Ok(())
+    }
+
+    /// Layout of the tape
+    fn layout(&self) -> Layout {
+        match (self.underflow, self.tape_size) {
+            (Underflow::Wrap(n), _) => Layout::Circular(n),
+            (_, None) => Layout::Growing,
+            (Underflow::Grow, Some(n)) => Layout::Fixed(n, n / 2, self.checks),
+            (_, Some(n)) => Layout::Fixed(n, 0, true),
+        }
+    }
+
+    /// Contents of `bfrscc_rt.h`: the types and the declarations of the ABI
+    pub fn header(&self) -> String {
+        let mut out = String::new();
+        out.push_str("/* Runtime of programs compiled by bfrscc */\n");
+        out.push_str("#ifndef BFRSCC_RT_H\n#define BFRSCC_RT_H\n\n");
+        out.push_str(&self.declarations());
+        out.push_str("\n#endif\n");
+        out
+    }
+
+    /// Contents of `bfrscc_rt.c`: the implementation of the ABI
+    pub fn source(&self) -> String {
+        format!("#include \"{}\"\n{}", HEADER, self.definitions())
+    }
+
+    /// Declarations and implementation in one piece, to be put in front of a single program
+    pub fn inline(&self) -> String {
+        format!("{}{}", self.declarations(), self.definitions())
+    }
+
+    /// Includes, types and prototypes
+    fn declarations(&self) -> String {
+        let mut out = String::from("#include <stddef.h>\n");
+        if self.cells == CellType::I16 || self.cells == CellType::I32 {
+            out.push_str("#include <stdint.h>\n");
+        }
+        let (cell, ucell) = match self.cells {
+            CellType::U8 => ("unsigned char", "unsigned char"),
+            CellType::I8 => ("signed char", "unsigned char"),
+            CellType::I16 => ("int16_t", "uint16_t"),
+            CellType::I32 => ("int32_t", "uint32_t"),
+        };
+        out.push_str(&format!("\ntypedef {} bf_cell;\ntypedef {} bf_ucell;\n", cell, ucell));
+        out.push_str(concat!(
+            "\n",
+            "extern bf_cell *bf_tape;\n",
+            "extern size_t bf_pos;\n",
+            "\n",
+            "void bf_init(void);\n",
+            "void bf_right(size_t n);\n",
+            "void bf_left(size_t n);\n",
+            "size_t bf_at(long off);\n",
+            "void bf_put(bf_cell c);\n",
+            "bf_cell bf_get(void);\n",
+            "void bf_error(const char *msg);\n",
+        ));
+        out
+    }
+
+    /// Definitions of the tape and the functions
+    fn definitions(&self) -> String {
+        let mut out = String::new();
+        let layout = self.layout();
+        if self.freestanding {
+            out.push_str("\n/* Provided by the environment */\n");
+            out.push_str("void bf_putchar(unsigned char c);\nint bf_getchar(void);\n");
+            out.push_str("void bf_fail(const char *msg);\n");
+        } else {
+            out.push_str("\n#include <stdio.h>\n#include <stdlib.h>\n");
+            if layout == Layout::Growing {
+                out.push_str("#include <string.h>\n");
+            }
+        }
+        out.push('\n');
+        match layout {
+            Layout::Fixed(n, start, _) => out.push_str(&format!(
+                "static bf_cell cells[{}];\nbf_cell *bf_tape = cells;\nsize_t bf_pos = {};\n",
+                n, start
+            )),
+            Layout::Circular(n) => out.push_str(&format!(
+                "static bf_cell cells[{}];\nbf_cell *bf_tape = cells;\nsize_t bf_pos;\n",
+                n
+            )),
+            Layout::Growing => out.push_str(
+                "bf_cell *bf_tape;\nsize_t bf_pos;\nstatic size_t used = 1, cap;\n",
+            ),
+        }
+        out.push_str(if self.freestanding {
+            concat!(
+                "\n",
+                "void bf_error(const char *msg) {\n",
+                "    bf_fail(msg);\n",
+                "    for (;;) {}\n",
+                "}\n",
+            )
+        } else {
+            concat!(
+                "\n",
+                "void bf_error(const char *msg) {\n",
+                "    fflush(stdout);\n",
+                "    fprintf(stderr, \"error: %s\\n\", msg);\n",
+                "    exit(1);\n",
+                "}\n",
+            )
+        });
+        let left_error = "bf_error(\"pointer moved left of the first cell\");";
+        match layout {
+            Layout::Fixed(n, _, checks) => {
+                out.push_str("\nvoid bf_init(void) {}\n");
+                out.push_str("\nvoid bf_right(size_t n) {\n");
+                if checks {
+                    out.push_str(&format!(
+                        "    if (n >= {} - bf_pos) bf_error(\"pointer moved right of the last \
+                         cell\");\n",
+                        n
+                    ));
+                }
+                out.push_str("    bf_pos += n;\n}\n");
+                out.push_str("\nvoid bf_left(size_t n) {\n");
+                if checks {
+                    out.push_str(&format!("    if (n > bf_pos) {}\n", left_error));
+                }
+                out.push_str("    bf_pos -= n;\n}\n");
+            }
+            Layout::Circular(n) => {
+                out.push_str("\nvoid bf_init(void) {}\n");
+                out.push_str(&format!(
+                    "\nvoid bf_right(size_t n) {{\n    bf_pos = (bf_pos + n % {0}) % {0};\n}}\n",
+                    n
+                ));
+                out.push_str(&format!(
+                    "\nvoid bf_left(size_t n) {{\n    bf_pos = (bf_pos + {0} - n % {0}) % {0};\n}}\n",
+                    n
+                ));
+            }
+            Layout::Growing => {
+                let limit = |need: &str| match self.max_tape {
+                    Some(max) => format!(
+                        "        if ({} > {}) bf_error(\"tape limit of {} cells exceeded\");\n",
+                        need, max, max
+                    ),
+                    None => String::new(),
+                };
+                out.push_str(concat!(
+                    "\n",
+                    "static void reserve(size_t need) {\n",
+                    "    if (need > cap) {\n",
+                    "        size_t c = cap * 2 > need ? cap * 2 : need;\n",
+                    "        bf_tape = realloc(bf_tape, c * sizeof *bf_tape);\n",
+                    "        if (!bf_tape) bf_error(\"out of memory\");\n",
+                    "        cap = c;\n",
+                    "    }\n",
+                    "}\n",
+                    "\n",
+                    "void bf_init(void) {\n",
+                    "    reserve(1024);\n",
+                    "    bf_tape[0] = 0;\n",
+                    "}\n",
+                ));
+                out.push_str("\nvoid bf_right(size_t n) {\n");
+                out.push_str("    if (n >= used - bf_pos) {\n");
+                out.push_str("        size_t need = bf_pos + n + 1;\n");
+                out.push_str(&limit("need"));
+                out.push_str("        reserve(need);\n");
+                out.push_str("        memset(bf_tape + used, 0, (need - used) * sizeof *bf_tape);\n");
+                out.push_str("        used = need;\n    }\n    bf_pos += n;\n}\n");
+                out.push_str("\nvoid bf_left(size_t n) {\n    if (n > bf_pos) {\n");
+                if self.underflow == Underflow::Grow {
+                    out.push_str("        size_t k = n - bf_pos;\n");
+                    out.push_str(&limit("used + k"));
+                    out.push_str("        reserve(used + k);\n");
+                    out.push_str("        memmove(bf_tape + k, bf_tape, used * sizeof *bf_tape);\n");
+                    out.push_str("        memset(bf_tape, 0, k * sizeof *bf_tape);\n");
+                    out.push_str("        used += k;\n        bf_pos += k;\n");
+                } else {
+                    out.push_str(&format!("        {}\n", left_error));
+                }
+                out.push_str("    }\n    bf_pos -= n;\n}\n");
+            }
+        }
+        let (get, end) = if self.freestanding {
+            ("bf_getchar", "c < 0")
+        } else {
+            ("getchar", "c == EOF")
+        };
+        let eof = match self.eof {
+            Eof::Zero => 0,
+            Eof::Max => 255,
+        };
+        out.push_str(concat!(
+            "\n",
+            "size_t bf_at(long off) {\n",
+            "    size_t q;\n",
+            "    if (off >= 0) {\n",
+            "        bf_right((size_t)off);\n",
+            "        q = bf_pos;\n",
+            "        bf_left((size_t)off);\n",
+            "    } else {\n",
+            "        bf_left((size_t)-off);\n",
+            "        q = bf_pos;\n",
+            "        bf_right((size_t)-off);\n",
+            "    }\n",
+            "    return q;\n",
+            "}\n",
+        ));
+        out.push_str(&format!(
+            "\nvoid bf_put(bf_cell c) {{\n    {}((unsigned char)c);\n}}\n",
+            if self.freestanding { "bf_putchar" } else { "putchar" }
+        ));
+        out.push_str(&format!(
+            "\nbf_cell bf_get(void) {{\n    int c = {}();\n    return (bf_cell)({} ? {} : c);\n}}\n",
+            get, end, eof
+        ));
+        out
+    }
+}
@@ -0,0 +1,152 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Sampling profiler
+//!
+//! Counting every execution of every instruction (`RunConfig::profile`) slows very long runs
+//! down. With `RunConfig::sample_hz`, a `Sampler` thread raises a flag that many times per
+//! second instead, and the run loop only records the instruction it is at when the flag is
+//! raised (see `RunReport::samples`). The samples are proportional to the time spent at every
+//! instruction.
+//!
+//! ```
+//! use bfrscc_core::run::{compile, run_with_config, RunConfig};
+//! use bfrscc_core::sampler::hotspots;
+//!
+//! let config = RunConfig {
+//!     sample_hz: Some(1000),
+//!     optimize: true,
+//!     ..RunConfig::default()
+//! };
+//! let source = "++++++++[>++++++++[>++++++++[>++++++++[>++[-->+<]<-]<-]<-]<-]";
+//! let report = run_with_config(source, &config).unwrap();
+//! let samples = report.samples.unwrap();
+//! // Samples are taken of the compiled program.
+//! let prog = compile(source, &config).unwrap();
+//! for hotspot in hotspots(&prog, &samples, 3) {
+//!     assert!(hotspot.samples > 0);
+//! }
+//! ```
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use diagnostics::line_col;
+use program::Program;
+
+/// Raises a flag at a fixed rate from a background thread, until it is dropped
+#[derive(Debug)]
+pub struct Sampler {
+    /// Raised once per period, lowered by `tick`
+    flag: Arc<AtomicBool>,
+    /// Stops the thread
+    stop: Arc<AtomicBool>,
+    /// The thread raising the flag
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Sampler {
+    /// Start raising the flag `hz` times per second.
+    pub fn start(hz: u32) -> Self {
+        let flag = Arc::new(AtomicBool::new(false));
+        let stop = Arc::new(AtomicBool::new(false));
+        let period = Duration::from_secs(1) / hz.max(1);
+        let thread = {
+            let (flag, stop) = (flag.clone(), stop.clone());
+            thread::spawn(move || {
+                let mut next = Instant::now() + period;
+                while !stop.load(Ordering::Relaxed) {
+                    let now = Instant::now();
+                    if now < next {
+                        thread::park_timeout(next - now);
+                        continue;
+                    }
+                    flag.store(true, Ordering::Relaxed);
+                    next += period;
+                }
+            })
+        };
+        Sampler {
+            flag,
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// Returns `true` if the flag was raised since the last call, and lowers it.
+    #[inline]
+    pub fn tick(&self) -> bool {
+        self.flag.load(Ordering::Relaxed) && self.flag.swap(false, Ordering::Relaxed)
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            thread.join().ok();
+        }
+    }
+}
+
+/// An instruction and the share of the samples taken at it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hotspot {
+    /// Index of the instruction
+    pub index: usize,
+    /// Line of the instruction in the source, starting at 1
+    pub line: usize,
+    /// Column of the instruction in the source, starting at 1
+    pub col: usize,
+    /// The instruction
+    pub instruction: String,
+    /// Number of samples taken at the instruction
+    pub samples: u64,
+    /// Share of all samples
+    pub share: f64,
+}
+
+impl fmt::Display for Hotspot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:5.1}% {:>8} samples  {}:{}  {}",
+            100.0 * self.share,
+            self.samples,
+            self.line,
+            self.col,
+            self.instruction
+        )
+    }
+}
+
+/// The at most `n` instructions of `prog` with the most `samples`, most samples first.
+/// Instructions without samples are left out.
+pub fn hotspots(prog: &Program, samples: &[u64], n: usize) -> Vec<Hotspot> {
+    let total = samples.iter().sum::<u64>().max(1);
+    let mut indices: Vec<usize> = (0..samples.len()).filter(|&i| samples[i] > 0).collect();
+    indices.sort_by_key(|&i| (::std::cmp::Reverse(samples[i]), i));
+    indices
+        .into_iter()
+        .take(n)
+        .map(|index| {
+            let x = prog.instructions().get(index);
+            let (line, col) = line_col(prog.source(), x.map_or(0, |x| x.position()));
+            Hotspot {
+                index,
+                line,
+                col,
+                instruction: x.map_or(String::new(), |x| x.to_string()),
+                samples: samples[index],
+                share: samples[index] as f64 / total as f64,
+            }
+        })
+        .collect()
+}
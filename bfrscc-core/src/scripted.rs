@@ -0,0 +1,364 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Scripted machine
+//!
+//! A machine for tests, which knows the calls it should receive. Every call is compared with the
+//! next expected one, so a test states exactly what the run loop does with a program, including
+//! the loops it executes in a single step:
+//!
+//! ```
+//! use bfrscc_core::machine::MachineExt;
+//! use bfrscc_core::program::Program;
+//! use bfrscc_core::scripted::ScriptedMachine;
+//!
+//! let prog = Program::compile("+++[->++<]>.", &Default::default(), 1).unwrap();
+//! let mut machine = ScriptedMachine::from_script("+3 [1:2] >1 .").unwrap();
+//! machine.run(&prog).unwrap();
+//! assert_eq!(machine.finish().unwrap().take_output(), [6]);
+//! ```
+//!
+//! The calls are delegated to a wrapped machine, a `BufferMachine` by default, which decides
+//! the loops. By default, the first unexpected call panics. A recording machine collects the
+//! mismatches instead, and `finish` returns them together with the expected calls which were
+//! not made:
+//!
+//! ```
+//! use bfrscc_core::machine::MachineExt;
+//! use bfrscc_core::scripted::{Call, ScriptedMachine};
+//!
+//! let mut machine = ScriptedMachine::new(vec![Call::Increase(1), Call::Output]).recording();
+//! machine.run(&"-".parse().unwrap()).unwrap();
+//! let mismatches = machine.finish().err().unwrap();
+//! assert_eq!(mismatches[0].to_string(), "call 0: expected `+1`, got `-1`");
+//! assert_eq!(mismatches[1].to_string(), "call 1: expected `.`, got nothing");
+//! ```
+//!
+//! The script of `ScriptedMachine::from_script` lists the calls separated by whitespace, written
+//! like `adapter::Operation`: `<n`, `>n`, `^n`, `vn`, `+n`, `-n`, `.`, `,b`, `#`, `~` and `?`.
+//! `[-]` is a clear, and a transfer lists its effects as `offset:factor`, e.g. `[1:2,-1:255]`.
+
+use std::fmt;
+use std::str::FromStr;
+use buffer::BufferMachine;
+use machine::Machine;
+use run::RunError;
+
+/// Call of a `Machine` method
+#[derive(Debug, Clone, PartialEq)]
+pub enum Call {
+    /// `left` by the given number of cells
+    Left(u8),
+    /// `right` by the given number of cells
+    Right(u8),
+    /// `up` by the given number of cells
+    Up(u8),
+    /// `down` by the given number of cells
+    Down(u8),
+    /// `increase` by the given value
+    Increase(u8),
+    /// `decrease` by the given value
+    Decrease(u8),
+    /// `output`
+    Output,
+    /// `input` of the given byte
+    Input(u8),
+    /// `clear`
+    Clear,
+    /// `transfer` with the given effects
+    Transfer(Vec<(isize, u8)>),
+    /// `breakpoint`
+    Breakpoint,
+    /// `switch_tape`
+    SwitchTape,
+    /// `random`
+    Random,
+}
+
+impl fmt::Display for Call {
+    /// Writes the call in the notation of scripts.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Call::Left(n) => write!(f, "<{}", n),
+            Call::Right(n) => write!(f, ">{}", n),
+            Call::Up(n) => write!(f, "^{}", n),
+            Call::Down(n) => write!(f, "v{}", n),
+            Call::Increase(n) => write!(f, "+{}", n),
+            Call::Decrease(n) => write!(f, "-{}", n),
+            Call::Output => write!(f, "."),
+            Call::Input(b) => write!(f, ",{}", b),
+            Call::Clear => write!(f, "[-]"),
+            Call::Transfer(ref effects) => {
+                let effects: Vec<String> = effects
+                    .iter()
+                    .map(|&(offset, factor)| format!("{}:{}", offset, factor))
+                    .collect();
+                write!(f, "[{}]", effects.join(","))
+            }
+            Call::Breakpoint => write!(f, "#"),
+            Call::SwitchTape => write!(f, "~"),
+            Call::Random => write!(f, "?"),
+        }
+    }
+}
+
+impl FromStr for Call {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid call `{}`", s);
+        let arg = || s[1..].parse::<u8>().map_err(|_| invalid());
+        Ok(match s.chars().next().ok_or_else(invalid)? {
+            '<' => Call::Left(arg()?),
+            '>' => Call::Right(arg()?),
+            '^' => Call::Up(arg()?),
+            'v' => Call::Down(arg()?),
+            '+' => Call::Increase(arg()?),
+            '-' => Call::Decrease(arg()?),
+            ',' => Call::Input(arg()?),
+            '.' if s.len() == 1 => Call::Output,
+            '#' if s.len() == 1 => Call::Breakpoint,
+            '~' if s.len() == 1 => Call::SwitchTape,
+            '?' if s.len() == 1 => Call::Random,
+            '[' if s == "[-]" => Call::Clear,
+            '[' => {
+                let inner = s[1..].strip_suffix(']').ok_or_else(invalid)?;
+                let effects = inner
+                    .split(',')
+                    .filter(|x| !x.is_empty())
+                    .map(|effect| {
+                        let mut parts = effect.splitn(2, ':');
+                        let offset = parts.next().and_then(|x| x.parse().ok());
+                        let factor = parts.next().and_then(|x| x.parse().ok());
+                        offset.zip(factor).ok_or_else(invalid)
+                    })
+                    .collect::<Result<_, _>>()?;
+                Call::Transfer(effects)
+            }
+            _ => return Err(invalid()),
+        })
+    }
+}
+
+/// Difference between the expected and the actual calls
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    /// Index of the call
+    pub index: usize,
+    /// Expected call, `None` if the script had ended
+    pub expected: Option<Call>,
+    /// Actual call, `None` if the run ended before the script
+    pub actual: Option<Call>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let show = |call: &Option<Call>| match *call {
+            Some(ref call) => format!("`{}`", call),
+            None => "nothing".to_string(),
+        };
+        write!(
+            f,
+            "call {}: expected {}, got {}",
+            self.index,
+            show(&self.expected),
+            show(&self.actual)
+        )
+    }
+}
+
+/// Machine which compares its calls with a script (see the module documentation)
+pub struct ScriptedMachine<M: Machine = BufferMachine> {
+    /// Machine the calls are delegated to
+    inner: M,
+    /// Expected calls
+    script: Vec<Call>,
+    /// Number of calls made so far
+    calls: usize,
+    /// Collect mismatches instead of panicking
+    recording: bool,
+    /// Mismatches found so far, only when recording
+    mismatches: Vec<Mismatch>,
+}
+
+impl ScriptedMachine {
+    /// Machine expecting the calls of `script`, which delegates to a `BufferMachine`.
+    pub fn new(script: Vec<Call>) -> Self {
+        ScriptedMachine::with_machine(BufferMachine::new(), script)
+    }
+
+    /// Machine expecting the calls of a script in text form.
+    pub fn from_script(script: &str) -> Result<Self, String> {
+        let calls = script
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<_, _>>()?;
+        Ok(ScriptedMachine::new(calls))
+    }
+}
+
+impl<M: Machine> ScriptedMachine<M> {
+    /// Machine expecting the calls of `script`, which delegates to `inner`.
+    pub fn with_machine(inner: M, script: Vec<Call>) -> Self {
+        ScriptedMachine {
+            inner,
+            script,
+            calls: 0,
+            recording: false,
+            mismatches: vec![],
+        }
+    }
+
+    /// Collect mismatches instead of panicking at the first one.
+    pub fn recording(mut self) -> Self {
+        self.recording = true;
+        self
+    }
+
+    /// Machine the calls are delegated to
+    pub fn inner(&self) -> &M {
+        &self.inner
+    }
+
+    /// Expected calls which have not been made yet
+    pub fn remaining(&self) -> &[Call] {
+        self.script.get(self.calls..).unwrap_or_default()
+    }
+
+    /// Mismatches found so far
+    pub fn mismatches(&self) -> &[Mismatch] {
+        &self.mismatches
+    }
+
+    /// Return the wrapped machine if all calls were as expected and the script is complete.
+    /// Otherwise, return the mismatches, including the expected calls which were not made.
+    /// Without recording, the first missing call panics.
+    pub fn finish(mut self) -> Result<M, Vec<Mismatch>> {
+        let missing: Vec<Mismatch> = (self.calls..self.script.len())
+            .map(|index| Mismatch {
+                index,
+                expected: Some(self.script[index].clone()),
+                actual: None,
+            })
+            .collect();
+        if let (false, Some(first)) = (self.recording, missing.first()) {
+            panic!("{}", first);
+        }
+        self.mismatches.extend(missing);
+        if self.mismatches.is_empty() {
+            Ok(self.inner)
+        } else {
+            Err(self.mismatches)
+        }
+    }
+
+    /// Compare `actual` with the next expected call.
+    fn check(&mut self, actual: Call) {
+        let index = self.calls;
+        self.calls += 1;
+        let expected = self.script.get(index);
+        if expected == Some(&actual) {
+            return;
+        }
+        let mismatch = Mismatch {
+            index,
+            expected: expected.cloned(),
+            actual: Some(actual),
+        };
+        if !self.recording {
+            panic!("{}", mismatch);
+        }
+        self.mismatches.push(mismatch);
+    }
+}
+
+impl<M: Machine> Machine for ScriptedMachine<M> {
+    fn left(&mut self, val: u8) {
+        self.check(Call::Left(val));
+        self.inner.left(val);
+    }
+
+    fn right(&mut self, val: u8) {
+        self.check(Call::Right(val));
+        self.inner.right(val);
+    }
+
+    fn up(&mut self, val: u8) {
+        self.check(Call::Up(val));
+        self.inner.up(val);
+    }
+
+    fn down(&mut self, val: u8) {
+        self.check(Call::Down(val));
+        self.inner.down(val);
+    }
+
+    fn increase(&mut self, val: u8) {
+        self.check(Call::Increase(val));
+        self.inner.increase(val);
+    }
+
+    fn decrease(&mut self, val: u8) {
+        self.check(Call::Decrease(val));
+        self.inner.decrease(val);
+    }
+
+    fn output(&mut self) {
+        self.check(Call::Output);
+        self.inner.output();
+    }
+
+    fn input(&mut self, val: u8) {
+        self.check(Call::Input(val));
+        self.inner.input(val);
+    }
+
+    fn get_val(&self) -> u8 {
+        self.inner.get_val()
+    }
+
+    fn is_zero(&self) -> bool {
+        self.inner.is_zero()
+    }
+
+    fn clear(&mut self) {
+        self.check(Call::Clear);
+        self.inner.clear();
+    }
+
+    fn transfer(&mut self, effects: &[(isize, u8)]) {
+        self.check(Call::Transfer(effects.to_vec()));
+        self.inner.transfer(effects);
+    }
+
+    fn tape_len(&self) -> usize {
+        self.inner.tape_len()
+    }
+
+    fn tape_capacity(&self) -> usize {
+        self.inner.tape_capacity()
+    }
+
+    fn breakpoint(&mut self) {
+        self.check(Call::Breakpoint);
+        self.inner.breakpoint();
+    }
+
+    fn switch_tape(&mut self) {
+        self.check(Call::SwitchTape);
+        self.inner.switch_tape();
+    }
+
+    fn random(&mut self) {
+        self.check(Call::Random);
+        self.inner.random();
+    }
+
+    fn status(&self) -> Result<(), RunError> {
+        self.inner.status()
+    }
+}
@@ -0,0 +1,258 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Shadow execution
+//!
+//! Runs the unoptimized and the optimized instruction stream of a program side by side on two
+//! machines. Both streams contain the same loops, inputs and outputs at the same source
+//! positions, which serve as synchronization points: the machines are run until they reach the
+//! next such instruction, and there the pointer, the current cell and the output produced so far
+//! have to agree. At the end, the whole tapes are compared as well. Loops which the optimizer
+//! removed, e.g. by unrolling them (see `optimizer::unroll`), are no synchronization points, nor
+//! is the `]` of optimized loops which the run loop executes in a single step (see
+//! `LoopKind::is_single_step`). The unoptimized execution runs every loop instruction by
+//! instruction.
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use buffer::BufferMachine;
+use std::io::{self, Read};
+use lexer::{Dialect, Token};
+use machine::Machine;
+use program::Program;
+use run::{clock_seed, compile, interpreter, step, step_literally, RunConfig, RunError};
+use tape::{GridTape, LinearTape, Tape};
+
+/// Describes where the two executions diverged
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    /// Number of steps of the unoptimized execution up to the divergence
+    pub step: u64,
+    /// Source position of the synchronization point where the divergence was detected
+    pub position: Option<usize>,
+    /// What differs
+    pub description: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.position {
+            Some(pos) => write!(
+                f,
+                "executions diverged after {} steps at source position {}: {}",
+                self.step, pos, self.description
+            ),
+            None => write!(
+                f,
+                "executions diverged after {} steps at the end of the program: {}",
+                self.step, self.description
+            ),
+        }
+    }
+}
+
+impl Error for Divergence {}
+
+/// Reasons for a shadow execution to fail
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShadowError {
+    /// One of the executions failed
+    Run(RunError),
+    /// The executions behaved differently
+    Diverged(Divergence),
+}
+
+impl fmt::Display for ShadowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ShadowError::Run(ref e) => e.fmt(f),
+            ShadowError::Diverged(ref d) => d.fmt(f),
+        }
+    }
+}
+
+impl Error for ShadowError {}
+
+/// Executes a single instruction (see `run::step`)
+type Step<T> = fn(&Program, &mut BufferMachine<T>, &mut dyn Iterator<Item = u8>, &mut usize);
+
+/// Returns `true` if the instruction is a synchronization point. Brackets are only if their
+/// position is in `loops`.
+fn is_sync(prog: &Program, idx: usize, loops: &HashSet<usize>) -> bool {
+    match prog.instructions().get(idx) {
+        Some(instr) => match instr.token {
+            Token::LoopBegin | Token::LoopEnd => loops.contains(&instr.position()),
+            Token::Input | Token::Output => true,
+            _ => false,
+        },
+        None => true,
+    }
+}
+
+/// The cells which are not zero by their coordinates relative to the cell the pointer started
+/// at (see `Tape::coordinates`). Tapes which only grew differently have the same content.
+fn content<T: Tape>(tape: &T) -> Vec<((i64, i64), u8)> {
+    tape.grid().unwrap_or_else(|| {
+        let origin = tape.origin() as i64;
+        tape.cells()
+            .iter()
+            .enumerate()
+            .filter(|&(_, &val)| val != 0)
+            .map(|(i, &val)| ((i as i64 - origin, 0), val))
+            .collect()
+    })
+}
+
+/// Run `prog` on `machine` with `step` until the next synchronization point is reached (see
+/// `is_sync`), or for at most `limit` instructions. Returns the number of executed instructions.
+fn run_to_sync<T: Tape>(
+    prog: &Program,
+    machine: &mut BufferMachine<T>,
+    idx: &mut usize,
+    loops: &HashSet<usize>,
+    limit: u64,
+    step: Step<T>,
+) -> u64 {
+    let mut n = 0;
+    while n < limit && !is_sync(prog, *idx, loops) {
+        // Only inputs read, and those are synchronization points.
+        step(prog, machine, &mut ::std::iter::empty(), idx);
+        n += 1;
+    }
+    n
+}
+
+/// Run the unoptimized and the optimized version of `prog` in lockstep and compare their
+/// observable behavior. Only `config.lexer`, `config.input`, `config.tape_init`, `config.pointer`,
+/// `config.max_steps` (counted on the unoptimized execution) and `config.interrupt` are honored.
+/// If neither `config.input` nor inline input is given, stdin is read completely up front.
+/// Returns the output of the program.
+pub fn shadow_run(prog: &str, config: &RunConfig) -> Result<Vec<u8>, ShadowError> {
+    let config = RunConfig {
+        optimize: false,
+        ..config.clone()
+    };
+    let plain = compile(prog, &config).map_err(ShadowError::Run)?;
+    let opt = plain.clone().optimized_with(config.arithmetic.dead_stores());
+    compare(&plain, &opt, &config)
+}
+
+/// Like `shadow_run`, but compares the unoptimized program `plain` with `opt`, whose instructions
+/// are any transformation of those of `plain` which keeps loops, inputs and outputs at their
+/// source positions (see `Program::with_instructions`).
+pub fn compare(plain: &Program, opt: &Program, config: &RunConfig) -> Result<Vec<u8>, ShadowError> {
+    match config.lexer.dialect {
+        Dialect::Grid => compare_with::<GridTape>(plain, opt, config),
+        _ => compare_with::<LinearTape>(plain, opt, config),
+    }
+}
+
+/// Implementation of `compare` for a given kind of tape.
+fn compare_with<T: Tape>(
+    plain: &Program,
+    opt: &Program,
+    config: &RunConfig,
+) -> Result<Vec<u8>, ShadowError> {
+    // Both executions need to see the same random bytes.
+    let config = RunConfig {
+        seed: Some(config.seed.unwrap_or_else(clock_seed)),
+        ..config.clone()
+    };
+    let input = match (config.input.clone(), plain.inline_input().map(|x| x.to_vec())) {
+        (Some(input), _) | (None, Some(input)) => input,
+        (None, None) => {
+            let mut input = vec![];
+            if plain.instructions().iter().any(|x| x.token == Token::Input) {
+                // Read errors are treated like the end of the input, as in `run`.
+                io::stdin().read_to_end(&mut input).ok();
+            }
+            input
+        }
+    };
+    let (mut input_a, mut input_b) = (input.clone().into_iter(), input.into_iter());
+    let (mut a, mut b): (BufferMachine<T>, BufferMachine<T>) = (
+        BufferMachine::with_interpreter(interpreter(plain, &config).map_err(ShadowError::Run)?),
+        BufferMachine::with_interpreter(interpreter(opt, &config).map_err(ShadowError::Run)?),
+    );
+    // Positions of the brackets which are left in the optimized program, without the `]` of
+    // loops executed in a single step
+    let loops: HashSet<usize> = opt
+        .ops()
+        .iter()
+        .zip(opt.instructions())
+        .filter(|&(op, _)| match op.token {
+            Token::LoopBegin => true,
+            Token::LoopEnd => !op.kind.is_single_step(),
+            _ => false,
+        })
+        .map(|(_, x)| x.position())
+        .collect();
+    let max = config.max_steps.unwrap_or(u64::MAX);
+    let (mut ia, mut ib) = (0, 0);
+    let mut steps = 0;
+    loop {
+        if let Some(ref flag) = config.interrupt {
+            if flag.load(::std::sync::atomic::Ordering::Relaxed) {
+                return Err(ShadowError::Run(RunError::Interrupted));
+            }
+        }
+        steps += run_to_sync(plain, &mut a, &mut ia, &loops, max - steps, step_literally);
+        if !is_sync(plain, ia, &loops) {
+            return Err(ShadowError::Run(RunError::StepLimit(max)));
+        }
+        // An optimized execution which does not reach the synchronization point within the step
+        // limit diverges.
+        run_to_sync(opt, &mut b, &mut ib, &loops, max, step);
+
+        let position = plain.instructions().get(ia).map(|x| x.position());
+        let diverged = |description: String| {
+            Err(ShadowError::Diverged(Divergence {
+                step: steps,
+                position,
+                description,
+            }))
+        };
+        let opt_position = opt.instructions().get(ib).map(|x| x.position());
+        if position != opt_position {
+            return diverged(format!(
+                "control flow differs, optimized execution is at {:?}",
+                opt_position
+            ));
+        }
+        if a.get_output() != b.get_output() {
+            return diverged(format!(
+                "output differs: {:?} vs {:?}",
+                a.get_output(),
+                b.get_output()
+            ));
+        }
+        let (sa, sb) = (a.interpreter(), b.interpreter());
+        let (pa, pb) = (sa.tape().coordinates(), sb.tape().coordinates());
+        if pa != pb || sa.get_val() != sb.get_val() {
+            return diverged(format!(
+                "pointer/cell differs: {:?}/{} vs {:?}/{}",
+                pa,
+                sa.get_val(),
+                pb,
+                sb.get_val()
+            ));
+        }
+        if position.is_none() {
+            if content(sa.tape()) != content(sb.tape()) {
+                return diverged("final tapes differ".to_string());
+            }
+            return Ok(a.take_output());
+        }
+        if steps >= max {
+            return Err(ShadowError::Run(RunError::StepLimit(max)));
+        }
+        step_literally(plain, &mut a, &mut input_a, &mut ia);
+        step(opt, &mut b, &mut input_b, &mut ib);
+        steps += 1;
+    }
+}
@@ -0,0 +1,165 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Output sinks
+//!
+//! An `OutputSink` receives the output of a program byte by byte while it runs, together with
+//! events at the start and the end of the execution. This allows GUIs and servers to stream
+//! the output, for instance through a channel:
+//!
+//! ```
+//! use std::sync::mpsc;
+//! use std::thread;
+//! use bfrscc_core::program::Program;
+//! use bfrscc_core::run::RunConfig;
+//! use bfrscc_core::sink::{run_with_sink, Event};
+//!
+//! let (tx, rx) = mpsc::channel();
+//! thread::spawn(move || {
+//!     let prog: Program = "++++++++[>++++++++<-]>+.+.".parse().unwrap();
+//!     let mut tx = tx;
+//!     run_with_sink(&prog, &RunConfig::default(), &mut tx).unwrap();
+//! });
+//! let events: Vec<Event> = rx.iter().collect();
+//! assert_eq!(events[0], Event::Started);
+//! assert_eq!(events[1..3], [Event::Byte(b'A'), Event::Byte(b'B')]);
+//! assert!(matches!(events[3], Event::Finished(_)));
+//! ```
+
+use std::io::Write;
+use std::sync::mpsc::Sender;
+use adapter::{Hook, Layer, Operation};
+use buffer::BufferMachine;
+use interpreter::Interpreter;
+use lexer::Dialect;
+use program::Program;
+use run::{interpreter, run_program, RunConfig, RunError, RunReport, Underflow};
+use tape::{CircularTape, GridTape, LinearTape, Tape};
+
+/// What happens during an execution
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// The execution starts
+    Started,
+    /// The program wrote a byte
+    Byte(u8),
+    /// The execution finished successfully
+    Finished(RunReport),
+    /// The execution failed
+    Failed(RunError),
+}
+
+/// Receiver of the events of an execution
+pub trait OutputSink {
+    /// Handle `event`.
+    fn event(&mut self, event: Event);
+}
+
+/// Collects the bytes, the other events are ignored.
+impl OutputSink for Vec<u8> {
+    fn event(&mut self, event: Event) {
+        if let Event::Byte(b) = event {
+            self.push(b);
+        }
+    }
+}
+
+/// Sends all events. Events are dropped once the receiver is gone.
+impl OutputSink for Sender<Event> {
+    fn event(&mut self, event: Event) {
+        self.send(event).ok();
+    }
+}
+
+/// Writes the bytes to a writer and flushes it at the end. Write errors are ignored.
+#[derive(Debug)]
+pub struct WriterSink<W: Write>(pub W);
+
+impl<W: Write> OutputSink for WriterSink<W> {
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Byte(b) => {
+                self.0.write_all(&[b]).ok();
+            }
+            Event::Finished(_) | Event::Failed(_) => {
+                self.0.flush().ok();
+            }
+            Event::Started => {}
+        }
+    }
+}
+
+/// Passes the output of a `BufferMachine` to a sink as soon as it is written
+pub struct ToSink<'a>(&'a mut dyn OutputSink);
+
+impl<'a, T: Tape> Hook<BufferMachine<T>> for ToSink<'a> {
+    fn after(&mut self, op: Operation, inner: &mut BufferMachine<T>) {
+        if op == Operation::Output {
+            self.after_batch(inner);
+        }
+    }
+
+    /// Only the output matters, so the fast paths of the machine are kept.
+    fn every_operation(&self) -> bool {
+        false
+    }
+
+    fn after_batch(&mut self, inner: &mut BufferMachine<T>) {
+        for val in inner.take_output() {
+            self.0.event(Event::Byte(val));
+        }
+    }
+}
+
+/// A `Machine` which behaves like the `Interpreter`, but passes the output to a sink.
+pub type SinkMachine<'a, T = LinearTape> = Layer<BufferMachine<T>, ToSink<'a>>;
+
+impl<'a, T: Tape> SinkMachine<'a, T> {
+    /// Pass the output of `interpreter` to `sink`.
+    pub fn with_interpreter(interpreter: Interpreter<T>, sink: &'a mut dyn OutputSink) -> Self {
+        Layer::with_hook(BufferMachine::with_interpreter(interpreter), ToSink(sink))
+    }
+
+    /// Return the underlying interpreter, for instance to look at the tape.
+    pub fn interpreter(&self) -> &Interpreter<T> {
+        self.inner().interpreter()
+    }
+}
+
+/// Run `prog` with the settings of `config` and pass its output to `sink`. The sink gets
+/// `Event::Started` first and `Event::Finished` or `Event::Failed` last. Core dumps are not
+/// written.
+pub fn run_with_sink(
+    prog: &Program,
+    config: &RunConfig,
+    sink: &mut dyn OutputSink,
+) -> Result<RunReport, RunError> {
+    sink.event(Event::Started);
+    let res = match prog.dialect() {
+        Dialect::Grid => run_on_tape::<GridTape>(prog, config, sink),
+        _ if matches!(config.underflow, Underflow::Wrap(_)) => {
+            run_on_tape::<CircularTape>(prog, config, sink)
+        }
+        _ => run_on_tape::<LinearTape>(prog, config, sink),
+    };
+    sink.event(match res {
+        Ok(ref report) => Event::Finished(report.clone()),
+        Err(ref e) => Event::Failed(e.clone()),
+    });
+    res
+}
+
+/// Implementation of `run_with_sink` for a given kind of tape.
+fn run_on_tape<T: Tape>(
+    prog: &Program,
+    config: &RunConfig,
+    sink: &mut dyn OutputSink,
+) -> Result<RunReport, RunError> {
+    let mut machine: SinkMachine<T> =
+        SinkMachine::with_interpreter(interpreter(prog, config)?, sink);
+    run_program(prog, &mut machine, config)
+}
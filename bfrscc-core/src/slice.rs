@@ -0,0 +1,198 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Program slicing
+//!
+//! Finds the instructions which influence the final value of a cell. The program is run, and
+//! every cell keeps the set of instructions its value depends on: the `+`, `-`, `,` and `?` which
+//! changed it, the instructions the changed cells depended on, and the loops whose condition
+//! decided whether these instructions ran. The loops enclosing an instruction of the slice are
+//! added from the structure of the program, so every part of the slice is a balanced program.
+//!
+//! Moves are not part of the slice: they decide which cell an instruction works on, not how
+//! the value of a cell is computed.
+//!
+//! ```
+//! use bfrscc_core::program::Program;
+//! use bfrscc_core::run::RunConfig;
+//! use bfrscc_core::slice::slice;
+//!
+//! let prog: Program = "+++>++<[->>+<<]>>.".parse().unwrap();
+//! let s = slice(&prog, &RunConfig::default(), 2).unwrap();
+//! assert_eq!(s.value, 3);
+//! assert_eq!(s.render(prog.source()), "+++    [-  +  ]   ");
+//! ```
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::ops::Range;
+use buffer::BufferMachine;
+use lexer::{Dialect, Token};
+use machine::Machine;
+use program::Program;
+use run::{input_source, interpreter, step_literally, RunConfig, RunError};
+use tape::{GridTape, LinearTape, Tape};
+
+/// Instructions influencing the final value of a cell
+#[derive(Debug, Clone, PartialEq)]
+pub struct Slice {
+    /// Index of the cell on the first tape
+    pub cell: isize,
+    /// Final value of the cell
+    pub value: u8,
+    /// Number of executed instructions
+    pub steps: u64,
+    /// Indices of the instructions of the slice, in program order
+    pub instructions: Vec<usize>,
+    /// Source positions of the instructions of the slice, in program order
+    pub spans: Vec<Range<usize>>,
+}
+
+impl Slice {
+    /// `source` with all instructions outside of the slice replaced by spaces. Line breaks are
+    /// kept, so positions stay recognizable.
+    pub fn render(&self, source: &str) -> String {
+        let mut kept = vec![false; source.len()];
+        for span in &self.spans {
+            for k in kept.iter_mut().take(span.end).skip(span.start) {
+                *k = true;
+            }
+        }
+        source
+            .char_indices()
+            .map(|(i, c)| match c {
+                '\n' | '\r' => c,
+                _ if kept.get(i).cloned().unwrap_or(false) => c,
+                _ => ' ',
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Slice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "cell {} is {} after {} steps, {} instructions influence it",
+            self.cell,
+            self.value,
+            self.steps,
+            self.instructions.len()
+        )
+    }
+}
+
+/// Run `prog` and find the instructions influencing the final value of `cell` of the first
+/// tape. Only `config.input`, `config.tape_init`, `config.pointer`, `config.seed`, `config.eof`
+/// and `config.max_steps` are honored. Loops are executed instruction by instruction, also
+/// with an optimized program.
+pub fn slice(prog: &Program, config: &RunConfig, cell: isize) -> Result<Slice, RunError> {
+    match prog.dialect() {
+        Dialect::Grid => slice_with::<GridTape>(prog, config, cell),
+        _ => slice_with::<LinearTape>(prog, config, cell),
+    }
+}
+
+/// Implementation of `slice` for a given kind of tape.
+fn slice_with<T: Tape>(prog: &Program, config: &RunConfig, cell: isize) -> Result<Slice, RunError> {
+    let mut machine: BufferMachine<T> = BufferMachine::with_interpreter(interpreter(prog, config)?);
+    let mut input = input_source(config, prog);
+    let tapes = prog.dialect().tapes();
+    let mut deps: HashMap<(usize, isize), BTreeSet<usize>> = HashMap::new();
+    // Instructions deciding whether the current instruction runs, one set per entered loop
+    let mut control: Vec<BTreeSet<usize>> = vec![BTreeSet::new()];
+    let (mut idx, mut steps, mut tape) = (0, 0u64, 0);
+    let t = machine.interpreter().tape();
+    let offset = cell + t.origin() as isize;
+    let mut value = if offset < 0 {
+        0
+    } else {
+        t.cells().get(offset as usize).cloned().unwrap_or(0)
+    };
+    while idx < prog.len() {
+        if let Some(max) = config.max_steps {
+            if steps >= max {
+                return Err(RunError::StepLimit(max));
+            }
+        }
+        let token = prog.instructions()[idx].token;
+        let t = machine.interpreter().tape();
+        let current = (tape, t.pos() as isize - t.origin() as isize);
+        let nonzero = !machine.is_zero();
+        let this = idx;
+        step_literally(prog, &mut machine, &mut *input, &mut idx);
+        steps += 1;
+        let ctrl = control.last().cloned().unwrap_or_default();
+        let writes = matches!(
+            token,
+            Token::Increase | Token::Decrease | Token::Input | Token::Random
+        );
+        if writes && current == (0, cell) {
+            value = machine.get_val();
+        }
+        match token {
+            Token::Increase | Token::Decrease => {
+                let d = deps.entry(current).or_default();
+                d.insert(this);
+                d.extend(ctrl);
+            }
+            Token::Input | Token::Random => {
+                let mut d = ctrl;
+                d.insert(this);
+                deps.insert(current, d);
+            }
+            Token::LoopBegin if nonzero => {
+                let mut frame = ctrl;
+                frame.extend(deps.get(&current).into_iter().flatten().cloned());
+                frame.insert(this);
+                frame.insert(prog.jump(this));
+                control.push(frame);
+            }
+            Token::LoopEnd if nonzero => {
+                let cond = deps.get(&current).cloned().unwrap_or_default();
+                if let Some(frame) = control.last_mut() {
+                    frame.extend(cond);
+                }
+            }
+            Token::LoopEnd => {
+                control.pop();
+            }
+            Token::SwitchTape => tape = (tape + 1) % tapes,
+            _ => {}
+        }
+    }
+    let mut instructions = deps.remove(&(0, cell)).unwrap_or_default();
+    // Add the loops enclosing the instructions, so every part of the slice is balanced.
+    for i in instructions.clone() {
+        let mut depth = 0usize;
+        for j in (0..i).rev() {
+            match prog.instructions()[j].token {
+                Token::LoopEnd => depth += 1,
+                Token::LoopBegin if depth > 0 => depth -= 1,
+                Token::LoopBegin => {
+                    instructions.insert(j);
+                    instructions.insert(prog.jump(j));
+                }
+                _ => {}
+            }
+        }
+    }
+    let spans = instructions
+        .iter()
+        .map(|&i| {
+            let x = &prog.instructions()[i];
+            x.position()..x.end
+        })
+        .collect();
+    Ok(Slice {
+        cell,
+        value,
+        steps,
+        instructions: instructions.into_iter().collect(),
+        spans,
+    })
+}
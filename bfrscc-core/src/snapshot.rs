@@ -0,0 +1,254 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Snapshot
+//!
+//! A `Snapshot` captures everything needed to look at (or continue) an execution: the program
+//! and how it was compiled, the index of the current instruction, the tape and the position of
+//! the pointer. Snapshots are stored in a simple line based text format:
+//!
+//! ```text
+//! bfrscc-snapshot 1
+//! pc 12
+//! pointer 3
+//! steps 1234
+//! tape 0,0,72,101
+//! opt_level 1
+//! dead_stores all
+//! dialect multitape 2
+//! comments dialect
+//! repeat_counts false
+//! alias 👍=+
+//! program
+//! ++++[>++<-]
+//! ```
+//!
+//! The lines between `tape` and `program` are optional, files without them hold an unoptimized
+//! program of the standard dialect.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use alias::parse_alias;
+use error::ParseError;
+use lexer::{CommentPolicy, Dialect, LexerOptions};
+use optimizer::DeadStores;
+use program::Program;
+
+/// Header of a snapshot file
+const MAGIC: &str = "bfrscc-snapshot 1";
+
+/// State of an execution at a given point in time
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    /// Source of the program
+    pub program: String,
+    /// Index of the current instruction within the `InstructionStream`
+    pub pc: usize,
+    /// Position of the pointer on the tape
+    pub pointer: usize,
+    /// Number of instructions executed so far
+    pub steps: u64,
+    /// Content of the tape
+    pub tape: Vec<u8>,
+    /// Options the program was lexed with. The limits are not stored.
+    pub lexer: LexerOptions,
+    /// Optimization level the program was compiled with
+    pub opt_level: u8,
+    /// Dead stores the optimizer removed, if the program was optimized
+    pub dead_stores: DeadStores,
+}
+
+impl Snapshot {
+    /// Constructor
+    pub fn new(program: &str, pc: usize, pointer: usize, steps: u64, tape: Vec<u8>) -> Self {
+        Snapshot {
+            program: program.to_string(),
+            pc,
+            pointer,
+            steps,
+            tape,
+            lexer: LexerOptions::default(),
+            opt_level: 0,
+            dead_stores: DeadStores::All,
+        }
+    }
+
+    /// Return the snapshot of a program compiled with `lexer` and `opt_level`, whose optimizer
+    /// removed `dead_stores`.
+    pub fn compiled_with(
+        mut self,
+        lexer: &LexerOptions,
+        opt_level: u8,
+        dead_stores: DeadStores,
+    ) -> Self {
+        self.lexer = LexerOptions {
+            limits: Default::default(),
+            ..lexer.clone()
+        };
+        self.opt_level = opt_level;
+        self.dead_stores = dead_stores;
+        self
+    }
+
+    /// Compile the program again, so that the program counter refers to its instructions.
+    ///
+    /// ```
+    /// use bfrscc_core::lexer::{Dialect, LexerOptions};
+    /// use bfrscc_core::optimizer::DeadStores;
+    /// use bfrscc_core::snapshot::Snapshot;
+    ///
+    /// let lexer = LexerOptions {
+    ///     dialect: Dialect::MultiTape(2),
+    ///     aliases: vec![("add".to_string(), '+')],
+    ///     ..LexerOptions::default()
+    /// };
+    /// let snapshot = Snapshot::new("addadd>>+[]", 3, 2, 50, vec![2, 0, 1])
+    ///     .compiled_with(&lexer, 1, DeadStores::All);
+    /// let mut file = Vec::new();
+    /// snapshot.write_to(&mut file).unwrap();
+    /// let snapshot = Snapshot::read_from(&file[..]).unwrap();
+    /// let prog = snapshot.compile().unwrap();
+    /// assert_eq!(prog.instructions()[snapshot.pc].position(), 9);
+    /// ```
+    pub fn compile(&self) -> Result<Program, ParseError> {
+        let prog = Program::compile(&self.program, &self.lexer, 0)?;
+        Ok(if self.opt_level > 0 {
+            prog.optimized_with(self.dead_stores)
+        } else {
+            prog
+        })
+    }
+
+    /// Serialize the snapshot into `w`.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "{}", MAGIC)?;
+        writeln!(w, "pc {}", self.pc)?;
+        writeln!(w, "pointer {}", self.pointer)?;
+        writeln!(w, "steps {}", self.steps)?;
+        let tape: Vec<String> = self.tape.iter().map(|x| x.to_string()).collect();
+        writeln!(w, "tape {}", tape.join(","))?;
+        writeln!(w, "opt_level {}", self.opt_level)?;
+        writeln!(w, "dead_stores {}", self.dead_stores)?;
+        match self.lexer.dialect {
+            Dialect::Standard => writeln!(w, "dialect standard")?,
+            Dialect::MultiTape(n) => writeln!(w, "dialect multitape {}", n)?,
+            Dialect::Grid => writeln!(w, "dialect grid")?,
+            Dialect::Random => writeln!(w, "dialect random")?,
+        }
+        let comments = match self.lexer.comments {
+            CommentPolicy::Ignore => "ignore",
+            CommentPolicy::Error => "error",
+            CommentPolicy::Dialect => "dialect",
+        };
+        writeln!(w, "comments {}", comments)?;
+        writeln!(w, "repeat_counts {}", self.lexer.repeat_counts)?;
+        for &(ref alias, c) in &self.lexer.aliases {
+            writeln!(w, "alias {}={}", alias, c)?;
+        }
+        writeln!(w, "program")?;
+        write!(w, "{}", self.program)
+    }
+
+    /// Deserialize a snapshot from `r`.
+    pub fn read_from<R: Read>(r: R) -> io::Result<Self> {
+        let mut lines = BufReader::new(r).lines();
+        if lines.next().transpose()?.as_deref() != Some(MAGIC) {
+            return Err(invalid("not a bfrscc snapshot"));
+        }
+        let pc = parse_field(lines.next().transpose()?, "pc")?;
+        let pointer = parse_field(lines.next().transpose()?, "pointer")?;
+        let steps = parse_field(lines.next().transpose()?, "steps")?;
+        let tape = match lines.next().transpose()? {
+            Some(ref line) if line.starts_with("tape") => line["tape".len()..]
+                .split(',')
+                .map(|x| x.trim())
+                .filter(|x| !x.is_empty())
+                .map(|x| x.parse::<u8>().map_err(|_| invalid("invalid tape cell")))
+                .collect::<io::Result<Vec<u8>>>()?,
+            _ => return Err(invalid("missing field `tape`")),
+        };
+        let mut snapshot = Snapshot::new("", pc, pointer, steps, tape);
+        loop {
+            let line = lines
+                .next()
+                .transpose()?
+                .ok_or_else(|| invalid("missing field `program`"))?;
+            if line == "program" {
+                break;
+            }
+            snapshot.option(&line)?;
+        }
+        snapshot.program = lines.collect::<io::Result<Vec<String>>>()?.join("\n");
+        Ok(snapshot)
+    }
+
+    /// Write the snapshot to the file `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut f = File::create(path)?;
+        self.write_to(&mut f)
+    }
+
+    /// Load a snapshot from the file `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Snapshot::read_from(File::open(path)?)
+    }
+
+    /// Read a line `<name> <value>` describing how the program was compiled.
+    fn option(&mut self, line: &str) -> io::Result<()> {
+        let (name, value) = line.split_once(' ').unwrap_or((line, ""));
+        let invalid_value = || invalid(&format!("invalid value of field `{}`", name));
+        match name {
+            "opt_level" => self.opt_level = value.parse().map_err(|_| invalid_value())?,
+            "dead_stores" => self.dead_stores = value.parse().map_err(|_| invalid_value())?,
+            "repeat_counts" => {
+                self.lexer.repeat_counts = value.parse().map_err(|_| invalid_value())?
+            }
+            "comments" => {
+                self.lexer.comments = match value {
+                    "ignore" => CommentPolicy::Ignore,
+                    "error" => CommentPolicy::Error,
+                    "dialect" => CommentPolicy::Dialect,
+                    _ => return Err(invalid_value()),
+                }
+            }
+            "dialect" => {
+                self.lexer.dialect = match value {
+                    "standard" => Dialect::Standard,
+                    "grid" => Dialect::Grid,
+                    "random" => Dialect::Random,
+                    _ => match value.strip_prefix("multitape ").map(str::parse) {
+                        Some(Ok(n)) => Dialect::MultiTape(n),
+                        _ => return Err(invalid_value()),
+                    },
+                }
+            }
+            "alias" => self
+                .lexer
+                .aliases
+                .push(parse_alias(value).map_err(|e| invalid(&e))?),
+            _ => return Err(invalid(&format!("unknown field `{}`", name))),
+        }
+        Ok(())
+    }
+}
+
+/// Parse a line of the form `<name> <value>`.
+fn parse_field<T: ::std::str::FromStr>(line: Option<String>, name: &str) -> io::Result<T> {
+    match line {
+        Some(ref line) if line.starts_with(name) => line[name.len()..]
+            .trim()
+            .parse()
+            .map_err(|_| invalid(&format!("invalid value of field `{}`", name))),
+        _ => Err(invalid(&format!("missing field `{}`", name))),
+    }
+}
+
+/// Construct an `InvalidData` error.
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
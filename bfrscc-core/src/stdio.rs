@@ -0,0 +1,117 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Standard I/O
+//!
+//! Programs are often used as filters, e.g. `cat data | bfrscc run rot13.bf > out`. Their
+//! output is collected in a buffer and written in chunks, their input is read in chunks as
+//! well. The output is written
+//!
+//! - when the buffer is full,
+//! - at every line break if stdout is a terminal,
+//! - before more input is read from stdin, so prompts are visible and a pipeline only waits
+//!   for input once all output so far has been passed on,
+//! - at the end of every run (see `flush`).
+//!
+//! The buffer belongs to the current thread.
+
+use std::cell::RefCell;
+use std::io::{self, IsTerminal, Read, Write};
+
+/// Size of the chunks of output and input
+const CHUNK: usize = 64 * 1024;
+
+/// Pending output of the current thread
+struct Output {
+    /// Bytes which have not been written yet
+    buf: Vec<u8>,
+    /// Write at every line break
+    line_buffered: bool,
+}
+
+thread_local! {
+    static OUTPUT: RefCell<Output> = RefCell::new(Output {
+        buf: Vec::with_capacity(CHUNK),
+        line_buffered: io::stdout().is_terminal(),
+    });
+}
+
+/// Write `b` to stdout. Panics if stdout cannot be written, like `print!`.
+#[inline]
+pub fn write_byte(b: u8) {
+    let due = OUTPUT.with(|out| {
+        let mut out = out.borrow_mut();
+        out.buf.push(b);
+        out.buf.len() >= CHUNK || (b == b'\n' && out.line_buffered)
+    });
+    if due {
+        if let Err(e) = flush() {
+            panic!("failed printing to stdout: {}", e);
+        }
+    }
+}
+
+/// Write the pending output of the current thread to stdout and flush it.
+pub fn flush() -> io::Result<()> {
+    let buf = OUTPUT.with(|out| ::std::mem::take(&mut out.borrow_mut().buf));
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    stdout.write_all(&buf)?;
+    stdout.flush()?;
+    OUTPUT.with(|out| {
+        // Keep the allocation for the next chunk.
+        let mut out = out.borrow_mut();
+        if out.buf.is_empty() {
+            out.buf = buf;
+            out.buf.clear();
+        }
+    });
+    Ok(())
+}
+
+/// Bytes of stdin, read in chunks. The pending output is written before every chunk. Read
+/// errors are treated like the end of the input.
+pub struct StdinBytes {
+    /// Current chunk
+    buf: Box<[u8]>,
+    /// Number of valid bytes in `buf`
+    len: usize,
+    /// Index of the next byte in `buf`
+    pos: usize,
+}
+
+/// Bytes of stdin (see `StdinBytes`)
+pub fn stdin_bytes() -> StdinBytes {
+    StdinBytes {
+        buf: vec![0; CHUNK].into_boxed_slice(),
+        len: 0,
+        pos: 0,
+    }
+}
+
+impl Iterator for StdinBytes {
+    type Item = u8;
+
+    #[inline]
+    fn next(&mut self) -> Option<u8> {
+        if self.pos == self.len {
+            flush().ok();
+            self.len = loop {
+                match io::stdin().read(&mut self.buf) {
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    res => break res.unwrap_or(0),
+                }
+            };
+            self.pos = 0;
+            if self.len == 0 {
+                return None;
+            }
+        }
+        self.pos += 1;
+        Some(self.buf[self.pos - 1])
+    }
+}
@@ -0,0 +1,227 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Streaming compiler
+//!
+//! Compiles programs which are too large for the usual pipeline, e.g. hundreds of megabytes of
+//! generated brainfuck. The program is read in one pass: every byte is lexed, runs of `+-<>^v`
+//! are folded and the packed instructions (see `Program::ops`) are emitted right away. Besides
+//! the instructions, only the open loops are kept in memory, neither the source nor tokens.
+//!
+//! Positions are byte positions. Aliases and repeat counts are not supported, and the program is
+//! not optimized any further.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufReader, Read};
+use std::ops::Range;
+use error::ParseError;
+use lexer::{ByteClasses, LexerOptions, Span, Token};
+use parser::{Instruction, InstructionStream};
+use program::{LoopKind, Op, Program};
+
+/// Reasons for a streaming compilation to fail
+#[derive(Debug)]
+pub enum StreamError {
+    /// The program could not be read
+    Io(io::Error),
+    /// The program is invalid
+    Parse(ParseError),
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StreamError::Io(ref e) => e.fmt(f),
+            StreamError::Parse(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for StreamError {}
+
+impl From<io::Error> for StreamError {
+    fn from(e: io::Error) -> Self {
+        StreamError::Io(e)
+    }
+}
+
+impl From<ParseError> for StreamError {
+    fn from(e: ParseError) -> Self {
+        StreamError::Parse(e)
+    }
+}
+
+/// A loop which has not been closed yet
+struct Open {
+    /// Index of the `[`
+    idx: usize,
+    /// Position of the `[`
+    pos: usize,
+    /// Net movement of the pointer so far
+    offset: isize,
+    /// Set if all nested loops are balanced
+    balanced: bool,
+    /// Set if the loop reads or writes
+    io: bool,
+}
+
+/// Compile the program read from `reader` with `options`. With `CommentPolicy::Dialect`, the
+/// bytes after the first top-level `!` are the inline input of the program.
+///
+/// ```
+/// use bfrscc_core::lexer::LexerOptions;
+/// use bfrscc_core::stream::compile_reader;
+///
+/// let prog = compile_reader(&b"++ +[->\n+<]"[..], &LexerOptions::default()).unwrap();
+/// assert_eq!(prog.instructions().len(), prog.ops().len());
+/// assert_eq!(prog.spans(0), [0..2, 3..4]);
+/// assert_eq!((prog.instructions()[4].span.line, prog.instructions()[4].span.col), (2, 1));
+/// assert_eq!(prog.jump(1), 6);
+/// ```
+pub fn compile_reader<R: Read>(reader: R, options: &LexerOptions) -> Result<Program, StreamError> {
+    compile_bytes(BufReader::new(reader).bytes(), options)
+}
+
+/// Like `compile_reader`, but lexes `prog` in place, e.g. a memory-mapped file (see
+/// `mmap::Mapping`).
+pub fn compile_slice(prog: &[u8], options: &LexerOptions) -> Result<Program, StreamError> {
+    compile_bytes(prog.iter().map(|&b| Ok(b)), options)
+}
+
+/// Implementation of `compile_reader` and `compile_slice`
+fn compile_bytes<I: Iterator<Item = io::Result<u8>>>(
+    mut bytes: I,
+    options: &LexerOptions,
+) -> Result<Program, StreamError> {
+    let max_length = options.limits.max_length.unwrap_or(usize::MAX);
+    let max_depth = options.limits.max_depth.unwrap_or(usize::MAX);
+    let classes = ByteClasses::new(options);
+    let mut ops: Vec<Op> = vec![];
+    let mut instructions: InstructionStream = vec![];
+    let mut comments: Vec<Range<usize>> = vec![];
+    let mut open: Vec<Open> = vec![];
+    let mut inline_input = None;
+    let mut pos = 0;
+    let (mut line, mut col) = (1, 1);
+    while let Some(byte) = bytes.next() {
+        if pos >= max_length {
+            return Err(ParseError::ProgramTooLong(max_length).into());
+        }
+        let byte = byte?;
+        let t = classes.token(pos, byte)?;
+        let span = Span {
+            position: pos,
+            offset: pos,
+            line,
+            col,
+            len: 1,
+        };
+        match t {
+            Token::Comment => match comments.last_mut() {
+                Some(c) if c.end == pos => c.end += 1,
+                _ => comments.push(pos..pos + 1),
+            },
+            Token::Increase | Token::Decrease | Token::MoveLeft | Token::MoveRight
+            | Token::MoveUp | Token::MoveDown => {
+                if let Some(l) = open.last_mut() {
+                    match t {
+                        Token::MoveRight => l.offset += 1,
+                        Token::MoveLeft => l.offset -= 1,
+                        Token::MoveUp | Token::MoveDown => l.balanced = false,
+                        _ => {}
+                    }
+                }
+                // Comments do not interrupt a run.
+                match (ops.last_mut(), instructions.last_mut()) {
+                    (Some(op), Some(x)) if op.token == t && op.multiplier < u8::MAX => {
+                        op.multiplier += 1;
+                        x.multiplier += 1;
+                        x.end = pos + 1;
+                    }
+                    _ => {
+                        ops.push(packed(t, ops.len()));
+                        instructions.push(Instruction::with_span(span, t, 1));
+                    }
+                }
+            }
+            Token::LoopBegin if open.len() >= max_depth => {
+                return Err(ParseError::NestingTooDeep(pos, max_depth).into())
+            }
+            Token::LoopBegin => {
+                open.push(Open {
+                    idx: ops.len(),
+                    pos,
+                    offset: 0,
+                    balanced: true,
+                    io: false,
+                });
+                let mut op = packed(t, ops.len());
+                op.depth = open.len() as u32;
+                ops.push(op);
+                instructions.push(Instruction::with_span(span, t, 1));
+            }
+            Token::LoopEnd => {
+                let l = open.pop().ok_or(ParseError::UnmatchedLoopEnd(pos))?;
+                let balanced = l.offset == 0 && l.balanced;
+                let idx = ops.len();
+                ops.push(packed(t, l.idx));
+                instructions.push(Instruction::with_span(span, t, 1));
+                for &i in &[l.idx, idx] {
+                    ops[i].balanced = balanced;
+                    ops[i].io = l.io;
+                }
+                ops[l.idx].jump = idx as u32;
+                if let Some(outer) = open.last_mut() {
+                    outer.balanced &= balanced;
+                    outer.io |= l.io;
+                }
+            }
+            Token::InputSeparator if open.is_empty() => {
+                let input = bytes.collect::<Result<Vec<u8>, _>>()?;
+                inline_input = Some(input);
+                break;
+            }
+            _ => {
+                if let Some(l) = open.last_mut() {
+                    match t {
+                        Token::Input | Token::Output => l.io = true,
+                        Token::SwitchTape => l.balanced = false,
+                        _ => {}
+                    }
+                }
+                ops.push(packed(t, ops.len()));
+                instructions.push(Instruction::with_span(span, t, 1));
+            }
+        }
+        pos += 1;
+        if byte == b'\n' {
+            line += 1;
+            col = 1;
+        } else {
+            col += 1;
+        }
+    }
+    if let Some(l) = open.first() {
+        return Err(ParseError::UnmatchedLoopBegin(l.pos).into());
+    }
+    Ok(Program::from_ops(instructions, ops, options.clone(), inline_input, comments))
+}
+
+/// Packed instruction `token` which jumps to `jump`
+fn packed(token: Token, jump: usize) -> Op {
+    Op {
+        token,
+        multiplier: 1,
+        depth: 0,
+        jump: jump as u32,
+        balanced: false,
+        io: false,
+        kind: LoopKind::Loop,
+        run: 0,
+    }
+}
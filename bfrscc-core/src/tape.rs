@@ -0,0 +1,670 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Tape
+//!
+//! The memory of the interpreter. The `Tape` trait abstracts over the layout of the memory, which
+//! allows the interpreter to run dialects with a different memory model.
+
+use std::collections::{BTreeMap, HashMap, TryReserveError, VecDeque};
+use std::fmt::Write;
+use std::num::Wrapping;
+use std::ops::Range;
+use std::str::FromStr;
+use annotations::CellNames;
+
+/// Number of cells on each side of the pointer shown above a dump
+const DUMP_WINDOW: usize = 8;
+
+/// Number of cells of a `CircularTape` by default, the size of the tape of the original
+/// brainfuck implementation
+pub const DEFAULT_CIRCULAR_CELLS: usize = 30000;
+
+/// Memory of the interpreter together with the pointer into it
+pub trait Tape: Default {
+    /// Move the pointer `n` cells to the left
+    fn left(&mut self, n: usize);
+
+    /// Move the pointer `n` cells to the right
+    fn right(&mut self, n: usize);
+
+    /// Move the pointer `n` cells up. Does nothing by default.
+    fn up(&mut self, _n: usize) {}
+
+    /// Move the pointer `n` cells down. Does nothing by default.
+    fn down(&mut self, _n: usize) {}
+
+    /// Value of the current cell
+    fn get(&self) -> u8;
+
+    /// Set the value of the current cell
+    fn set(&mut self, val: u8);
+
+    /// Number of allocated cells
+    fn allocated(&self) -> usize;
+
+    /// Allocate memory for at least `n` cells, so the tape does not need to grow until then.
+    /// Fails if the memory cannot be allocated. Does nothing by default.
+    fn reserve(&mut self, _n: usize) -> Result<(), TryReserveError> {
+        Ok(())
+    }
+
+    /// Number of cells the tape has memory for. Returns `allocated` by default.
+    fn capacity(&self) -> usize {
+        self.allocated()
+    }
+
+    /// Change the number of cells of a tape of fixed size. Does nothing by default.
+    fn set_size(&mut self, _n: usize) {}
+
+    /// All cells in a linear layout
+    fn cells(&self) -> Vec<u8>;
+
+    /// Position of the pointer within `cells`
+    fn pos(&self) -> usize;
+
+    /// Position of the cell the pointer started at within `cells`
+    fn origin(&self) -> usize;
+
+    /// Coordinates `(x, y)` of the pointer relative to the cell it started at. Linear tapes only
+    /// have the row `0`.
+    fn coordinates(&self) -> (i64, i64) {
+        (self.pos() as i64 - self.origin() as i64, 0)
+    }
+
+    /// The cells which are not zero by their coordinates (see `coordinates`), ordered by row and
+    /// column, if the tape is a grid. Unlike `cells`, this does not lay out the cells in between.
+    /// Returns `None` by default.
+    fn grid(&self) -> Option<Vec<((i64, i64), u8)>> {
+        None
+    }
+
+    /// Replace the content of the tape with `cells` and move the pointer to `pos`. The tape grows
+    /// if `pos` is beyond the given cells.
+    fn load(&mut self, cells: &[u8], pos: usize);
+}
+
+/// A vector which can grow at the front and at the end. This allows the tape to be infinite on
+/// both sides (limited only by available memory). The type of each cell is `Wrapping<u8>` which
+/// is an `u8` which allows for overflows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinearTape {
+    /// Cells
+    cells: VecDeque<Wrapping<u8>>,
+    /// Current position in the buffer
+    pos: usize,
+    /// Position of the cell the pointer started at in the buffer
+    origin: usize,
+}
+
+impl LinearTape {
+    /// Return the cells of the tape.
+    pub fn get_cells(&self) -> &VecDeque<Wrapping<u8>> {
+        &self.cells
+    }
+
+    /// Set the cell at index `idx` to `val`. The tape grows to the right if necessary.
+    pub fn set_cell(&mut self, idx: usize, val: u8) {
+        while self.cells.len() <= idx {
+            self.cells.push_back(Wrapping(0));
+        }
+        self.cells[idx] = Wrapping(val);
+    }
+
+    /// Add `n` cells in front of the first cell, which moves the pointer and the origin.
+    #[cold]
+    #[inline(never)]
+    fn grow_front(&mut self, n: usize) {
+        // We would move beyond the beginning of the tape, so we will just push to the front.
+        for _ in 0..n {
+            self.cells.push_front(Wrapping(0));
+        }
+        self.origin += n;
+        self.pos += n;
+    }
+
+    /// Add cells behind the last cell up to the pointer.
+    #[cold]
+    #[inline(never)]
+    fn grow_back(&mut self) {
+        self.cells.resize(self.pos + 1, Wrapping(0));
+    }
+}
+
+impl Default for LinearTape {
+    fn default() -> Self {
+        let mut cells = VecDeque::new();
+        cells.push_back(Wrapping(0));
+        LinearTape {
+            cells,
+            pos: 0,
+            origin: 0,
+        }
+    }
+}
+
+impl Tape for LinearTape {
+    #[inline]
+    fn left(&mut self, n: usize) {
+        if n > self.pos {
+            self.grow_front(n - self.pos);
+        }
+        // Just move the pointer to the left
+        self.pos -= n;
+    }
+
+    #[inline]
+    fn right(&mut self, n: usize) {
+        self.pos += n;
+        // Add cells if we have exceeded the tape
+        if self.pos >= self.cells.len() {
+            self.grow_back();
+        }
+    }
+
+    fn get(&self) -> u8 {
+        self.cells[self.pos].0
+    }
+
+    fn set(&mut self, val: u8) {
+        self.cells[self.pos] = Wrapping(val);
+    }
+
+    fn allocated(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn reserve(&mut self, n: usize) -> Result<(), TryReserveError> {
+        let len = self.cells.len();
+        self.cells.try_reserve(n.saturating_sub(len))
+    }
+
+    fn capacity(&self) -> usize {
+        self.cells.capacity()
+    }
+
+    fn cells(&self) -> Vec<u8> {
+        self.cells.iter().map(|x| x.0).collect()
+    }
+
+    fn pos(&self) -> usize {
+        self.pos
+    }
+
+    fn load(&mut self, cells: &[u8], pos: usize) {
+        self.cells = cells.iter().map(|&x| Wrapping(x)).collect();
+        self.pos = 0;
+        self.origin = 0;
+        self.right(pos);
+    }
+
+    fn origin(&self) -> usize {
+        self.origin
+    }
+}
+
+/// A fixed number of cells forming a circle: moving right of the last cell leads to the first
+/// one and vice versa. All cells are allocated up front.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CircularTape {
+    /// Cells
+    cells: Vec<u8>,
+    /// Position of the pointer within `cells`
+    pos: usize,
+}
+
+impl CircularTape {
+    /// Tape of `n` cells (at least one)
+    pub fn new(n: usize) -> Self {
+        CircularTape {
+            cells: vec![0; n.max(1)],
+            pos: 0,
+        }
+    }
+}
+
+impl Default for CircularTape {
+    fn default() -> Self {
+        CircularTape::new(DEFAULT_CIRCULAR_CELLS)
+    }
+}
+
+impl Tape for CircularTape {
+    fn left(&mut self, n: usize) {
+        let len = self.cells.len();
+        self.pos = (self.pos + len - n % len) % len;
+    }
+
+    fn right(&mut self, n: usize) {
+        self.pos = (self.pos + n % self.cells.len()) % self.cells.len();
+    }
+
+    fn get(&self) -> u8 {
+        self.cells[self.pos]
+    }
+
+    fn set(&mut self, val: u8) {
+        self.cells[self.pos] = val;
+    }
+
+    fn allocated(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn cells(&self) -> Vec<u8> {
+        self.cells.clone()
+    }
+
+    fn pos(&self) -> usize {
+        self.pos
+    }
+
+    fn origin(&self) -> usize {
+        0
+    }
+
+    /// Cells beyond the size of the tape are dropped, and `pos` wraps around.
+    fn load(&mut self, cells: &[u8], pos: usize) {
+        let n = cells.len().min(self.cells.len());
+        self.cells.iter_mut().for_each(|x| *x = 0);
+        self.cells[..n].copy_from_slice(&cells[..n]);
+        self.pos = pos % self.cells.len();
+    }
+
+    /// Cells beyond the new size are dropped, and the pointer wraps around.
+    fn set_size(&mut self, n: usize) {
+        self.cells.resize(n.max(1), 0);
+        self.pos %= self.cells.len();
+    }
+}
+
+/// A two dimensional grid of cells which is infinite in all directions. Only cells which have
+/// been visited are allocated.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GridTape {
+    /// Visited cells, indexed by `(x, y)`
+    cells: HashMap<(i64, i64), u8>,
+    /// Column of the pointer
+    x: i64,
+    /// Row of the pointer
+    y: i64,
+}
+
+impl GridTape {
+    /// Smallest and largest visited coordinates `((x_min, y_min), (x_max, y_max))`, including the
+    /// origin
+    fn bounds(&self) -> ((i64, i64), (i64, i64)) {
+        self.cells.keys().chain(&[(self.x, self.y), (0, 0)]).fold(
+            ((self.x, self.y), (self.x, self.y)),
+            |((x0, y0), (x1, y1)), &(x, y)| ((x0.min(x), y0.min(y)), (x1.max(x), y1.max(y))),
+        )
+    }
+}
+
+impl Tape for GridTape {
+    fn left(&mut self, n: usize) {
+        self.x -= n as i64;
+    }
+
+    fn right(&mut self, n: usize) {
+        self.x += n as i64;
+    }
+
+    fn up(&mut self, n: usize) {
+        self.y -= n as i64;
+    }
+
+    fn down(&mut self, n: usize) {
+        self.y += n as i64;
+    }
+
+    fn get(&self) -> u8 {
+        self.cells.get(&(self.x, self.y)).cloned().unwrap_or(0)
+    }
+
+    fn set(&mut self, val: u8) {
+        self.cells.insert((self.x, self.y), val);
+    }
+
+    fn allocated(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn reserve(&mut self, n: usize) -> Result<(), TryReserveError> {
+        let len = self.cells.len();
+        self.cells.try_reserve(n.saturating_sub(len))
+    }
+
+    fn capacity(&self) -> usize {
+        self.cells.capacity()
+    }
+
+    /// The bounding box of all visited cells in row-major order
+    fn cells(&self) -> Vec<u8> {
+        let ((x0, y0), (x1, y1)) = self.bounds();
+        (y0..y1 + 1)
+            .flat_map(|y| (x0..x1 + 1).map(move |x| (x, y)))
+            .map(|k| self.cells.get(&k).cloned().unwrap_or(0))
+            .collect()
+    }
+
+    fn pos(&self) -> usize {
+        let ((x0, y0), (x1, _)) = self.bounds();
+        ((self.y - y0) * (x1 - x0 + 1) + (self.x - x0)) as usize
+    }
+
+    /// The cells are loaded into the first row, starting at the origin.
+    fn load(&mut self, cells: &[u8], pos: usize) {
+        self.cells = cells
+            .iter()
+            .enumerate()
+            .map(|(x, &val)| ((x as i64, 0), val))
+            .collect();
+        self.x = pos as i64;
+        self.y = 0;
+    }
+
+    fn origin(&self) -> usize {
+        let ((x0, y0), (x1, _)) = self.bounds();
+        ((-y0) * (x1 - x0 + 1) - x0) as usize
+    }
+
+    fn coordinates(&self) -> (i64, i64) {
+        (self.x, self.y)
+    }
+
+    fn grid(&self) -> Option<Vec<((i64, i64), u8)>> {
+        let mut cells: Vec<_> = self.cells
+            .iter()
+            .filter(|&(_, &val)| val != 0)
+            .map(|(&k, &val)| (k, val))
+            .collect();
+        cells.sort_by_key(|&((x, y), _)| (y, x));
+        Some(cells)
+    }
+}
+
+/// How cells are written by `TapeView::dump`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DumpFormat {
+    /// Hexadecimal values
+    Hex,
+    /// Decimal values
+    Dec,
+    /// Printable ASCII characters, `.` otherwise
+    Chars,
+}
+
+impl FromStr for DumpFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hex" => Ok(DumpFormat::Hex),
+            "dec" => Ok(DumpFormat::Dec),
+            "chars" => Ok(DumpFormat::Chars),
+            _ => Err(format!("unknown dump format `{}`", s)),
+        }
+    }
+}
+
+/// Cells of a tape addressed by signed indices: the cell the pointer started at has index `0`,
+/// cells to the left of it have negative indices. On a grid, the indices address the row of the
+/// cell the pointer started at, and only the cells which are not zero are kept.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TapeView {
+    /// Cells of the tape
+    cells: Cells,
+    /// Names of cells, shown next to their values
+    names: CellNames,
+}
+
+/// Cells of a `TapeView`
+#[derive(Debug, Clone, PartialEq)]
+enum Cells {
+    /// All cells of a linear tape
+    Linear {
+        /// Cells in a linear layout
+        cells: Vec<u8>,
+        /// Position of cell `0` within `cells`
+        origin: usize,
+        /// Position of the pointer within `cells`
+        pos: usize,
+    },
+    /// The cells of a grid which are not zero
+    Grid {
+        /// Values by row and column
+        cells: BTreeMap<(i64, i64), u8>,
+        /// Coordinates `(x, y)` of the pointer
+        pointer: (i64, i64),
+    },
+}
+
+impl TapeView {
+    /// Take a view of `tape`.
+    pub fn new<T: Tape>(tape: &T) -> Self {
+        let cells = match tape.grid() {
+            Some(cells) => Cells::Grid {
+                cells: cells.into_iter().map(|((x, y), val)| ((y, x), val)).collect(),
+                pointer: tape.coordinates(),
+            },
+            None => Cells::Linear {
+                cells: tape.cells(),
+                origin: tape.origin(),
+                pos: tape.pos(),
+            },
+        };
+        TapeView {
+            cells,
+            names: CellNames::default(),
+        }
+    }
+
+    /// View of `cells` with the pointer at `pos`. Indices are positions within `cells`.
+    pub fn from_cells(cells: &[u8], pos: usize) -> Self {
+        TapeView {
+            cells: Cells::Linear {
+                cells: cells.to_vec(),
+                origin: 0,
+                pos,
+            },
+            names: CellNames::default(),
+        }
+    }
+
+    /// Show the names of `names` next to the values of their cells.
+    pub fn with_names(mut self, names: &CellNames) -> Self {
+        self.names = names.clone();
+        self
+    }
+
+    /// Names of cells
+    pub fn names(&self) -> &CellNames {
+        &self.names
+    }
+
+    /// Index of the pointer, its column on a grid
+    pub fn pointer(&self) -> isize {
+        match self.cells {
+            Cells::Linear { origin, pos, .. } => pos as isize - origin as isize,
+            Cells::Grid { pointer, .. } => pointer.0 as isize,
+        }
+    }
+
+    /// Indices of the allocated cells
+    pub fn range(&self) -> Range<isize> {
+        self.columns(0)
+    }
+
+    /// Value of cell `idx`. Cells which have not been allocated are `0`.
+    pub fn get(&self, idx: isize) -> u8 {
+        self.at(idx, 0)
+    }
+
+    /// Value of the cell under the pointer
+    pub fn current(&self) -> u8 {
+        self.at(self.pointer(), self.row())
+    }
+
+    /// Write the cells of `range` (all allocated cells if `None`) in lines of 16 cells, each line
+    /// starting with the index of its first cell. The named cells of `range` follow, one per
+    /// line. On a grid, the lines hold the columns of `range` (all columns if `None`) of every
+    /// row, starting with the index and the row of their first cell, and lines without a cell
+    /// which is not zero are left out.
+    pub fn dump(&self, range: Option<Range<isize>>, format: DumpFormat) -> String {
+        let range = range.unwrap_or_else(|| match self.cells {
+            Cells::Linear { .. } => self.range(),
+            Cells::Grid { .. } => isize::MIN..isize::MAX,
+        });
+        let mut out = match self.cells {
+            Cells::Linear { .. } => format!("pointer: {}\n", self.pointer()),
+            Cells::Grid { pointer, .. } => format!("pointer: {},{}\n", pointer.0, pointer.1),
+        };
+        writeln!(out, "{}", self.render(DUMP_WINDOW, format)).unwrap();
+        let sep = if format == DumpFormat::Chars { "" } else { " " };
+        let line = |row: i64, cols: Range<isize>| {
+            let cells: Vec<String> = cols
+                .map(|i| match format {
+                    DumpFormat::Dec => format!("{:3}", self.at(i, row)),
+                    _ => cell(self.at(i, row), format),
+                })
+                .collect();
+            cells.join(sep)
+        };
+        match self.cells {
+            Cells::Linear { .. } => {
+                let mut start = range.start;
+                while start < range.end {
+                    let end = (start + 16).min(range.end);
+                    writeln!(out, "{:>6}: {}", start, line(0, start..end)).unwrap();
+                    start = end;
+                }
+            }
+            Cells::Grid { ref cells, .. } => {
+                // Every line starts at a cell which is not zero and is not on a previous line,
+                // and ends after the last cell of its row which is not zero.
+                let mut next = (i64::MIN, range.start);
+                for &(row, x) in cells.keys() {
+                    let start = x as isize;
+                    if !range.contains(&start) || (row, start) < next {
+                        continue;
+                    }
+                    let last = cells
+                        .range((row, x)..=(row, i64::MAX))
+                        .next_back()
+                        .map_or(x, |(&(_, x), _)| x);
+                    let end = (start + 16).min(range.end).min(last as isize + 1);
+                    let label = format!("{},{}", start, row);
+                    writeln!(out, "{:>6}: {}", label, line(row, start..end)).unwrap();
+                    next = (row, end);
+                }
+            }
+        }
+        for (idx, name) in self.names.iter().filter(|&(i, _)| range.contains(&i)) {
+            writeln!(out, "{:>6}: {} = {}", idx, name, self.get(idx)).unwrap();
+        }
+        out
+    }
+
+    /// Write the `width` cells on each side of the pointer on a single line, with the pointer in
+    /// brackets, e.g. `... 0 0 [72] 101 108 ...`. An ellipsis marks allocated cells outside of
+    /// the window. Named cells are written as `name=value`. On a grid, the line shows the row of
+    /// the pointer.
+    pub fn render(&self, width: usize, radix: DumpFormat) -> String {
+        let ptr = self.pointer();
+        let row = self.row();
+        let range = self.columns(row);
+        let from = (ptr - width as isize).max(range.start.min(ptr));
+        let to = (ptr + width as isize + 1).min(range.end.max(ptr + 1));
+        let mut cells: Vec<String> = (from..to)
+            .map(|i| {
+                let cell = cell(self.at(i, row), radix);
+                let cell = match self.names.get(i).filter(|_| row == 0) {
+                    Some(name) => format!("{}={}", name, cell),
+                    None => cell,
+                };
+                if i == ptr {
+                    format!("[{}]", cell)
+                } else {
+                    cell
+                }
+            })
+            .collect();
+        if from > range.start {
+            cells.insert(0, "...".to_string());
+        }
+        if to < range.end {
+            cells.push("...".to_string());
+        }
+        cells.join(" ")
+    }
+
+    /// Row of the pointer, which is `0` unless the tape is a grid
+    fn row(&self) -> i64 {
+        match self.cells {
+            Cells::Linear { .. } => 0,
+            Cells::Grid { pointer, .. } => pointer.1,
+        }
+    }
+
+    /// Indices of the allocated cells of `row`. On a grid, these are the columns from the first
+    /// to the last cell which is not zero, including the pointer and the cell it started at.
+    fn columns(&self, row: i64) -> Range<isize> {
+        match self.cells {
+            Cells::Linear {
+                ref cells, origin, ..
+            } => -(origin as isize)..(cells.len() - origin) as isize,
+            Cells::Grid {
+                ref cells,
+                pointer,
+            } => {
+                let mut xs: Vec<i64> = cells
+                    .range((row, i64::MIN)..=(row, i64::MAX))
+                    .map(|(&(_, x), _)| x)
+                    .collect();
+                if pointer.1 == row {
+                    xs.push(pointer.0);
+                }
+                if row == 0 {
+                    xs.push(0);
+                }
+                let from = xs.iter().cloned().min().unwrap_or(0);
+                let to = xs.iter().cloned().max().map_or(0, |x| x + 1);
+                from as isize..to as isize
+            }
+        }
+    }
+
+    /// Value of the cell at index `idx` of `row`
+    fn at(&self, idx: isize, row: i64) -> u8 {
+        match self.cells {
+            Cells::Linear {
+                ref cells, origin, ..
+            } => {
+                let i = idx + origin as isize;
+                if i < 0 || row != 0 {
+                    0
+                } else {
+                    cells.get(i as usize).cloned().unwrap_or(0)
+                }
+            }
+            Cells::Grid { ref cells, .. } => cells.get(&(row, idx as i64)).cloned().unwrap_or(0),
+        }
+    }
+}
+
+/// `val` written in `format`
+fn cell(val: u8, format: DumpFormat) -> String {
+    match format {
+        DumpFormat::Hex => format!("{:02x}", val),
+        DumpFormat::Dec => val.to_string(),
+        DumpFormat::Chars => match val {
+            c @ 0x20..=0x7e => (c as char).to_string(),
+            _ => ".".to_string(),
+        },
+    }
+}
@@ -0,0 +1,50 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # TOML lines
+//!
+//! The pieces of TOML shared by alias files and project manifests, which both only support a
+//! small subset of it: comments and `<key> = <value>` entries with bare or quoted keys.
+//!
+//! ```
+//! use bfrscc_core::toml::{split_key, strip_comment};
+//!
+//! let line = strip_comment("\"#\" = \"+\"  # a comment").trim();
+//! assert_eq!(split_key(line), Some(("#".to_string(), " \"+\"")));
+//! ```
+
+/// `line` without a trailing comment. A `#` within a string does not start a comment.
+pub fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Split an entry into its (unquoted) key and the rest after the `=`.
+pub fn split_key(line: &str) -> Option<(String, &str)> {
+    if let Some(rest) = line.strip_prefix('"') {
+        let end = rest.find('"')?;
+        let value = rest[end + 1..].trim_start().strip_prefix('=')?;
+        Some((rest[..end].to_string(), value))
+    } else {
+        let idx = line.find('=')?;
+        let key = line[..idx].trim();
+        if key.is_empty() {
+            return None;
+        }
+        Some((key.to_string(), &line[idx + 1..]))
+    }
+}
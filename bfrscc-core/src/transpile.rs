@@ -0,0 +1,115 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! # Transpile
+//!
+//! Translates all programs in a directory tree with a backend, preserving the directory
+//! structure (`bfrscc transpile <dir> --emit <backend> --out-dir <dir>`).
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use backend::Backend;
+use codesize::SizeReport;
+use lexer::{source_from_bytes, LexerOptions};
+use lint::{lint_bounds, Warning};
+use logging::Level;
+use program::Program;
+
+/// Extensions of files which are treated as brainfuck programs
+const EXTENSIONS: &[&str] = &["bf", "b"];
+
+/// Outcome of translating a directory tree
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Summary {
+    /// Files which have been written
+    pub written: Vec<PathBuf>,
+    /// Programs which could not be translated together with the reason
+    pub failed: Vec<(PathBuf, String)>,
+    /// Files shared by the written programs (see `Backend::support_files`)
+    pub support: Vec<PathBuf>,
+    /// Programs which may leave the tape of the backend (see `Backend::tape_bounds`)
+    pub warnings: Vec<(PathBuf, Warning)>,
+    /// Size of the code of every written program
+    pub sizes: Vec<(PathBuf, SizeReport)>,
+}
+
+/// Translate every program below `src` (or `src` itself if it is a file) with `backend` and
+/// write the results to the same relative path below `out_dir`, with the extension of the
+/// backend. Programs which fail to compile or translate are listed in the summary, I/O errors
+/// abort.
+pub fn transpile_dir(
+    src: &Path,
+    out_dir: &Path,
+    backend: &dyn Backend,
+    options: &LexerOptions,
+    opt_level: u8,
+) -> io::Result<Summary> {
+    let mut files = vec![];
+    let root = if src.is_dir() {
+        collect(src, &mut files)?;
+        src
+    } else {
+        files.push(src.to_path_buf());
+        src.parent().unwrap_or(src)
+    };
+    files.sort();
+    let mut summary = Summary::default();
+    for file in files {
+        let rel = file.strip_prefix(root).unwrap_or(&file);
+        let target = out_dir.join(rel).with_extension(backend.extension());
+        let source = source_from_bytes(fs::read(&file)?);
+        let prog = Program::compile(&source, options, opt_level).map_err(|e| e.to_string());
+        if let (Ok(prog), Some((left, right))) = (&prog, backend.tape_bounds()) {
+            for warning in lint_bounds(prog, left, right) {
+                summary.warnings.push((file.clone(), warning));
+            }
+        }
+        let code = prog.and_then(|prog| {
+            let (code, spans) = backend.emit_mapped(&prog).map_err(|e| e.to_string())?;
+            let size = SizeReport::from_code(backend.name(), &prog, &code, spans.as_deref());
+            Ok((code, size))
+        });
+        match code {
+            Ok((code, size)) => {
+                if let Some(dir) = target.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                fs::write(&target, code)?;
+                log_msg!(Level::Info, "transpile: {} -> {}", file.display(), target.display());
+                summary.written.push(target);
+                summary.sizes.push((file, size));
+            }
+            Err(e) => summary.failed.push((file, e)),
+        }
+    }
+    if !summary.written.is_empty() {
+        for (name, contents) in backend.support_files() {
+            let target = out_dir.join(name);
+            fs::write(&target, contents)?;
+            log_msg!(Level::Info, "transpile: wrote {}", target.display());
+            summary.support.push(target);
+        }
+    }
+    Ok(summary)
+}
+
+/// Add all programs below `dir` to `files`.
+fn collect(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect(&path, files)?;
+        } else if path.extension()
+            .and_then(|x| x.to_str())
+            .is_some_and(|x| EXTENSIONS.contains(&x))
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
@@ -5,9 +5,12 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
-/// Todo
-pub mod interpreter;
-pub mod lexer;
-pub mod parser;
-pub mod machine;
-pub mod run;
+//! # bfrscc
+//!
+//! Re-exports the modules of `bfrscc-core`, so code written against the single crate keeps
+//! working. The features of this crate enable the features of the same name of `bfrscc-core`.
+//! The command line tool lives in `bfrscc-cli`.
+
+extern crate bfrscc_core;
+
+pub use bfrscc_core::*;
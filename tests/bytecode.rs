@@ -0,0 +1,122 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Programs stored as bytecode and loaded again.
+
+extern crate bfrscc;
+
+use bfrscc::bytecode::{decode, encode, encode_version, version, BytecodeError, VERSION};
+use bfrscc::disasm::disassemble;
+use bfrscc::lexer::{CommentPolicy, Dialect, LexerOptions};
+use bfrscc::program::Program;
+use bfrscc::run::{run_program, RunConfig};
+
+/// Output of `prog` with `input`
+fn output(prog: &Program, input: &[u8]) -> Vec<u8> {
+    let config = RunConfig {
+        input: Some(input.to_vec()),
+        ..RunConfig::default()
+    };
+    let mut machine = prog.spawn_machine();
+    run_program(prog, &mut machine, &config).unwrap();
+    machine.take_output()
+}
+
+#[test]
+fn loaded_programs_behave_like_their_source() {
+    let programs = [
+        "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.\
+         ------.--------.>>+.>++.",
+        ",[.,]",
+        "++++[->+++<]>[-<++>]<.[-],+.",
+    ];
+    for source in &programs {
+        for level in 0..2 {
+            let prog = Program::compile(source, &LexerOptions::default(), level).unwrap();
+            let loaded = decode(&encode(&prog)).unwrap();
+            assert_eq!(loaded.ops(), prog.ops());
+            assert_eq!(loaded.opt_level(), level);
+            assert_eq!(output(&loaded, b"abc"), output(&prog, b"abc"));
+        }
+    }
+}
+
+#[test]
+fn loaded_programs_can_be_disassembled() {
+    let prog = Program::compile("++++ [->+++<] >.", &LexerOptions::default(), 1).unwrap();
+    let listing = disassemble(&decode(&encode(&prog)).unwrap());
+    assert_eq!(listing.lines().count(), prog.len());
+    // The spans refer to the canonical source of the loaded instructions.
+    assert!(listing.lines().next().unwrap().ends_with("0..4        ++++"));
+    assert!(listing.contains("transfer"));
+}
+
+#[test]
+fn dialect_and_inline_input_are_kept() {
+    let options = LexerOptions {
+        comments: CommentPolicy::Dialect,
+        dialect: Dialect::MultiTape(2),
+        ..LexerOptions::default()
+    };
+    let prog = Program::compile(",~,~.~.!ab", &options, 1).unwrap();
+    let loaded = decode(&encode(&prog)).unwrap();
+    assert_eq!(loaded.dialect(), Dialect::MultiTape(2));
+    assert_eq!(loaded.inline_input(), Some(&b"ab"[..]));
+    let config = RunConfig::default();
+    let mut machine = loaded.spawn_machine();
+    run_program(&loaded, &mut machine, &config).unwrap();
+    assert_eq!(machine.take_output(), b"ab");
+}
+
+#[test]
+fn corrupt_bytecode_is_rejected() {
+    let prog: Program = "+[>.<-]".parse().unwrap();
+    let bytes = encode(&prog);
+    let len = bytes.len();
+    assert!(matches!(decode(b"++[-]"), Err(BytecodeError::NotBytecode)));
+    assert!(matches!(decode(&bytes[..5]), Err(BytecodeError::Truncated)));
+    let mut flipped = bytes.clone();
+    flipped[len - 9] ^= 1;
+    assert!(matches!(
+        decode(&flipped),
+        Err(BytecodeError::Checksum { .. })
+    ));
+    // Version 1 has no checksum, so the instructions themselves are checked.
+    let bytes = encode_version(&prog, 1).unwrap();
+    let len = bytes.len();
+    assert!(matches!(decode(&bytes[..len - 1]), Err(BytecodeError::Truncated)));
+    let corrupt = |at: usize, val: u8| {
+        let mut bytes = bytes.clone();
+        bytes[at] = val;
+        decode(&bytes)
+    };
+    // The first token and the first `]`
+    assert!(matches!(corrupt(len - 14, 13), Err(BytecodeError::Corrupt(_))));
+    assert!(matches!(corrupt(len - 2, 4), Err(BytecodeError::Corrupt(_))));
+    assert!(matches!(
+        decode(&[&bytes[..], &[0]].concat()),
+        Err(BytecodeError::Corrupt(_))
+    ));
+}
+
+#[test]
+fn older_versions_are_read_and_newer_ones_rejected() {
+    let prog: Program = "++[->+<]>.".parse().unwrap();
+    let old = encode_version(&prog, 1).unwrap();
+    assert_eq!(version(&old).unwrap(), 1);
+    assert_eq!(decode(&old).unwrap().ops(), prog.ops());
+    let mut newer = encode(&prog);
+    newer[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+    assert!(matches!(
+        decode(&newer),
+        Err(BytecodeError::NewerVersion(v)) if v == VERSION + 1
+    ));
+    assert!(matches!(
+        encode_version(&prog, VERSION + 1),
+        Err(BytecodeError::UnsupportedVersion(_))
+    ));
+}
@@ -0,0 +1,481 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Execution of programs: arithmetic, the tape and the input.
+
+extern crate bfrscc;
+
+mod support;
+
+use std::env;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use bfrscc::buffer::BufferMachine;
+use bfrscc::cell::{CellMachine, CellType};
+use bfrscc::checkpoint::Checkpointing;
+use bfrscc::lexer::{Dialect, LexerOptions};
+use bfrscc::machine::{Machine, MachineExt};
+use bfrscc::newline::Newline;
+use bfrscc::shadow::compare;
+use bfrscc::sink::{run_with_sink, SinkMachine};
+use bfrscc::tape::DumpFormat;
+use bfrscc::run::{
+    compile, interpreter, run_program, run_with_config, Arithmetic, Eof, Progress, RunConfig,
+    RunError, Underflow,
+};
+use support::{run_both, run_with};
+
+#[test]
+fn hello_world() {
+    let prog = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.\
+                ------.--------.>>+.>++.";
+    assert_eq!(run_both(prog, b"").output, b"Hello World!\n");
+}
+
+#[test]
+fn cat() {
+    assert_eq!(run_both(",[.,]", b"echo").output, b"echo");
+}
+
+#[test]
+fn increment_wraps_around() {
+    let out = run_both(&"+".repeat(256), b"");
+    assert_eq!(out.cell(0), 0);
+    assert_eq!(run_both(&"+".repeat(300), b"").cell(0), 44);
+}
+
+#[test]
+fn decrement_wraps_around() {
+    assert_eq!(run_both("-", b"").cell(0), 255);
+    assert_eq!(run_both("--.", b"").output, [254]);
+}
+
+#[test]
+fn loops_terminate_through_wrapping() {
+    // Counts up from 1 until the cell wraps to 0.
+    let out = run_both("+[+>+<]", b"");
+    assert_eq!(out.cell(0), 0);
+    assert_eq!(out.cell(1), 255);
+}
+
+#[test]
+fn saturating_arithmetic() {
+    let config = RunConfig {
+        arithmetic: Arithmetic::Saturate,
+        ..RunConfig::default()
+    };
+    let out = run_with(&"+".repeat(300), b"", config.clone()).unwrap();
+    assert_eq!(out.cell(0), 255);
+    assert_eq!(run_with("-", b"", config).unwrap().cell(0), 0);
+}
+
+#[test]
+fn trapping_arithmetic() {
+    let config = RunConfig {
+        arithmetic: Arithmetic::Trap,
+        ..RunConfig::default()
+    };
+    match run_with("+>-", b"", config) {
+        Err(RunError::Overflow(Some(pos))) => assert_eq!(pos, 2),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("overflow not detected"),
+    }
+}
+
+#[test]
+fn optimizer_keeps_overflows_with_trapping_arithmetic() {
+    for &optimize in &[false, true] {
+        let config = RunConfig {
+            arithmetic: Arithmetic::Trap,
+            optimize,
+            ..RunConfig::default()
+        };
+        match run_with("-[-]+.", b"", config) {
+            Err(RunError::Overflow(Some(pos))) => assert_eq!(pos, 0),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("overflow not detected with optimize = {}", optimize),
+        }
+    }
+}
+
+#[test]
+fn optimizer_keeps_stores_before_increment_loops_with_saturating_arithmetic() {
+    let run = |optimize| {
+        let config = RunConfig {
+            arithmetic: Arithmetic::Saturate,
+            optimize,
+            max_steps: Some(1000),
+            ..RunConfig::default()
+        };
+        match run_with("+[+].", b"", config) {
+            Err(RunError::StepLimit(_)) => None,
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(out) => Some(out.output),
+        }
+    };
+    assert_eq!(run(false), None);
+    assert_eq!(run(true), run(false));
+}
+
+#[test]
+fn transfer_loops_subtract_from_wide_cells() {
+    // The factor 255 of `[->-<]` is -1, also with cells wider than a byte.
+    for &optimize in &[false, true] {
+        let config = RunConfig {
+            cells: CellType::I16,
+            optimize,
+            ..RunConfig::default()
+        };
+        let prog = compile("+++[->-<]>>+++[->+++<]<<-[->++<]", &config).unwrap();
+        let mut machine: CellMachine<i16> = CellMachine::new();
+        machine.run_with(&prog, &config).unwrap();
+        assert_eq!(machine.cells(), [0, -5, 0, 9]);
+    }
+}
+
+#[test]
+fn wide_cells_only_wrap() {
+    let prog = &"+".repeat(300);
+    let config = RunConfig {
+        cells: CellType::I16,
+        arithmetic: Arithmetic::Trap,
+        ..RunConfig::default()
+    };
+    assert!(matches!(run_with_config(prog, &config), Err(RunError::Unsupported(_))));
+    let config = RunConfig {
+        cells: CellType::I16,
+        underflow: Underflow::Error,
+        ..RunConfig::default()
+    };
+    assert!(matches!(run_with_config(prog, &config), Err(RunError::Unsupported(_))));
+    let config = RunConfig {
+        cells: CellType::I16,
+        ..RunConfig::default()
+    };
+    assert!(run_with_config(prog, &config).is_ok());
+}
+
+#[test]
+fn tape_grows_to_the_right() {
+    let out = run_both(&format!("{}+", ">".repeat(100_000)), b"");
+    assert_eq!(out.cell(100_000), 1);
+    assert_eq!(out.pos - out.origin, 100_000);
+}
+
+#[test]
+fn tape_grows_to_the_left() {
+    let out = run_both("+<++<<+++>", b"");
+    assert_eq!(out.cell(0), 1);
+    assert_eq!(out.cell(-1), 2);
+    assert_eq!(out.cell(-2), 0);
+    assert_eq!(out.cell(-3), 3);
+    assert_eq!(out.pos as isize - out.origin as isize, -2);
+}
+
+#[test]
+fn cells_keep_their_value_when_the_tape_grows_left() {
+    let out = run_both("+++>++++<<<<<<<<<<[-]>>>>>>>>>.>.", b"");
+    assert_eq!(out.output, [3, 4]);
+}
+
+#[test]
+fn moving_left_of_the_first_cell_fails() {
+    let config = RunConfig {
+        underflow: Underflow::Error,
+        ..RunConfig::default()
+    };
+    assert!(run_with(">+<", b"", config.clone()).is_ok());
+    match run_with("+<", b"", config) {
+        Err(RunError::Underflow(Some(pos))) => assert_eq!(pos, 1),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("underflow not detected"),
+    }
+}
+
+#[test]
+fn circular_tape() {
+    let config = RunConfig {
+        underflow: Underflow::Wrap(4),
+        ..RunConfig::default()
+    };
+    let out = run_with("+<++>>>>>.", b"", config).unwrap();
+    assert_eq!(out.output, [1]);
+}
+
+#[test]
+fn grid_tapes_are_kept_sparse() {
+    // The bounding box of the visited cells holds 10^12 cells.
+    let prog = format!("+{}{}++", ">".repeat(1_000_000), "v".repeat(1_000_000));
+    let config = RunConfig {
+        lexer: LexerOptions {
+            dialect: Dialect::Grid,
+            ..LexerOptions::default()
+        },
+        ..RunConfig::default()
+    };
+    let plain = compile(&prog, &config).unwrap();
+    let config = RunConfig {
+        optimize: true,
+        ..config
+    };
+    let opt = compile(&prog, &config).unwrap();
+    assert!(compare(&plain, &opt, &config).is_ok());
+    let tape = run_with_config(&prog, &config).unwrap().tape.unwrap();
+    assert_eq!((tape.get(0), tape.current(), tape.pointer()), (1, 2, 1_000_000));
+    assert_eq!(
+        tape.dump(None, DumpFormat::Dec),
+        "pointer: 1000000,1000000\n[2]\n   0,0:   1\n1000000,1000000:   2\n"
+    );
+}
+
+#[test]
+fn tape_limit() {
+    let config = RunConfig {
+        max_tape: Some(10),
+        ..RunConfig::default()
+    };
+    assert!(run_with(">>>>>>>>>", b"", config.clone()).is_ok());
+    assert!(matches!(
+        run_with("+[>+]", b"", config),
+        Err(RunError::TapeLimit(10))
+    ));
+}
+
+#[test]
+fn tape_reserve_is_bounded_by_the_tape_limit() {
+    let config = RunConfig {
+        tape_reserve: Some(usize::MAX),
+        ..RunConfig::default()
+    };
+    assert_eq!(
+        run_with_config("+", &config).err(),
+        Some(RunError::Reserve(usize::MAX))
+    );
+    let config = RunConfig {
+        max_tape: Some(1000),
+        ..config
+    };
+    let report = run_with_config("+", &config).unwrap();
+    assert!(report.tape_capacity >= 1000);
+}
+
+#[test]
+fn step_limit() {
+    let config = RunConfig {
+        max_steps: Some(100),
+        ..RunConfig::default()
+    };
+    assert!(matches!(
+        run_with("+[]", b"", config),
+        Err(RunError::StepLimit(100))
+    ));
+}
+
+#[test]
+fn output_limit() {
+    let config = RunConfig {
+        max_output_bytes: Some(5),
+        ..RunConfig::default()
+    };
+    assert!(run_with("+.+.+.+.+.", b"", config.clone()).is_ok());
+    assert!(matches!(
+        run_with("+[.]", b"", config),
+        Err(RunError::OutputLimit(5))
+    ));
+}
+
+#[test]
+fn output_up_to_the_limit_is_kept() {
+    for &optimize in &[false, true] {
+        let config = RunConfig {
+            max_output_bytes: Some(7),
+            optimize,
+            ..RunConfig::default()
+        };
+        let prog = compile("++++++++[>++++++++<-]>+[.+]", &config).unwrap();
+        let mut machine: BufferMachine =
+            BufferMachine::with_interpreter(interpreter(&prog, &config).unwrap());
+        let res = run_program(&prog, &mut machine, &config);
+        assert!(matches!(res, Err(RunError::OutputLimit(7))));
+        assert_eq!(machine.get_output(), b"ABCDEFG");
+    }
+}
+
+#[test]
+fn progress_is_published_when_the_run_stops() {
+    let progress = Arc::new(Progress::new());
+    let config = RunConfig {
+        progress: Some(progress.clone()),
+        ..RunConfig::default()
+    };
+    let prog = compile("++++[>++++<-]>.", &config).unwrap();
+    let mut machine: BufferMachine = BufferMachine::with_interpreter(interpreter(&prog, &config).unwrap());
+    let report = run_program(&prog, &mut machine, &config).unwrap();
+    assert_eq!(progress.steps(), report.steps);
+    assert_eq!(progress.pc(), prog.len());
+}
+
+#[test]
+fn time_limit() {
+    let config = RunConfig {
+        max_time: Some(Duration::from_millis(20)),
+        ..RunConfig::default()
+    };
+    assert!(matches!(
+        run_with("+[]", b"", config),
+        Err(RunError::TimeLimit(_))
+    ));
+}
+
+#[test]
+fn loop_limit() {
+    for &optimize in &[false, true] {
+        let config = RunConfig {
+            max_loop_iters: Some(4),
+            optimize,
+            ..RunConfig::default()
+        };
+        // The limit applies each time a loop is entered, not to its iterations in total.
+        let out = run_with("++++[>++++[>+<.-]<-]", b"", config.clone()).unwrap();
+        assert_eq!((out.output.len(), out.cell(2)), (16, 16));
+        assert_eq!(
+            run_with("++++[.-]+++++[.-]", b"", config).err(),
+            Some(RunError::LoopLimit {
+                limit: 4,
+                start: 13,
+                end: 16
+            })
+        );
+    }
+}
+
+#[test]
+fn loop_limit_across_checkpoints() {
+    // Checkpointed executions run in chunks of 2^24 steps.
+    let config = RunConfig {
+        max_loop_iters: Some(20_000_000),
+        checkpoint: Some(Checkpointing {
+            every: Duration::from_secs(3600),
+            file: env::temp_dir().join("bfrscc-loop-limit.checkpoint"),
+        }),
+        ..RunConfig::default()
+    };
+    assert_eq!(
+        run_with_config("+[]", &config).err(),
+        Some(RunError::LoopLimit {
+            limit: 20_000_000,
+            start: 1,
+            end: 2
+        })
+    );
+}
+
+#[test]
+fn core_dumps_are_reported_only_if_written() {
+    let dumped = |file| {
+        let config = RunConfig {
+            max_steps: Some(10),
+            core_file: Some(file),
+            core_dumped: Some(Arc::new(AtomicBool::new(false))),
+            ..RunConfig::default()
+        };
+        assert!(run_with_config("+[]", &config).is_err());
+        config.core_dumped.unwrap().load(Ordering::Relaxed)
+    };
+    assert!(dumped(env::temp_dir().join("bfrscc-reported.core")));
+    assert!(!dumped(env::temp_dir().join("bfrscc-missing").join("bfrscc.core")));
+}
+
+#[test]
+fn step_limit_within_a_run_on_one_cell() {
+    let config = |n| RunConfig {
+        max_steps: Some(n),
+        ..RunConfig::default()
+    };
+    assert_eq!(run_with("+.+.+.", b"", config(6)).unwrap().output, [1, 2, 3]);
+    assert!(matches!(
+        run_with("+.+.+.", b"", config(5)),
+        Err(RunError::StepLimit(5))
+    ));
+}
+
+#[test]
+fn runs_on_one_cell_read_and_write() {
+    let out = run_both(",+.-.,.+++.", b"ab");
+    assert_eq!(out.output, b"babe");
+    assert_eq!(out.cell(0), b'e');
+}
+
+#[test]
+fn captured_output_has_translated_line_endings() {
+    for &optimize in &[false, true] {
+        let config = RunConfig {
+            newline: Newline::Crlf,
+            optimize,
+            ..RunConfig::default()
+        };
+        let prog = compile("++++++++++.>++++++++++.", &config).unwrap();
+        let mut machine: BufferMachine =
+            BufferMachine::with_interpreter(interpreter(&prog, &config).unwrap());
+        run_program(&prog, &mut machine, &config).unwrap();
+        assert_eq!(machine.get_output(), b"\r\n\r\n");
+        let mut sink = vec![];
+        run_with_sink(&prog, &config, &mut sink).unwrap();
+        assert_eq!(sink, b"\r\n\r\n");
+    }
+    let config = RunConfig {
+        newline: Newline::Crlf,
+        ..RunConfig::default()
+    };
+    let plain = compile("++++++++++.", &config).unwrap();
+    let opt = plain.clone().optimized();
+    assert_eq!(compare(&plain, &opt, &config).unwrap(), b"\r\n");
+}
+
+#[test]
+fn sink_machine_keeps_the_fast_paths() {
+    let config = RunConfig::default();
+    let prog = compile("+.+.", &config).unwrap();
+    let mut sink = vec![];
+    {
+        let state = interpreter(&prog, &config).unwrap();
+        let mut machine: SinkMachine = SinkMachine::with_interpreter(state, &mut sink);
+        assert!(machine.exec_run(prog.ops(), &mut None.into_iter()));
+    }
+    assert_eq!(sink, [1, 2]);
+    let prog = compile("++++++++[>++++++++<-]>+.+.[-].", &config).unwrap().optimized();
+    let mut sink = vec![];
+    run_with_sink(&prog, &config, &mut sink).unwrap();
+    assert_eq!(sink, b"AB\0");
+}
+
+#[test]
+fn end_of_input_reads_zero() {
+    let out = run_both(",>,>+,", b"a");
+    assert_eq!(out.cell(0), b'a');
+    assert_eq!(out.cell(1), 0);
+    assert_eq!(out.cell(2), 0);
+}
+
+#[test]
+fn end_of_input_reads_255() {
+    let config = RunConfig {
+        eof: Eof::Max,
+        ..RunConfig::default()
+    };
+    let out = run_with(",>,>,", b"a", config).unwrap();
+    assert_eq!(out.cell(0), b'a');
+    assert_eq!(out.cell(1), 255);
+    assert_eq!(out.cell(2), 255);
+}
+
+#[test]
+fn input_after_the_end_stays_at_the_end() {
+    let out = run_both(",,,.", b"x");
+    assert_eq!(out.output, [0]);
+}
@@ -0,0 +1,152 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Classification of characters into tokens.
+
+extern crate bfrscc;
+
+use bfrscc::error::ParseError;
+use bfrscc::lexer::{
+    lexer, lexer_bytes, lexer_with_options, split_input, CommentPolicy, Dialect, LexerOptions,
+    Token,
+};
+
+/// Tokens of `prog` lexed with `options`
+fn tokens(prog: &str, options: &LexerOptions) -> Vec<Token> {
+    lexer_with_options(prog, options)
+        .unwrap()
+        .into_iter()
+        .map(|(_, t)| t)
+        .collect()
+}
+
+#[test]
+fn instructions() {
+    let expected = [
+        Token::Increase,
+        Token::Decrease,
+        Token::MoveLeft,
+        Token::MoveRight,
+        Token::LoopBegin,
+        Token::LoopEnd,
+        Token::Input,
+        Token::Output,
+    ];
+    assert_eq!(tokens("+-<>[],.", &LexerOptions::default()), expected);
+}
+
+#[test]
+fn everything_else_is_a_comment() {
+    let prog = "a Z0 #!~^v?\n\t";
+    let all = tokens(prog, &LexerOptions::default());
+    assert_eq!(all.len(), prog.chars().count());
+    assert!(all.iter().all(|&t| t == Token::Comment));
+}
+
+#[test]
+fn tokens_display_as_their_character() {
+    let prog = "+-<>[],.";
+    let shown: String = lexer(prog).iter().map(|(_, t)| t.to_string()).collect();
+    assert_eq!(shown, prog);
+    assert_eq!(Token::Comment.to_string(), "");
+}
+
+#[test]
+fn one_token_per_character() {
+    let all = lexer("ä+€-");
+    assert_eq!(all.len(), 4);
+    assert_eq!(all[1].1, Token::Increase);
+    assert_eq!(all[1].0.position, 1);
+    assert_eq!(all[1].0.offset, 2);
+    assert_eq!(all[3].1, Token::Decrease);
+    assert_eq!(all[3].0.position, 3);
+    assert_eq!(all[3].0.offset, 6);
+}
+
+#[test]
+fn lines_and_columns() {
+    let all = lexer("+\n  -");
+    let (span, token) = all[4];
+    assert_eq!(token, Token::Decrease);
+    assert_eq!((span.line, span.col), (2, 3));
+}
+
+#[test]
+fn bytes_which_are_not_ascii_are_comments() {
+    let all = lexer_bytes(b"+\xff\x80.");
+    let kinds: Vec<Token> = all.iter().map(|&(_, t)| t).collect();
+    assert_eq!(kinds, [Token::Increase, Token::Comment, Token::Comment, Token::Output]);
+    assert_eq!(all[3].0.position, 3);
+}
+
+#[test]
+fn dialect_comments() {
+    let options = LexerOptions {
+        comments: CommentPolicy::Dialect,
+        ..LexerOptions::default()
+    };
+    assert_eq!(
+        tokens("#!x", &options),
+        [Token::Breakpoint, Token::InputSeparator, Token::Comment]
+    );
+}
+
+#[test]
+fn comments_as_errors() {
+    let options = LexerOptions {
+        comments: CommentPolicy::Error,
+        ..LexerOptions::default()
+    };
+    assert_eq!(tokens("+ \n-", &options).len(), 4);
+    assert_eq!(
+        lexer_with_options("+ x", &options),
+        Err(ParseError::UnexpectedChar(2, 'x'))
+    );
+}
+
+#[test]
+fn dialect_instructions() {
+    let with = |dialect| LexerOptions {
+        dialect,
+        ..LexerOptions::default()
+    };
+    assert_eq!(tokens("~", &with(Dialect::MultiTape(2))), [Token::SwitchTape]);
+    assert_eq!(tokens("^v", &with(Dialect::Grid)), [Token::MoveUp, Token::MoveDown]);
+    assert_eq!(tokens("?", &with(Dialect::Random)), [Token::Random]);
+    assert_eq!(tokens("~^v?", &with(Dialect::Standard)), [Token::Comment; 4]);
+}
+
+#[test]
+fn aliases() {
+    let options = LexerOptions {
+        aliases: vec![("inc".to_string(), '+'), ("in".to_string(), ',')],
+        ..LexerOptions::default()
+    };
+    let all = tokens("incin", &options);
+    assert_eq!(all.len(), 5);
+    assert_eq!(all[0], Token::Increase);
+    assert_eq!(all[3], Token::Input);
+    assert_eq!(all.iter().filter(|&&t| t != Token::Comment).count(), 2);
+}
+
+#[test]
+fn program_length_limit() {
+    let mut options = LexerOptions::default();
+    options.limits.max_length = Some(3);
+    assert!(lexer_with_options("+++", &options).is_ok());
+    assert_eq!(
+        lexer_with_options("++++", &options),
+        Err(ParseError::ProgramTooLong(3))
+    );
+}
+
+#[test]
+fn inline_input_starts_after_the_first_separator_outside_of_loops() {
+    assert_eq!(split_input(",.!abc"), (",.", Some("abc")));
+    assert_eq!(split_input("[!],!x!"), ("[!],", Some("x!")));
+    assert_eq!(split_input("+."), ("+.", None));
+}
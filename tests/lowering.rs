@@ -0,0 +1,76 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! The machine calls the run loop makes for optimized and unoptimized programs.
+
+extern crate bfrscc;
+
+use bfrscc::lexer::LexerOptions;
+use bfrscc::machine::MachineExt;
+use bfrscc::program::Program;
+use bfrscc::run::RunConfig;
+use bfrscc::scripted::ScriptedMachine;
+
+/// Run `prog`, optimized at `level`, with `input` and check the calls against `script`.
+fn expect_calls(prog: &str, level: u8, input: &[u8], script: &str) {
+    let prog = Program::compile(prog, &LexerOptions::default(), level).unwrap();
+    let config = RunConfig {
+        input: Some(input.to_vec()),
+        ..RunConfig::default()
+    };
+    let mut machine = ScriptedMachine::from_script(script).unwrap().recording();
+    machine.run_with(&prog, &config).unwrap();
+    if let Err(mismatches) = machine.finish().map(|_| ()) {
+        let lines: Vec<String> = mismatches.iter().map(|m| m.to_string()).collect();
+        panic!("unexpected calls:\n{}", lines.join("\n"));
+    }
+}
+
+#[test]
+fn runs_are_folded() {
+    expect_calls("+++>>.<", 1, b"", "+3 >2 . <1");
+}
+
+#[test]
+fn unoptimized_programs_make_one_call_per_instruction() {
+    expect_calls("++>", 0, b"", "+1 +1 >1");
+}
+
+#[test]
+fn clear_loops_are_a_single_call() {
+    expect_calls("+++.[-].", 1, b"", "+3 . [-] .");
+}
+
+#[test]
+fn stores_which_are_overwritten_are_removed() {
+    // The clear of a cell which is already zero makes no call.
+    expect_calls("+++[-]+.", 1, b"", "+1 .");
+    expect_calls("+>++<[-][-]>.", 1, b"", ">1 +2 <1 >1 .");
+    expect_calls("--,.", 1, b"a", ",97 .");
+}
+
+#[test]
+fn stores_which_are_read_are_kept() {
+    expect_calls("++.[-].", 1, b"", "+2 . [-] .");
+    expect_calls("++>[<]<[-]", 1, b"", "+2 >1 <1 [-]");
+    expect_calls("+++[-]", 0, b"", "+1 +1 +1 [-]");
+}
+
+#[test]
+fn transfer_loops_are_a_single_call() {
+    expect_calls("++[->+++>+<<]", 1, b"", "+2 [1:3,2:1]");
+}
+
+#[test]
+fn loops_which_are_not_entered_make_no_calls() {
+    expect_calls(">[-]<[->+<]", 1, b"", ">1 <1");
+}
+
+#[test]
+fn input_is_passed_on() {
+    expect_calls(",.,.", 1, b"a", ",97 . ,0 .");
+}
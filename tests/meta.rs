@@ -0,0 +1,43 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Runs programs on dbfi, a brainfuck interpreter written in brainfuck.
+
+extern crate bfrscc;
+
+use bfrscc::buffer::BufferMachine;
+use bfrscc::meta::{meta_input, DBFI};
+use bfrscc::run::{run_on, RunConfig};
+
+/// Run `prog` with `input` on dbfi and return the output.
+fn run_meta(prog: &str, input: &[u8]) -> Vec<u8> {
+    let config = RunConfig {
+        input: Some(meta_input(prog, input)),
+        optimize: true,
+        ..RunConfig::default()
+    };
+    let mut machine = BufferMachine::new();
+    run_on(DBFI, &mut machine, &config).unwrap();
+    machine.take_output()
+}
+
+#[test]
+fn hello_world() {
+    let prog = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.\
+                ------.--------.>>+.>++.";
+    assert_eq!(run_meta(prog, b""), b"Hello World!\n");
+}
+
+#[test]
+fn cat_until_end_of_input() {
+    assert_eq!(run_meta(",[.,]", b"meta!"), b"meta!");
+}
+
+#[test]
+fn comments_are_stripped() {
+    assert_eq!(run_meta("read, then write. done!", b"x"), b"x");
+}
@@ -0,0 +1,172 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Bracket matching and parsing.
+
+extern crate bfrscc;
+
+mod support;
+
+use bfrscc::error::ParseError;
+use bfrscc::lexer::{lexer, LexerOptions, Limits, Token};
+use bfrscc::parser::{
+    parser, parser_with_counts, validate, validate_all, validate_with_limits, JumpTable,
+    MAX_REPEAT_COUNT,
+};
+use bfrscc::program::Program;
+
+/// Jump table of `prog`, which must be valid
+fn jumps(prog: &str) -> (Vec<Token>, JumpTable) {
+    let instr = parser(lexer(prog));
+    let tokens = instr.iter().map(|x| x.token).collect();
+    (tokens, JumpTable::new(&instr))
+}
+
+#[test]
+fn matching_brackets() {
+    let (tokens, table) = jumps("[+[-]>[<]]");
+    assert_eq!(table.get(0), 9);
+    assert_eq!(table.get(9), 0);
+    assert_eq!(table.get(2), 4);
+    assert_eq!(table.get(6), 8);
+    assert_eq!(table.get(1), 1);
+    for (i, t) in tokens.iter().enumerate() {
+        match *t {
+            Token::LoopBegin => assert_eq!(tokens[table.get(i)], Token::LoopEnd),
+            Token::LoopEnd => assert_eq!(tokens[table.get(i)], Token::LoopBegin),
+            _ => assert_eq!(table.get(i), i),
+        }
+    }
+}
+
+#[test]
+fn adjacent_and_empty_loops() {
+    let (_, table) = jumps("[][][[]]");
+    let targets: Vec<usize> = (0..8).map(|i| table.get(i)).collect();
+    assert_eq!(targets, [1, 0, 3, 2, 7, 6, 5, 4]);
+}
+
+#[test]
+fn unmatched_brackets() {
+    assert_eq!(validate(&lexer("+[[]")), Err(ParseError::UnmatchedLoopBegin(1)));
+    assert_eq!(validate(&lexer("[]]")), Err(ParseError::UnmatchedLoopEnd(2)));
+    assert_eq!(validate(&lexer("][")), Err(ParseError::UnmatchedLoopEnd(0)));
+    assert_eq!(validate(&lexer("a[b]c")), Ok(()));
+}
+
+#[test]
+fn all_unmatched_brackets() {
+    assert_eq!(
+        validate_all(&lexer("][[]]][")),
+        [
+            ParseError::UnmatchedLoopEnd(0),
+            ParseError::UnmatchedLoopEnd(5),
+            ParseError::UnmatchedLoopBegin(6),
+        ]
+    );
+    assert!(validate_all(&lexer("[[]]")).is_empty());
+}
+
+#[test]
+fn deeply_nested_loops() {
+    let depth = 100_000;
+    let prog = format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+    let tokens = lexer(&prog);
+    assert_eq!(validate(&tokens), Ok(()));
+    let table = JumpTable::new(&parser(tokens));
+    assert_eq!(table.get(0), 2 * depth - 1);
+    assert_eq!(table.get(depth - 1), depth);
+    let prog: Program = prog.parse().unwrap();
+    assert_eq!(prog.len(), 2 * depth);
+    assert_eq!(support::run(&prog.to_string(), b"").output, b"");
+}
+
+#[test]
+fn deeply_nested_unmatched_bracket() {
+    let depth = 100_000;
+    let prog = format!("{}{}", "[".repeat(depth), "]".repeat(depth - 1));
+    assert_eq!(validate(&lexer(&prog)), Err(ParseError::UnmatchedLoopBegin(0)));
+}
+
+#[test]
+fn nesting_limit() {
+    let limits = Limits {
+        max_depth: Some(2),
+        ..Limits::default()
+    };
+    assert_eq!(validate_with_limits(&lexer("[[]][[]]"), &limits), Ok(()));
+    assert_eq!(
+        validate_with_limits(&lexer("[[[]]]"), &limits),
+        Err(ParseError::NestingTooDeep(2, 2))
+    );
+}
+
+#[test]
+fn comments_are_dropped() {
+    let instr = parser(lexer("a+b-c"));
+    assert_eq!(instr.len(), 2);
+    assert_eq!(instr[0].token, Token::Increase);
+    assert_eq!(instr[0].position(), 1);
+    assert_eq!(instr[1].position(), 3);
+    assert!(instr.iter().all(|x| x.multiplier == 1));
+}
+
+#[test]
+fn brackets_keep_their_positions_between_comments() {
+    let instr = parser(lexer("a[b+c]d"));
+    let positions: Vec<usize> = instr.iter().map(|x| x.position()).collect();
+    assert_eq!(positions, [1, 3, 5]);
+    assert_eq!(JumpTable::new(&instr).get(0), 2);
+}
+
+#[test]
+fn repeat_counts() {
+    let options = LexerOptions {
+        repeat_counts: true,
+        ..LexerOptions::default()
+    };
+    let code = "300+3.2[";
+    let tokens = bfrscc::lexer::lexer_with_options(code, &options).unwrap();
+    let instr = parser_with_counts(tokens, code).unwrap();
+    let summary: Vec<(Token, u8)> = instr.iter().map(|x| (x.token, x.multiplier)).collect();
+    assert_eq!(
+        summary,
+        [
+            (Token::Increase, 255),
+            (Token::Increase, 45),
+            (Token::Output, 1),
+            (Token::Output, 1),
+            (Token::Output, 1),
+            (Token::LoopBegin, 1),
+        ]
+    );
+}
+
+#[test]
+fn repeat_counts_are_bounded() {
+    let options = LexerOptions {
+        repeat_counts: true,
+        ..LexerOptions::default()
+    };
+    let count = |code: &str| {
+        let tokens = bfrscc::lexer::lexer_with_options(code, &options).unwrap();
+        parser_with_counts(tokens, code).map(|x| x.len())
+    };
+    assert_eq!(count("65535."), Ok(MAX_REPEAT_COUNT));
+    assert_eq!(
+        count("+99999999999999999999."),
+        Err(ParseError::RepeatCountTooLarge(1, MAX_REPEAT_COUNT))
+    );
+}
+
+#[test]
+fn invalid_programs_do_not_compile() {
+    assert_eq!(
+        "+]".parse::<Program>().map(|_| ()),
+        Err(ParseError::UnmatchedLoopEnd(1))
+    );
+}
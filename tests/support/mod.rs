@@ -0,0 +1,73 @@
+// Copyright 2018 Stefan Kroboth
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Helpers shared by the tests.
+
+#![allow(dead_code)]
+
+use bfrscc::buffer::BufferMachine;
+use bfrscc::run::{compile, interpreter, run_program, RunConfig, RunError};
+use bfrscc::tape::Tape;
+
+/// Outcome of a run on a `BufferMachine`
+pub struct Outcome {
+    /// Everything written by `.`
+    pub output: Vec<u8>,
+    /// Cells of the tape, from the leftmost cell on
+    pub cells: Vec<u8>,
+    /// Index of the cell the pointer started at, within `cells`
+    pub origin: usize,
+    /// Index of the cell under the pointer, within `cells`
+    pub pos: usize,
+}
+
+impl Outcome {
+    /// Value of the cell `offset` cells from the cell the pointer started at
+    pub fn cell(&self, offset: isize) -> u8 {
+        let idx = self.origin as isize + offset;
+        assert!(idx >= 0, "cell {} is left of the tape", offset);
+        self.cells.get(idx as usize).cloned().unwrap_or(0)
+    }
+}
+
+/// Run `prog` with `input` and the settings of `config`, collecting output and tape.
+pub fn run_with(prog: &str, input: &[u8], config: RunConfig) -> Result<Outcome, RunError> {
+    let config = RunConfig {
+        input: Some(input.to_vec()),
+        seed: Some(0),
+        ..config
+    };
+    let prog = compile(prog, &config)?;
+    let mut machine: BufferMachine = BufferMachine::with_interpreter(interpreter(&prog, &config)?);
+    run_program(&prog, &mut machine, &config)?;
+    let tape = machine.interpreter().tape();
+    Ok(Outcome {
+        cells: tape.cells(),
+        origin: tape.origin(),
+        pos: tape.pos(),
+        output: machine.take_output(),
+    })
+}
+
+/// Run `prog` with `input` and the default settings, which must succeed.
+pub fn run(prog: &str, input: &[u8]) -> Outcome {
+    run_with(prog, input, RunConfig::default()).unwrap()
+}
+
+/// Like `run`, but optimized as well, and checks both runs agree.
+pub fn run_both(prog: &str, input: &[u8]) -> Outcome {
+    let plain = run(prog, input);
+    let optimized = run_with(prog, input, RunConfig { optimize: true, ..RunConfig::default() })
+        .unwrap();
+    assert_eq!(plain.output, optimized.output, "output of the optimized program differs");
+    assert_eq!(
+        plain.cell(0),
+        optimized.cell(0),
+        "first cell of the optimized program differs"
+    );
+    plain
+}